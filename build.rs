@@ -0,0 +1,5 @@
+fn main() {
+    //Node needs the addon linked with its own symbols left undefined
+    #[cfg(feature = "node")]
+    napi_build::setup();
+}
@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use vlive_notifs::vlive::Parser;
+
+//Any input must end up as videos, parse errors or an unavailable page
+fuzz_target!(|data: &[u8]| {
+    if let Ok(html) = std::str::from_utf8(data) {
+        let _ = Parser::default().parse_page(html);
+    }
+});
@@ -0,0 +1,78 @@
+#ifndef VLIVE_H
+#define VLIVE_H
+
+/* Generated with cbindgen from src/vlive/ffi.rs, don't edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// A listener, configured until `vlive_start` and running until `vlive_stop`
+typedef struct VLiveHandle VLiveHandle;
+
+// Called with every new video as JSON and the `user_data` given to
+// `vlive_set_callback`. The JSON is only valid during the call. Return 0 to
+// keep listening, anything else to stop
+typedef int (*VLiveCallbackFn)(const char *video, void *user_data);
+
+// New listener polling every `wait_ms` milliseconds, see `VLive::new`
+//
+// Free it with `vlive_free`
+VLiveHandle *vlive_new(uint64_t wait_ms);
+
+// Poll the channel `code` every `wait_ms` milliseconds, see `VLive::watch`
+//
+// Returns 0, or -1 if `handle` or `code` is unusable or the listener is
+// already running
+//
+// # Safety
+//
+// `handle` must come from `vlive_new` and `code` must be a NUL terminated
+// string, or either can be NULL
+int vlive_watch(VLiveHandle *handle, const char *code, uint64_t wait_ms);
+
+// Call `callback` with every new video, replacing any earlier callback
+//
+// Returns 0, or -1 if `handle` is NULL or the listener is already running
+//
+// # Safety
+//
+// `handle` must come from `vlive_new` or be NULL. `user_data` is handed to
+// `callback` from another thread, and must stay valid until `vlive_stop`
+// or `vlive_free` returns
+int vlive_set_callback(VLiveHandle *handle, VLiveCallbackFn callback, void *user_data);
+
+// Start listening in the background, see `VLive::run_async`
+//
+// Returns 0, or -1 if `handle` is NULL, has no callback or is already
+// running
+//
+// # Safety
+//
+// `handle` must come from `vlive_new` or be NULL
+int vlive_start(VLiveHandle *handle);
+
+// Stop a listener started with `vlive_start`, and wait for it
+//
+// Videos already found are still handed to the callback, and a poll
+// that's running is finished first. Once this returns the callback won't
+// be called again, and `user_data` can be freed. Does nothing if the
+// listener isn't running
+//
+// # Safety
+//
+// `handle` must come from `vlive_new` or be NULL. Don't call this from
+// the callback, it would wait for itself, return non-zero instead
+void vlive_stop(VLiveHandle *handle);
+
+// Stop the listener if it's running, wait for it like `vlive_stop` and
+// free `handle`
+//
+// # Safety
+//
+// `handle` must come from `vlive_new` or be NULL, and must not be used
+// again. Don't call this from the callback
+void vlive_free(VLiveHandle *handle);
+
+#endif  /* VLIVE_H */
@@ -1,8 +1,9 @@
 struct Listener;
 
 impl super::vlive::VLiveCallback for Listener {
-    fn on_new(&self, video: super::vlive::VLiveVideo) {
+    fn on_new(&mut self, _: &super::vlive::Ctx, video: super::vlive::VLiveVideo) -> super::vlive::Flow {
         println!("Hello from callback {:?}", video);
+        super::vlive::Flow::Continue
     }
 }
 
@@ -15,3 +16,2918 @@ fn callback() {
     sleep(Duration::from_secs(5));
     stopper.stop();
 }
+
+#[test]
+fn full_queues_follow_their_policy() {
+    use std::sync::{mpsc, Arc, Barrier};
+    use std::thread;
+    use std::time::Duration;
+    use super::vlive::{Queue, QueuePolicy};
+
+    let oldest = Queue::new(2, QueuePolicy::DropOldest);
+    assert_eq!(oldest.push(1), None);
+    assert_eq!(oldest.push(2), None);
+    assert_eq!(oldest.push(3), Some(1));
+    assert_eq!(oldest.pop_until(None), Ok(Some(2)));
+    assert_eq!(oldest.pop_until(None), Ok(Some(3)));
+    assert_eq!(oldest.len(), 0);
+
+    let newest = Queue::new(2, QueuePolicy::DropNewest);
+    assert_eq!(newest.push(1), None);
+    assert_eq!(newest.push(2), None);
+    assert_eq!(newest.push(3), Some(3));
+    assert_eq!(newest.len(), 2);
+    assert_eq!(newest.pop_until(None), Ok(Some(1)));
+    assert_eq!(newest.pop_until(None), Ok(Some(2)));
+    assert_eq!(newest.len(), 0);
+
+    let block = Queue::new(1, QueuePolicy::Block);
+    let pusher = |item| {
+        let block = block.clone();
+        let started = Arc::new(Barrier::new(2));
+        let (pushed, done) = mpsc::channel();
+        let wait = started.clone();
+        thread::spawn(move || {
+            wait.wait();
+            pushed.send(block.push(item)).unwrap();
+        });
+        started.wait();
+        done
+    };
+
+    //The second push waits until the first item is taken
+    assert_eq!(block.push(1), None);
+    let done = pusher(2);
+    assert!(done.recv_timeout(Duration::from_millis(50)).is_err());
+    assert_eq!(block.pop_until(None), Ok(Some(1)));
+    assert_eq!(done.recv().unwrap(), None);
+    assert_eq!(block.pop_until(None), Ok(Some(2)));
+
+    //Closing gives up on a waiting push
+    assert_eq!(block.push(3), None);
+    let done = pusher(4);
+    assert!(done.recv_timeout(Duration::from_millis(50)).is_err());
+    block.close();
+    assert_eq!(done.recv().unwrap(), None);
+    assert_eq!(block.pop_until(None), Ok(Some(3)));
+    assert_eq!(block.pop_until(None), Ok(None));
+}
+
+#[test]
+fn callbacks_run_on_the_named_dispatcher() {
+    use std::sync::Arc;
+    use std::sync::mpsc::{channel, Sender};
+    use std::thread;
+    use std::time::Duration;
+    use super::vlive::{Ctx, Flow, VLive, VLiveCallback, VLiveError, VLiveVideo};
+    use super::vlive::transport::{Transport, TransportError};
+
+    struct Offline;
+
+    impl Transport for Offline {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            Err("offline".into())
+        }
+    }
+
+    struct Names(Sender<Option<String>>);
+
+    impl VLiveCallback for Names {
+        fn on_new(&mut self, _: &Ctx, _: VLiveVideo) -> Flow {
+            Flow::Continue
+        }
+
+        fn on_error(&mut self, _: VLiveError) -> Flow {
+            let _ = self.0.send(thread::current().name().map(str::to_string));
+            Flow::Stop
+        }
+    }
+
+    let (tx, rx) = channel();
+    let _stopper = VLive::new(Names(tx), Duration::from_secs(60))
+        .transport(Arc::new(Offline))
+        .thread_name("bias")
+        .run_async();
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap().as_deref(), Some("bias-dispatcher"));
+}
+
+#[test]
+fn callbacks_hear_about_starts_stops_and_reconnects() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::{channel, Sender};
+    use std::time::Duration;
+    use super::vlive::{Ctx, Flow, VLive, VLiveCallback, VLiveError, VLiveVideo};
+    use super::vlive::transport::{Transport, TransportError};
+
+    //Down for the first two polls
+    struct Flaky(AtomicUsize);
+
+    impl Transport for Flaky {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            match self.0.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => Err("offline".into()),
+                _ => Ok(String::new()),
+            }
+        }
+    }
+
+    struct Hooks {
+        calls: Sender<&'static str>,
+        reconnected: bool,
+    }
+
+    impl VLiveCallback for Hooks {
+        fn on_new(&mut self, _: &Ctx, _: VLiveVideo) -> Flow {
+            Flow::Continue
+        }
+
+        fn on_error(&mut self, _: VLiveError) -> Flow {
+            if self.reconnected { Flow::Stop } else { Flow::Continue }
+        }
+
+        fn on_started(&mut self, _: &Ctx) {
+            let _ = self.calls.send("started");
+        }
+
+        fn on_stopped(&mut self) {
+            let _ = self.calls.send("stopped");
+        }
+
+        fn on_reconnected(&mut self, outage: Duration) {
+            assert!(outage > Duration::ZERO);
+            self.reconnected = true;
+            let _ = self.calls.send("reconnected");
+        }
+    }
+
+    let (tx, rx) = channel();
+    let _stopper = VLive::new(Hooks { calls: tx, reconnected: false }, Duration::from_millis(10))
+        .transport(Arc::new(Flaky(AtomicUsize::new(0))))
+        .run_async();
+    let calls: Vec<_> = (0..3).filter_map(|_| rx.recv_timeout(Duration::from_secs(5)).ok()).collect();
+    assert_eq!(calls, vec!["started", "reconnected", "stopped"]);
+}
+
+#[test]
+fn admin_endpoints_report_and_pause_the_listener() {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use super::vlive::{Flow, VLive, VLiveVideo};
+    use super::vlive::transport::{Transport, TransportError};
+
+    struct Offline;
+
+    impl Transport for Offline {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            Err("offline".into())
+        }
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let stopper = VLive::new(|_: VLiveVideo| Flow::Continue, Duration::from_secs(60 * 60))
+        .transport(Arc::new(Offline))
+        .admin(listener)
+        .run_async();
+    let request = |request: &str| {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "{} HTTP/1.1\r\nHost: localhost\r\n\r\n", request).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+
+    assert!(request("GET /healthz").starts_with("HTTP/1.1 503"));
+    assert!(request("GET /stats").contains("Content-Type: application/json"));
+    assert!(request("GET /config").starts_with("HTTP/1.1 200"));
+    assert!(request("GET /nowhere").starts_with("HTTP/1.1 404"));
+    assert!(request("GET /pause").starts_with("HTTP/1.1 405"));
+
+    //Paused on purpose counts as healthy
+    assert!(request("POST /pause").starts_with("HTTP/1.1 200"));
+    assert!(stopper.ctx().stats().paused);
+    assert!(request("GET /healthz").starts_with("HTTP/1.1 200"));
+    assert!(request("DELETE /pause").starts_with("HTTP/1.1 200"));
+    assert!(!stopper.ctx().stats().paused);
+    stopper.stop();
+
+    //The port is free again once the listener has stopped
+    let started = std::time::Instant::now();
+    while TcpListener::bind(addr).is_err() && started.elapsed() < Duration::from_secs(5) {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(TcpListener::bind(addr).is_ok());
+}
+
+#[test]
+fn admin_serves_journaled_events() {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use super::vlive::{testing, Event, Flow, Journal, VLive, VLiveVideo};
+    use super::vlive::transport::{Transport, TransportError};
+
+    struct Offline;
+
+    impl Transport for Offline {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            Err("offline".into())
+        }
+    }
+
+    let path = std::env::temp_dir().join(format!("vlive-admin-journal-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut journal = Journal::open(&path).unwrap();
+    let bts = testing::event(1, testing::video().seq(1).channel("/channels/FE619", 13, "BTS").build());
+    let iu = testing::event(2, testing::video().seq(2).channel("/channels/EBDF", 7, "IU").build());
+    let later = testing::event(3, testing::video().seq(3).channel("/channels/FE619", 13, "BTS").build());
+    for event in [&bts, &iu, &later] {
+        journal.append(event).unwrap();
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let stopper = VLive::new(|_: VLiveVideo| Flow::Continue, Duration::from_secs(60 * 60))
+        .transport(Arc::new(Offline))
+        .journal(journal)
+        .admin(listener)
+        .run_async();
+    let events = |target: &str| -> Vec<Event> {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\n\r\n", target).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        serde_json::from_str(body).unwrap()
+    };
+
+    assert_eq!(events("/events"), vec![bts.clone(), iu, later.clone()]);
+    assert_eq!(events("/events?channel=FE619"), vec![bts, later.clone()]);
+    assert_eq!(events("/events?since=2&channel=FE619"), vec![later]);
+    stopper.stop();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn callbacks_act_through_their_ctx() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+    use super::vlive::{Ctx, Feed, Flow, VLive, VLiveCallback, VLiveError, VLiveVideo};
+    use super::vlive::transport::{Transport, TransportError};
+
+    struct Counting(Arc<AtomicUsize>);
+
+    impl Transport for Counting {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Err("offline".into())
+        }
+    }
+
+    //Polls again right away on the first error
+    struct Impatient(Option<Ctx>);
+
+    impl VLiveCallback for Impatient {
+        fn on_new(&mut self, _: &Ctx, _: VLiveVideo) -> Flow {
+            Flow::Continue
+        }
+
+        fn on_error(&mut self, _: VLiveError) -> Flow {
+            let ctx = self.0.take().expect("started first");
+            assert_eq!(ctx.stats().dead_letters, 0);
+            assert_eq!((ctx.poll_id(), ctx.feed()), (1, Some(Feed::Recent)));
+            ctx.poll_now();
+            Flow::Continue
+        }
+
+        fn on_started(&mut self, ctx: &Ctx) {
+            self.0 = Some(ctx.clone());
+        }
+    }
+
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let stopper = VLive::new(Impatient(None), Duration::from_secs(60 * 60))
+        .transport(Arc::new(Counting(fetches.clone())))
+        .run_async();
+    let started = Instant::now();
+    while fetches.load(Ordering::SeqCst) < 2 && started.elapsed() < Duration::from_secs(5) {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    assert_eq!(stopper.ctx().stats().last_seen, 0);
+    stopper.ctx().stop();
+}
+
+#[test]
+fn per_video_callbacks_get_the_ctx() {
+    use super::vlive::{testing, Ctx, Fanout, Flow, VLiveCallback, VLiveVideo};
+
+    struct Pausing;
+
+    impl VLiveCallback for Pausing {
+        fn on_new(&mut self, ctx: &Ctx, _: VLiveVideo) -> Flow {
+            ctx.pause();
+            Flow::Continue
+        }
+    }
+
+    //Wrappers hand theirs on to what they wrap
+    let ctx = Ctx::detached();
+    let mut sinks = Fanout::new().sink(Pausing);
+    assert_eq!(sinks.deliver(&ctx, &testing::event(1, testing::video().build())).unwrap(), Flow::Continue);
+    assert!(ctx.stats().paused);
+
+    let ctx = Ctx::detached();
+    assert!(!ctx.stats().paused);
+    Pausing.on_digest(&ctx, vec![testing::video().build()]);
+    assert!(ctx.stats().paused);
+}
+
+#[test]
+fn unparseable_seq_is_an_error() {
+    use super::vlive::{Parser, ParseError};
+    use super::vlive::parser::Document;
+
+    let html = r#"<div class="video_list_cont">
+        <a class="thumb_area" href="/video/50000" data-seq="not a number"></a>
+        <a class="name" href="/channels/EBDF"></a>
+    </div>"#;
+    let document = Document::parse_document(html);
+    let parser = Parser::default();
+    let videos: Vec<_> = parser.videos(&document).collect();
+    assert_eq!(videos.len(), 1);
+    match parser.parse_node(videos[0]) {
+        Err(ParseError::InvalidAttribute { attribute: "data-seq", .. }) => (),
+        other => panic!("expected invalid data-seq, got {:?}", other.map(|video| video.video_seq)),
+    }
+
+    //Missing or 0 seqs would all look like the same video
+    for seq in &["", r#"data-seq="0""#] {
+        let html = format!(r#"<div class="video_list_cont">
+            <a class="thumb_area" href="/video/50000" {}></a>
+            <a class="name" href="/channels/EBDF"></a>
+        </div>"#, seq);
+        let document = Document::parse_document(&html);
+        let videos: Vec<_> = parser.videos(&document).collect();
+        assert!(parser.parse_node(videos[0]).is_err());
+    }
+
+    //Seqs past `u32::MAX` are fine
+    let html = r#"<div class="video_list_cont">
+        <a class="thumb_area" href="/video/5000000000" data-seq="5000000000"></a>
+        <a class="name" href="/channels/EBDF"></a>
+    </div>"#;
+    let document = Document::parse_document(html);
+    let videos: Vec<_> = parser.videos(&document).collect();
+    assert_eq!(parser.parse_node(videos[0]).unwrap().video_seq, 5_000_000_000);
+}
+
+const RECENT: &str = include_str!("fixtures/recent.html");
+
+#[test]
+fn parse_recent_fixture() {
+    let videos = super::vlive::Parser::default().parse_page(RECENT).unwrap();
+    let videos: Vec<_> = videos.into_iter().map(Result::unwrap).collect();
+    let seqs: Vec<_> = videos.iter().map(|video| video.video_seq).collect();
+    assert_eq!(seqs, vec![50002, 50001, 50000]);
+    let paid: Vec<_> = videos.iter().map(|video| video.video_paid).collect();
+    assert_eq!(paid, vec![true, false, false]);
+}
+
+/// What a page was turned into, as short strings, errors are skipped
+fn announced(events: Vec<Result<super::vlive::VLiveEvent, super::vlive::VLiveError>>) -> Vec<String> {
+    use super::vlive::VLiveEvent;
+
+    events.into_iter()
+        .filter_map(|event| match event {
+            Ok(VLiveEvent::New(event)) => Some(event.video.video_seq.to_string()),
+            Ok(VLiveEvent::PossibleGap { missed_range, .. }) => Some(format!("{:?}", missed_range)),
+            Ok(VLiveEvent::Transition(transition)) => Some(format!("{:?}", transition.to)),
+            Ok(VLiveEvent::LiveEnded { live, .. }) => Some(format!("ended {}", live.video_seq)),
+            Ok(VLiveEvent::VodPublished(video)) => Some(format!("vod {}", video.video_seq)),
+            Ok(VLiveEvent::FollowerMilestone { followers, milestone, .. }) => Some(format!("{} of {}", milestone, followers)),
+            Ok(VLiveEvent::NewChannelCreated(channel)) => Some(channel.channel_name),
+            Err(_) => None,
+        })
+        .collect()
+}
+
+#[test]
+fn poller_announces_each_video_once() {
+    use std::time::{Duration, Instant, SystemTime};
+    use super::vlive::{Feed, Parser, Poller};
+
+    let now = Instant::now();
+    let parser = Parser::default();
+    let mut poller = Poller::new(vec![(Feed::Recent, Duration::from_secs(5))], now);
+    assert_eq!(poller.due(now, SystemTime::UNIX_EPOCH), vec![0]);
+    assert_eq!(poller.due(now, SystemTime::UNIX_EPOCH), Vec::<usize>::new());
+    assert_eq!(poller.next_due(), Some(now + Duration::from_secs(5)));
+
+    assert_eq!(announced(poller.page(0, RECENT, &parser, now, SystemTime::UNIX_EPOCH)), vec!["50002", "50001", "50000"]);
+    assert_eq!(poller.last_seen(), 50002);
+    assert!(announced(poller.page(0, RECENT, &parser, now, SystemTime::UNIX_EPOCH)).is_empty());
+
+    //Rewinding forgets what was announced
+    poller.seek(50001);
+    assert_eq!(announced(poller.page(0, RECENT, &parser, now, SystemTime::UNIX_EPOCH)), vec!["50002"]);
+
+    //The last video we saw is gone, so something might be missing
+    poller.seek(40000);
+    assert_eq!(announced(poller.page(0, RECENT, &parser, now, SystemTime::UNIX_EPOCH)), vec!["50002", "50001", "50000", "40001..50000"]);
+}
+
+#[test]
+fn poller_stops_at_the_last_seen_video() {
+    use std::time::{Duration, Instant, SystemTime};
+    use super::vlive::{Feed, Parser, Poller};
+
+    let page = |seqs: &[u64]| -> String {
+        let videos: Vec<_> = seqs.iter()
+            .map(|seq| format!(r#"<div class="video_list_cont">
+                <a class="thumb_area" href="/video/{0}" data-seq="{0}" data-ga-cseq="363"></a>
+                <a class="name" href="/channels/FE619"></a>
+            </div>"#, seq))
+            .collect();
+        videos.join("")
+    };
+    //Can't be parsed, so walking this far would be an error
+    let broken = r#"<div class="video_list_cont"><a class="thumb_area" data-seq="80"></a></div>"#;
+
+    let now = Instant::now();
+    let parser = Parser::default();
+    let mut poller = Poller::new(vec![(Feed::Recent, Duration::from_secs(5))], now);
+    assert_eq!(announced(poller.page(0, &page(&[101, 100]), &parser, now, SystemTime::UNIX_EPOCH)), vec!["101", "100"]);
+
+    //Pinned videos on top are skipped, and nothing below 101 is parsed
+    let events = poller.page(0, &(page(&[100, 90, 102, 101]) + broken), &parser, now, SystemTime::UNIX_EPOCH);
+    assert!(events.iter().all(Result::is_ok));
+    assert_eq!(announced(events), vec!["102"]);
+    assert_eq!(poller.last_seen(), 102);
+}
+
+#[test]
+fn pollers_only_announce_the_kinds_asked_for() {
+    use std::time::{Duration, Instant, SystemTime};
+    use super::vlive::{EventKinds, Feed, Parser, Poller};
+
+    let now = Instant::now();
+    let parser = Parser::default();
+    let poller = |kinds| Poller::new(vec![(Feed::Recent, Duration::from_secs(5))], now).kinds(kinds);
+
+    //Skipped videos still count as seen
+    let mut live = poller(EventKinds::LIVE_ONLY);
+    assert_eq!(announced(live.page(0, RECENT, &parser, now, SystemTime::UNIX_EPOCH)), vec!["50001"]);
+    assert_eq!(live.last_seen(), 50002);
+    assert!(announced(live.page(0, RECENT, &parser, now, SystemTime::UNIX_EPOCH)).is_empty());
+
+    let mut vod = poller(EventKinds::VOD_ONLY);
+    assert_eq!(announced(vod.page(0, RECENT, &parser, now, SystemTime::UNIX_EPOCH)), vec!["50002", "50000"]);
+}
+
+#[test]
+fn seeking_skips_ahead_on_vlive_feeds_only() {
+    use std::time::{Duration, Instant, SystemTime};
+    use super::vlive::{Feed, Parser, Poller};
+
+    let youtube = r#"<feed><entry><yt:videoId>dQw4w9WgXcQ</yt:videoId><yt:channelId>UCLkAepWjdylmXSltofFvsYQ</yt:channelId>
+        <title>Butter</title><author><name>BANGTANTV</name></author>
+        <published>2021-06-01T09:00:00+00:00</published></entry></feed>"#;
+
+    let now = Instant::now();
+    let parser = Parser::default();
+    let feeds = vec![(Feed::Recent, Duration::from_secs(5)), (Feed::YouTube("UCLkAepWjdylmXSltofFvsYQ".to_string()), Duration::from_secs(5))];
+    let mut poller = Poller::new(feeds, now);
+
+    //Skipping ahead ignores the backlog, and the YouTube seq isn't a VLive one
+    poller.seek(50001);
+    assert_eq!(announced(poller.page(0, RECENT, &parser, now, SystemTime::UNIX_EPOCH)), vec!["50002"]);
+    assert_eq!(announced(poller.page(1, youtube, &parser, now, SystemTime::UNIX_EPOCH)).len(), 1);
+    poller.seek(0);
+    assert!(announced(poller.page(1, youtube, &parser, now, SystemTime::UNIX_EPOCH)).is_empty());
+}
+
+#[test]
+fn manual_clock_sleeps_instantly() {
+    use std::time::{Duration, Instant};
+    use super::vlive::Clock;
+    use super::vlive::clock::ManualClock;
+
+    let clock = ManualClock::new();
+    let (start, system_start) = (clock.now(), clock.system_now());
+    let real = Instant::now();
+    clock.sleep(Duration::from_secs(60 * 60));
+    clock.advance(Duration::from_secs(1));
+    assert!(real.elapsed() < Duration::from_secs(60));
+    assert_eq!(clock.now() - start, Duration::from_secs(60 * 60 + 1));
+    assert_eq!(clock.system_now().duration_since(system_start).unwrap(), Duration::from_secs(60 * 60 + 1));
+}
+
+//Videos are still there, but nothing can be read from them
+const CHANGED: &str = r#"<div class="video_list_cont"><a class="thumb_area"></a></div>"#;
+
+#[test]
+fn poller_announces_follower_milestones() {
+    use std::time::{Duration, Instant, SystemTime};
+    use super::vlive::{Feed, Parser, Poller};
+
+    let now = Instant::now();
+    let parser = Parser::default();
+    let mut poller = Poller::new(vec![(Feed::Channel("FE619".to_string()), Duration::from_secs(5))], now)
+        .milestones(100_000);
+    let mut poll = |followers: &str| {
+        let html = format!(r#"<div class="channel_info"><span class="fan_count">{}</span></div>"#, followers);
+        announced(poller.page(0, &html, &parser, now, SystemTime::UNIX_EPOCH))
+    };
+
+    assert!(poll("99,950").is_empty());
+    assert_eq!(poll("100,020"), vec!["100000 of 100020"]);
+    assert!(poll("100,500").is_empty());
+    assert_eq!(poll("320,000"), vec!["300000 of 320000"]);
+}
+
+#[test]
+fn poller_discovers_new_channels() {
+    use std::time::{Duration, Instant, SystemTime};
+    use super::vlive::{Feed, Parser, Poller};
+
+    let now = Instant::now();
+    let parser = Parser::default();
+    let mut poller = Poller::new(vec![(Feed::Directory, Duration::from_secs(60))], now);
+    let mut poll = |codes: &[&str]| {
+        let html: String = codes.iter()
+            .map(|code| format!(r#"<li><a href="/channels/{0}" data-ga-cname="{0}">{0}</a></li>"#, code))
+            .collect();
+        announced(poller.page(0, &html, &parser, now, SystemTime::UNIX_EPOCH))
+    };
+
+    assert!(poll(&["FE619", "EBDF"]).is_empty());
+    assert_eq!(poll(&["F001E5", "FE619", "EBDF"]), vec!["F001E5"]);
+    assert!(poll(&["F001E5", "FE619"]).is_empty());
+    assert_eq!(poller.state().channels.map(|channels| channels.len()), Some(3));
+}
+
+#[test]
+fn poller_reports_layout_change_once() {
+    use std::time::{Duration, Instant, SystemTime};
+    use super::vlive::{Feed, Parser, Poller, VLiveError};
+
+    let now = Instant::now();
+    let parser = Parser::default();
+    let mut poller = Poller::new(vec![(Feed::Recent, Duration::from_secs(5))], now);
+    let changed = (0..5)
+        .flat_map(|_| poller.page(0, CHANGED, &parser, now, SystemTime::UNIX_EPOCH))
+        .filter(|event| matches!(event, Err(VLiveError::LayoutChanged { polls: 3, .. })))
+        .count();
+    assert_eq!(changed, 1);
+    assert_eq!(poller.state().feeds[0].failures, 5);
+}
+
+#[test]
+fn empty_pages_are_not_a_layout_change() {
+    use std::time::{Duration, Instant, SystemTime};
+    use super::vlive::{Feed, Parser, Poller, Unavailable, VLiveError};
+
+    let now = Instant::now();
+    let parser = Parser::default();
+    let mut poller = Poller::new(vec![(Feed::Channel("F001E5".to_string()), Duration::from_secs(5))], now);
+    for _ in 0..10 {
+        let events = poller.page(0, r#"<ul class="video_list"></ul>"#, &parser, now, SystemTime::UNIX_EPOCH);
+        assert!(matches!(events[..], [Err(VLiveError::ServiceUnavailable(Unavailable::Empty))]));
+    }
+    assert_eq!(poller.state().feeds[0].failures, 0);
+}
+
+#[test]
+fn ticker_hands_out_errors_then_carries_on() {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use super::vlive::{Clock, Feed, Poller, Ticker, VLiveError};
+    use super::vlive::clock::ManualClock;
+    use super::vlive::transport::{Transport, TransportError};
+
+    //Fails every other request
+    struct Flaky(Mutex<bool>);
+
+    impl Transport for Flaky {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            let mut fail = self.0.lock().unwrap();
+            *fail = !*fail;
+            if *fail { Err("connection reset".into()) } else { Ok(RECENT.to_string()) }
+        }
+    }
+
+    let clock = Arc::new(ManualClock::new());
+    let feeds = vec![(Feed::Recent, Duration::from_secs(5)), (Feed::Channel("EBDF".to_string()), Duration::from_secs(5))];
+    let mut ticker = Ticker::new(Poller::new(feeds, clock.now()))
+        .transport(Arc::new(Flaky(Mutex::new(false))))
+        .clock(clock.clone());
+
+    match ticker.tick() {
+        Err(VLiveError::Transport(_)) => (),
+        other => panic!("expected a transport error, got {:?}", other.map(|events| events.len())),
+    }
+    assert_eq!(ticker.tick().unwrap().len(), 3);
+    assert!(ticker.tick().unwrap().is_empty());
+
+    clock.advance(Duration::from_secs(5));
+    assert!(ticker.tick().is_err());
+    assert!(ticker.tick().unwrap().is_empty());
+}
+
+#[test]
+fn routed_transport_picks_the_session_by_channel() {
+    use std::sync::Arc;
+    use super::vlive::Feed;
+    use super::vlive::transport::{Routed, Transport, TransportError};
+
+    //Answers with its own name
+    struct Named(&'static str);
+
+    impl Transport for Named {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    let fanship = Arc::new(Named("fanship"));
+    let transport = Routed::new(Named("default"))
+        .route("FE619", fanship.clone())
+        .route("EBDF", fanship)
+        .route("F001E5", Arc::new(Named("plus")));
+    let get = |feed: Feed| transport.get(&feed.url()).unwrap();
+    assert_eq!(get(Feed::Channel("FE619".to_string())), "fanship");
+    assert_eq!(get(Feed::Channel("EBDF".to_string())), "fanship");
+    assert_eq!(get(Feed::Channel("F001E5".to_string())), "plus");
+    assert_eq!(get(Feed::Channel("C1B7AF".to_string())), "default");
+    assert_eq!(get(Feed::Recent), "default");
+}
+
+#[test]
+fn tap_sees_responses_before_parsing() {
+    use std::sync::{Arc, Mutex};
+    use super::vlive::transport::{Tap, Transport, TransportError};
+
+    //Fails for anything but the front page
+    struct Front;
+
+    impl Transport for Front {
+        fn get(&self, url: &str) -> Result<String, TransportError> {
+            if url == "/" { Ok("<html></html>".to_string()) } else { Err("not found".into()) }
+        }
+    }
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+    let transport = Tap::new(Front, move |url: &str, body: &str| log.lock().unwrap().push(format!("{} {}", url, body)));
+    assert!(transport.get("/").is_ok());
+    assert!(transport.get("/missing").is_err());
+    assert_eq!(*seen.lock().unwrap(), vec!["/ <html></html>"]);
+}
+
+#[test]
+fn cached_transport_fetches_once_per_max_age() {
+    use std::fs;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use super::vlive::transport::{Cached, Transport, TransportError};
+
+    //Counts how many times it was asked
+    #[derive(Default)]
+    struct Counting(AtomicUsize);
+
+    impl Transport for Counting {
+        fn get(&self, url: &str) -> Result<String, TransportError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(url.to_string())
+        }
+    }
+
+    let dir = std::env::temp_dir().join(format!("vlive-cache-{}", std::process::id()));
+    let counting = Arc::new(Counting::default());
+    let cached = Cached::new(counting.clone(), Duration::from_secs(60 * 60)).on_disk(&dir);
+    assert_eq!(cached.get("/channels/FE619").unwrap(), "/channels/FE619");
+    assert_eq!(cached.get("/channels/FE619").unwrap(), "/channels/FE619");
+    assert_eq!(cached.get("/channels/EBDF").unwrap(), "/channels/EBDF");
+    assert_eq!(counting.0.load(Ordering::SeqCst), 2);
+
+    //A fresh cache picks up what the last one left on disk, under a name
+    //that doesn't change between Rust versions
+    let restarted = Cached::new(counting.clone(), Duration::from_secs(60 * 60)).on_disk(&dir);
+    assert_eq!(restarted.get("/channels/FE619").unwrap(), "/channels/FE619");
+    assert_eq!(counting.0.load(Ordering::SeqCst), 2);
+    assert_eq!(fs::read_to_string(dir.join("a58a47fa1b36c996")).unwrap(), "/channels/FE619\n/channels/FE619");
+
+    //Somebody else's response under the same name isn't served
+    fs::write(dir.join("850653cb7c447d53"), "/channels/EBDF\nIU").unwrap();
+    assert_eq!(restarted.get("/channels/C1B7AF").unwrap(), "/channels/C1B7AF");
+    assert_eq!(counting.0.load(Ordering::SeqCst), 3);
+    let _ = fs::remove_dir_all(&dir);
+
+    let stale = Cached::new(counting.clone(), Duration::from_secs(0));
+    stale.get("/channels/FE619").unwrap();
+    stale.get("/channels/FE619").unwrap();
+    assert_eq!(counting.0.load(Ordering::SeqCst), 5);
+}
+
+#[test]
+fn thumbnail_cache_downloads_once_and_evicts() {
+    use std::fs;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use super::vlive::ThumbnailCache;
+
+    let dir = std::env::temp_dir().join(format!("vlive-thumbs-{}", std::process::id()));
+    let downloads = Arc::new(AtomicUsize::new(0));
+    let counter = downloads.clone();
+    let thumbnails = ThumbnailCache::new(&dir, 10).fetch(move |url| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Ok(url.as_bytes()[..6].to_vec())
+    });
+    let video = |id| {
+        let mut video = event(id).video;
+        video.video_thumbnail = Some(format!("https://v-phinf.pstatic.net/{}.jpg", id));
+        video
+    };
+
+    assert_eq!(thumbnails.get(&video(1)).unwrap(), Some(b"https:".to_vec()));
+    assert_eq!(thumbnails.get(&video(1)).unwrap(), Some(b"https:".to_vec()));
+    assert_eq!(downloads.load(Ordering::SeqCst), 1);
+    assert_eq!(thumbnails.get(&event(2).video).unwrap(), None);
+
+    //Two don't fit, so the one used longest ago goes
+    thumbnails.get(&video(3)).unwrap();
+    assert!(!thumbnails.path(&video(1)).exists());
+    assert!(thumbnails.path(&video(3)).exists());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn statsd_sends_counters_and_timings() {
+    use std::net::UdpSocket;
+    use std::time::Duration;
+    use super::vlive::{Feed, Metrics, VLiveError, VLiveEvent};
+    use super::vlive::metrics::Statsd;
+
+    let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    agent.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let received = || {
+        let mut buffer = [0; 512];
+        let length = agent.recv(&mut buffer).unwrap();
+        String::from_utf8_lossy(&buffer[..length]).into_owned()
+    };
+
+    let statsd = Statsd::new(agent.local_addr().unwrap()).unwrap();
+    statsd.error(0, &VLiveError::Panic("oops".to_string()));
+    assert_eq!(received(), "vlive.errors.panic:1|c");
+
+    let dogstatsd = Statsd::new(agent.local_addr().unwrap()).unwrap().prefix("bot.").dogstatsd();
+    dogstatsd.poll(1, &Feed::Channel("FE619".to_string()), Duration::from_millis(250));
+    assert_eq!(received(), "bot.polls:1|c|#feed:FE619");
+    assert_eq!(received(), "bot.poll.latency:250|ms|#feed:FE619");
+    dogstatsd.event(1, &VLiveEvent::New(event(1)));
+    assert_eq!(received(), "bot.events:1|c|#kind:new");
+}
+
+#[cfg(unix)]
+#[test]
+fn systemd_hears_readiness_and_keep_alives() {
+    use std::os::unix::net::UnixDatagram;
+    use std::time::Duration;
+    use super::vlive::systemd::Notify;
+
+    let path = std::env::temp_dir().join(format!("vlive-notify-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let systemd = UnixDatagram::bind(&path).unwrap();
+    systemd.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let received = || {
+        let mut buffer = [0; 64];
+        let length = systemd.recv(&mut buffer).unwrap();
+        String::from_utf8_lossy(&buffer[..length]).into_owned()
+    };
+
+    let notify = Notify::new(&path, Some(Duration::from_secs(30))).unwrap();
+    assert_eq!(notify.watchdog_every(), Some(Duration::from_secs(30)));
+    notify.ready().unwrap();
+    assert_eq!(received(), "READY=1");
+    notify.watchdog().unwrap();
+    assert_eq!(received(), "WATCHDOG=1");
+    notify.status("Polling 3 feeds").unwrap();
+    assert_eq!(received(), "STATUS=Polling 3 feeds");
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn json_log_writes_one_object_per_line() {
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use super::vlive::{Feed, Metrics, VLiveError, VLiveEvent};
+    use super::vlive::metrics::JsonLog;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(bytes)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let out = Shared::default();
+    let log = JsonLog::to(out.clone());
+    log.poll(3, &Feed::Channel("FE619".to_string()), Duration::from_millis(250));
+    log.error(3, &VLiveError::Panic("oops".to_string()));
+    log.event(3, &VLiveEvent::New(event(7)));
+
+    let written = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<serde_json::Value> = written.lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines.iter().all(|line| line["ts"].is_string()));
+    assert!(lines.iter().all(|line| line["poll_id"] == 3));
+
+    assert_eq!(lines[0]["msg"], "poll");
+    assert_eq!(lines[0]["latency_ms"], 250);
+    assert_eq!(lines[0]["url"], "http://channels.vlive.tv/FE619/video");
+    assert_eq!(lines[1]["level"], "error");
+    assert_eq!(lines[1]["kind"], "panic");
+    assert_eq!(lines[2]["msg"], "event");
+    assert_eq!(lines[2]["kind"], "new");
+    assert_eq!(lines[2]["seq"], 7);
+}
+
+#[test]
+fn enrichment_keeps_events_in_order() {
+    use super::vlive::{Enrich, VLiveError, VLiveEvent, VLiveVideo};
+    use super::vlive::enrich::{enrich_events, Blocking};
+
+    struct Shout;
+
+    impl Enrich for Shout {
+        async fn enrich(&self, video: &mut VLiveVideo) {
+            video.video_title = format!("{}!", video.video_seq);
+        }
+    }
+
+    let enricher = Blocking {
+        enrich: Shout,
+        #[cfg(feature = "async-tokio")]
+        runtime: None,
+    };
+    let mut events = vec![
+        Ok(VLiveEvent::New(event(1))),
+        Err(VLiveError::Panic("in between".to_string())),
+        Ok(VLiveEvent::New(event(2))),
+    ];
+    enrich_events(&mut events, &enricher);
+    let titles: Vec<_> = events.iter()
+        .map(|event| match event {
+            Ok(VLiveEvent::New(event)) => event.video.video_title.clone(),
+            _ => "error".to_string(),
+        })
+        .collect();
+    assert_eq!(titles, vec!["1!", "error", "2!"]);
+}
+
+#[cfg(feature = "async-tokio")]
+#[test]
+fn sinks_send_events_in_order() {
+    use std::future::Future;
+    use std::sync::mpsc::{channel, Sender};
+    use std::thread;
+    use std::time::Duration;
+    use super::vlive::{Ctx, DeliveryError, Event, Flow, Sink, TokioSink, VLiveCallback};
+
+    struct Collect(Sender<u64>);
+
+    impl Sink for Collect {
+        fn send(&mut self, event: Event) -> impl Future<Output = Result<(), DeliveryError>> + Send {
+            let sent = self.0.send(event.video.video_seq).map_err(DeliveryError::from);
+            async move { sent }
+        }
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+    let (tx, rx) = channel();
+    //Room for one event, so the rest have to wait for the sink
+    let mut sink = TokioSink::new(Collect(tx), 1, runtime.handle().clone());
+    thread::spawn(move || runtime.block_on(std::future::pending::<()>()));
+    for seq in 1..=3 {
+        assert_eq!(sink.deliver(&Ctx::detached(), &event(seq)).unwrap(), Flow::Continue);
+    }
+    let sent: Vec<_> = (0..3).map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap()).collect();
+    assert_eq!(sent, vec![1, 2, 3]);
+}
+
+#[test]
+fn bare_videos_are_delivered_with_their_own_ids() {
+    use super::vlive::{testing, Ctx, DeliveryError, Event, Flow, VLiveCallback, VLiveVideo};
+    use super::vlive::sink::deliver_new;
+
+    struct Ids(Vec<u64>);
+
+    impl VLiveCallback for Ids {
+        fn on_new(&mut self, ctx: &Ctx, video: VLiveVideo) -> Flow {
+            deliver_new(self, ctx, video)
+        }
+
+        fn deliver(&mut self, _: &Ctx, event: &Event) -> Result<Flow, DeliveryError> {
+            self.0.push(event.id);
+            if event.video.video_seq == 2 {
+                return Err("down".into());
+            }
+            Ok(Flow::Continue)
+        }
+    }
+
+    let mut ids = Ids(Vec::new());
+    assert_eq!(ids.on_new(&Ctx::detached(), testing::video().seq(1).build()), Flow::Continue);
+    //Nothing to retry it with, so a failure carries on
+    assert_eq!(ids.on_new(&Ctx::detached(), testing::video().seq(2).build()), Flow::Continue);
+    assert_ne!(ids.0[0], ids.0[1]);
+    assert!(ids.0.iter().all(|&id| id > u64::MAX / 2));
+}
+
+#[test]
+fn dedup_forgets_after_ttl_and_capacity() {
+    use std::time::{Duration, Instant};
+    use super::vlive::Dedup;
+
+    let start = Instant::now();
+    let mut dedup = Dedup::new(2, Duration::from_secs(60));
+    assert!(!dedup.check("/video/1", start));
+    assert!(dedup.check("/video/1", start + Duration::from_secs(30)));
+    assert!(!dedup.check("/video/1", start + Duration::from_secs(61)));
+
+    assert!(!dedup.check("/video/2", start + Duration::from_secs(62)));
+    assert!(!dedup.check("/video/3", start + Duration::from_secs(63)));
+    assert!(!dedup.check("/video/1", start + Duration::from_secs(64)));
+}
+
+#[test]
+fn dedup_survives_a_restore() {
+    use std::time::{Duration, Instant};
+    use super::vlive::Dedup;
+
+    let now = Instant::now();
+    let mut dedup = Dedup::new(2, Duration::from_secs(60));
+    dedup.check("/video/1", now);
+    dedup.check("/video/2", now);
+
+    let mut restored = Dedup::new(2, Duration::from_secs(60));
+    restored.restore(&dedup.entries());
+    assert!(restored.check("/video/1", now));
+    assert!(restored.check("/video/2", now));
+    assert!(!restored.check("/video/3", now));
+
+    //A day later everything has expired
+    let mut stale = Dedup::new(2, Duration::from_secs(60));
+    let entries: Vec<_> = dedup.entries().into_iter()
+        .map(|(code, at)| (code, at - Duration::from_secs(24 * 60 * 60)))
+        .collect();
+    stale.restore(&entries);
+    assert!(stale.entries().is_empty());
+}
+
+#[test]
+fn dedup_reclassifies_lives_turning_into_vods() {
+    use std::time::{Duration, Instant};
+    use super::vlive::{Dedup, Sighting, VideoType};
+
+    let now = Instant::now();
+    let mut dedup = Dedup::new(8, Duration::from_secs(60));
+    assert_eq!(dedup.sight("/video/1", VideoType::LIVE, now), Sighting::New);
+    assert_eq!(dedup.sight("/video/1", VideoType::LIVE, now), Sighting::Again);
+    assert_eq!(dedup.sight("/video/1", VideoType::VOD, now), Sighting::Vod);
+    assert_eq!(dedup.sight("/video/1", VideoType::VOD, now), Sighting::Again);
+
+    //Uploaded as a VOD, so there was never a live to end
+    assert_eq!(dedup.sight("/video/2", VideoType::VOD, now), Sighting::New);
+    assert_eq!(dedup.sight("/video/2", VideoType::VOD, now), Sighting::Again);
+
+    assert_eq!(dedup.sight("/video/3", VideoType::LIVE, now), Sighting::New);
+    assert_eq!(dedup.lives(), vec!["/video/3"]);
+    let mut restored = Dedup::new(8, Duration::from_secs(60));
+    restored.restore(&dedup.entries());
+    restored.restore_lives(&dedup.lives());
+    assert_eq!(restored.sight("/video/3", VideoType::VOD, now), Sighting::Vod);
+}
+
+#[test]
+fn lifecycle_follows_a_live_until_its_vod() {
+    use std::borrow::Cow;
+    use std::time::{Duration, SystemTime};
+    use super::vlive::{ChannelType, Lifecycle, VLiveEvent, VideoStage, VideoType};
+    use super::vlive::parser::VideoRef;
+
+    let video = |video_seq, video_stage, video_title| VideoRef {
+        video_id: Cow::Owned(format!("/video/{}", video_seq)),
+        video_seq,
+        video_title: Cow::Borrowed(video_title),
+        video_type: if video_stage == VideoStage::VOD { VideoType::VOD } else { VideoType::LIVE },
+        video_stage,
+        video_paid: false,
+        video_thumbnail: None,
+        channel_id: Cow::Borrowed("/channels/FE619"),
+        channel_seq: 363,
+        channel_name: Cow::Borrowed(""),
+        channel_type: ChannelType::BASIC,
+        platform: Default::default(),
+    };
+    let minute = |minutes: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(minutes * 60);
+
+    let mut lifecycle = Lifecycle::new(Duration::from_secs(60 * 60));
+    lifecycle.start(&video(50000, VideoStage::UPCOMING, "Comeback"), minute(0));
+    assert!(lifecycle.see(video(50000, VideoStage::UPCOMING, "Comeback"), minute(1)).is_empty());
+    match &lifecycle.see(video(50000, VideoStage::LIVE, "Comeback"), minute(2))[..] {
+        [VLiveEvent::Transition(live)] => {
+            assert_eq!((live.from, live.to, live.since, live.at), (VideoStage::UPCOMING, VideoStage::LIVE, minute(0), minute(2)));
+        },
+        other => panic!("expected the stream to go live, got {:?}", other),
+    }
+    match &lifecycle.see(video(50000, VideoStage::VOD, "Comeback"), minute(30))[..] {
+        [VLiveEvent::Transition(vod), VLiveEvent::LiveEnded { live, vod: Some(recording) }] => {
+            assert_eq!((vod.from, vod.to, vod.since), (VideoStage::LIVE, VideoStage::VOD, minute(2)));
+            assert_eq!((live.video_stage, recording.video_seq), (VideoStage::LIVE, 50000));
+        },
+        other => panic!("expected the stream to end, got {:?}", other),
+    }
+    assert!(!lifecycle.follows(50000));
+
+    //The recording of this one comes under a new seq
+    lifecycle.start(&video(50001, VideoStage::LIVE, "Birthday"), minute(31));
+    assert!(lifecycle.archive(&video(50002, VideoStage::VOD, "Dance practice")).is_none());
+    match lifecycle.archive(&video(50003, VideoStage::VOD, "[Replay] Birthday")) {
+        Some(VLiveEvent::LiveEnded { live, vod: Some(vod) }) => assert_eq!((live.video_seq, vod.video_seq), (50001, 50003)),
+        other => panic!("expected the replay to end the stream, got {:?}", other),
+    }
+
+    //Streams that vanish are given up on
+    lifecycle.start(&video(50004, VideoStage::LIVE, "Vanished"), minute(31));
+    assert!(lifecycle.expire(minute(60)).is_empty());
+    match &lifecycle.expire(minute(200))[..] {
+        [VLiveEvent::LiveEnded { live, vod: None }] => assert_eq!(live.video_seq, 50004),
+        other => panic!("expected the stream to be given up on, got {:?}", other),
+    }
+}
+
+#[test]
+fn quiet_hours_go_past_midnight() {
+    use std::time::{Duration, SystemTime};
+    use chrono::{DateTime, Local, NaiveTime, Timelike};
+    use super::vlive::QuietHours;
+
+    const DAY: u32 = 24 * 60 * 60;
+    let now = SystemTime::now();
+    let local = DateTime::<Local>::from(now).time().num_seconds_from_midnight();
+    let at = |offset: u32| NaiveTime::from_num_seconds_from_midnight_opt((local + offset) % DAY, 0).unwrap();
+
+    //From an hour ago until in an hour, wherever midnight is
+    let quiet = QuietHours::new(at(DAY - 60 * 60), at(60 * 60));
+    assert_eq!(quiet.remaining(now), Some(Duration::from_secs(60 * 60)));
+    assert_eq!(quiet.remaining(now + Duration::from_secs(2 * 60 * 60)), None);
+
+    let quiet = QuietHours::new(at(60 * 60), at(2 * 60 * 60));
+    assert_eq!(quiet.remaining(now), None);
+    assert!(QuietHours::new(at(0), at(0)).remaining(now).is_none());
+}
+
+#[test]
+fn videos_held_by_quiet_hours_are_never_lost() {
+    use std::sync::Arc;
+    use std::sync::mpsc::{channel, Sender};
+    use std::time::{Duration, SystemTime};
+    use chrono::{DateTime, Local, NaiveTime, Timelike};
+    use super::vlive::{Ctx, DeadLetter, Flow, QuietHours, VLive, VLiveCallback, VLiveVideo};
+    use super::vlive::clock::ManualClock;
+    use super::vlive::transport::{Transport, TransportError};
+
+    struct Recent;
+
+    impl Transport for Recent {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            Ok(RECENT.to_string())
+        }
+    }
+
+    //Had enough after the first video
+    struct Sleepy(Sender<&'static str>);
+
+    impl VLiveCallback for Sleepy {
+        fn on_new(&mut self, _: &Ctx, _: VLiveVideo) -> Flow {
+            let _ = self.0.send("new");
+            Flow::Stop
+        }
+
+        fn on_dead_letter(&mut self, _: DeadLetter) -> Flow {
+            let _ = self.0.send("dead letter");
+            Flow::Continue
+        }
+
+        fn on_stopped(&mut self) {
+            let _ = self.0.send("stopped");
+        }
+    }
+
+    const DAY: u32 = 24 * 60 * 60;
+    let clock = Arc::new(ManualClock::new());
+    let local = DateTime::<Local>::from(SystemTime::now()).time().num_seconds_from_midnight();
+    let at = |offset: u32| NaiveTime::from_num_seconds_from_midnight_opt((local + offset) % DAY, 0).unwrap();
+
+    //All three videos wait out the hour, the first one stops the listener
+    //and the other two aren't dropped on the floor
+    let (tx, rx) = channel();
+    let _stopper = VLive::new(Sleepy(tx), Duration::from_secs(5))
+        .transport(Arc::new(Recent))
+        .clock(clock)
+        .quiet_hours(QuietHours::new(at(DAY - 60), at(60 * 60)))
+        .run_async();
+    let calls: Vec<_> = (0..4).filter_map(|_| rx.recv_timeout(Duration::from_secs(10)).ok()).collect();
+    assert_eq!(calls, vec!["new", "dead letter", "dead letter", "stopped"]);
+}
+
+#[test]
+fn schedule_follows_the_korean_evening() {
+    use std::time::{Duration, SystemTime};
+    use chrono::{FixedOffset, NaiveTime};
+    use super::vlive::Schedule;
+
+    let hour = |hours: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(hours * 60 * 60);
+    let schedule = Schedule::every(Duration::from_secs(5 * 60))
+        .between(NaiveTime::from_hms_opt(17, 0, 0).unwrap(), NaiveTime::from_hms_opt(23, 0, 0).unwrap(), Duration::from_secs(10))
+        .offset(FixedOffset::east_opt(9 * 60 * 60).unwrap());
+
+    //The epoch is 09:00 in Korea
+    assert_eq!(schedule.wait(hour(0)), Duration::from_secs(5 * 60));
+    assert_eq!(schedule.wait(hour(8)), Duration::from_secs(10));
+    assert_eq!(schedule.wait(hour(14)), Duration::from_secs(5 * 60));
+    assert_eq!(schedule.wait(hour(8) - Duration::from_secs(60)), Duration::from_secs(60));
+}
+
+#[test]
+fn schedule_polls_when_its_cron_expressions_match() {
+    use std::time::{Duration, SystemTime};
+    use chrono::FixedOffset;
+    use super::vlive::{CronError, Schedule};
+
+    let hour = |hours: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(hours * 60 * 60);
+    let schedule = Schedule::every(Duration::from_secs(5 * 60))
+        .cron("*/10 * 17-22 * * *").unwrap()
+        .offset(FixedOffset::east_opt(9 * 60 * 60).unwrap());
+
+    //The epoch is 09:00 in Korea
+    assert_eq!(schedule.wait(hour(0)), Duration::from_secs(5 * 60));
+    assert_eq!(schedule.wait(hour(8)), Duration::from_secs(10));
+    assert_eq!(schedule.wait(hour(8) + Duration::from_secs(3)), Duration::from_secs(7));
+    assert_eq!(schedule.wait(hour(14)), Duration::from_secs(5 * 60));
+    assert_eq!(schedule.wait(hour(8) - Duration::from_secs(60)), Duration::from_secs(60));
+
+    //The epoch was a Thursday, and Sunday is 7 too
+    let week = Duration::from_secs(7 * 24 * 60 * 60);
+    let utc = FixedOffset::east_opt(0).unwrap();
+    let mondays = Schedule::every(week).cron("30 4 * * 1").unwrap().offset(utc);
+    assert_eq!(mondays.wait(hour(0)), Duration::from_secs((4 * 24 + 4) * 60 * 60 + 30 * 60));
+    let sundays = Schedule::every(week).cron("0 0 * * 7").unwrap().offset(utc);
+    assert_eq!(sundays.wait(hour(0)), Duration::from_secs(3 * 24 * 60 * 60));
+
+    //Either the 2nd of the month or a Sunday
+    let either = Schedule::every(week).cron("0 0 2 * 0").unwrap().offset(utc);
+    assert_eq!(either.wait(hour(0)), Duration::from_secs(24 * 60 * 60));
+    assert_eq!(either.wait(hour(24)), Duration::from_secs(2 * 24 * 60 * 60));
+
+    assert_eq!(Schedule::every(week).cron("* * *"), Err(CronError::Fields(3)));
+    assert_eq!(Schedule::every(week).cron("*/0 * * * *"), Err(CronError::Invalid { field: "minute", value: "*/0".to_string() }));
+    assert_eq!(Schedule::every(week).cron("0 24 * * *"), Err(CronError::Invalid { field: "hour", value: "24".to_string() }));
+    assert_eq!(Schedule::every(week).cron("0 9-5 * * *"), Err(CronError::Invalid { field: "hour", value: "9-5".to_string() }));
+}
+
+#[test]
+fn parse_failure_snippet_is_truncated_on_a_char_boundary() {
+    use super::vlive::{ParseError, ParseFailure};
+
+    let html = "<div>".to_string() + &"한".repeat(1000) + "</div>";
+    let failure = ParseFailure::new(ParseError::MissingElement { selector: ".thumb_area" }, &html);
+    assert!(failure.snippet.len() <= 1024 + '…'.len_utf8());
+    assert!(failure.snippet.starts_with("<div>한"));
+    assert!(failure.snippet.ends_with('…'));
+}
+
+#[test]
+fn parse_failures_name_the_first_strategy() {
+    use super::vlive::{Parser, ParseError};
+    use super::vlive::parser::{ClassSelectors, DataAttributes};
+
+    //Neither finds a channel link
+    let html = r#"<div class="video_list_cont">
+        <a class="thumb_area" href="/video/50000" data-seq="50000"></a>
+    </div>"#;
+    let parser = Parser::new().strategy(ClassSelectors).strategy(DataAttributes);
+    let videos = parser.parse_page(html).unwrap();
+    let failure = videos[0].as_ref().unwrap_err();
+    assert_eq!(failure.strategy, Some("class selectors"));
+    assert!(failure.to_string().contains("with class selectors"));
+
+    let videos = Parser::new().parse_page(html).unwrap();
+    let failure = videos[0].as_ref().unwrap_err();
+    assert_eq!(failure.error, ParseError::MissingElement { selector: ".video_list_cont" });
+    assert_eq!(failure.strategy, None);
+}
+
+/// An event for a made up VOD with seq `id`
+fn event(id: u64) -> super::vlive::Event {
+    use std::time::SystemTime;
+    use super::vlive::{Event, VLiveVideo, VideoType, ChannelType};
+
+    Event {
+        id,
+        poll_id: 0,
+        detected_at: SystemTime::UNIX_EPOCH,
+        video: VLiveVideo {
+            video_id: format!("/video/{}", id),
+            video_seq: id,
+            video_title: String::new(),
+            video_titles: Default::default(),
+            video_type: VideoType::VOD,
+            video_stage: Default::default(),
+            video_paid: false,
+            video_thumbnail: None,
+            channel_id: String::new(),
+            channel_seq: 0,
+            channel_name: String::new(),
+            channel_type: ChannelType::BASIC,
+            platform: Default::default(),
+            video_mirrors: Vec::new(),
+        },
+    }
+}
+
+#[test]
+fn journal_replays_events_after_an_id() {
+    use super::vlive::{journal, Journal};
+
+    let path = std::env::temp_dir().join(format!("vlive-journal-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mut writer = Journal::open(&path).unwrap();
+    for id in 1..=3 {
+        writer.append(&event(id)).unwrap();
+    }
+    drop(writer);
+
+    assert_eq!(Journal::open(&path).unwrap().last_id(), 3);
+    assert_eq!(journal::replay(&path, 1).unwrap(), vec![event(2), event(3)]);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn journals_skip_only_a_half_written_last_line() {
+    use std::io::{ErrorKind, Write};
+    use super::vlive::{journal, Journal};
+
+    let path = std::env::temp_dir().join(format!("vlive-journal-torn-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mut writer = Journal::open(&path).unwrap();
+    writer.append(&event(1)).unwrap();
+    drop(writer);
+    let line = serde_json::to_string(&event(2)).unwrap();
+    std::fs::OpenOptions::new().append(true).open(&path).unwrap()
+        .write_all(&line.as_bytes()[..line.len() / 2]).unwrap();
+    assert_eq!(journal::replay(&path, 0).unwrap(), vec![event(1)]);
+
+    //Reopening drops the torn line instead of appending onto it
+    let mut writer = Journal::open(&path).unwrap();
+    assert_eq!(writer.last_id(), 1);
+    writer.append(&event(3)).unwrap();
+    assert_eq!(writer.replay(0).unwrap(), vec![event(1), event(3)]);
+    drop(writer);
+
+    let mut written = std::fs::read_to_string(&path).unwrap();
+    written.insert_str(0, "not an event\n");
+    std::fs::write(&path, written).unwrap();
+    assert_eq!(journal::replay(&path, 0).unwrap_err().kind(), ErrorKind::InvalidData);
+    assert_eq!(Journal::open(&path).err().map(|why| why.kind()), Some(ErrorKind::InvalidData));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn failed_deliveries_are_retried_then_dead_lettered() {
+    use std::time::{Duration, Instant};
+    use super::vlive::{Ctx, DeadLetter, DeliveryError, Event, Flow, VLiveCallback, VLiveVideo};
+    use super::vlive::delivery::Outbox;
+
+    #[derive(Default)]
+    struct Flaky {
+        up: bool,
+        attempts: u32,
+        dead: Vec<DeadLetter>,
+    }
+
+    impl VLiveCallback for Flaky {
+        fn on_new(&mut self, _: &Ctx, _: VLiveVideo) -> Flow {
+            Flow::Continue
+        }
+
+        fn deliver(&mut self, _: &Ctx, _: &Event) -> Result<Flow, DeliveryError> {
+            self.attempts += 1;
+            if self.up { Ok(Flow::Continue) } else { Err("webhook is down".into()) }
+        }
+
+        fn on_dead_letter(&mut self, letter: DeadLetter) -> Flow {
+            self.dead.push(letter);
+            Flow::Continue
+        }
+    }
+
+    let ctx = Ctx::detached();
+    let mut flaky = Flaky::default();
+    let mut outbox = Outbox::new(3, Duration::from_secs(1));
+    let start = Instant::now();
+    outbox.send(&mut flaky, &ctx, event(1), 0);
+    assert_eq!(flaky.attempts, 1);
+    assert!(outbox.next_due().unwrap() >= start + Duration::from_secs(1));
+
+    outbox.retry(&mut flaky, &ctx, start);
+    assert_eq!(flaky.attempts, 1);
+    outbox.retry(&mut flaky, &ctx, start + Duration::from_secs(10));
+    outbox.retry(&mut flaky, &ctx, start + Duration::from_secs(20));
+    assert_eq!(flaky.attempts, 3);
+    assert_eq!(flaky.dead.len(), 1);
+    assert_eq!(flaky.dead[0].attempts, 3);
+    assert!(outbox.next_due().is_none());
+
+    //Asking for a retry gives dead letters a fresh set of attempts
+    let dead_letters = outbox.dead_letters();
+    assert_eq!(dead_letters.lock().unwrap().letters.len(), 1);
+    dead_letters.lock().unwrap().retry = true;
+    outbox.retry(&mut flaky, &ctx, start);
+    assert_eq!(flaky.attempts, 4);
+    assert!(dead_letters.lock().unwrap().letters.is_empty());
+    assert!(outbox.next_due().is_some());
+
+    //Once the webhook is back, the retried letter goes through for good
+    outbox.retry(&mut flaky, &ctx, start + Duration::from_secs(30));
+    outbox.retry(&mut flaky, &ctx, start + Duration::from_secs(60));
+    assert_eq!(flaky.attempts, 6);
+    assert_eq!(flaky.dead.len(), 2);
+    dead_letters.lock().unwrap().retry = true;
+    flaky.up = true;
+    outbox.retry(&mut flaky, &ctx, start + Duration::from_secs(90));
+    assert_eq!(flaky.attempts, 7);
+    assert_eq!(flaky.dead.len(), 2);
+    assert!(dead_letters.lock().unwrap().letters.is_empty());
+    assert!(outbox.next_due().is_none());
+}
+
+#[test]
+fn stoppers_retry_dead_letters() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::{channel, Sender};
+    use std::time::Duration;
+    use super::vlive::{Ctx, DeadLetter, DeliveryError, Event, Flow, VLive, VLiveCallback, VLiveVideo};
+    use super::vlive::clock::ManualClock;
+    use super::vlive::transport::{Transport, TransportError};
+
+    struct Recent;
+
+    impl Transport for Recent {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            Ok(RECENT.to_string())
+        }
+    }
+
+    //Fails until the webhook comes back up
+    struct Webhook(Arc<AtomicBool>, Sender<String>);
+
+    impl VLiveCallback for Webhook {
+        fn on_new(&mut self, _: &Ctx, _: VLiveVideo) -> Flow {
+            Flow::Continue
+        }
+
+        fn deliver(&mut self, _: &Ctx, event: &Event) -> Result<Flow, DeliveryError> {
+            if !self.0.load(Ordering::SeqCst) {
+                return Err("webhook is down".into());
+            }
+            let _ = self.1.send(format!("delivered {}", event.video.video_seq));
+            Ok(Flow::Continue)
+        }
+
+        fn on_dead_letter(&mut self, letter: DeadLetter) -> Flow {
+            let _ = self.1.send(format!("dead {}", letter.event.video.video_seq));
+            Flow::Continue
+        }
+    }
+
+    let up = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = channel();
+    let stopper = VLive::new(Webhook(up.clone(), tx), Duration::from_secs(5))
+        .transport(Arc::new(Recent))
+        .clock(Arc::new(ManualClock::new()))
+        .retry(1, Duration::from_secs(1))
+        .run_async();
+    let calls = |count| (0..count).filter_map(|_| rx.recv_timeout(Duration::from_secs(10)).ok()).collect::<Vec<_>>();
+    assert_eq!(calls(3), vec!["dead 50002", "dead 50001", "dead 50000"]);
+    assert_eq!(stopper.dead_letters().len(), 3);
+
+    up.store(true, Ordering::SeqCst);
+    stopper.retry_dead_letters();
+    assert_eq!(calls(3), vec!["delivered 50002", "delivered 50001", "delivered 50000"]);
+    assert!(stopper.dead_letters().is_empty());
+    stopper.stop();
+}
+
+mod properties {
+    use proptest::prelude::*;
+    use super::super::vlive::Parser;
+    use super::RECENT;
+
+    proptest! {
+        #[test]
+        fn arbitrary_html_never_panics(html in "\\PC*") {
+            let _ = Parser::default().parse_page(&html);
+        }
+
+        #[test]
+        fn truncated_page_never_panics(end in 0..RECENT.len()) {
+            if let Some(html) = RECENT.get(..end) {
+                let _ = Parser::default().parse_page(html);
+            }
+        }
+
+        #[test]
+        fn seq_is_parsed_or_rejected(seq in "[0-9a-z-]{0,12}") {
+            let html = RECENT.replace("data-seq=\"50002\"", &format!("data-seq=\"{}\"", seq));
+            let videos = Parser::default().parse_page(&html).unwrap();
+            match seq.parse::<u64>() {
+                Ok(expected) if expected != 0 => prop_assert_eq!(videos[0].as_ref().unwrap().video_seq, expected),
+                _ => prop_assert!(videos[0].is_err()),
+            }
+        }
+    }
+
+    #[cfg(feature = "test-util")]
+    proptest! {
+        #[test]
+        fn arbitrary_videos_survive_json(video in any::<super::super::vlive::VLiveVideo>()) {
+            let json = serde_json::to_string(&video).unwrap();
+            prop_assert_eq!(serde_json::from_str::<super::super::vlive::VLiveVideo>(&json).unwrap(), video);
+        }
+    }
+}
+
+#[test]
+fn concurrent_fetches_overlap_but_are_handled_in_order() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::{channel, Sender};
+    use std::thread;
+    use std::time::Duration;
+    use super::vlive::{Ctx, Flow, VLive, VLiveCallback, VLiveVideo};
+    use super::vlive::transport::{Transport, TransportError};
+
+    //The first channel answers last, and counts how many fetches overlap
+    #[derive(Default)]
+    struct Slow {
+        fetching: AtomicUsize,
+        most: AtomicUsize,
+    }
+
+    impl Transport for Slow {
+        fn get(&self, url: &str) -> Result<String, TransportError> {
+            let fetching = self.fetching.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most.fetch_max(fetching, Ordering::SeqCst);
+            let channel = if url.contains("UCfirst") { "UCfirst" } else { "UCsecond" };
+            thread::sleep(Duration::from_millis(if channel == "UCfirst" { 300 } else { 100 }));
+            self.fetching.fetch_sub(1, Ordering::SeqCst);
+            Ok(format!("<feed><entry><yt:videoId>{0}</yt:videoId><yt:channelId>{0}</yt:channelId>
+                <title>{0}</title><author><name>{0}</name></author>
+                <published>2021-06-01T09:00:00+00:00</published></entry></feed>", channel))
+        }
+    }
+
+    struct Titles(Sender<String>);
+
+    impl VLiveCallback for Titles {
+        fn on_new(&mut self, _: &Ctx, video: VLiveVideo) -> Flow {
+            let _ = self.0.send(video.video_title);
+            Flow::Continue
+        }
+    }
+
+    let transport = Arc::new(Slow::default());
+    let (tx, rx) = channel();
+    let stopper = VLive::new(Titles(tx), Duration::from_secs(60))
+        .transport(transport.clone())
+        .youtube("UCfirst", Duration::from_secs(60))
+        .youtube("UCsecond", Duration::from_secs(60))
+        .concurrency(2)
+        .run_async();
+    let titles: Vec<_> = (0..2).filter_map(|_| rx.recv_timeout(Duration::from_secs(10)).ok()).collect();
+    stopper.stop();
+    assert_eq!(titles, vec!["UCfirst", "UCsecond"]);
+    assert_eq!(transport.most.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn rate_limits_hold_across_fetch_workers() {
+    use std::sync::{Arc, Mutex};
+    use std::sync::mpsc::{channel, Sender};
+    use std::time::{Duration, Instant};
+    use super::vlive::{Ctx, Flow, VLive, VLiveCallback, VLiveVideo};
+    use super::vlive::transport::{RateLimited, Transport, TransportError};
+
+    //Tells when every request went out
+    struct Stamped(Mutex<Sender<Instant>>);
+
+    impl Transport for Stamped {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            let _ = self.0.lock().unwrap().send(Instant::now());
+            Ok(RECENT.to_string())
+        }
+    }
+
+    struct Quiet;
+
+    impl VLiveCallback for Quiet {
+        fn on_new(&mut self, _: &Ctx, _: VLiveVideo) -> Flow {
+            Flow::Continue
+        }
+    }
+
+    let (tx, rx) = channel();
+    let stopper = VLive::new(Quiet, Duration::from_secs(60))
+        .transport(Arc::new(RateLimited::new(Stamped(Mutex::new(tx)), Duration::from_millis(200))))
+        .watch("EBDF", Duration::from_secs(60))
+        .watch("F001E5", Duration::from_secs(60))
+        .watch("FE619", Duration::from_secs(60))
+        .concurrency(3)
+        .run_async();
+    let mut sent: Vec<_> = (0..3).filter_map(|_| rx.recv_timeout(Duration::from_secs(10)).ok()).collect();
+    stopper.stop();
+    sent.sort();
+    assert_eq!(sent.len(), 3);
+    assert!(sent[2] - sent[0] >= Duration::from_millis(200));
+}
+
+#[cfg(feature = "async-tokio")]
+#[test]
+fn async_callbacks_are_abandoned_after_their_deadline() {
+    use std::future::Future;
+    use std::sync::Arc;
+    use std::sync::mpsc::{channel, Sender};
+    use std::thread;
+    use std::time::Duration;
+    use super::vlive::{AsyncVLiveCallback, VLive, VLiveVideo};
+    use super::vlive::transport::{Transport, TransportError};
+
+    struct Recent;
+
+    impl Transport for Recent {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            Ok(RECENT.to_string())
+        }
+    }
+
+    //Lets the test know when the task holding it is dropped
+    struct Dropped(Sender<u64>, u64);
+
+    impl Drop for Dropped {
+        fn drop(&mut self) {
+            let _ = self.0.send(self.1);
+        }
+    }
+
+    //Never finishes
+    struct Stuck(Sender<u64>);
+
+    impl AsyncVLiveCallback for Stuck {
+        fn on_new(&self, video: VLiveVideo) -> impl Future<Output = ()> + Send {
+            let dropped = Dropped(self.0.clone(), video.video_seq);
+            async move {
+                let _dropped = dropped;
+                std::future::pending::<()>().await
+            }
+        }
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+    let handle = runtime.handle().clone();
+    thread::spawn(move || runtime.block_on(std::future::pending::<()>()));
+    let _runtime = handle.enter();
+
+    let (tx, rx) = channel();
+    let stopper = VLive::new_async(Stuck(tx), Duration::from_secs(60))
+        .transport(Arc::new(Recent))
+        .abandon_after(Duration::from_millis(50))
+        .run_async();
+    let mut abandoned: Vec<_> = (0..3).filter_map(|_| rx.recv_timeout(Duration::from_secs(5)).ok()).collect();
+    stopper.stop();
+    abandoned.sort();
+    assert_eq!(abandoned, vec![50000, 50001, 50002]);
+}
+
+#[test]
+fn slow_callbacks_are_reported() {
+    use std::sync::Arc;
+    use std::sync::mpsc::{channel, Sender};
+    use std::thread;
+    use std::time::Duration;
+    use super::vlive::{Ctx, Flow, VLive, VLiveCallback, VLiveError, VLiveVideo};
+    use super::vlive::transport::{Transport, TransportError};
+
+    struct Recent;
+
+    impl Transport for Recent {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            Ok(RECENT.to_string())
+        }
+    }
+
+    //Takes its time with the first video only
+    struct Slow(Sender<String>);
+
+    impl VLiveCallback for Slow {
+        fn on_new(&mut self, _: &Ctx, video: VLiveVideo) -> Flow {
+            if video.video_seq == 50002 {
+                thread::sleep(Duration::from_millis(100));
+            }
+            let _ = self.0.send(format!("new {}", video.video_seq));
+            Flow::Continue
+        }
+
+        fn on_error(&mut self, why: VLiveError) -> Flow {
+            if let VLiveError::SlowCallback { elapsed, deadline } = why {
+                assert!(elapsed > deadline);
+                let _ = self.0.send("slow".to_string());
+            }
+            Flow::Continue
+        }
+    }
+
+    let (tx, rx) = channel();
+    let stopper = VLive::new(Slow(tx), Duration::from_secs(60))
+        .transport(Arc::new(Recent))
+        .callback_deadline(Duration::from_millis(50))
+        .run_async();
+    let calls: Vec<_> = (0..4).filter_map(|_| rx.recv_timeout(Duration::from_secs(5)).ok()).collect();
+    stopper.stop();
+    assert_eq!(calls, vec!["new 50002", "slow", "new 50001", "new 50000"]);
+}
+
+#[test]
+fn health_checks_are_pinged_after_polls() {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use super::vlive::{Ctx, Flow, VLive, VLiveCallback, VLiveVideo};
+    use super::vlive::transport::{Transport, TransportError};
+
+    struct Recent;
+
+    impl Transport for Recent {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            Ok(RECENT.to_string())
+        }
+    }
+
+    struct Quiet;
+
+    impl VLiveCallback for Quiet {
+        fn on_new(&mut self, _: &Ctx, _: VLiveVideo) -> Flow {
+            Flow::Continue
+        }
+    }
+
+    let (base, requests) = http_server("");
+    let stopper = VLive::new(Quiet, Duration::from_secs(60))
+        .transport(Arc::new(Recent))
+        .health_check(&format!("{}/ping/uuid", base), Duration::from_secs(60))
+        .run_async();
+    let (head, _) = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+    stopper.stop();
+    assert!(head.starts_with("GET /ping/uuid "));
+}
+
+#[test]
+fn stoppers_rewind_the_listener() {
+    use std::sync::Arc;
+    use std::sync::mpsc::{channel, Sender};
+    use std::time::Duration;
+    use super::vlive::{Ctx, Flow, VLive, VLiveCallback, VLiveVideo};
+    use super::vlive::clock::ManualClock;
+    use super::vlive::transport::{Transport, TransportError};
+
+    struct Recent;
+
+    impl Transport for Recent {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            Ok(RECENT.to_string())
+        }
+    }
+
+    struct Seqs(Sender<u64>);
+
+    impl VLiveCallback for Seqs {
+        fn on_new(&mut self, _: &Ctx, video: VLiveVideo) -> Flow {
+            let _ = self.0.send(video.video_seq);
+            Flow::Continue
+        }
+    }
+
+    let (tx, rx) = channel();
+    let stopper = VLive::new(Seqs(tx), Duration::from_secs(5))
+        .transport(Arc::new(Recent))
+        .clock(Arc::new(ManualClock::new()))
+        .run_async();
+    let seqs = |count| (0..count).filter_map(|_| rx.recv_timeout(Duration::from_secs(10)).ok()).collect::<Vec<_>>();
+    assert_eq!(seqs(3), vec![50002, 50001, 50000]);
+
+    stopper.set_last_seen(50000);
+    assert_eq!(seqs(2), vec![50002, 50001]);
+    stopper.stop();
+}
+
+#[test]
+fn new_videos_are_translated_on_vlive_feeds_only() {
+    use std::time::{Duration, Instant, SystemTime};
+    use super::vlive::{Feed, Parser, Poller, VLiveEvent};
+    use super::vlive::poller::translate;
+    use super::vlive::transport::{Transport, TransportError};
+
+    //Serves the recent videos in Japanese, and nothing else
+    struct Japanese;
+
+    impl Transport for Japanese {
+        fn get(&self, url: &str) -> Result<String, TransportError> {
+            match url.ends_with("lang=ja") {
+                true => Ok(RECENT.replace("Comeback countdown LIVE", "カムバックカウントダウン")),
+                false => Err(format!("unexpected fetch of {}", url).into()),
+            }
+        }
+    }
+
+    let now = Instant::now();
+    let parser = Parser::default();
+    let mut poller = Poller::new(vec![(Feed::Recent, Duration::from_secs(5))], now);
+    let languages = ["ja".to_string()];
+    let titles = |feed: &Feed, mut events| {
+        translate(&mut events, feed, &languages, &Japanese, &parser);
+        events.into_iter()
+            .map(|event| match event {
+                Ok(VLiveEvent::New(event)) => event.video.video_titles.get("ja").cloned(),
+                other => panic!("expected a new video, got {:?}", other.map(|_| ())),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let events = poller.page(0, RECENT, &parser, now, SystemTime::UNIX_EPOCH);
+    assert_eq!(titles(&Feed::Recent, events)[1].as_deref(), Some("カムバックカウントダウン"));
+    poller.seek(0);
+    let events = poller.page(0, RECENT, &parser, now, SystemTime::UNIX_EPOCH);
+    assert!(titles(&Feed::YouTube("UCLkAepWjdylmXSltofFvsYQ".to_string()), events).iter().all(Option::is_none));
+}
+
+#[test]
+fn digests_flush_when_full_or_due() {
+    use std::sync::Arc;
+    use std::sync::mpsc::{channel, Sender};
+    use std::time::Duration;
+    use super::vlive::{Ctx, Flow, VLive, VLiveCallback, VLiveVideo};
+    use super::vlive::clock::ManualClock;
+    use super::vlive::transport::{Transport, TransportError};
+
+    struct Recent;
+
+    impl Transport for Recent {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            Ok(RECENT.to_string())
+        }
+    }
+
+    struct Mailer(Sender<Vec<u64>>);
+
+    impl VLiveCallback for Mailer {
+        fn on_new(&mut self, _: &Ctx, video: VLiveVideo) -> Flow {
+            panic!("{} skipped the digest", video.video_seq);
+        }
+
+        fn on_digest(&mut self, _: &Ctx, videos: Vec<VLiveVideo>) -> Flow {
+            let _ = self.0.send(videos.iter().map(|video| video.video_seq).collect());
+            Flow::Continue
+        }
+    }
+
+    //The first two fill a digest, the third waits out the hour on its own
+    let (tx, rx) = channel();
+    let stopper = VLive::new(Mailer(tx), Duration::from_secs(5))
+        .transport(Arc::new(Recent))
+        .clock(Arc::new(ManualClock::new()))
+        .digest(Duration::from_secs(60 * 60), 2)
+        .run_async();
+    let digests: Vec<_> = (0..2).filter_map(|_| rx.recv_timeout(Duration::from_secs(10)).ok()).collect();
+    assert_eq!(digests, vec![vec![50002, 50001], vec![50000]]);
+    stopper.stop();
+}
+
+#[test]
+fn digests_go_by_the_clock() {
+    use std::sync::Arc;
+    use std::sync::mpsc::{channel, Sender};
+    use std::time::Duration;
+    use super::vlive::{Ctx, Flow, VLive, VLiveCallback, VLiveVideo};
+    use super::vlive::clock::ManualClock;
+    use super::vlive::transport::{Transport, TransportError};
+
+    struct Recent;
+
+    impl Transport for Recent {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            Ok(RECENT.to_string())
+        }
+    }
+
+    struct Digested(Sender<usize>);
+
+    impl VLiveCallback for Digested {
+        fn on_new(&mut self, _: &Ctx, _: VLiveVideo) -> Flow {
+            Flow::Continue
+        }
+
+        fn on_digest(&mut self, _: &Ctx, videos: Vec<VLiveVideo>) -> Flow {
+            let _ = self.0.send(videos.len());
+            Flow::Continue
+        }
+    }
+
+    //An hour goes by as fast as the poller can sleep through it
+    let (tx, rx) = channel();
+    let stopper = VLive::new(Digested(tx), Duration::from_secs(5))
+        .transport(Arc::new(Recent))
+        .clock(Arc::new(ManualClock::new()))
+        .digest(Duration::from_secs(60 * 60), 50)
+        .run_async();
+    assert_eq!(rx.recv_timeout(Duration::from_secs(10)), Ok(3));
+    stopper.stop();
+}
+
+#[test]
+fn template_renders_fields_and_branches() {
+    use super::vlive::template::{Template, TemplateError};
+    use super::vlive::VideoType;
+
+    let mut video = event(42).video;
+    video.video_title = "Behind".to_string();
+    video.channel_name = "BTS".to_string();
+
+    let template = Template::parse("{{channel_name}} is {{#if live}}LIVE{{else}}uploaded{{/if}}: {{title}} {{url}}").unwrap();
+    assert_eq!(template.render(&video), "BTS is uploaded: Behind https://vlive.tv/video/42");
+    video.video_type = VideoType::LIVE;
+    assert_eq!(template.render(&video), "BTS is LIVE: Behind https://vlive.tv/video/42");
+
+    assert_eq!(Template::parse("{{views}}"), Err(TemplateError::UnknownField("views".to_string())));
+    assert_eq!(Template::parse("{{#if live}}LIVE"), Err(TemplateError::MissingEndIf));
+    assert_eq!(Template::parse("{{/if}}"), Err(TemplateError::Unexpected("/if".to_string())));
+    assert_eq!(Template::parse("{{title"), Err(TemplateError::Unclosed));
+}
+
+#[test]
+fn filter_expressions_match_videos() {
+    use super::vlive::filter::Filter;
+    use super::vlive::VideoType;
+
+    let mut video = event(42).video;
+    video.video_title = "BTS Behind The Scenes".to_string();
+    video.channel_seq = 363;
+    video.video_type = VideoType::LIVE;
+
+    let matches = |source: &str| Filter::parse(source).unwrap().matches(&video);
+    assert!(matches(r#"channel_seq in [363, 6] && type == LIVE && title ~ "behind""#));
+    assert!(!matches("channel_seq in [6] || type == VOD"));
+    assert!(matches("live && !paid && (seq >= 42 || seq < 0)"));
+    assert!(!matches("seq > 42"));
+
+    let error = |source: &str| Filter::parse(source).unwrap_err().position;
+    assert_eq!(error("views > 10"), 0);
+    assert_eq!(error("seq > LIVE"), 6);
+    assert_eq!(error("(live"), 5);
+    assert_eq!(error(r#"title ~ "behind"#), 8);
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn filter_regexes_match_videos() {
+    use super::vlive::filter::Filter;
+    use super::vlive::testing;
+
+    let teaser = testing::video().title("[Teaser] Comeback Show").channel("/channel/FE619", 363, "BTS").build();
+    let show = testing::video().title("Comeback Show").channel("/channel/FE619", 363, "BTS").build();
+
+    let filter = Filter::parse(r#"channel_name =~ "^BTS$" && title !~ "(?i)\\[teaser\\]""#).unwrap();
+    assert!(!filter.matches(&teaser));
+    assert!(filter.matches(&show));
+
+    let built = Filter::channel_regex("^BTS$").unwrap() & !Filter::title_regex(r"(?i)\[teaser\]").unwrap();
+    assert_eq!(built, filter);
+    assert!(Filter::title_regex("Comeback").unwrap().or(Filter::parse("paid").unwrap()).matches(&teaser));
+
+    assert_eq!(Filter::parse(r#"title =~ "(unclosed""#).unwrap_err().position, 9);
+    assert_eq!(Filter::parse("title =~ Teaser").unwrap_err().position, 9);
+    assert!(Filter::regex("views", ".").is_err());
+}
+
+#[cfg(feature = "sentry")]
+#[test]
+fn sentry_passes_everything_on() {
+    use std::sync::mpsc::{channel, Sender};
+    use std::time::Duration;
+    use super::vlive::{testing, ContentEvent, Ctx, Flow, Sentry, VLiveCallback, VLiveError, VLiveEvent, VLiveVideo};
+
+    struct Calls(Sender<&'static str>);
+
+    impl VLiveCallback for Calls {
+        fn on_new(&mut self, _: &Ctx, _: VLiveVideo) -> Flow {
+            let _ = self.0.send("new");
+            Flow::Continue
+        }
+
+        fn on_content(&mut self, _: &Ctx, _: ContentEvent) -> Flow {
+            let _ = self.0.send("content");
+            Flow::Continue
+        }
+
+        fn on_error(&mut self, _: VLiveError) -> Flow {
+            let _ = self.0.send("error");
+            Flow::Stop
+        }
+
+        fn on_reconnected(&mut self, _: Duration) {
+            let _ = self.0.send("reconnected");
+        }
+    }
+
+    let (tx, rx) = channel();
+    let ctx = Ctx::detached();
+    let mut sentry = Sentry::new(Calls(tx));
+    sentry.on_started(&ctx);
+    sentry.on_event(&ctx, VLiveEvent::New(testing::event(1, testing::video().build())));
+    assert_eq!(sentry.on_error(VLiveError::Panic("boom".to_string())), Flow::Stop);
+    sentry.on_reconnected(Duration::from_secs(60));
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec!["content", "error", "reconnected"]);
+}
+
+#[cfg(feature = "rhai")]
+#[test]
+fn scripts_change_and_drop_videos() {
+    use super::vlive::script::Script;
+
+    let script = Script::new(r#"
+        if video.channel_seq == 363 {
+            video.video_title = "[BTS] " + video.video_title;
+        }
+        !video.video_paid
+    "#).unwrap();
+
+    let mut video = event(42).video;
+    video.channel_seq = 363;
+    video.video_title = "Behind".to_string();
+    assert_eq!(script.run(&video).unwrap().unwrap().video_title, "[BTS] Behind");
+
+    video.video_paid = true;
+    assert!(script.run(&video).unwrap().is_none());
+}
+
+#[test]
+fn simulation_runs_fixture_pages_through_a_dry_run() {
+    use super::vlive::{middleware, DryRun, Feed, Flow, Simulation, Template};
+
+    let template = Template::parse("{{seq}}: {{title}}").unwrap();
+    let mut simulation = Simulation::new(DryRun::new().template(template), vec![Feed::Recent])
+        .middleware(middleware::filter(|video| video.video_seq != 50001));
+    assert_eq!(simulation.page(&Feed::Recent, RECENT), Flow::Continue);
+    assert_eq!(simulation.page(&Feed::Recent, RECENT), Flow::Continue);
+
+    let sent: Vec<_> = simulation.callback().sent.iter()
+        .map(|message| message.split(':').next().unwrap())
+        .collect();
+    assert_eq!(sent, vec!["50002", "50000"]);
+}
+
+#[test]
+fn testing_fixtures_match_the_parser() {
+    use super::vlive::{testing, Parser, VideoStage};
+
+    let videos: Vec<_> = Parser::default().parse_page(testing::RECENT_PAGE).unwrap()
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(videos, testing::recent_videos());
+
+    let video = testing::video().seq(42).stage(VideoStage::UPCOMING).build();
+    assert_eq!((video.video_id.as_str(), video.video_type), ("/video/42", super::vlive::VideoType::LIVE));
+}
+
+#[test]
+fn poller_reads_weverse_posts() {
+    use std::time::{Duration, Instant, SystemTime};
+    use super::vlive::{Feed, Parser, Poller};
+
+    let page = |seqs: &[u32]| {
+        let posts: Vec<_> = seqs.iter()
+            .map(|seq| format!(r#"{{"postId": "0-{0}", "title": "Weverse live {0}",
+                "extension": {{"video": {{"videoId": {0}, "type": "LIVE", "thumb": "https://phinf.wevpstatic.net/{0}.jpg"}}}},
+                "community": {{"communityId": 2, "communityName": "BTS", "urlPath": "bts"}}}}"#, seq))
+            .collect();
+        format!(r#"{{"data": [{}]}}"#, posts.join(","))
+    };
+
+    let now = Instant::now();
+    let parser = Parser::default();
+    let mut poller = Poller::new(vec![(Feed::Weverse("bts".to_string()), Duration::from_secs(5))], now);
+    let events = poller.page(0, &page(&[101, 100]), &parser, now, SystemTime::UNIX_EPOCH);
+    assert_eq!(announced(events), vec!["101", "100"]);
+    assert_eq!(announced(poller.page(0, &page(&[102, 101, 100]), &parser, now, SystemTime::UNIX_EPOCH)), vec!["102"]);
+
+    let events = poller.page(0, &page(&[103]), &parser, now, SystemTime::UNIX_EPOCH);
+    match events.first() {
+        Some(Ok(super::vlive::VLiveEvent::New(event))) => assert_eq!(event.video.url(), "https://weverse.io/bts/live/0-103"),
+        other => panic!("expected a new video, got {:?}", other),
+    }
+}
+
+#[test]
+fn reordered_pages_announce_each_video_once() {
+    use std::time::{Duration, Instant, SystemTime};
+    use super::vlive::{Feed, Parser, Poller};
+
+    let page = |seqs: &[u64]| {
+        let posts: Vec<_> = seqs.iter()
+            .map(|seq| format!(r#"{{"postId": "0-{0}", "title": "Weverse live {0}",
+                "extension": {{"video": {{"videoId": {0}, "type": "LIVE", "thumb": "https://phinf.wevpstatic.net/{0}.jpg"}}}},
+                "community": {{"communityId": 2, "communityName": "BTS", "urlPath": "bts"}}}}"#, seq))
+            .collect();
+        format!(r#"{{"data": [{}]}}"#, posts.join(","))
+    };
+
+    let now = Instant::now();
+    let parser = Parser::default();
+    let mut poller = Poller::new(vec![(Feed::Weverse("bts".to_string()), Duration::from_secs(5))], now);
+    assert_eq!(announced(poller.page(0, &page(&[101, 100]), &parser, now, SystemTime::UNIX_EPOCH)), vec!["101", "100"]);
+
+    //An old video pinned on top, and 102 showing up below 101
+    let reordered = page(&[90, 103, 101, 102, 100]);
+    assert_eq!(announced(poller.page(0, &reordered, &parser, now, SystemTime::UNIX_EPOCH)), vec!["103", "102"]);
+    assert_eq!(poller.last_seen(), 103);
+    assert!(announced(poller.page(0, &reordered, &parser, now, SystemTime::UNIX_EPOCH)).is_empty());
+
+    //Remembered across restarts too
+    let mut restored = Poller::new(vec![(Feed::Weverse("bts".to_string()), Duration::from_secs(5))], now).restore(&poller.state(), now);
+    assert!(announced(restored.page(0, &page(&[102, 103, 101]), &parser, now, SystemTime::UNIX_EPOCH)).is_empty());
+}
+
+#[test]
+fn events_carry_the_poll_that_found_them() {
+    use std::time::{Duration, Instant, SystemTime};
+    use super::vlive::{Feed, Parser, Poller, VLiveEvent};
+
+    let page = |seq: u64| format!(r#"{{"data": [{{"postId": "0-{0}", "title": "Weverse live {0}",
+        "extension": {{"video": {{"videoId": {0}, "type": "VOD", "thumb": "https://phinf.wevpstatic.net/{0}.jpg"}}}},
+        "community": {{"communityId": 2, "communityName": "BTS", "urlPath": "bts"}}}}]}}"#, seq);
+    let poll_ids = |events: Vec<Result<VLiveEvent, _>>| -> Vec<u64> {
+        events.into_iter()
+            .filter_map(|event| match event {
+                Ok(VLiveEvent::New(event)) => Some(event.poll_id),
+                _ => None,
+            })
+            .collect()
+    };
+
+    let now = Instant::now();
+    let parser = Parser::default();
+    let mut poller = Poller::new(vec![(Feed::Weverse("bts".to_string()), Duration::from_secs(5))], now);
+    assert_eq!(poller.poll_id(), 0);
+    assert_eq!(poller.due(now, SystemTime::UNIX_EPOCH), vec![0]);
+    assert_eq!(poll_ids(poller.page(0, &page(100), &parser, now, SystemTime::UNIX_EPOCH)), vec![1]);
+
+    //Nothing due, still the same round
+    assert!(poller.due(now, SystemTime::UNIX_EPOCH).is_empty());
+    assert_eq!(poller.poll_id(), 1);
+
+    let later = now + Duration::from_secs(5);
+    assert_eq!(poller.due(later, SystemTime::UNIX_EPOCH), vec![0]);
+    assert_eq!(poll_ids(poller.page(0, &page(101), &parser, later, SystemTime::UNIX_EPOCH)), vec![2]);
+}
+
+#[test]
+fn feeds_that_keep_failing_to_parse_back_off() {
+    use std::time::{Duration, Instant, SystemTime};
+    use super::vlive::{Feed, ParsePolicy, Parser, Poller, VLiveError};
+
+    let now = Instant::now();
+    let parser = Parser::default();
+    let mut poller = Poller::new(vec![(Feed::Recent, Duration::from_secs(5))], now)
+        .layout_changed(2, &ParsePolicy::Backoff { max: Duration::from_secs(15) });
+    let mut poll = |at: Instant| {
+        assert_eq!(poller.due(at, SystemTime::UNIX_EPOCH), vec![0]);
+        let events = poller.page(0, CHANGED, &parser, at, SystemTime::UNIX_EPOCH);
+        let reported = events.iter().any(|event| matches!(event, Err(VLiveError::LayoutChanged { polls: 2, .. })));
+        (reported, poller.next_due().unwrap() - at)
+    };
+
+    assert_eq!(poll(now), (false, Duration::from_secs(5)));
+    assert_eq!(poll(now + Duration::from_secs(5)), (true, Duration::from_secs(5)));
+    //Twice as long after every failure, up to the max
+    assert_eq!(poll(now + Duration::from_secs(10)), (false, Duration::from_secs(10)));
+    assert_eq!(poll(now + Duration::from_secs(20)), (false, Duration::from_secs(15)));
+    assert_eq!(poll(now + Duration::from_secs(35)), (false, Duration::from_secs(15)));
+}
+
+#[test]
+fn stop_policy_waits_for_a_real_layout_change() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::{channel, Sender};
+    use std::time::{Duration, Instant};
+    use super::vlive::{Ctx, Feed, Flow, ParsePolicy, VLive, VLiveCallback, VLiveVideo};
+    use super::vlive::transport::{Transport, TransportError};
+
+    struct Page(&'static str, Arc<AtomicUsize>);
+
+    impl Transport for Page {
+        fn get(&self, _: &str) -> Result<String, TransportError> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            Ok(self.0.to_string())
+        }
+    }
+
+    struct Stopped(Sender<()>);
+
+    impl VLiveCallback for Stopped {
+        fn on_new(&mut self, _: &Ctx, _: VLiveVideo) -> Flow {
+            Flow::Continue
+        }
+
+        fn on_stopped(&mut self) {
+            let _ = self.0.send(());
+        }
+    }
+
+    let listen = |page: &'static str| {
+        let (tx, rx) = channel();
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let stopper = VLive::new(Stopped(tx), Duration::from_millis(10))
+            .feed(Feed::Channel("F001E5".to_string()), Duration::from_millis(10))
+            .transport(Arc::new(Page(page, fetches.clone())))
+            .layout_changed(2, ParsePolicy::Stop)
+            .run_async();
+        (stopper, rx, fetches)
+    };
+
+    //A channel without uploads yet keeps being polled
+    let (stopper, rx, fetches) = listen(r#"<ul class="video_list"></ul>"#);
+    let started = Instant::now();
+    while fetches.load(Ordering::SeqCst) < 5 && started.elapsed() < Duration::from_secs(5) {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(fetches.load(Ordering::SeqCst) >= 5);
+    assert!(rx.try_recv().is_err());
+    stopper.stop();
+
+    let (_stopper, rx, _) = listen(CHANGED);
+    assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+}
+
+#[test]
+fn settle_delay_drops_videos_that_vanish() {
+    use std::time::{Duration, Instant, SystemTime};
+    use super::vlive::{Feed, Parser, Poller};
+
+    let page = |seqs: &[u64]| {
+        let posts: Vec<_> = seqs.iter()
+            .map(|seq| format!(r#"{{"postId": "0-{0}", "title": "Weverse live {0}",
+                "extension": {{"video": {{"videoId": {0}, "type": "VOD", "thumb": "https://phinf.wevpstatic.net/{0}.jpg"}}}},
+                "community": {{"communityId": 2, "communityName": "BTS", "urlPath": "bts"}}}}"#, seq))
+            .collect();
+        format!(r#"{{"data": [{}]}}"#, posts.join(","))
+    };
+
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let parser = Parser::default();
+    let mut poller = Poller::new(vec![(Feed::Weverse("bts".to_string()), Duration::from_secs(5))], start)
+        .settle_delay(Duration::from_secs(30));
+    let mut poll = |seqs: &[u64], now| announced(poller.page(0, &page(seqs), &parser, now, SystemTime::UNIX_EPOCH));
+    assert!(poll(&[101, 100], at(0)).is_empty());
+    assert!(poll(&[102, 101, 100], at(10)).is_empty());
+
+    //101 was deleted before it settled
+    assert_eq!(poll(&[102, 100], at(31)), vec!["100"]);
+    //Back again, so it waits all over
+    assert_eq!(poll(&[102, 101, 100], at(41)), vec!["102"]);
+    assert_eq!(poll(&[102, 101, 100], at(72)), vec!["101"]);
+    assert!(poll(&[102, 101, 100], at(100)).is_empty());
+
+    //Still held when restored, so it waits from then on the poller's time
+    assert!(poll(&[103, 102, 101, 100], at(110)).is_empty());
+    let mut restored = Poller::new(vec![(Feed::Weverse("bts".to_string()), Duration::from_secs(5))], at(3600))
+        .settle_delay(Duration::from_secs(30))
+        .restore(&poller.state(), at(3600));
+    let mut poll = |now| announced(restored.page(0, &page(&[103, 102, 101, 100]), &parser, now, SystemTime::UNIX_EPOCH));
+    assert!(poll(at(3620)).is_empty());
+    assert_eq!(poll(at(3630)), vec!["103"]);
+}
+
+#[test]
+fn youtube_feeds_parse_to_videos() {
+    use super::vlive::{Platform, Source, VideoStage};
+    use super::vlive::source::YouTube;
+
+    let feed = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns:yt="http://www.youtube.com/xml/schemas/2015" xmlns:media="http://search.yahoo.com/mrss/" xmlns="http://www.w3.org/2005/Atom">
+ <title>BANGTANTV</title>
+ <entry>
+  <yt:videoId>dQw4w9WgXcQ</yt:videoId>
+  <yt:channelId>UCLkAepWjdylmXSltofFvsYQ</yt:channelId>
+  <title>[BANGTAN BOMB] Jin &amp; Jimin&#39;s dance</title>
+  <author><name>BANGTANTV</name></author>
+  <published>2021-06-01T09:00:00+00:00</published>
+  <media:group><media:thumbnail url="https://i3.ytimg.com/vi/dQw4w9WgXcQ/hqdefault.jpg" width="480" height="360"/></media:group>
+ </entry>
+ <entry>
+  <yt:channelId>UCLkAepWjdylmXSltofFvsYQ</yt:channelId>
+ </entry>
+</feed>"#;
+
+    let videos = YouTube.parse(feed).unwrap();
+    let video = videos[0].as_ref().unwrap();
+    assert_eq!(video.url(), "https://www.youtube.com/watch?v=dQw4w9WgXcQ");
+    assert_eq!(video.channel_url(), "https://www.youtube.com/channel/UCLkAepWjdylmXSltofFvsYQ");
+    assert_eq!(video.video_title, "[BANGTAN BOMB] Jin & Jimin's dance");
+    assert_eq!(video.video_seq >> 20, 1_622_538_000);
+    assert_eq!(video.video_stage, VideoStage::VOD);
+    assert_eq!(video.video_thumbnail.as_deref(), Some("https://i3.ytimg.com/vi/dQw4w9WgXcQ/hqdefault.jpg"));
+    assert_eq!(video.platform, Platform::YOUTUBE);
+    assert!(videos[1].is_err());
+
+    assert!(YouTube.parse("<html>Sign in to continue</html>").is_err());
+}
+
+#[test]
+fn youtube_uploads_in_the_same_second_are_all_announced() {
+    use std::time::{Duration, Instant, SystemTime};
+    use super::vlive::{Feed, Parser, Poller};
+
+    let feed = |ids: &[&str]| {
+        let entries: String = ids.iter()
+            .map(|id| format!("<entry><yt:videoId>{0}</yt:videoId><yt:channelId>UCLkAepWjdylmXSltofFvsYQ</yt:channelId>
+                <title>{0}</title><author><name>BANGTANTV</name></author>
+                <published>2021-06-01T09:00:00+00:00</published></entry>", id))
+            .collect();
+        format!("<feed>{}</feed>", entries)
+    };
+
+    let now = Instant::now();
+    let parser = Parser::default();
+    let mut poller = Poller::new(vec![(Feed::YouTube("UCLkAepWjdylmXSltofFvsYQ".to_string()), Duration::from_secs(60))], now);
+    let mut poll = |ids: &[&str]| announced(poller.page(0, &feed(ids), &parser, now, SystemTime::UNIX_EPOCH)).len();
+    assert_eq!(poll(&["dQw4w9WgXcQ"]), 1);
+    assert_eq!(poll(&["9bZkp7q19f0", "dQw4w9WgXcQ"]), 1);
+    assert_eq!(poll(&["kJQP7kiw5Fk", "9bZkp7q19f0", "dQw4w9WgXcQ"]), 1);
+    //Hashes lower than everything seen so far
+    assert_eq!(poll(&["OPf0YbXqDm0", "kJQP7kiw5Fk", "9bZkp7q19f0", "dQw4w9WgXcQ"]), 1);
+    assert_eq!(poll(&["OPf0YbXqDm0", "kJQP7kiw5Fk", "9bZkp7q19f0", "dQw4w9WgXcQ"]), 0);
+}
+
+#[test]
+fn naver_tv_clips_parse_to_videos() {
+    use super::vlive::{Platform, Source, VideoStage};
+    use super::vlive::source::NaverTv;
+
+    let page = r#"{"channel": {"channelNo": 4321, "channelName": "BTS", "channelUrlPath": "bts"},
+        "clips": [
+            {"clipNo": 2002, "title": "Run BTS! EP.1", "clipType": "LIVE", "status": "RESERVED"},
+            {"clipNo": 2001, "title": "Bon Voyage", "clipType": "VOD", "thumbnailImageUrl": "https://phinf.pstatic.net/tvcast/2001.jpg"},
+            {"title": "No number"}
+        ]}"#;
+
+    let videos = NaverTv.parse(page).unwrap();
+    let live = videos[0].as_ref().unwrap();
+    assert_eq!(live.url(), "https://tv.naver.com/l/2002");
+    assert_eq!(live.video_stage, VideoStage::UPCOMING);
+    let clip = videos[1].as_ref().unwrap();
+    assert_eq!(clip.url(), "https://tv.naver.com/v/2001");
+    assert_eq!(clip.channel_url(), "https://tv.naver.com/bts");
+    assert_eq!(clip.channel_seq, 4321);
+    assert_eq!(clip.platform, Platform::NAVERTV);
+    assert!(videos[2].is_err());
+
+    assert!(NaverTv.parse(r#"{"clips": []}"#).is_err());
+}
+
+#[test]
+fn content_events_carry_their_platform() {
+    use super::vlive::{ContentEvent, Ctx, Event, Extension, Flow, Platform, VLiveCallback, VLiveEvent, VLiveVideo};
+    use super::vlive::testing;
+
+    let weverse = testing::video()
+        .platform(Platform::WEVERSE)
+        .channel("https://weverse.io/bts", 2, "BTS")
+        .build();
+    let weverse = VLiveVideo { video_id: "https://weverse.io/bts/live/0-103".to_string(), ..weverse };
+    let event = ContentEvent::from(testing::event(7, weverse));
+    assert_eq!(event.platform(), Platform::WEVERSE);
+    assert_eq!(event.extension, Extension::WEVERSE { community: "bts".to_string(), post_id: "0-103".to_string() });
+
+    let json = serde_json::to_value(&event).unwrap();
+    assert_eq!(json["platform"], "WEVERSE");
+    assert_eq!(json["post_id"], "0-103");
+    assert_eq!(serde_json::from_value::<ContentEvent>(json).unwrap(), event);
+    assert_eq!(Event::from(event.clone()).video, event.video);
+
+    let vlive = ContentEvent::from(testing::event(8, testing::video().build()));
+    assert_eq!(vlive.extension, Extension::VLIVE { video_seq: 50000, channel_code: "FE619".to_string() });
+
+    struct Platforms(Vec<Platform>);
+    impl VLiveCallback for Platforms {
+        fn on_new(&mut self, _: &Ctx, _: VLiveVideo) -> Flow {
+            Flow::Continue
+        }
+
+        fn on_content(&mut self, _: &Ctx, event: ContentEvent) -> Flow {
+            self.0.push(event.platform());
+            Flow::Continue
+        }
+    }
+    let mut platforms = Platforms(Vec::new());
+    platforms.on_event(&Ctx::detached(), VLiveEvent::New(testing::event(9, testing::video().platform(Platform::YOUTUBE).build())));
+    assert_eq!(platforms.0, vec![Platform::YOUTUBE]);
+}
+
+#[test]
+fn correlate_collapses_simulcasts() {
+    use std::time::{Duration, Instant};
+    use super::vlive::{Correlate, Platform, VLiveVideo};
+    use super::vlive::testing;
+
+    let now = Instant::now();
+    let mut correlate = Correlate::new(Duration::from_secs(60));
+    assert!(!correlate.push(testing::event(0, testing::video().seq(1).title("[LIVE] Comeback Show!").live().build()), now));
+    let weverse = VLiveVideo {
+        video_id: "https://weverse.io/bts/live/0-1".to_string(),
+        ..testing::video().title("Comeback show").live().platform(Platform::WEVERSE).build()
+    };
+    assert!(correlate.push(testing::event(0, weverse.clone()), now + Duration::from_secs(10)));
+    //Already folded, and not a mirror of itself
+    assert!(correlate.push(testing::event(0, weverse), now + Duration::from_secs(20)));
+    //Same platform, or a different show, are videos of their own
+    assert!(!correlate.push(testing::event(0, testing::video().seq(2).title("Comeback show").build()), now));
+    let other = testing::video().title("Dance practice").platform(Platform::YOUTUBE).build();
+    assert!(!correlate.push(testing::event(0, other), now));
+
+    assert_eq!(correlate.next_due(), Some(now + Duration::from_secs(60)));
+    assert!(correlate.take_due(now + Duration::from_secs(59)).is_empty());
+    let due = correlate.take_due(now + Duration::from_secs(60));
+    assert_eq!(due.len(), 3);
+    assert_eq!(due[0].video.video_seq, 1);
+    assert_eq!(due[0].video.video_mirrors, vec!["https://weverse.io/bts/live/0-1".to_string()]);
+    assert!(due[1].video.video_mirrors.is_empty());
+    assert!(correlate.take().is_empty());
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn ffi_handles_refuse_bad_input() {
+    use std::os::raw::{c_char, c_int, c_void};
+    use std::ptr;
+    use super::vlive::ffi;
+
+    extern "C" fn on_video(_: *const c_char, _: *mut c_void) -> c_int {
+        0
+    }
+
+    unsafe {
+        assert_eq!(ffi::vlive_start(ptr::null_mut()), -1);
+        assert_eq!(ffi::vlive_watch(ptr::null_mut(), b"FE619\0".as_ptr() as *const c_char, 5000), -1);
+
+        let handle = ffi::vlive_new(5000);
+        assert_eq!(ffi::vlive_watch(handle, ptr::null(), 5000), -1);
+        assert_eq!(ffi::vlive_watch(handle, b"FE619\0".as_ptr() as *const c_char, 5000), 0);
+        //Nothing to call yet
+        assert_eq!(ffi::vlive_start(handle), -1);
+        assert_eq!(ffi::vlive_set_callback(handle, on_video, ptr::null_mut()), 0);
+        ffi::vlive_stop(handle);
+        ffi::vlive_free(handle);
+        ffi::vlive_free(ptr::null_mut());
+    }
+}
+
+#[test]
+fn prelude_covers_a_listener() {
+    use std::time::Duration;
+    use super::vlive::prelude::*;
+
+    struct Handler;
+
+    impl VLiveCallback for Handler {
+        fn on_new(&mut self, _: &Ctx, video: VLiveVideo) -> Flow {
+            assert_eq!(video.platform, Platform::VLIVE);
+            Flow::Continue
+        }
+    }
+
+    let vlive = VLive::new(Handler, Duration::from_secs(5)).feed(Feed::Recent, Duration::from_secs(5));
+    drop(vlive);
+    //The old paths still work
+    let _: super::vlive::model::Flow = super::vlive::Flow::Continue;
+}
+
+#[test]
+fn subscriptions_route_videos_to_their_subscribers() {
+    use std::sync::{Arc, Mutex};
+    use super::vlive::{testing, ChannelFilter, Ctx, Subscription, Subscriptions, VLiveCallback};
+
+    let path = std::env::temp_dir().join(format!("vlive-subscriptions-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let posted = Arc::new(Mutex::new(Vec::new()));
+    let log = posted.clone();
+    let mut subscriptions = Subscriptions::open(&path).unwrap()
+        .sink("webhook", move |subscription, event| {
+            log.lock().unwrap().push((subscription.target.clone(), event.video.video_seq));
+            Ok(())
+        });
+    let subscribe = |subscriber: &str, channels: ChannelFilter| Subscription {
+        subscriber: subscriber.to_string(),
+        channels,
+        sink: "webhook".to_string(),
+        target: format!("https://hooks.example/{}", subscriber),
+        ..Subscription::default()
+    };
+    subscriptions.subscribe(subscribe("army", ChannelFilter::default().allow(&["FE619"]))).unwrap();
+    subscriptions.subscribe(subscribe("everyone", ChannelFilter::default())).unwrap();
+    subscriptions.subscribe(subscribe("uaena", ChannelFilter::default().allow(&["EBDF"]))).unwrap();
+    assert_eq!(subscriptions.unsubscribe("uaena").unwrap().map(|gone| gone.subscriber), Some("uaena".to_string()));
+
+    let bts = testing::event(1, testing::video().seq(1).channel("/channels/FE619", 13, "BTS").build());
+    let iu = testing::event(2, testing::video().seq(2).channel("/channels/EBDF", 7, "IU").build());
+    subscriptions.deliver(&Ctx::detached(), &bts).unwrap();
+    subscriptions.deliver(&Ctx::detached(), &iu).unwrap();
+    assert_eq!(*posted.lock().unwrap(), vec![
+        ("https://hooks.example/army".to_string(), 1),
+        ("https://hooks.example/everyone".to_string(), 1),
+        ("https://hooks.example/everyone".to_string(), 2),
+    ]);
+
+    //Saved on every change, sinks aren't
+    let reopened = Subscriptions::open(&path).unwrap();
+    assert_eq!(reopened.list(), subscriptions.list());
+    assert_eq!(reopened.len(), 2);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn subscribers_get_videos_their_own_way() {
+    use std::sync::{Arc, Mutex};
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use super::vlive::{testing, Ctx, Quiet, Subscription, Subscriptions, VLiveCallback};
+
+    let posted = Arc::new(Mutex::new(Vec::new()));
+    let log = posted.clone();
+    let mut subscriptions = Subscriptions::new()
+        .sink("chat", move |subscription, event| {
+            log.lock().unwrap().push((subscription.subscriber.clone(), event.video.video_title.clone(), subscription.mention.clone()));
+            Ok(())
+        });
+    let subscriber = |name: &str| Subscription {
+        subscriber: name.to_string(),
+        sink: "chat".to_string(),
+        ..Subscription::default()
+    };
+
+    //Quiet for the hour around now, in UTC
+    let hour = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() / 3600 % 24;
+    let asleep = Quiet {
+        start: format!("{:02}:00", (hour + 23) % 24),
+        end: format!("{:02}:00", (hour + 1) % 24),
+        ..Quiet::default()
+    };
+    subscriptions.subscribe(Subscription { live_only: true, mention: Some("@here".to_string()), ..subscriber("live") }).unwrap();
+    subscriptions.subscribe(Subscription { language: Some("en".to_string()), ..subscriber("english") }).unwrap();
+    subscriptions.subscribe(Subscription { quiet: Some(asleep.clone()), ..subscriber("asleep") }).unwrap();
+    subscriptions.subscribe(Subscription { quiet: Some(Quiet { exempt_live: true, ..asleep }), ..subscriber("night owl") }).unwrap();
+    let broken = Quiet { start: "late".to_string(), ..Quiet::default() };
+    assert!(subscriptions.subscribe(Subscription { quiet: Some(broken), ..subscriber("broken") }).is_err());
+
+    let vod = testing::event(1, testing::video().seq(1).title("방탄소년단").translated("en", "BTS").build());
+    let live = testing::event(2, testing::video().seq(2).title("아이유").live().build());
+    subscriptions.deliver(&Ctx::detached(), &vod).unwrap();
+    subscriptions.deliver(&Ctx::detached(), &live).unwrap();
+
+    let delivered = |name: &str, title: &str, mention: Option<&str>| (name.to_string(), title.to_string(), mention.map(str::to_string));
+    assert_eq!(*posted.lock().unwrap(), vec![
+        delivered("english", "BTS", None),
+        delivered("english", "아이유", None),
+        delivered("live", "아이유", Some("@here")),
+        delivered("night owl", "아이유", None),
+    ]);
+}
+
+#[test]
+fn subscriptions_move_hosts_with_a_backup() {
+    use super::vlive::{Backup, ChannelFilter, PollerState, Quiet, Subscription, Subscriptions};
+
+    let old = Subscriptions::new();
+    old.subscribe(Subscription {
+        subscriber: "army".to_string(),
+        channels: ChannelFilter::default().allow(&["FE619"]),
+        sink: "discord".to_string(),
+        language: Some("en".to_string()),
+        ..Subscription::default()
+    }).unwrap();
+    let state = PollerState {
+        channels: Some(vec!["/channels/FE619".to_string()]),
+        ..PollerState::default()
+    };
+    let backup = old.export(Some(state.clone()));
+
+    let new = Subscriptions::new();
+    new.subscribe(Subscription { subscriber: "stale".to_string(), ..Subscription::default() }).unwrap();
+    assert_eq!(new.import(backup.clone()).unwrap(), Some(state));
+    assert_eq!(new.list(), old.list());
+
+    //All or nothing
+    let mut broken = backup.clone();
+    broken.subscriptions.push(Subscription {
+        subscriber: "broken".to_string(),
+        quiet: Some(Quiet { start: "25:00".to_string(), end: "08:00".to_string(), ..Quiet::default() }),
+        ..Subscription::default()
+    });
+    assert!(new.import(broken).is_err());
+    assert_eq!(new.len(), 1);
+
+    let mut json = Vec::new();
+    backup.write(&mut json).unwrap();
+    assert_eq!(Backup::read(&json[..]).unwrap(), backup);
+}
+
+/// Answer every request on a local port with `body`, handing out the head
+/// and body of each request. Returns the port's base URL
+fn http_server(body: &'static str) -> (String, std::sync::mpsc::Receiver<(String, String)>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut head = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                head.push_str(&line);
+            }
+            let mut request = vec![0; length];
+            reader.read_exact(&mut request).unwrap();
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(), body).unwrap();
+            if tx.send((head, String::from_utf8_lossy(&request).into_owned())).is_err() {
+                break;
+            }
+        }
+    });
+    (base, rx)
+}
+
+#[test]
+fn matrix_posts_html_and_uploads_thumbnails() {
+    use std::time::Duration;
+    use super::vlive::{testing, Ctx, Matrix, Template, VLiveCallback};
+
+    let (base, requests) = http_server(r#"{"event_id": "$posted", "content_uri": "mxc://example.org/thumbnail"}"#);
+    let mut matrix = Matrix::new(&base, "secret", "!fans:example.org");
+    let video = testing::video().seq(7).title("Run <BTS> & more")
+        .thumbnail(&format!("{}/thumbnail.jpg", base))
+        .build();
+    let event = testing::event(3, video);
+    matrix.deliver(&Ctx::detached(), &event).unwrap();
+
+    let next = || requests.recv_timeout(Duration::from_secs(5)).unwrap();
+    let (head, message) = next();
+    assert!(head.starts_with("PUT /_matrix/client/v3/rooms/%21fans%3Aexample.org/send/m.room.message/vlive-7-3 "));
+    assert!(head.contains("Authorization: Bearer secret"));
+    assert!(message.contains("Run &lt;BTS&gt; &amp; more"));
+    assert!(message.contains("org.matrix.custom.html"));
+    assert!(next().0.starts_with("GET /thumbnail.jpg "));
+    //Served as JSON, so the type is guessed from the image
+    let (head, _) = next();
+    assert!(head.starts_with("POST /_matrix/media/v3/upload"));
+    assert!(head.contains("Content-Type: image/jpeg"));
+    let (head, image) = next();
+    assert!(head.contains("/send/m.room.message/vlive-7-3-thumbnail "));
+    assert!(image.contains("mxc://example.org/thumbnail"));
+    assert!(image.contains("\"mimetype\":\"image/jpeg\""));
+
+    //A retry reuses the transaction id, so the homeserver drops it
+    let mut matrix = matrix.thumbnails(false);
+    matrix.deliver(&Ctx::detached(), &event).unwrap();
+    let (head, message) = next();
+    assert!(head.contains("/send/m.room.message/vlive-7-3 "));
+    assert!(message.contains("\"body\":\"BTS uploaded: Run <BTS> & more\\n"));
+
+    //Worded by the templates, only escaped in HTML
+    let mut matrix = matrix.template(Template::parse("{{title}} ({{channel_name}})").unwrap());
+    matrix.deliver(&Ctx::detached(), &event).unwrap();
+    let (_, message) = next();
+    assert!(message.contains("\"body\":\"Run <BTS> & more (BTS)\""));
+    assert!(!message.contains("formatted_body"));
+    let mut matrix = matrix.html_template(Template::parse("<i>{{title}}</i>").unwrap());
+    matrix.deliver(&Ctx::detached(), &event).unwrap();
+    assert!(next().1.contains("\"formatted_body\":\"<i>Run &lt;BTS&gt; &amp; more</i>\""));
+}
+
+#[test]
+fn mastodon_toots_paid_videos_behind_a_warning() {
+    use std::time::Duration;
+    use super::vlive::{testing, Ctx, Mastodon, Template, VLiveCallback};
+
+    let (base, requests) = http_server(r#"{"id": "1"}"#);
+    let mut mastodon = Mastodon::new(&format!("{}/", base), "secret").visibility("unlisted");
+    let next = || requests.recv_timeout(Duration::from_secs(5)).unwrap();
+
+    mastodon.deliver(&Ctx::detached(), &testing::event(1, testing::video().seq(1).title("Free").build())).unwrap();
+    let (head, status) = next();
+    assert!(head.starts_with("POST /api/v1/statuses "));
+    assert!(head.contains("Idempotency-Key: vlive-1-1"));
+    assert!(status.contains("\"visibility\":\"unlisted\""));
+    assert!(status.contains("\"spoiler_text\":\"\""));
+
+    mastodon.deliver(&Ctx::detached(), &testing::event(2, testing::video().seq(2).title("Concert").paid().build())).unwrap();
+    let (_, status) = next();
+    assert!(status.contains("\"status\":\"BTS uploaded (paid): Concert\\n\\n"));
+    assert!(status.contains("\"spoiler_text\":\"Paid content\""));
+    assert!(status.contains("\"sensitive\":true"));
+
+    let mut mastodon = mastodon.template(Template::parse("{{title}} #{{channel_name}}").unwrap());
+    mastodon.deliver(&Ctx::detached(), &testing::event(3, testing::video().seq(3).title("Run").build())).unwrap();
+    assert!(next().1.contains("\"status\":\"Run #BTS\""));
+}
+
+#[test]
+fn ntfy_publishes_with_title_link_and_thumbnail() {
+    use std::time::Duration;
+    use super::vlive::{testing, Ctx, Ntfy, Template, VLiveCallback};
+
+    let (base, requests) = http_server(r#"{"id": "1"}"#);
+    let mut ntfy = Ntfy::new("vlive-bts").server(&base);
+    let video = testing::video().seq(1).title("Run BTS").live()
+        .channel("/channels/FE619", 13, "BTS")
+        .thumbnail("//phinf.pstatic.net/run.jpg?type=f228_128")
+        .build();
+    ntfy.deliver(&Ctx::detached(), &testing::event(1, video.clone())).unwrap();
+
+    let (head, message) = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(head.starts_with("PUT /vlive-bts?title=BTS%20is%20live&click="));
+    assert!(head.contains(&format!("click={}", video.url().replace(':', "%3A").replace('/', "%2F"))));
+    //Absolute, and as large as the other sinks' thumbnails
+    assert!(head.contains("&tags=red_circle&attach=https%3A%2F%2Fphinf.pstatic.net%2Frun.jpg%3Ftype%3Df1280_720 "));
+    assert!(!head.contains("Authorization"));
+    assert_eq!(message, "Run BTS");
+
+    let mut ntfy = ntfy.template(Template::parse("{{title}} ({{type}})").unwrap())
+        .title_template(Template::parse("{{channel_name}} 라이브").unwrap());
+    ntfy.deliver(&Ctx::detached(), &testing::event(2, video)).unwrap();
+    let (head, message) = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(head.starts_with("PUT /vlive-bts?title=BTS%20%EB%9D%BC%EC%9D%B4%EB%B8%8C&click="));
+    assert_eq!(message, "Run BTS (LIVE)");
+}
+
+#[test]
+fn fanout_runs_sinks_side_by_side() {
+    use std::sync::{Arc, Mutex};
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+    use super::vlive::{Ctx, Fanout, Flow, VLiveCallback, VLiveVideo};
+    use super::vlive::testing;
+
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let slow = |name: &'static str, order: Arc<Mutex<Vec<&'static str>>>| move |_: VLiveVideo| {
+        sleep(Duration::from_millis(200));
+        order.lock().unwrap().push(name);
+    };
+    let event = testing::event(1, testing::video().seq(1).build());
+
+    let mut sinks = Fanout::new()
+        .sink(slow("telegram", order.clone()))
+        .sink(|_: VLiveVideo| -> Flow { panic!("broken sink") })
+        .sink(slow("file", order.clone()));
+    let started = Instant::now();
+    assert_eq!(sinks.deliver(&Ctx::detached(), &event).unwrap(), Flow::Continue);
+    assert!(started.elapsed() < Duration::from_millis(390));
+    assert_eq!(order.lock().unwrap().len(), 2);
+
+    //In order, and a stop from any of them counts
+    order.lock().unwrap().clear();
+    let mut sinks = Fanout::new()
+        .sink(slow("first", order.clone()))
+        .sink(slow("second", order.clone()))
+        .sink(|_: VLiveVideo| Flow::Stop)
+        .sequential();
+    assert_eq!(sinks.on_new(&Ctx::detached(), event.video.clone()), Flow::Stop);
+    assert_eq!(*order.lock().unwrap(), vec!["first", "second"]);
+}
+
+#[test]
+fn throttled_sinks_wait_their_turn() {
+    use std::time::{Duration, Instant};
+    use super::vlive::{Ctx, Feed, Throttled, VLiveCallback, VLiveEvent, VLiveVideo};
+    use super::vlive::testing;
+
+    let video = testing::video().seq(1).build();
+    let mut sink = Throttled::new(|_: VLiveVideo| (), 2, Duration::from_millis(200));
+    let started = Instant::now();
+    for _ in 0..2 {
+        sink.on_new(&Ctx::detached(), video.clone());
+    }
+    assert!(started.elapsed() < Duration::from_millis(100));
+    for _ in 0..2 {
+        sink.on_new(&Ctx::detached(), video.clone());
+    }
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(200) && elapsed < Duration::from_millis(390), "{:?}", elapsed);
+
+    //Evenly spaced instead of in a burst
+    let mut sink = Throttled::new(|_: VLiveVideo| (), 2, Duration::from_millis(200)).smooth();
+    let started = Instant::now();
+    for _ in 0..3 {
+        sink.on_new(&Ctx::detached(), video.clone());
+    }
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(200) && elapsed < Duration::from_millis(390), "{:?}", elapsed);
+
+    //A digest is as many videos as it holds
+    let mut sink = Throttled::new(|_: VLiveVideo| (), 2, Duration::from_millis(200));
+    let started = Instant::now();
+    sink.on_digest(&Ctx::detached(), vec![video.clone(); 3]);
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(200) && elapsed < Duration::from_millis(390), "{:?}", elapsed);
+
+    //Events other than new videos don't use up slots
+    let mut sink = Throttled::new(|_: VLiveVideo| (), 1, Duration::from_secs(60));
+    let started = Instant::now();
+    for _ in 0..3 {
+        sink.on_event(&Ctx::detached(), VLiveEvent::PossibleGap { feed: Feed::Recent, missed_range: 1..5 });
+    }
+    sink.on_event(&Ctx::detached(), VLiveEvent::New(testing::event(1, video)));
+    assert!(started.elapsed() < Duration::from_millis(100));
+}
+
+#[test]
+fn keywords_route_to_their_callbacks() {
+    use std::sync::mpsc::channel;
+    use super::vlive::{testing, Ctx, Keywords, VLiveCallback, VLiveVideo};
+
+    let (subtitled, subtitles) = channel::<VLiveVideo>();
+    let (comeback, comebacks) = channel::<VLiveVideo>();
+    let mut keywords = Keywords::new()
+        .watch(&["ENG SUB", "English"], subtitled)
+        .watch(&["comeback"], comeback);
+
+    let video = testing::video().seq(1).title("[ENG SUB] Comeback Show").build();
+    assert_eq!(keywords.matching(&video), vec!["eng sub", "comeback"]);
+    keywords.on_new(&Ctx::detached(), video);
+    keywords.on_new(&Ctx::detached(), testing::video().seq(2).title("Dance practice").translated("ko", "컴백 comeback").build());
+    keywords.on_new(&Ctx::detached(), testing::video().seq(3).title("Dance practice").build());
+
+    assert_eq!(subtitles.try_iter().map(|video| video.video_seq).collect::<Vec<_>>(), vec![1]);
+    assert_eq!(comebacks.try_iter().map(|video| video.video_seq).collect::<Vec<_>>(), vec![1, 2]);
+}
+
+#[test]
+fn channel_filters_allow_and_block() {
+    use super::vlive::ChannelFilter;
+
+    let everything = ChannelFilter::default();
+    assert!(everything.contains("/channel/FE619", 363));
+
+    let filter = ChannelFilter::default().allow(&["fe619", "6"]).block(&["F001E5"]);
+    assert!(filter.contains("/channel/FE619", 363));
+    assert!(filter.contains("/channel/ABCDE", 6));
+    assert!(!filter.contains("/channel/ABCDE", 7));
+
+    let blocked = ChannelFilter::default().block(&["363"]);
+    assert!(!blocked.contains("/channel/FE619", 363));
+    assert!(blocked.contains("/channel/F001E5", 13));
+    assert!(!filter.block(&["6"]).contains("/channel/ABCDE", 6));
+}
+
+#[test]
+fn scraped_text_is_normalized() {
+    use std::borrow::Cow;
+    use super::vlive::parser::normalize;
+
+    assert!(matches!(normalize("Comeback Show"), Cow::Borrowed("Comeback Show")));
+    assert_eq!(normalize("  Comeback &amp; Show\n"), "Comeback & Show");
+    assert_eq!(normalize("It&#39;s&nbsp;live &#x1F49C;"), "It's live \u{1f49c}");
+    assert_eq!(normalize("BTS\u{200b}\tBehind"), "BTS Behind");
+    assert_eq!(normalize("Cafe\u{301}"), "Caf\u{e9}");
+    //Only decoded once, and what isn't an entity stays
+    assert_eq!(normalize("&amp;amp; R&B &unknown; &#xZZ;"), "&amp; R&B &unknown; &#xZZ;");
+}
+
+#[test]
+fn videos_link_to_absolute_urls() {
+    use super::vlive::{testing, Platform};
+
+    let video = testing::video().seq(50000).channel("/channels/FE619", 363, "BTS").build();
+    assert_eq!(video.url(), "https://vlive.tv/video/50000");
+    assert_eq!(video.channel_url(), "https://vlive.tv/channels/FE619");
+    assert_eq!(video.video_code(), "50000");
+    assert_eq!(video.channel_code(), "FE619");
+
+    let mut video = testing::video().channel("channels/FE619/", 363, "BTS").platform(Platform::YOUTUBE).build();
+    video.video_id = "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string();
+    assert_eq!(video.url(), video.video_id);
+    assert_eq!(video.video_code(), "dQw4w9WgXcQ");
+    assert_eq!(video.channel_url(), "https://vlive.tv/channels/FE619/");
+    assert_eq!(video.channel_code(), "FE619");
+
+    video.video_id = "//tv.naver.com/v/42?list=1".to_string();
+    video.platform = Platform::NAVERTV;
+    assert_eq!(video.url(), "https://tv.naver.com/v/42?list=1");
+    assert_eq!(video.video_code(), "42");
+}
+
+#[test]
+fn thumbnails_scale_to_sizes() {
+    use super::vlive::{testing, ThumbSize};
+
+    let video = testing::video().thumbnail("https://v-phinf.pstatic.net/20180101_1/thumb.jpg?type=f228_128").build();
+    assert_eq!(video.thumbnail_url(ThumbSize::LARGE).unwrap(), "https://v-phinf.pstatic.net/20180101_1/thumb.jpg?type=f1280_720");
+    assert_eq!(video.thumbnail_url(ThumbSize::ORIGINAL).unwrap(), "https://v-phinf.pstatic.net/20180101_1/thumb.jpg");
+
+    let video = testing::video().thumbnail("//v-phinf.pstatic.net/thumb.jpg?v=2").build();
+    assert_eq!(video.thumbnail_url(ThumbSize::SMALL).unwrap(), "https://v-phinf.pstatic.net/thumb.jpg?v=2&type=f228_128");
+
+    let video = testing::video().thumbnail("https://i.ytimg.com/vi/dQw4w9WgXcQ/hqdefault.jpg").build();
+    assert_eq!(video.thumbnail_url(ThumbSize::LARGE).unwrap(), "https://i.ytimg.com/vi/dQw4w9WgXcQ/hqdefault.jpg");
+
+    let mut video = testing::video().build();
+    video.video_thumbnail = None;
+    assert_eq!(video.thumbnail_url(ThumbSize::LARGE), None);
+}
+
+#[test]
+fn lazy_thumbnails_are_found() {
+    use super::vlive::Parser;
+
+    let html = r#"
+        <div class="video_list_cont">
+            <a class="thumb_area" href="/video/50002" data-seq="50002" data-ga-cseq="363">
+                <img src="data:image/gif;base64,R0lGODlhAQABAAAAACw=" data-src="https://v-phinf.pstatic.net/lazy.jpg?type=f228_128">
+            </a>
+            <a class="name" href="/channels/FE619"></a>
+        </div>
+        <div class="video_list_cont">
+            <a class="thumb_area" href="/video/50001" data-seq="50001" data-ga-cseq="363"
+                style="display: block; background-image: url('https://v-phinf.pstatic.net/background.jpg')"></a>
+            <a class="name" href="/channels/FE619"></a>
+        </div>
+        <div class="video_list_cont">
+            <a class="thumb_area" href="/video/50000" data-seq="50000" data-ga-cseq="363">
+                <img src="https://ssl.pstatic.net/static/blank.gif">
+            </a>
+            <a class="name" href="/channels/FE619"></a>
+        </div>"#;
+    let thumbnails: Vec<_> = Parser::default().parse_page(html).unwrap()
+        .into_iter()
+        .map(|video| video.unwrap().video_thumbnail)
+        .collect();
+    assert_eq!(thumbnails, vec![
+        Some("https://v-phinf.pstatic.net/lazy.jpg?type=f228_128".to_string()),
+        Some("https://v-phinf.pstatic.net/background.jpg".to_string()),
+        None,
+    ]);
+}
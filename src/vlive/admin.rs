@@ -0,0 +1,143 @@
+//! A small HTTP server for looking after a running listener, see
+//! `VLive::admin`
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use super::{journal, Ctx};
+
+/// What the admin endpoints answer from
+pub(crate) struct Admin {
+    pub ctx: Ctx,
+    /// Served as is on `/config`
+    pub config: String,
+    /// Journal `/events` are read from, if there is one
+    pub journal: Option<PathBuf>,
+}
+
+/// How long to wait between looking for new connections
+const ACCEPT_EVERY: Duration = Duration::from_millis(50);
+
+/// Answer requests on `listener` about the listener behind `admin`, until
+/// `stopped` hangs up
+///
+/// Connections are accepted without blocking, so the port is let go of
+/// once the listener stops and can be bound again
+pub(crate) fn serve(listener: TcpListener, admin: Admin, stopped: Receiver<()>) {
+    if let Err(why) = listener.set_nonblocking(true) {
+        eprintln!("VLive Error: Admin endpoint can't tell when to stop: {}", why);
+        return;
+    }
+    loop {
+        let result = match listener.accept() {
+            Ok((stream, _)) => stream.set_nonblocking(false).and_then(|_| respond(stream, &admin)),
+            Err(ref why) if why.kind() == io::ErrorKind::WouldBlock => match stopped.recv_timeout(ACCEPT_EVERY) {
+                Err(RecvTimeoutError::Timeout) => continue,
+                _ => return,
+            },
+            Err(why) => Err(why),
+        };
+        if let Err(why) = result {
+            eprintln!("VLive Error: Admin request failed: {}", why);
+        }
+    }
+}
+
+/// Read one request from `stream` and answer it
+fn respond(stream: TcpStream, admin: &Admin) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+
+    //Nothing here needs the headers or a body
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (status, content_type, body) = route(method, path, query, admin);
+
+    let mut stream = &stream;
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body)?;
+    stream.flush()
+}
+
+const TEXT: &str = "text/plain; charset=utf-8";
+const JSON: &str = "application/json";
+
+/// Status, content type and body of the answer to `method` on `path`
+fn route(method: &str, path: &str, query: &str, admin: &Admin) -> (&'static str, &'static str, String) {
+    let ctx = &admin.ctx;
+    match (method, path) {
+        ("GET", "/healthz") => {
+            let stats = ctx.stats();
+            if stats.online || stats.paused {
+                ("200 OK", TEXT, "ok\n".to_string())
+            } else {
+                ("503 Service Unavailable", TEXT, "offline\n".to_string())
+            }
+        },
+        ("GET", "/stats") => match serde_json::to_string(&ctx.stats()) {
+            Ok(stats) => ("200 OK", JSON, stats),
+            Err(why) => ("500 Internal Server Error", TEXT, format!("{}\n", why)),
+        },
+        ("GET", "/config") => ("200 OK", JSON, admin.config.clone()),
+        ("GET", "/events") => match admin.journal {
+            Some(ref path) => events(path, query),
+            None => ("404 Not Found", TEXT, "no journal, see VLive::journal\n".to_string()),
+        },
+        ("POST", "/pause") => {
+            ctx.pause();
+            ("200 OK", TEXT, "paused\n".to_string())
+        },
+        ("DELETE", "/pause") => {
+            ctx.resume();
+            ("200 OK", TEXT, "resumed\n".to_string())
+        },
+        (_, "/healthz") | (_, "/stats") | (_, "/config") | (_, "/events") | (_, "/pause") =>
+            ("405 Method Not Allowed", TEXT, "method not allowed\n".to_string()),
+        _ => ("404 Not Found", TEXT, "not found\n".to_string()),
+    }
+}
+
+/// Events in the journal at `path` after `?since=`, only from the channel
+/// `?channel=` if it's given
+fn events(path: &Path, query: &str) -> (&'static str, &'static str, String) {
+    let since = match param(query, "since").map(str::parse) {
+        None => 0,
+        Some(Ok(since)) => since,
+        Some(Err(_)) => return ("400 Bad Request", TEXT, "since must be an event id\n".to_string()),
+    };
+    let channel = param(query, "channel");
+
+    let events = match journal::replay(path, since) {
+        Ok(events) => events,
+        Err(why) => return ("500 Internal Server Error", TEXT, format!("{}\n", why)),
+    };
+    let events: Vec<_> = events.into_iter()
+        .filter(|event| channel.is_none_or(|channel| event.video.channel_code() == channel))
+        .collect();
+    match serde_json::to_string(&events) {
+        Ok(events) => ("200 OK", JSON, events),
+        Err(why) => ("500 Internal Server Error", TEXT, format!("{}\n", why)),
+    }
+}
+
+/// Value of `key` in a query string like `since=4&channel=FE619`
+fn param<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|&(name, _)| name == key)
+        .map(|(_, value)| value)
+}
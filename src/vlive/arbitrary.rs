@@ -0,0 +1,199 @@
+//! `Arbitrary` for the models, for property tests and fuzzers
+//!
+//! Needs the `test-util` feature. Generated videos look like the ones VLive
+//! has: `video_id` matches `video_seq`, `video_type` matches `video_stage`,
+//! channel codes are short and uppercase, and titles mix Korean and English.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use proptest::collection::hash_map;
+use proptest::option;
+use proptest::prelude::*;
+
+use super::lifecycle::Transition;
+use super::{ChannelType, Event, Feed, Platform, VLiveChannel, VLiveEvent, VLiveVideo, VideoStage, VideoType};
+
+/// Titles as they show up on VLive
+const TITLE: &str = "[A-Za-z0-9가-힣 \\[\\]()!?.+'-]{1,60}";
+/// Channel codes, like `FE619`
+const CODE: &str = "[0-9A-F]{4,6}";
+
+/// Some time between 1970 and 2096
+fn time() -> impl Strategy<Value = SystemTime> {
+    (0u64..4_000_000_000).prop_map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+impl Arbitrary for VideoType {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![Just(VideoType::VOD), Just(VideoType::LIVE)].boxed()
+    }
+}
+
+impl Arbitrary for VideoStage {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            Just(VideoStage::UPCOMING),
+            Just(VideoStage::LIVE),
+            Just(VideoStage::ENDED),
+            Just(VideoStage::VOD),
+        ].boxed()
+    }
+}
+
+impl Arbitrary for Platform {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            Just(Platform::VLIVE),
+            Just(Platform::WEVERSE),
+            Just(Platform::YOUTUBE),
+            Just(Platform::NAVERTV),
+        ].boxed()
+    }
+}
+
+impl Arbitrary for ChannelType {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![Just(ChannelType::BASIC), Just(ChannelType::PLUS)].boxed()
+    }
+}
+
+impl Arbitrary for Feed {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            Just(Feed::Recent),
+            CODE.prop_map(Feed::Channel),
+            Just(Feed::Directory),
+            "[a-z]{2,12}".prop_map(Feed::Weverse),
+            "UC[0-9A-Za-z_-]{22}".prop_map(Feed::YouTube),
+            "[a-z]{2,12}".prop_map(Feed::NaverTv),
+        ].boxed()
+    }
+}
+
+impl Arbitrary for VLiveChannel {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (CODE, 1u64..5000, "[A-Za-z0-9가-힣 ]{1,20}", any::<ChannelType>())
+            .prop_map(|(code, channel_seq, name, channel_type)| {
+                //Channel+ channels have a + on their code and name
+                let plus = if channel_type == ChannelType::PLUS { "+" } else { "" };
+                VLiveChannel {
+                    channel_id: format!("/channels/{}{}", code, plus),
+                    channel_seq,
+                    channel_name: format!("{}{}", name, plus),
+                    channel_type,
+                }
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for VLiveVideo {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let titles = hash_map(prop_oneof![Just("en".to_string()), Just("ko".to_string()), Just("ja".to_string())], TITLE, 0..3);
+        let thumbnail = option::of("[0-9]{8}_[0-9]{1,3}".prop_map(|name| format!("https://v-phinf.pstatic.net/{}/thumb.jpg", name)));
+        (1u64..5_000_000, TITLE, titles, any::<VideoStage>(), any::<bool>(), thumbnail, any::<VLiveChannel>(), any::<Platform>())
+            .prop_map(|(seq, title, titles, stage, paid, thumbnail, channel, platform)| VLiveVideo {
+                video_id: format!("/video/{}", seq),
+                video_seq: seq,
+                video_title: title,
+                video_titles: titles,
+                video_type: if stage == VideoStage::VOD { VideoType::VOD } else { VideoType::LIVE },
+                video_stage: stage,
+                video_paid: paid,
+                video_thumbnail: thumbnail,
+                channel_id: channel.channel_id,
+                channel_seq: channel.channel_seq,
+                channel_name: channel.channel_name,
+                channel_type: channel.channel_type,
+                platform,
+                video_mirrors: Vec::new(),
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Event {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (1u64..1_000_000, 0u64..1_000_000, time(), any::<VLiveVideo>())
+            .prop_map(|(id, poll_id, detected_at, video)| Event { id, poll_id, detected_at, video })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Transition {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (any::<VLiveVideo>(), any::<VideoStage>(), time(), 0u64..86_400)
+            .prop_map(|(video, from, since, took)| Transition {
+                from,
+                to: video.video_stage,
+                since,
+                at: since + Duration::from_secs(took),
+                video,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for VLiveEvent {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            any::<Event>().prop_map(VLiveEvent::New),
+            (any::<Feed>(), 1u64..5_000_000, 1u64..1000)
+                .prop_map(|(feed, start, len)| VLiveEvent::PossibleGap { feed, missed_range: start..start + len }),
+            any::<Transition>().prop_map(VLiveEvent::Transition),
+            (any::<VLiveVideo>(), option::of(any::<VLiveVideo>()))
+                .prop_map(|(mut live, vod)| {
+                    live.video_type = VideoType::LIVE;
+                    live.video_stage = VideoStage::ENDED;
+                    let vod = vod.map(|vod| VLiveVideo {
+                        video_type: VideoType::VOD,
+                        video_stage: VideoStage::VOD,
+                        ..vod
+                    });
+                    VLiveEvent::LiveEnded { live, vod }
+                }),
+            any::<VLiveVideo>().prop_map(|video| VLiveEvent::VodPublished(VLiveVideo {
+                video_type: VideoType::VOD,
+                video_stage: VideoStage::VOD,
+                ..video
+            })),
+            (CODE, 1u64..100, 1u64..100_000)
+                .prop_map(|(code, step, milestones)| VLiveEvent::FollowerMilestone {
+                    feed: Feed::Channel(code),
+                    followers: step * milestones + step / 2,
+                    milestone: step * milestones,
+                }),
+            any::<VLiveChannel>().prop_map(VLiveEvent::NewChannelCreated),
+        ].boxed()
+    }
+}
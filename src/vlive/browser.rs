@@ -0,0 +1,257 @@
+//! Watching feeds from a browser, through `fetch`
+//!
+//! Browsers have no threads to block and no clock `std` can read, so the
+//! threaded `VLive` listener and the `Poller`, which keeps time with
+//! `Instant`, aren't built for `wasm32`. What browsers share with native
+//! programs is the parsing: a `Watcher` fetches its feeds with the browser's
+//! own `fetch`, runs them through the same `Parser` and `Source`s, and hands
+//! out the videos it hasn't seen before.
+//!
+//! ```js
+//! import init, { Watcher } from "./vlive_notifs.js";
+//!
+//! await init();
+//! const watcher = new Watcher();
+//! watcher.channel("FE619");
+//! watcher.run(video => console.log(video.video_title), 10000);
+//! ```
+//!
+//! VLive doesn't send CORS headers, so this works from browser extensions
+//! with permissions for the sites they poll, not from ordinary web pages.
+
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::rc::Rc;
+use std::time::Duration;
+
+use js_sys::{Function, Promise, Reflect, JSON};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+use web_sys::{console, Response};
+
+use super::error::snippet;
+use super::transport::{HttpError, TransportError};
+use super::{Feed, Parser, Source, VLiveError, VLiveVideo};
+
+/// How many video ids a `Watcher` remembers
+const SEEN: usize = 512;
+
+/// A function of the global scope, which is `window` on pages and `self` in
+/// workers
+fn global(name: &str) -> Result<Function, JsValue> {
+    Reflect::get(&js_sys::global(), &JsValue::from_str(name))?
+        .dyn_into::<Function>()
+        .map_err(|_| JsValue::from_str(&format!("`{}` is not available here", name)))
+}
+
+/// What went wrong on the JavaScript side, as text
+fn js_error(value: JsValue) -> TransportError {
+    value.as_string().unwrap_or_else(|| format!("{:?}", value)).into()
+}
+
+/// Fetch `url` with the browser's `fetch`
+///
+/// The asynchronous counterpart of a `Transport`. Error statuses become
+/// `HttpError`s, like with the built-in transports
+pub async fn fetch(url: &str) -> Result<String, TransportError> {
+    let promise = global("fetch").and_then(|fetch| fetch.call1(&JsValue::NULL, &JsValue::from_str(url)))
+        .map_err(js_error)?;
+    let response: Response = JsFuture::from(Promise::from(promise)).await
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(js_error)?;
+    let body = JsFuture::from(response.text().map_err(js_error)?).await
+        .map_err(js_error)?
+        .as_string()
+        .unwrap_or_default();
+    if !response.ok() {
+        return Err(Box::new(HttpError {
+            url: url.to_string(),
+            status: response.status(),
+            body: snippet(&body),
+        }));
+    }
+    Ok(body)
+}
+
+/// Wait for `duration` with `setTimeout`, without blocking the browser
+pub async fn sleep(duration: Duration) {
+    let promise = Promise::new(&mut |resolve, _| {
+        let waited = global("setTimeout").and_then(|set_timeout| {
+            set_timeout.call2(&JsValue::NULL, &resolve, &JsValue::from_f64(duration.as_millis() as f64))
+        });
+        //Better to poll too often than to never wake up again
+        if waited.is_err() {
+            let _ = resolve.call0(&JsValue::NULL);
+        }
+    });
+    let _ = JsFuture::from(promise).await;
+}
+
+/// Polls feeds from JavaScript, see the module docs
+#[wasm_bindgen]
+pub struct Watcher {
+    state: Rc<RefCell<State>>,
+}
+
+struct State {
+    feeds: Vec<Feed>,
+    parser: Parser,
+    /// Ids of the videos handed out, and the order they were, oldest first
+    seen: HashSet<String>,
+    order: VecDeque<String>,
+    /// Cleared by `Watcher::stop`
+    running: bool,
+}
+
+impl State {
+    /// Remember `video_id`, returns `true` if it wasn't seen before
+    fn remember(&mut self, video_id: &str) -> bool {
+        if !self.seen.insert(video_id.to_string()) {
+            return false;
+        }
+        self.order.push_back(video_id.to_string());
+        while self.order.len() > SEEN {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+#[wasm_bindgen]
+impl Watcher {
+    /// New watcher without any feeds
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Watcher {
+        Watcher {
+            state: Rc::new(RefCell::new(State {
+                feeds: Vec::new(),
+                parser: Parser::default(),
+                seen: HashSet::new(),
+                order: VecDeque::new(),
+                running: false,
+            })),
+        }
+    }
+
+    /// Watch the recent videos page, see `Feed::Recent`
+    pub fn recent(&self) {
+        self.state.borrow_mut().feeds.push(Feed::Recent);
+    }
+
+    /// Watch a channel by its code, see `Feed::Channel`
+    pub fn channel(&self, code: &str) {
+        self.state.borrow_mut().feeds.push(Feed::Channel(code.to_string()));
+    }
+
+    /// Watch a Weverse community, see `Feed::Weverse`
+    pub fn weverse(&self, community: &str) {
+        self.state.borrow_mut().feeds.push(Feed::Weverse(community.to_string()));
+    }
+
+    /// Watch a YouTube channel, see `Feed::YouTube`
+    pub fn youtube(&self, channel: &str) {
+        self.state.borrow_mut().feeds.push(Feed::YouTube(channel.to_string()));
+    }
+
+    /// Watch a Naver TV channel, see `Feed::NaverTv`
+    #[wasm_bindgen(js_name = naverTv)]
+    pub fn naver_tv(&self, channel: &str) {
+        self.state.borrow_mut().feeds.push(Feed::NaverTv(channel.to_string()));
+    }
+
+    /// Fetch every feed once
+    ///
+    /// Resolves to an array of the videos not seen before, as plain objects
+    /// with the fields of `VLiveVideo`
+    pub fn poll(&self) -> Promise {
+        let state = self.state.clone();
+        future_to_promise(async move {
+            let videos = poll(&state).await.map_err(|why| JsValue::from_str(&why.to_string()))?;
+            let array = js_sys::Array::new();
+            for video in videos {
+                array.push(&to_js(&video)?);
+            }
+            Ok(array.into())
+        })
+    }
+
+    /// Poll every `interval` milliseconds and call `callback` with each new
+    /// video, until `stop` is called
+    ///
+    /// Errors are logged to the console and polling carries on, like the
+    /// native listener does
+    pub fn run(&self, callback: Function, interval: u32) -> Promise {
+        let state = self.state.clone();
+        state.borrow_mut().running = true;
+        future_to_promise(async move {
+            while state.borrow().running {
+                match poll(&state).await {
+                    Ok(videos) => for video in videos {
+                        callback.call1(&JsValue::NULL, &to_js(&video)?)?;
+                    },
+                    Err(why) => console::error_1(&JsValue::from_str(&format!("VLive Error: {}", why))),
+                }
+                sleep(Duration::from_millis(interval.into())).await;
+            }
+            Ok(JsValue::UNDEFINED)
+        })
+    }
+
+    /// Stop `run` after the poll it's on
+    pub fn stop(&self) {
+        self.state.borrow_mut().running = false;
+    }
+}
+
+impl Default for Watcher {
+    fn default() -> Self {
+        Watcher::new()
+    }
+}
+
+/// Fetch and parse every feed of `state`, returns the videos not seen before
+///
+/// Like `fetch_recent`, videos that can't be parsed are skipped, and errors
+/// only come back if no feed had anything new
+async fn poll(state: &Rc<RefCell<State>>) -> Result<Vec<VLiveVideo>, VLiveError> {
+    let feeds = state.borrow().feeds.clone();
+    let mut videos = Vec::new();
+    let mut error = None;
+    for feed in feeds {
+        //Nothing may be borrowed while waiting, `stop` could be called meanwhile
+        let body = match fetch(&feed.url()).await {
+            Ok(body) => body,
+            Err(why) => {
+                error.get_or_insert(VLiveError::Transport(why));
+                continue;
+            },
+        };
+        let mut state = state.borrow_mut();
+        let page = match feed.source() {
+            Some(source) => source.parse(&body),
+            None => state.parser.parse_page(&body),
+        };
+        match page {
+            Ok(page) => for video in page.into_iter().flatten() {
+                if state.remember(&video.video_id) {
+                    videos.push(video);
+                }
+            },
+            Err(why) => { error.get_or_insert(VLiveError::ServiceUnavailable(why)); },
+        }
+    }
+    match error {
+        Some(why) if videos.is_empty() => Err(why),
+        _ => Ok(videos),
+    }
+}
+
+/// `video` as a plain JavaScript object
+fn to_js(video: &VLiveVideo) -> Result<JsValue, JsValue> {
+    let json = serde_json::to_string(video).map_err(|why| JsValue::from_str(&why.to_string()))?;
+    JSON::parse(&json)
+}
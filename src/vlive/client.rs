@@ -0,0 +1,1431 @@
+//! The listener, polling on one thread and calling back on another
+
+use std::{process, thread, time};
+use std::any::Any;
+use std::collections::VecDeque;
+use std::net::TcpListener;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+#[cfg(feature = "async-tokio")]
+use super::{AsyncVLiveCallback, Sink, TokioCallback, TokioSink};
+use super::clock::{self, Clock};
+use super::admin::{self, Admin};
+use super::ctx::{Ctx, Cursor};
+use super::delivery::{DeadLetter, Outbox};
+use super::digest::Digest;
+use super::enrich::{self, Enrich};
+use super::model::recent_url;
+use super::poller::{self, ParsePolicy};
+use super::queue::{Queue, QueuePolicy};
+#[cfg(unix)]
+use super::systemd::Notify;
+use super::transport::{self, Transport, TransportError};
+use super::{ChannelFilter, Correlate, Dedup, Event, EventKinds, Feed, Flow, Journal, Lifecycle, Metrics, Middleware, Parser, PollerState, Poller,
+    QuietHours, Schedule, VLiveCallback, VLiveError, VLiveEvent, VLiveVideo};
+
+/// Get the message out of a caught panic
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Fetch the `page_size` most recent videos once
+///
+/// For programs that just want a snapshot, like CLIs and cron jobs. No
+/// threads are started. Videos that can't be parsed are skipped, unless
+/// none of them can be
+///
+/// ```rust,ignore
+/// for video in vlive::fetch_recent(20)? {
+///     println!("{}", video.video_title);
+/// }
+/// ```
+pub fn fetch_recent(page_size: u32) -> Result<Vec<VLiveVideo>, VLiveError> {
+    let body = transport::Compressed::default().get(&recent_url(page_size))
+        .map_err(VLiveError::Transport)?;
+    let page = Parser::default().parse_page(&body)
+        .map_err(VLiveError::ServiceUnavailable)?;
+
+    let mut videos = Vec::new();
+    let mut error = None;
+    for video in page {
+        match video {
+            Ok(video) => videos.push(video),
+            Err(why) => { error.get_or_insert(why); },
+        }
+    }
+    match error {
+        Some(why) if videos.is_empty() => Err(VLiveError::Parse(why)),
+        _ => Ok(videos),
+    }
+}
+
+/// How the listener's threads are started
+#[derive(Debug, Clone)]
+struct Threads {
+    /// Threads are called `{name}-poller` and `{name}-dispatcher`, and
+    /// `{name}-admin` with `VLive::admin`, `{name}-health-check` with
+    /// `VLive::health_check`
+    name: String,
+    stack_size: Option<usize>,
+    abort_on_panic: bool,
+}
+
+impl Threads {
+    fn spawn<F>(&self, role: &str, work: F) where F: FnOnce() + Send + 'static {
+        let mut builder = thread::Builder::new().name(format!("{}-{}", self.name, role));
+        if let Some(size) = self.stack_size {
+            builder = builder.stack_size(size);
+        }
+        let abort = self.abort_on_panic;
+        builder
+            .spawn(move || {
+                if !abort {
+                    return work();
+                }
+                //The panic hook has already printed what happened
+                if panic::catch_unwind(AssertUnwindSafe(work)).is_err() {
+                    eprintln!("VLive Error: {} panicked, aborting", thread::current().name().unwrap_or("VLive thread"));
+                    process::abort();
+                }
+            })
+            .expect("failed to spawn a VLive thread");
+    }
+}
+
+/// Longest the poller sleeps in one go, so it notices being stopped or
+/// asked to poll right away
+const WAKE_EVERY: time::Duration = time::Duration::from_millis(250);
+
+pub struct VLiveStopper {
+    ctx: Ctx,
+}
+
+impl VLiveStopper {
+    pub fn stop(self) {
+        self.ctx.stop();
+    }
+
+    /// Seq of the newest video seen so far, 0 before the first poll
+    pub fn last_seen(&self) -> u64 {
+        self.ctx.cursor.lock().unwrap().last_seen
+    }
+
+    /// Move every VLive feed's cursor to `seq`
+    ///
+    /// Before the next poll, the listener forgets what it has announced
+    /// and carries on as if `seq` was the last video it saw. Rewind to
+    /// the seq of an older video on the page to announce everything
+    /// after it again, for example to test a new sink, or skip ahead to
+    /// the newest seq to ignore a backlog. A `seq` of 0 announces the
+    /// whole page, like on startup.
+    ///
+    /// Feeds with a `Feed::source` number their videos their own way, so
+    /// their cursors are left where they are
+    pub fn set_last_seen(&self, seq: u64) {
+        let mut cursor = self.ctx.cursor.lock().unwrap();
+        cursor.last_seen = seq;
+        cursor.seek = Some(seq);
+    }
+
+    /// Everything the poller remembers, to be saved for `VLive::restore`
+    ///
+    /// ```rust,ignore
+    /// let state = serde_json::to_string(&stopper.snapshot())?;
+    /// ```
+    pub fn snapshot(&self) -> PollerState {
+        self.ctx.state()
+    }
+
+    /// Handle on the listener, the same one callbacks get
+    pub fn ctx(&self) -> Ctx {
+        self.ctx.clone()
+    }
+
+    /// Events the callback failed to deliver for good, oldest first
+    ///
+    /// See `VLive::retry`. Letters stay here until they are retried with
+    /// `retry_dead_letters`
+    pub fn dead_letters(&self) -> Vec<DeadLetter> {
+        self.ctx.dead_letters.lock().unwrap().letters.clone()
+    }
+
+    /// Try to deliver every dead letter again
+    ///
+    /// Call this once whatever the callback delivers to is back up. Each
+    /// letter gets a fresh set of attempts, and lands back in
+    /// `dead_letters` if those fail too
+    pub fn retry_dead_letters(&self) {
+        self.ctx.dead_letters.lock().unwrap().retry = true;
+        self.ctx.queue.wake();
+    }
+}
+
+/// Run a callback, measuring how long it took
+fn timed<F>(call: F) -> (Flow, time::Duration) where F: FnOnce() -> Flow {
+    let started = time::Instant::now();
+    let flow = call();
+    (flow, started.elapsed())
+}
+
+/// Hand a new event to the callback, or to the digest being collected
+/// at `now`
+fn deliver<CB>(callback: &mut CB, ctx: &Ctx, outbox: &mut Outbox, digest: &mut Option<Digest>, event: Event, now: time::Instant) -> Flow
+    where CB: VLiveCallback
+{
+    match *digest {
+        Some(ref mut digest) => match digest.push(event.video, now) {
+            Some(videos) => callback.on_digest(ctx, videos),
+            None => Flow::Continue,
+        },
+        None => outbox.send(callback, ctx, event, 0),
+    }
+}
+
+/// GET `url`, giving up after a few seconds
+fn ping(url: &str) -> Result<(), Box<ureq::Error>> {
+    ureq::get(url).timeout(time::Duration::from_secs(5)).call()?;
+    Ok(())
+}
+
+pub struct VLive<CB> where CB: VLiveCallback {
+    /// Up on new video, this callback is called
+    callback: CB,
+    /// How long to wait between refreshes
+    wait: time::Duration,
+    /// Feeds to poll with their own wait, `Feed::Recent` with `wait` if empty
+    feeds: Vec<(Feed, Schedule)>,
+    /// How many feeds can be fetched at the same time
+    concurrency: usize,
+    /// Video codes we've already announced
+    dedup: Dedup,
+    /// Live streams we follow until they're VODs
+    lifecycle: Option<Lifecycle>,
+    /// Follower count steps worth announcing
+    milestones: Option<u64>,
+    /// Kinds of videos we announce
+    kinds: EventKinds,
+    /// Channels to announce videos from
+    channel_filter: ChannelFilter,
+    /// How many videos can wait for the callback before `queue_policy` kicks in
+    queue_size: usize,
+    /// What to do with new videos when the queue is full
+    queue_policy: QueuePolicy,
+    /// Stages every new video goes through before reaching `callback`
+    middleware: Vec<Box<dyn Middleware>>,
+    /// Where delivered events are recorded
+    journal: Option<Journal>,
+    /// Events the callback failed to deliver, waiting to be retried
+    outbox: Outbox,
+    /// How long the callback may take before it's reported as slow
+    callback_deadline: Option<time::Duration>,
+    /// New videos are collected here instead of being delivered one by one
+    digest: Option<Digest>,
+    /// New videos wait here for the same broadcast on other platforms
+    correlate: Option<Correlate>,
+    /// How long new videos have to stay up before they're announced
+    settle_delay: Option<time::Duration>,
+    /// Polls in a row a feed can fail to parse before it's reported
+    layout_changed_after: u32,
+    /// What to do with a feed once it's reported
+    parse_policy: ParsePolicy,
+    /// When new videos are held back
+    quiet_hours: Option<QuietHours>,
+    /// Where to pick up from, instead of starting fresh
+    restore: Option<PollerState>,
+    /// What we fetch pages with, possibly shared with other listeners
+    transport: Arc<dyn Transport>,
+    /// How videos are parsed out of fetched pages
+    parser: Parser,
+    /// Where the poller gets the time from
+    clock: Arc<dyn Clock>,
+    /// Languages to look up titles in
+    languages: Vec<String>,
+    /// Adds to new videos on the poller
+    enrich: Option<Arc<dyn enrich::Enricher>>,
+    /// URL to ping after successful polls, and how often at most
+    health_check: Option<(String, time::Duration)>,
+    /// Where to serve the admin endpoints, see `VLive::admin`
+    admin: Option<TcpListener>,
+    /// Whether to notify systemd, see `VLive::systemd`
+    #[cfg(unix)]
+    systemd: bool,
+    /// Where polls, errors and events are counted
+    metrics: Option<Arc<dyn Metrics>>,
+    /// How the poller and dispatcher threads are started
+    threads: Threads,
+    /// Gets every fetched page before it's parsed
+    raw_response: Option<transport::Hook>,
+    /// Our channel we use to control the thread with
+    tx: Sender<&'static str>, rx: Receiver<&'static str>
+}
+
+impl<CB> VLive<CB> where CB: VLiveCallback {
+
+    /// New listener
+    ///
+    /// `callback` is your implementation of `VLiveCallback`
+    /// `wait` is the amount of time to wait between polls.
+    /// 2 to 10 seconds is recommended value for `wait`
+    pub fn new(callback: CB, wait: time::Duration) -> Self {
+        let (tx, rx) = channel();
+
+        VLive {
+            callback,
+            wait,
+            feeds: Vec::new(),
+            concurrency: 1,
+            dedup: Dedup::new(512, time::Duration::from_secs(24 * 60 * 60)),
+            lifecycle: None,
+            milestones: None,
+            kinds: EventKinds::ALL,
+            channel_filter: ChannelFilter::default(),
+            queue_size: 64,
+            queue_policy: QueuePolicy::Block,
+            middleware: Vec::new(),
+            journal: None,
+            outbox: Outbox::new(5, time::Duration::from_secs(1)),
+            callback_deadline: None,
+            digest: None,
+            correlate: None,
+            settle_delay: None,
+            layout_changed_after: 3,
+            parse_policy: ParsePolicy::KeepGoing,
+            quiet_hours: None,
+            restore: None,
+            transport: Arc::new(transport::Compressed::default()),
+            parser: Parser::default(),
+            clock: Arc::new(clock::SystemClock),
+            languages: Vec::new(),
+            enrich: None,
+            health_check: None,
+            admin: None,
+            #[cfg(unix)]
+            systemd: false,
+            metrics: None,
+            threads: Threads {
+                name: "vlive".to_string(),
+                stack_size: None,
+                abort_on_panic: false,
+            },
+            raw_response: None,
+            tx, rx
+        }
+    }
+
+    /// Change how long to wait between polls
+    pub fn wait(mut self, wait: time::Duration) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    /// Poll a feed every `wait`
+    ///
+    /// By default the listener only polls `Feed::Recent` every `wait` given
+    /// to `new`. Once feeds are added, only those are polled, each on its
+    /// own schedule, so you can check your favourite channels every few
+    /// seconds and everything else every few minutes. `wait` can also be
+    /// a `Schedule`, to poll more often at busy times of the day
+    pub fn feed<S>(mut self, feed: Feed, wait: S) -> Self where S: Into<Schedule> {
+        self.feeds.push((feed, wait.into()));
+        self
+    }
+
+    /// Poll a channel every `wait`
+    ///
+    /// Same as `feed(Feed::Channel(code), wait)`
+    ///
+    /// ```rust,ignore
+    /// VLive::new(Handler, Duration::from_secs(5))
+    ///     .watch("EBDF", Duration::from_secs(5))
+    ///     .watch("F001E5", Duration::from_secs(300))
+    ///     .run();
+    /// ```
+    pub fn watch<S>(self, code: &str, wait: S) -> Self where S: Into<Schedule> {
+        self.feed(Feed::Channel(code.to_string()), wait)
+    }
+
+    /// Poll a Weverse community every `wait`, for when a channel has
+    /// moved there
+    ///
+    /// Same as `feed(Feed::Weverse(community), wait)`. Weverse needs a
+    /// signed in session, see `source::Weverse`
+    pub fn weverse<S>(self, community: &str, wait: S) -> Self where S: Into<Schedule> {
+        self.feed(Feed::Weverse(community.to_string()), wait)
+    }
+
+    /// Poll a YouTube channel every `wait`, since most artists upload
+    /// there too
+    ///
+    /// Same as `feed(Feed::YouTube(channel), wait)`
+    pub fn youtube<S>(self, channel: &str, wait: S) -> Self where S: Into<Schedule> {
+        self.feed(Feed::YouTube(channel.to_string()), wait)
+    }
+
+    /// Poll a Naver TV channel every `wait`, for shows that are mirrored
+    /// or only shown there
+    ///
+    /// Same as `feed(Feed::NaverTv(channel), wait)`
+    pub fn naver_tv<S>(self, channel: &str, wait: S) -> Self where S: Into<Schedule> {
+        self.feed(Feed::NaverTv(channel.to_string()), wait)
+    }
+
+    /// Announce new channels, checking the directory every `wait`
+    ///
+    /// Same as `feed(Feed::Directory, wait)`. Channels already in the
+    /// directory on the first poll are taken as known, every one after
+    /// that is a `VLiveEvent::NewChannelCreated`, so a bot can start
+    /// covering debuting artists right away
+    pub fn discover_channels<S>(self, wait: S) -> Self where S: Into<Schedule> {
+        self.feed(Feed::Directory, wait)
+    }
+
+    /// Configure the dispatch queue
+    ///
+    /// Callbacks are run on their own dispatcher thread, so a slow
+    /// callback doesn't delay the next poll. New videos wait in a queue of
+    /// `size` entries, and `policy` decides what happens when it's full.
+    /// Defaults to 64 entries with `QueuePolicy::Block`
+    pub fn queue(mut self, size: usize, policy: QueuePolicy) -> Self {
+        self.queue_size = size;
+        self.queue_policy = policy;
+        self
+    }
+
+    /// Fetch up to `workers` feeds at the same time
+    ///
+    /// When you watch dozens of channels, polling them one after another
+    /// adds up to a lot of latency. With more workers, feeds that are due
+    /// at the same time are fetched in parallel. Defaults to 1.
+    ///
+    /// To stay under a global rate limit no matter how many workers
+    /// there are, use a `transport::RateLimited` transport
+    pub fn concurrency(mut self, workers: usize) -> Self {
+        self.concurrency = workers.max(1);
+        self
+    }
+
+    /// Only announce some kinds of videos
+    ///
+    /// For example `EventKinds::LIVE_ONLY` for a bot that only cares
+    /// about live broadcasts. Other videos are skipped by the poller and
+    /// never reach the callback. Defaults to `EventKinds::ALL`
+    pub fn kinds(mut self, kinds: EventKinds) -> Self {
+        self.kinds = kinds;
+        self
+    }
+
+    /// Only announce videos from `channels`, by channel code like `FE619`
+    /// or seq like `363`
+    ///
+    /// For listening to the recent videos page without hearing about every
+    /// channel on VLive. Can be called again to allow more channels
+    pub fn allow_channels(mut self, channels: &[&str]) -> Self {
+        self.channel_filter = self.channel_filter.allow(channels);
+        self
+    }
+
+    /// Never announce videos from `channels`, by channel code or seq
+    ///
+    /// Wins over `allow_channels`
+    pub fn block_channels(mut self, channels: &[&str]) -> Self {
+        self.channel_filter = self.channel_filter.block(channels);
+        self
+    }
+
+    /// Only announce videos from the channels `filter` allows, for when
+    /// the lists come from a config file, see `ChannelFilter`
+    pub fn channel_filter(mut self, filter: ChannelFilter) -> Self {
+        self.channel_filter = filter;
+        self
+    }
+
+    /// Configure deduplication
+    ///
+    /// Every announced video code is remembered for `ttl`, up to
+    /// `capacity` codes, and never announced again while remembered.
+    /// This catches rebroadcasts and lives reappearing as VODs, which the
+    /// seq alone doesn't. A live stream's first appearance as a VOD is a
+    /// `VLiveEvent::VodPublished` instead. Defaults to 512 codes for a
+    /// day, a `capacity` of 0 turns it off
+    pub fn dedup(mut self, capacity: usize, ttl: time::Duration) -> Self {
+        self.dedup = Dedup::new(capacity, ttl);
+        self
+    }
+
+    /// Follow live streams until they're VODs
+    ///
+    /// Announced live streams, and reservations for upcoming ones, are
+    /// looked at again on every poll as they go from
+    /// `VideoStage::UPCOMING` to `LIVE`, `ENDED` and `VOD`. Every step is
+    /// passed to `VLiveCallback::on_event` as a `VLiveEvent::Transition`,
+    /// instead of the stream being announced again. Once its recording
+    /// shows up, under the same seq or a new one, that's a
+    /// `VLiveEvent::LiveEnded`. Only streams still on one of the polled
+    /// pages can be followed, and those that haven't been seen for `ttl`
+    /// are given up on. Off by default
+    pub fn lifecycle(mut self, ttl: time::Duration) -> Self {
+        self.lifecycle = Some(Lifecycle::new(ttl));
+        self
+    }
+
+    /// Announce channels passing every `step` followers
+    ///
+    /// Channel feeds are sampled every time they are polled, and whenever
+    /// a follower count passes a multiple of `step`, like every 100 000,
+    /// that's a `VLiveEvent::FollowerMilestone`. Only channels added with
+    /// `watch` or `feed` are counted. Off by default
+    pub fn follower_milestones(mut self, step: u64) -> Self {
+        self.milestones = Some(step);
+        self
+    }
+
+    /// Configure retries for events that failed to deliver
+    ///
+    /// When `VLiveCallback::deliver` fails, the event is tried again after
+    /// `backoff`, doubling the wait every time, up to `max_attempts`
+    /// attempts in total. Other events keep being delivered meanwhile.
+    /// Defaults to 5 attempts starting with a second, a `max_attempts` of
+    /// 1 turns retrying off
+    pub fn retry(mut self, max_attempts: u32, backoff: time::Duration) -> Self {
+        self.outbox = Outbox::new(max_attempts, backoff);
+        self
+    }
+
+    /// Deliver new videos in digests instead of one by one
+    ///
+    /// New videos are collected and passed to `VLiveCallback::on_digest`
+    /// all at once, `interval` after the first one was found or as soon
+    /// as there are `max` of them. Good for mailing lists and the like,
+    /// where a summary every hour beats a ping for every upload. Digests
+    /// aren't retried, `deliver` is skipped for them
+    ///
+    /// ```rust,ignore
+    /// VLive::new(Mailer, Duration::from_secs(60))
+    ///     .digest(Duration::from_secs(60 * 60), 50)
+    ///     .run();
+    /// ```
+    pub fn digest(mut self, interval: time::Duration, max: usize) -> Self {
+        self.digest = Some(Digest::new(interval, max));
+        self
+    }
+
+    /// Collapse the same broadcast found on several platforms into one
+    /// video
+    ///
+    /// Every new video is held back for the window of `correlate`, and
+    /// the same broadcast turning up on another feed in the meantime is
+    /// added to its `video_mirrors` instead of being delivered on its
+    /// own. Only useful with feeds on more than one platform
+    ///
+    /// ```rust,ignore
+    /// VLive::new(Notifier, Duration::from_secs(5))
+    ///     .watch("FE619", Duration::from_secs(5))
+    ///     .weverse("bts", Duration::from_secs(10))
+    ///     .correlate(Correlate::new(Duration::from_secs(60)))
+    ///     .run();
+    /// ```
+    pub fn correlate(mut self, correlate: Correlate) -> Self {
+        self.correlate = Some(correlate);
+        self
+    }
+
+    /// Hold new videos for `delay`, and drop those deleted meanwhile
+    ///
+    /// Channels sometimes publish, delete and republish within a minute.
+    /// A held video is announced on the first poll of its feed after
+    /// `delay`, if it's still on the page, so something like 30 seconds
+    /// keeps the mistakes out. Videos that vanish can be announced again
+    /// if they come back. Off by default
+    pub fn settle_delay(mut self, delay: time::Duration) -> Self {
+        self.settle_delay = Some(delay);
+        self
+    }
+
+    /// Hold back new videos during `quiet_hours`
+    ///
+    /// Videos found during quiet hours are delivered all at once when
+    /// they end, so phone and desktop notifications don't wake anyone up
+    /// for a VOD at 3 AM. Use `QuietHours::exempt_live` to still get live
+    /// streams right away. Videos still held back when the listener stops
+    /// are passed to `VLiveCallback::on_dead_letter` instead
+    ///
+    /// ```rust,ignore
+    /// let night = QuietHours::new(NaiveTime::from_hms_opt(1, 0, 0)?, NaiveTime::from_hms_opt(8, 0, 0)?);
+    /// VLive::new(Notifier, Duration::from_secs(5))
+    ///     .quiet_hours(night.exempt_live(true))
+    ///     .run();
+    /// ```
+    pub fn quiet_hours(mut self, quiet_hours: QuietHours) -> Self {
+        self.quiet_hours = Some(quiet_hours);
+        self
+    }
+
+    /// Report callbacks that take longer than `deadline`
+    ///
+    /// Callbacks run one at a time, so a handler stuck on a slow webhook
+    /// makes every notification after it late. When handling an event
+    /// takes longer than `deadline`, `VLiveError::SlowCallback` is passed
+    /// to `on_error` with the time it actually took. The callback is not
+    /// interrupted, see `abandon_after` for async callbacks
+    pub fn callback_deadline(mut self, deadline: time::Duration) -> Self {
+        self.callback_deadline = Some(deadline);
+        self
+    }
+
+    /// Pick up where a previous listener stopped
+    ///
+    /// `state` is what `VLiveStopper::snapshot` returned. Feeds carry on
+    /// from their last seen video, and videos that were already announced
+    /// stay announced. Feeds that aren't in `state` start fresh, and
+    /// feeds in `state` that aren't polled anymore are ignored
+    pub fn restore(mut self, state: PollerState) -> Self {
+        self.restore = Some(state);
+        self
+    }
+
+    /// Fetch pages through `transport`
+    ///
+    /// Pass the same `Arc` to every listener in your program to share
+    /// connections, cookies and rate limits between them
+    ///
+    /// ```rust,ignore
+    /// let transport = Arc::new(RateLimited::new(Compressed::default(), Duration::from_secs(1)));
+    /// let recent = VLive::new(Handler, Duration::from_secs(5)).transport(transport.clone());
+    /// let bias = VLive::new(Handler, Duration::from_secs(5)).transport(transport);
+    /// ```
+    pub fn transport<T>(mut self, transport: Arc<T>) -> Self where T: Transport {
+        self.transport = transport;
+        self
+    }
+
+    /// Call `hook` with the URL and body of every fetched page
+    ///
+    /// Runs on the poller before the page is parsed, whichever transport
+    /// is used. Handy to archive responses as test fixtures, or to find
+    /// out what a page that didn't parse looked like
+    ///
+    /// ```rust,ignore
+    /// VLive::new(Handler, Duration::from_secs(5))
+    ///     .on_raw_response(|url, body| archive.save(url, body))
+    ///     .run();
+    /// ```
+    pub fn on_raw_response<F>(mut self, hook: F) -> Self where F: Fn(&str, &str) + Send + Sync + 'static {
+        self.raw_response = Some(Box::new(hook));
+        self
+    }
+
+    /// Decide what happens when a feed keeps failing to parse
+    ///
+    /// After `after` polls in a row without a single video parsed, the
+    /// feed reports `VLiveError::LayoutChanged`, and `policy` decides
+    /// whether polling carries on, backs off, stops or calls a hook. Pages
+    /// without any videos on them are `VLiveError::ServiceUnavailable`
+    /// instead and don't count, a channel can be new and empty. Defaults
+    /// to `ParsePolicy::KeepGoing` after 3 polls
+    ///
+    /// ```rust,ignore
+    /// VLive::new(Handler, Duration::from_secs(5))
+    ///     .layout_changed(5, ParsePolicy::Backoff { max: Duration::from_secs(10 * 60) })
+    ///     .run();
+    /// ```
+    pub fn layout_changed(mut self, after: u32, policy: ParsePolicy) -> Self {
+        self.layout_changed_after = after;
+        self.parse_policy = policy;
+        self
+    }
+
+    /// Parse pages with `parser`
+    ///
+    /// Defaults to `Parser::default()`, which tries every built-in
+    /// strategy. Useful to add your own `parser::Strategy` if VLive
+    /// changes its layout before this crate catches up
+    pub fn parser(mut self, parser: Parser) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// Look up titles in `languages` too
+    ///
+    /// Languages are codes like `"en"` or `"ja"`. Whenever a feed has
+    /// something new, it's fetched once more for every language, and the
+    /// translated titles end up in `VLiveVideo::video_titles`. Only
+    /// VLive's own feeds are translated, not Weverse, YouTube or Naver TV
+    ///
+    /// ```rust,ignore
+    /// VLive::new(Handler, Duration::from_secs(5))
+    ///     .languages(&["en", "ja"])
+    ///     .run();
+    /// ```
+    pub fn languages(mut self, languages: &[&str]) -> Self {
+        self.languages = languages.iter().map(|language| language.to_string()).collect();
+        self
+    }
+
+    /// Run new videos through `enrich` before they are dispatched
+    ///
+    /// `enrich` gets every new video from one poll at once, see
+    /// `Enrich::enrich_batch`. It runs on the poller, so keep it quick or
+    /// the next poll is late. With the `async-tokio` feature, enrichment
+    /// runs on the runtime this is called from, if any
+    ///
+    /// ```rust,ignore
+    /// VLive::new(Handler, Duration::from_secs(5))
+    ///     .enrich(Romanizer)
+    ///     .run();
+    /// ```
+    pub fn enrich<E>(mut self, enrich: E) -> Self where E: Enrich {
+        self.enrich = Some(Arc::new(enrich::Blocking {
+            enrich,
+            #[cfg(feature = "async-tokio")]
+            runtime: tokio::runtime::Handle::try_current().ok(),
+        }));
+        self
+    }
+
+    /// Tell the time with `clock`
+    ///
+    /// Defaults to `clock::SystemClock`. Polling, sleeping between polls,
+    /// `Event::detected_at`, digests, quiet hours and correlating all go
+    /// by this clock, use a `clock::ManualClock` to fast-forward through
+    /// them in tests. Retries and callback deadlines always use the real
+    /// time
+    pub fn clock<C>(mut self, clock: Arc<C>) -> Self where C: Clock {
+        self.clock = clock;
+        self
+    }
+
+    /// Ping `url` after successful polls, at most once every `every`
+    ///
+    /// A dead man's switch for services like healthchecks.io: as long as
+    /// pages keep being fetched and parsed, `url` keeps getting a GET.
+    /// When the poller gets stuck or every fetch fails, the pings stop
+    /// and the monitor raises the alarm, even if the process is still
+    /// alive. Pings are sent from a thread of their own, so a slow
+    /// monitor doesn't hold up polling. Failed pings are printed to stderr
+    ///
+    /// ```rust,ignore
+    /// VLive::new(Handler, Duration::from_secs(5))
+    ///     .health_check("https://hc-ping.com/your-uuid", Duration::from_secs(60))
+    ///     .run();
+    /// ```
+    pub fn health_check(mut self, url: &str, every: time::Duration) -> Self {
+        self.health_check = Some((url.to_string(), every));
+        self
+    }
+
+    /// Serve a small HTTP API for operators on `listener`
+    ///
+    /// For looking after the listener from outside the process, like
+    /// Kubernetes probes or a quick `curl`:
+    ///
+    /// - `GET /healthz` answers `200` while polls reach VLive or polling
+    ///   is paused, `503` otherwise
+    /// - `GET /stats` is `Ctx::stats` as JSON
+    /// - `GET /config` is how the listener was set up, as JSON
+    /// - `GET /events?since=<id>&channel=<code>` is a JSON array of the
+    ///   events in the `VLive::journal` after `since`, from the channel with
+    ///   `code` if it's given. Both are optional. For simple consumers to
+    ///   poll instead of being called back
+    /// - `POST /pause` pauses polling, `DELETE /pause` resumes it, same
+    ///   as `Ctx::pause` and `Ctx::resume`
+    ///
+    /// Requests are answered one at a time on a thread of their own, and
+    /// nothing is authenticated, so keep it on localhost or a private
+    /// network. The listener is closed once polling has stopped
+    ///
+    /// ```rust,ignore
+    /// VLive::new(Handler, Duration::from_secs(5))
+    ///     .admin(TcpListener::bind("127.0.0.1:9090")?)
+    ///     .run();
+    /// ```
+    pub fn admin(mut self, listener: TcpListener) -> Self {
+        self.admin = Some(listener);
+        self
+    }
+
+    /// Tell systemd when the listener is up, and feed its watchdog
+    ///
+    /// For running as a `Type=notify` service, see `vlive::systemd`. Sends
+    /// `READY=1` once polling starts and `STOPPING=1` when it stops. With
+    /// `WatchdogSec` set, successful polls send `WATCHDOG=1` at least twice
+    /// per interval, so systemd restarts the service if polling gets stuck
+    /// or every fetch fails. Does nothing when not started by systemd
+    ///
+    /// ```rust,ignore
+    /// VLive::new(Handler, Duration::from_secs(5))
+    ///     .systemd()
+    ///     .run();
+    /// ```
+    #[cfg(unix)]
+    pub fn systemd(mut self) -> Self {
+        self.systemd = true;
+        self
+    }
+
+    /// Report polls, errors and events to `metrics`
+    ///
+    /// Every fetch is reported with how long it took, and every error
+    /// and event right before it's passed to the callback. See
+    /// `metrics::Statsd` to send them to statsd or Datadog
+    pub fn metrics<M>(mut self, metrics: Arc<M>) -> Self where M: Metrics {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Name the listener's threads
+    ///
+    /// The poller and dispatcher threads are called `{name}-poller` and
+    /// `{name}-dispatcher`, as shown by `ps`, `top -H` and debuggers. Give
+    /// every listener its own name to tell them apart. Defaults to
+    /// `"vlive"`
+    pub fn thread_name(mut self, name: &str) -> Self {
+        self.threads.name = name.to_string();
+        self
+    }
+
+    /// Give the listener's threads stacks of `bytes`
+    ///
+    /// Defaults to Rust's default, which might not be enough for deeply
+    /// recursive callbacks
+    pub fn stack_size(mut self, bytes: usize) -> Self {
+        self.threads.stack_size = Some(bytes);
+        self
+    }
+
+    /// Abort the whole process if one of the listener's threads panics
+    ///
+    /// Polling survives panics, but a panicking callback takes the
+    /// dispatcher down and notifications stop while the process stays up.
+    /// Under a supervisor like systemd, crashing is better, as the
+    /// listener gets restarted. Off by default
+    pub fn abort_on_panic(mut self, abort: bool) -> Self {
+        self.threads.abort_on_panic = abort;
+        self
+    }
+
+    /// Add a middleware stage
+    ///
+    /// Stages are run in the order they're added, and can drop, change or
+    /// delay videos before they reach the callback. See `vlive::middleware`
+    /// for the built-in ones
+    ///
+    /// ```rust,ignore
+    /// VLive::new(Handler, Duration::from_secs(5))
+    ///     .middleware(middleware::filter(|video| video.channel_seq == 363))
+    ///     .middleware(middleware::RateLimit::new(Duration::from_secs(2)))
+    ///     .run();
+    /// ```
+    pub fn middleware<M>(mut self, middleware: M) -> Self where M: Middleware {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Record every delivered event in `journal`
+    ///
+    /// Events are written before they are passed to the callback, and
+    /// their ids continue from the last one in the journal. Use
+    /// `journal::replay` to catch up on events after a crash
+    ///
+    /// ```rust,ignore
+    /// VLive::new(Handler, Duration::from_secs(5))
+    ///     .journal(Journal::open("events.jsonl")?)
+    ///     .run();
+    /// ```
+    pub fn journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Start listening synchronously
+    ///
+    /// This is a blocking call until the async loop closes
+    /// (which shouldn't happen until you close your program)
+    /// See `run_async` if you need to perform actions after this
+    pub fn run(self) {
+        self.run_async();
+        loop {
+            thread::park();
+        }
+    }
+
+    /// Start listening async
+    ///
+    /// Asynchronous version of `run`
+    /// This method starts the event loop, but make sure your
+    /// program keeps running after this, most likely with a
+    /// infinite loop
+    pub fn run_async(self) -> VLiveStopper {
+        let mut callback = self.callback;
+        let mut feeds = self.feeds;
+        if feeds.is_empty() {
+            feeds.push((Feed::Recent, self.wait.into()));
+        }
+        let tx = self.tx;
+        let rx = self.rx;
+        let transport = match self.raw_response {
+            Some(hook) => Arc::new(transport::Tap::new(self.transport, hook)),
+            None => self.transport,
+        };
+        let parser = self.parser;
+        let concurrency = self.concurrency;
+        let restore = self.restore.unwrap_or_default();
+        let clock = self.clock;
+        let languages = self.languages;
+        let enricher = self.enrich;
+        let health_check = self.health_check;
+        //Serve how the listener was set up as it was started
+        let admin = match self.admin {
+            Some(listener) => {
+                let config = serde_json::json!({
+                    "name": self.threads.name,
+                    "feeds": feeds.iter()
+                        .map(|(feed, wait)| serde_json::json!({
+                            "feed": feed,
+                            "url": feed.url(),
+                            "wait": format!("{:?}", wait),
+                        }))
+                        .collect::<Vec<_>>(),
+                    "concurrency": concurrency,
+                    "queue_size": self.queue_size,
+                    "queue_policy": format!("{:?}", self.queue_policy),
+                    "languages": languages,
+                    "settle_delay_ms": self.settle_delay.map(|delay| delay.as_millis() as u64),
+                    "layout_changed_after": self.layout_changed_after,
+                });
+                Some((listener, config.to_string()))
+            },
+            None => None,
+        };
+        #[cfg(unix)]
+        let systemd = if self.systemd { Notify::from_env() } else { None };
+        #[cfg(unix)]
+        let mut fed: Option<time::Instant> = None;
+        let metrics = self.metrics;
+        let threads = self.threads;
+        let counted = metrics.clone();
+        let mut pinged: Option<time::Instant> = None;
+        let parse_policy = self.parse_policy;
+        let mut poller = Poller::new(feeds, clock.now())
+            .dedup(self.dedup)
+            .kinds(self.kinds)
+            .channel_filter(self.channel_filter)
+            .layout_changed(self.layout_changed_after, &parse_policy);
+        if let Some(lifecycle) = self.lifecycle {
+            poller = poller.lifecycle(lifecycle);
+        }
+        if let Some(step) = self.milestones {
+            poller = poller.milestones(step);
+        }
+        if let Some(delay) = self.settle_delay {
+            poller = poller.settle_delay(delay);
+        }
+        let mut poller = poller.restore(&restore, clock.now());
+        let queue = Queue::new(self.queue_size, self.queue_policy);
+        let cursor = Arc::new(Mutex::new(Cursor {
+            last_seen: poller.last_seen(),
+            seek: None,
+            poll_now: false,
+            paused: false,
+            online: false,
+            state: poller.state(),
+        }));
+
+        //Dispatcher, runs callbacks until the poller closes the queue
+        let dispatch = queue.clone();
+        let control = tx.clone();
+        let mut middleware = self.middleware;
+        let mut journal = self.journal;
+        let mut outbox = self.outbox;
+        let ctx = Ctx {
+            tx: tx.clone(),
+            cursor: cursor.clone(),
+            queue: queue.clone(),
+            dead_letters: outbox.dead_letters(),
+            poll_id: Arc::new(AtomicU64::new(0)),
+            feed: Arc::new(Mutex::new(None)),
+        };
+        let handle = ctx.clone();
+        //Hung up by the poller once it's done, so the admin thread goes too
+        let (stopping, stopped) = channel::<()>();
+        if let Some((listener, config)) = admin {
+            let admin = Admin {
+                ctx: ctx.clone(),
+                config,
+                journal: journal.as_ref().map(|journal| journal.path().to_path_buf()),
+            };
+            threads.spawn("admin", move || admin::serve(listener, admin, stopped));
+        }
+        let deadline = self.callback_deadline;
+        let timer = clock.clone();
+        let mut digest = self.digest;
+        let mut correlate = self.correlate;
+        let mut correlated = VecDeque::new();
+        let quiet_hours = self.quiet_hours;
+        let mut held = VecDeque::new();
+        //How long VLive was unreachable, set by the poller once it's back
+        let reconnected = Arc::new(Mutex::new(None));
+        let outages = reconnected.clone();
+        threads.spawn("dispatcher", move || {
+            let mut next_id = journal.as_ref().map_or(0, Journal::last_id);
+            callback.on_started(&ctx);
+            'events: loop {
+                //Retries, digests and held videos don't belong to any one poll
+                ctx.poll_id.store(0, Ordering::Relaxed);
+                *ctx.feed.lock().unwrap() = None;
+                if let Some(outage) = outages.lock().unwrap().take() {
+                    callback.on_reconnected(outage);
+                }
+
+                //Retry failed deliveries as they come due, in between new events
+                if outbox.retry(&mut callback, &ctx, time::Instant::now()) == Flow::Stop {
+                    dispatch.close();
+                    let _ = control.send("stop");
+                    break;
+                }
+                let due = digest.as_mut().and_then(|digest| digest.take_due(timer.now()));
+                if let Some(videos) = due {
+                    if callback.on_digest(&ctx, videos) == Flow::Stop {
+                        dispatch.close();
+                        let _ = control.send("stop");
+                        break;
+                    }
+                }
+
+                //Quiet hours are over, deliver everything that was held back
+                let quiet = quiet_hours.and_then(|quiet| quiet.remaining(timer.system_now()));
+                if quiet.is_none() {
+                    while let Some(event) = held.pop_front() {
+                        if deliver(&mut callback, &ctx, &mut outbox, &mut digest, event, timer.now()) == Flow::Stop {
+                            dispatch.close();
+                            let _ = control.send("stop");
+                            break 'events;
+                        }
+                    }
+                }
+
+                if let Some(ref mut correlate) = correlate {
+                    correlated.extend(correlate.take_due(timer.now()));
+                }
+
+                //Only retries are due in real time, look at the clock again
+                //every so often in case it runs faster
+                let now = timer.now();
+                let wake = digest.as_ref().and_then(Digest::next_due).into_iter()
+                    .chain(correlate.as_ref().and_then(Correlate::next_due))
+                    .chain(quiet.filter(|_| !held.is_empty()).map(|quiet| now + quiet))
+                    .min()
+                    .map(|due| time::Instant::now() + due.saturating_duration_since(now).min(WAKE_EVERY))
+                    .into_iter()
+                    .chain(outbox.next_due())
+                    .min();
+                //Videos done waiting for their mirrors go first, and aren't held again
+                let (mut next, held_back) = match correlated.pop_front() {
+                    Some(event) => (Some((event.poll_id, None, Ok(VLiveEvent::New(event)))), true),
+                    None => match dispatch.pop_until(wake) {
+                        Ok(next) => (next, false),
+                        Err(()) => continue,
+                    },
+                };
+                let poll_id = next.as_ref().map_or(0, |&(poll_id, _, _)| poll_id);
+                ctx.poll_id.store(poll_id, Ordering::Relaxed);
+                *ctx.feed.lock().unwrap() = next.as_mut().and_then(|(_, feed, _)| feed.take());
+                let event = match next.map(|(_, _, next)| next) {
+                    Some(Ok(event)) => event,
+                    Some(Err(why)) => {
+                        if let Some(ref metrics) = counted {
+                            metrics.error(poll_id, &why);
+                        }
+                        let flow = match why {
+                            VLiveError::Parse(failure) => callback.on_parse_error(failure),
+                            why => callback.on_error(why),
+                        };
+                        if flow == Flow::Stop {
+                            dispatch.close();
+                            let _ = control.send("stop");
+                            break;
+                        }
+                        continue;
+                    },
+                    //Nothing more is coming to fold into the held videos
+                    None => match correlate.as_mut().map(Correlate::take).filter(|held| !held.is_empty()) {
+                        Some(held) => {
+                            correlated.extend(held);
+                            continue;
+                        },
+                        None => break,
+                    },
+                };
+
+                //Only new videos go through the middleware, and get an id if they make it
+                let (flow, elapsed) = match event {
+                    VLiveEvent::New(mut event) => {
+                        if !held_back {
+                            if let Some(ref mut correlate) = correlate {
+                                correlate.push(event, timer.now());
+                                continue;
+                            }
+                        }
+                        for stage in &mut middleware {
+                            event.video = match stage.process(event.video) {
+                                Some(video) => video,
+                                None => continue 'events,
+                            };
+                        }
+                        next_id += 1;
+                        event.id = next_id;
+                        if let Some(ref metrics) = counted {
+                            metrics.event(poll_id, &VLiveEvent::New(event.clone()));
+                        }
+                        if let Some(ref mut journal) = journal {
+                            if let Err(why) = journal.append(&event) {
+                                let why = VLiveError::Journal(why);
+                                if let Some(ref metrics) = counted {
+                                    metrics.error(poll_id, &why);
+                                }
+                                if callback.on_error(why) == Flow::Stop {
+                                    dispatch.close();
+                                    let _ = control.send("stop");
+                                    break;
+                                }
+                            }
+                        }
+                        if quiet_hours.is_some_and(|quiet| quiet.holds(&event.video, timer.system_now())) {
+                            held.push_back(event);
+                            continue;
+                        }
+                        timed(|| deliver(&mut callback, &ctx, &mut outbox, &mut digest, event, timer.now()))
+                    },
+                    event => {
+                        if let Some(ref metrics) = counted {
+                            metrics.event(poll_id, &event);
+                        }
+                        timed(|| callback.on_event(&ctx, event))
+                    },
+                };
+
+                let flow = match deadline {
+                    Some(deadline) if flow == Flow::Continue && elapsed > deadline => {
+                        let why = VLiveError::SlowCallback { elapsed, deadline };
+                        if let Some(ref metrics) = counted {
+                            metrics.error(poll_id, &why);
+                        }
+                        callback.on_error(why)
+                    },
+                    _ => flow,
+                };
+                if flow == Flow::Stop {
+                    dispatch.close();
+                    let _ = control.send("stop");
+                    break;
+                }
+            }
+
+            //Nothing is coming back to retry these, or to deliver what quiet
+            //hours held back without waking anyone up
+            outbox.abandon(&mut callback);
+            for event in held {
+                outbox.give_up(&mut callback, event, "the listener stopped during quiet hours");
+            }
+            if let Some(mut digest) = digest {
+                let videos = digest.take();
+                if !videos.is_empty() {
+                    callback.on_digest(&ctx, videos);
+                }
+            }
+            callback.on_stopped();
+        });
+
+        let wake = queue.clone();
+        let halt = tx.clone();
+        let pinger = threads.clone();
+        threads.spawn("poller", move || {
+            //Since when every poll failed to fetch anything
+            let mut offline: Option<time::Instant> = None;
+            #[cfg(unix)]
+            if let Some(ref notify) = systemd {
+                if let Err(why) = notify.ready() {
+                    eprintln!("VLive Error: Could not notify systemd: {}", why);
+                }
+            }
+
+            //Queue an event for the dispatcher
+            let send = |poll_id: u64, feed: &Feed, event: Result<VLiveEvent, VLiveError>| {
+                if let Some((_, _, dropped)) = queue.push((poll_id, Some(feed.clone()), event)) {
+                    eprintln!("VLive Error: Dispatch queue full, dropped {:?}", dropped);
+                }
+            };
+
+            //Whatever a page looks like, it must not take the listener down
+            //Returns whether the page could be used at all
+            let handle = |poller: &mut Poller, feed: usize, page: Result<String, TransportError>, now| {
+                let html = match page {
+                    Ok(html) => html,
+                    Err(why) => {
+                        send(poller.poll_id(), poller.feed(feed), Err(VLiveError::Transport(why)));
+                        return false;
+                    },
+                };
+                let detected_at = clock.system_now();
+                let poll = || {
+                    let mut events = poller.page(feed, &html, &parser, now, detected_at);
+                    poller::translate(&mut events, poller.feed(feed), &languages, &*transport, &parser);
+                    if let Some(ref enricher) = enricher {
+                        enrich::enrich_events(&mut events, &**enricher);
+                    }
+                    events
+                };
+                match panic::catch_unwind(AssertUnwindSafe(poll)) {
+                    Ok(events) => {
+                        let layout_changed = events.iter().find_map(|event| match *event {
+                            Err(VLiveError::LayoutChanged { ref feed, polls }) => Some((feed.clone(), polls)),
+                            _ => None,
+                        });
+                        events.into_iter().for_each(|event| send(poller.poll_id(), poller.feed(feed), event));
+
+                        //The error is queued first, so the callback still hears about it
+                        if let Some((ref feed, polls)) = layout_changed {
+                            let flow = match parse_policy {
+                                ParsePolicy::Stop => Flow::Stop,
+                                ParsePolicy::Rescue(ref hook) => hook(feed, polls),
+                                ParsePolicy::KeepGoing | ParsePolicy::Backoff { .. } => Flow::Continue,
+                            };
+                            if flow == Flow::Stop {
+                                let _ = halt.send("stop");
+                            }
+                        }
+                        layout_changed.is_none()
+                    },
+                    Err(panic) => {
+                        send(poller.poll_id(), poller.feed(feed), Err(VLiveError::Panic(panic_message(&*panic))));
+                        false
+                    },
+                }
+            };
+
+            loop {
+                if let Ok(value) = rx.try_recv() {
+                    match value {
+                        "start" => println!("VLive thread started"),
+                        "stop" => { println!("VLive thread stopped"); break },
+                        _ => eprintln!("VLive Error: Unknown signal sent to thread")
+                    }
+
+                }
+
+                //Jump to wherever we were asked to, before anything is polled
+                let (seek, poll_now, paused) = {
+                    let mut cursor = cursor.lock().unwrap();
+                    (cursor.seek.take(), std::mem::take(&mut cursor.poll_now), cursor.paused)
+                };
+                if let Some(seq) = seek {
+                    poller.seek(seq);
+                }
+                if poll_now {
+                    poller.poll_now(clock.now());
+                }
+
+                //Paused, keep checking whether we're resumed or stopped
+                if paused {
+                    clock.sleep(WAKE_EVERY);
+                    continue;
+                }
+
+                //Sleep until the next feed is due
+                let next = match poller.next_due() {
+                    Some(next) => next,
+                    None => break,
+                };
+                let now = clock.now();
+                if next > now {
+                    clock.sleep((next - now).min(WAKE_EVERY));
+                    continue;
+                }
+
+                //Poll everything that's due, fetching up to `concurrency` feeds at once
+                let due = poller.due(now, clock.system_now());
+                let workers = concurrency.min(due.len());
+                let mut healthy = false;
+                let mut reached = due.is_empty();
+                if workers <= 1 {
+                    for feed in due {
+                        let started = time::Instant::now();
+                        let page = transport.get(&poller.feed(feed).url());
+                        if let Some(ref metrics) = metrics {
+                            metrics.poll(poller.poll_id(), poller.feed(feed), started.elapsed());
+                        }
+                        reached |= page.is_ok();
+                        healthy |= handle(&mut poller, feed, page, now);
+                    }
+                } else {
+                    let urls = Mutex::new(due.iter().map(|&feed| (feed, poller.feed(feed).url())).enumerate());
+                    let pages = Mutex::new(Vec::new());
+                    thread::scope(|scope| {
+                        for _ in 0..workers {
+                            scope.spawn(|| loop {
+                                let next = urls.lock().unwrap().next();
+                                match next {
+                                    Some((order, (feed, url))) => {
+                                        let started = time::Instant::now();
+                                        let page = transport.get(&url);
+                                        pages.lock().unwrap().push((order, feed, page, started.elapsed()));
+                                    },
+                                    None => break,
+                                }
+                            });
+                        }
+                    });
+
+                    //Handled in the order they were due, not the order they came back in
+                    let mut pages = pages.into_inner().unwrap();
+                    pages.sort_by_key(|&(order, ..)| order);
+                    for (_, feed, page, latency) in pages {
+                        if let Some(ref metrics) = metrics {
+                            metrics.poll(poller.poll_id(), poller.feed(feed), latency);
+                        }
+                        reached |= page.is_ok();
+                        healthy |= handle(&mut poller, feed, page, now);
+                    }
+                }
+
+                //Back after an outage, let the dispatcher know
+                match (reached, offline) {
+                    (true, Some(since)) => {
+                        offline = None;
+                        *reconnected.lock().unwrap() = Some(now.duration_since(since));
+                        wake.wake();
+                    },
+                    (false, None) => offline = Some(now),
+                    _ => (),
+                }
+
+                //Let the monitor know we're still making progress
+                if let Some((ref url, every)) = health_check {
+                    if healthy && pinged.is_none_or(|pinged| now.duration_since(pinged) >= every) {
+                        pinged = Some(now);
+                        let url = url.clone();
+                        pinger.spawn("health-check", move || if let Err(why) = ping(&url) {
+                            eprintln!("VLive Error: Health check ping failed: {}", why);
+                        });
+                    }
+                }
+                #[cfg(unix)]
+                if let Some(ref notify) = systemd {
+                    let due = notify.watchdog_every()
+                        .is_some_and(|every| fed.is_none_or(|fed| now.duration_since(fed) >= every / 2));
+                    if healthy && due {
+                        fed = Some(now);
+                        if let Err(why) = notify.watchdog() {
+                            eprintln!("VLive Error: Could not feed the systemd watchdog: {}", why);
+                        }
+                    }
+                }
+
+                let mut cursor = cursor.lock().unwrap();
+                if cursor.seek.is_none() {
+                    cursor.last_seen = poller.last_seen();
+                }
+                cursor.state = poller.state();
+                cursor.online = reached;
+            }
+
+            cursor.lock().unwrap().online = false;
+
+            #[cfg(unix)]
+            if let Some(ref notify) = systemd {
+                let _ = notify.stopping();
+            }
+            queue.close();
+            drop(stopping);
+        });
+        tx.send("start").unwrap();
+
+        VLiveStopper {
+            ctx: handle,
+        }
+    }
+}
+
+impl VLive<Sender<VLiveEvent>> {
+
+    /// Start listening and receive events from a channel
+    ///
+    /// Instead of calling back into your code, events are sent to the
+    /// returned `Receiver`, so you can `recv()` them in your own loop.
+    /// The channel is closed once the listener is stopped
+    ///
+    /// ```rust,ignore
+    /// let (_stopper, events) = VLive::channel(Duration::from_secs(5));
+    /// for event in events {
+    ///     println!("{:?}", event);
+    /// }
+    /// ```
+    pub fn channel(wait: time::Duration) -> (VLiveStopper, Receiver<VLiveEvent>) {
+        let (tx, rx) = channel();
+        (VLive::new(tx, wait).run_async(), rx)
+    }
+}
+
+impl<F, R> VLive<F> where F: FnMut(VLiveVideo) -> R + Send + 'static, R: Into<Flow> {
+
+    /// New listener calling a closure
+    ///
+    /// Shorthand for `VLive::new` when you don't need your own
+    /// `VLiveCallback` type. Polls every 5 seconds, use `wait` to change it
+    ///
+    /// ```rust,ignore
+    /// VLive::on_new(|video| println!("New video {}", video.video_title)).run();
+    /// ```
+    pub fn on_new(callback: F) -> Self {
+        VLive::new(callback, time::Duration::from_secs(5))
+    }
+}
+
+#[cfg(feature = "async-tokio")]
+impl VLive<TokioSink> {
+
+    /// New listener with a `Sink`
+    ///
+    /// Same as `new`, but new videos go to an async `sink`, which pushes
+    /// back when it's busy. Up to `capacity` videos wait for it before the
+    /// dispatcher does. This must be called from within a tokio runtime,
+    /// which is where the sink runs
+    pub fn new_sink<S>(sink: S, capacity: usize, wait: time::Duration) -> Self where S: Sink {
+        VLive::new(TokioSink::new(sink, capacity, tokio::runtime::Handle::current()), wait)
+    }
+}
+
+#[cfg(feature = "async-tokio")]
+impl<CB> VLive<TokioCallback<CB>> where CB: AsyncVLiveCallback {
+
+    /// New listener with an async callback
+    ///
+    /// Same as `new`, but `callback` is an `AsyncVLiveCallback`. This must
+    /// be called from within a tokio runtime, which is where the callback
+    /// tasks are spawned.
+    pub fn new_async(callback: CB, wait: time::Duration) -> Self {
+        VLive::new(TokioCallback {
+            callback: Arc::new(callback),
+            runtime: tokio::runtime::Handle::current(),
+            abandon_after: None,
+        }, wait)
+    }
+
+    /// Cancel callback tasks still running after `deadline`
+    ///
+    /// Tasks don't hold up each other, but one that never finishes, like
+    /// a request without a timeout, would pile up forever. Abandoned
+    /// tasks are reported on stderr
+    pub fn abandon_after(mut self, deadline: time::Duration) -> Self {
+        self.callback.abandon_after = Some(deadline);
+        self
+    }
+}
@@ -0,0 +1,88 @@
+//! Where the listener gets the time from
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Tells the time and sleeps
+///
+/// The poller asks its clock when feeds are due, how long to sleep until
+/// then, and when a video was detected. The dispatcher asks it when
+/// digests, quiet hours and correlating are due. Swap it out to run a
+/// listener faster than real time, see `ManualClock`. Retries and callback
+/// deadlines are always in real time
+pub trait Clock: Send + Sync + 'static {
+    /// Current time, for scheduling
+    fn now(&self) -> Instant;
+    /// Current wall clock time, for `Event::detected_at`
+    fn system_now(&self) -> SystemTime;
+    /// Block until `duration` has passed on this clock
+    fn sleep(&self, duration: Duration);
+}
+
+/// The real time, the default clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// A clock that only moves when told to
+///
+/// Sleeping moves it forward right away, so a listener on a manual clock
+/// polls as fast as pages can be fetched, and tests can skip over hours of
+/// waiting deterministically
+#[derive(Debug)]
+pub struct ManualClock {
+    start: Instant,
+    system_start: SystemTime,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    /// Starts at the current time
+    pub fn new() -> Self {
+        ManualClock {
+            start: Instant::now(),
+            system_start: SystemTime::now(),
+            elapsed: Mutex::new(Duration::from_secs(0)),
+        }
+    }
+
+    /// Move the clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.system_start + *self.elapsed.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+        thread::yield_now();
+    }
+}
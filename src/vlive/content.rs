@@ -0,0 +1,126 @@
+//! New videos with their platform spelled out
+//!
+//! The poller, dedup, the journal and delivery all work on `Event`, which
+//! looks the same whichever `Source` found the video. A `ContentEvent` is
+//! that same event tagged with its platform, plus what only makes sense on
+//! that platform in `extension`, for sinks that want to format Weverse posts
+//! differently from YouTube uploads. Implement
+//! `VLiveCallback::on_content` to get them.
+
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Event, Platform, VLiveVideo};
+
+/// A new video from any platform, see the module docs
+///
+/// Serializes like an `Event` with the fields of `extension` and a
+/// `"platform"` next to it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentEvent {
+    /// Same as `Event::id`
+    pub id: u64,
+    /// Same as `Event::poll_id`
+    #[serde(default)]
+    pub poll_id: u64,
+    /// Same as `Event::detected_at`
+    pub detected_at: SystemTime,
+    pub video: VLiveVideo,
+    /// What only the video's platform has
+    #[serde(flatten)]
+    pub extension: Extension,
+}
+
+impl ContentEvent {
+    /// Which platform the video is on, from `extension`
+    pub fn platform(&self) -> Platform {
+        self.extension.platform()
+    }
+}
+
+impl From<Event> for ContentEvent {
+    fn from(event: Event) -> Self {
+        ContentEvent {
+            id: event.id,
+            poll_id: event.poll_id,
+            detected_at: event.detected_at,
+            extension: Extension::of(&event.video),
+            video: event.video,
+        }
+    }
+}
+
+impl From<ContentEvent> for Event {
+    fn from(event: ContentEvent) -> Self {
+        Event {
+            id: event.id,
+            poll_id: event.poll_id,
+            detected_at: event.detected_at,
+            video: event.video,
+        }
+    }
+}
+
+/// The ids a video goes by on its own platform
+///
+/// Named like `Platform`, which is also what `"platform"` says when
+/// serialized
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "platform")]
+pub enum Extension {
+    VLIVE {
+        video_seq: u64,
+        /// Like `"FE619"`, see `Feed::Channel`
+        channel_code: String,
+    },
+    WEVERSE {
+        /// Like `"bts"`, see `Feed::Weverse`
+        community: String,
+        post_id: String,
+    },
+    YOUTUBE {
+        /// Like `"dQw4w9WgXcQ"`
+        video_id: String,
+        /// Like `"UCLkAepWjdylmXSltofFvsYQ"`, see `Feed::YouTube`
+        channel_id: String,
+    },
+    NAVERTV {
+        clip_no: u64,
+        /// Like `"bts"`, see `Feed::NaverTv`
+        channel: String,
+    },
+}
+
+impl Extension {
+    /// Pick the platform's ids out of `video`
+    pub fn of(video: &VLiveVideo) -> Self {
+        match video.platform {
+            Platform::VLIVE => Extension::VLIVE {
+                video_seq: video.video_seq,
+                channel_code: video.channel_code(),
+            },
+            Platform::WEVERSE => Extension::WEVERSE {
+                community: video.channel_code(),
+                post_id: video.video_code(),
+            },
+            Platform::YOUTUBE => Extension::YOUTUBE {
+                video_id: video.video_code(),
+                channel_id: video.channel_code(),
+            },
+            Platform::NAVERTV => Extension::NAVERTV {
+                clip_no: video.video_seq,
+                channel: video.channel_code(),
+            },
+        }
+    }
+
+    pub fn platform(&self) -> Platform {
+        match *self {
+            Extension::VLIVE { .. } => Platform::VLIVE,
+            Extension::WEVERSE { .. } => Platform::WEVERSE,
+            Extension::YOUTUBE { .. } => Platform::YOUTUBE,
+            Extension::NAVERTV { .. } => Platform::NAVERTV,
+        }
+    }
+}
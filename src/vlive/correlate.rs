@@ -0,0 +1,121 @@
+//! Collapsing simulcasts into one video
+//!
+//! Artists often go live on VLive and Weverse at once, or post the same
+//! upload to YouTube and Naver TV. Each platform's feed announces it
+//! separately, so without this every broadcast arrives two or three times.
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use super::Event;
+
+/// New videos held back for a moment, to fold in the same broadcast coming
+/// from other platforms
+///
+/// Two videos are the same broadcast when they're on different platforms,
+/// their channels have the same name, they were found within `window` of
+/// each other and their titles are at least `similarity` alike. The first one
+/// found is delivered `window` after it arrived, with links to the others in
+/// `video_mirrors`.
+#[derive(Debug, Clone)]
+pub struct Correlate {
+    window: Duration,
+    similarity: f64,
+    /// Events waiting to be delivered and when they're due, oldest first
+    pending: Vec<(Instant, Event)>,
+}
+
+impl Correlate {
+    /// Hold every new video for `window`, see `VLive::correlate`
+    pub fn new(window: Duration) -> Self {
+        Correlate {
+            window,
+            similarity: 0.6,
+            pending: Vec::new(),
+        }
+    }
+
+    /// How alike two titles need to be, from 0 (anything goes) to 1 (the
+    /// exact same words). 0.6 by default
+    ///
+    /// Titles are compared by the pairs of letters they share, ignoring case,
+    /// spaces and punctuation, so `"[LIVE] Comeback Show!"` and
+    /// `"Comeback show"` are alike. Raise it if a channel's titles all look
+    /// the same apart from the episode
+    pub fn similarity(mut self, similarity: f64) -> Self {
+        self.similarity = similarity;
+        self
+    }
+
+    /// Hold `event`, or fold it into a pending one it's the same broadcast
+    /// as. Returns `true` if it was folded
+    pub fn push(&mut self, event: Event, now: Instant) -> bool {
+        let similarity = self.similarity;
+        let window = self.window;
+        let same = self.pending.iter_mut()
+            .map(|(_, pending)| pending)
+            .find(|pending| same_broadcast(pending, &event, window, similarity));
+        match same {
+            Some(pending) => {
+                let url = event.video.url();
+                if !pending.video.video_mirrors.contains(&url) {
+                    pending.video.video_mirrors.push(url);
+                }
+                true
+            },
+            None => {
+                self.pending.push((now + self.window, event));
+                false
+            },
+        }
+    }
+
+    /// When the oldest pending event is due
+    pub fn next_due(&self) -> Option<Instant> {
+        self.pending.first().map(|&(due, _)| due)
+    }
+
+    /// The events that are due at `now`, oldest first
+    pub fn take_due(&mut self, now: Instant) -> Vec<Event> {
+        let due = self.pending.iter().take_while(|&&(due, _)| due <= now).count();
+        self.pending.drain(..due).map(|(_, event)| event).collect()
+    }
+
+    /// Every pending event, due or not
+    pub fn take(&mut self) -> Vec<Event> {
+        self.pending.drain(..).map(|(_, event)| event).collect()
+    }
+}
+
+fn same_broadcast(a: &Event, b: &Event, window: Duration, similarity: f64) -> bool {
+    let apart = a.detected_at.duration_since(b.detected_at)
+        .or_else(|_| b.detected_at.duration_since(a.detected_at))
+        .unwrap_or_default();
+    a.video.platform != b.video.platform
+        && apart <= window
+        && letters(&a.video.channel_name) == letters(&b.video.channel_name)
+        && alike(&a.video.video_title, &b.video.video_title) >= similarity
+}
+
+/// The letters and digits of `text`, lowercase
+fn letters(text: &str) -> Vec<char> {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// How many pairs of letters two titles share, from 0 to 1
+///
+/// Works for Korean titles too, which often don't have spaces to split
+/// words on
+fn alike(a: &str, b: &str) -> f64 {
+    let pairs = |text: &str| -> HashSet<(char, char)> {
+        letters(text).windows(2).map(|pair| (pair[0], pair[1])).collect()
+    };
+    let (a, b) = (pairs(a), pairs(b));
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    2.0 * a.intersection(&b).count() as f64 / (a.len() + b.len()) as f64
+}
@@ -0,0 +1,168 @@
+//! A handle on a running listener, for callbacks
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+use super::delivery::DeadLetters;
+#[cfg(not(target_arch = "wasm32"))]
+use super::queue::{Queue, QueuePolicy};
+use super::state::PollerState;
+use super::Feed;
+#[cfg(not(target_arch = "wasm32"))]
+use super::{VLiveError, VLiveEvent};
+
+/// Where the poller is, shared with `Ctx` and `VLiveStopper`
+#[derive(Default)]
+pub(crate) struct Cursor {
+    /// Newest video seq seen on any feed
+    pub last_seen: u64,
+    /// Set by `VLiveStopper::set_last_seen`, picked up before the next poll
+    #[cfg(not(target_arch = "wasm32"))]
+    pub seek: Option<u64>,
+    /// Set by `Ctx::poll_now`, picked up before the next poll
+    pub poll_now: bool,
+    /// Set by `Ctx::pause`, no polls until it's cleared
+    pub paused: bool,
+    /// Whether the last round of polling reached anything
+    pub online: bool,
+    /// Everything the poller knew after its last round
+    pub state: PollerState,
+}
+
+/// How a listener is doing, see `Ctx::stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct Stats {
+    /// Seq of the newest video seen so far, 0 before the first poll
+    pub last_seen: u64,
+    /// Events and errors waiting for the callback
+    pub queued: usize,
+    /// Events that couldn't be delivered for good, see `VLive::retry`
+    pub dead_letters: usize,
+    /// Whether polling is paused, see `Ctx::pause`
+    pub paused: bool,
+    /// Whether the last round of polling fetched at least one feed. False
+    /// before the first poll and once the listener has stopped
+    pub online: bool,
+}
+
+/// Handle on the listener a callback runs in
+///
+/// Passed to `VLiveCallback::on_started` and every callback about a
+/// video, to act on the listener without any globals. It's cheap to clone,
+/// keep one to hand to another thread
+///
+/// ```rust,ignore
+/// impl VLiveCallback for Handler {
+///     fn on_new(&mut self, ctx: &Ctx, video: VLiveVideo) -> Flow {
+///         if video.video_type == VideoType::LIVE {
+///             //Streams come in bursts, look again right away
+///             ctx.poll_now();
+///         }
+///         Flow::Continue
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Ctx {
+    pub(crate) tx: Sender<&'static str>,
+    pub(crate) cursor: Arc<Mutex<Cursor>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) queue: Queue<(u64, Option<Feed>, Result<VLiveEvent, VLiveError>)>,
+    pub(crate) dead_letters: Arc<Mutex<DeadLetters>>,
+    /// Round of polling the event or error being handled came from
+    pub(crate) poll_id: Arc<AtomicU64>,
+    /// Feed the event or error being handled came from
+    pub(crate) feed: Arc<Mutex<Option<Feed>>>,
+}
+
+impl Ctx {
+    /// A handle that isn't on any listener, for calling a callback
+    /// yourself, like in tests
+    ///
+    /// Acting on it doesn't reach any listener, but `stats` show it, so a
+    /// test can check what the callback did
+    pub fn detached() -> Ctx {
+        Ctx {
+            tx: mpsc::channel().0,
+            cursor: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            queue: Queue::new(1, QueuePolicy::DropNewest),
+            dead_letters: Default::default(),
+            poll_id: Default::default(),
+            feed: Default::default(),
+        }
+    }
+
+    /// Poll every feed right away, instead of waiting for its schedule
+    pub fn poll_now(&self) {
+        self.cursor.lock().unwrap().poll_now = true;
+    }
+
+    /// Stop the listener, same as returning `Flow::Stop`
+    ///
+    /// Events already queued are still delivered
+    pub fn stop(&self) {
+        //The thread might already be gone
+        let _ = self.tx.send("stop");
+    }
+
+    /// Stop polling until `resume`
+    ///
+    /// Events already queued are still delivered, and a poll that's
+    /// running is finished first
+    pub fn pause(&self) {
+        self.cursor.lock().unwrap().paused = true;
+    }
+
+    /// Carry on polling after `pause`, starting with every feed that came
+    /// due meanwhile
+    pub fn resume(&self) {
+        self.cursor.lock().unwrap().paused = false;
+    }
+
+    /// How the listener is doing right now
+    pub fn stats(&self) -> Stats {
+        let (last_seen, paused, online) = {
+            let cursor = self.cursor.lock().unwrap();
+            (cursor.last_seen, cursor.paused, cursor.online)
+        };
+        Stats {
+            last_seen,
+            #[cfg(not(target_arch = "wasm32"))]
+            queued: self.queue.len(),
+            //There's no dispatch queue in the browser
+            #[cfg(target_arch = "wasm32")]
+            queued: 0,
+            dead_letters: self.dead_letters.lock().unwrap().letters.len(),
+            paused,
+            online,
+        }
+    }
+
+    /// Which round of polling the event or error being handled came from
+    ///
+    /// Same as `Event::poll_id` for new videos, and the only way to tell
+    /// for errors and other events. It's on every line `JsonLog` writes
+    /// about that round too. 0 if it didn't come from polling, like a
+    /// retried delivery or a digest
+    pub fn poll_id(&self) -> u64 {
+        self.poll_id.load(Ordering::Relaxed)
+    }
+
+    /// Which feed the event or error being handled came from
+    ///
+    /// `None` if it didn't come from a page, like a retried delivery, a
+    /// digest or a video held back for its mirrors
+    pub fn feed(&self) -> Option<Feed> {
+        self.feed.lock().unwrap().clone()
+    }
+
+    /// Everything the poller remembers, to be saved for `VLive::restore`,
+    /// same as `VLiveStopper::snapshot`
+    pub fn state(&self) -> PollerState {
+        self.cursor.lock().unwrap().state.clone()
+    }
+}
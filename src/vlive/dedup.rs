@@ -0,0 +1,151 @@
+//! Remembering which videos were already announced
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{self, SystemTime};
+
+use super::VideoType;
+
+/// How a video code was seen before, see `Dedup::sight`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sighting {
+    /// Never, announce it
+    New,
+    /// Already announced
+    Again,
+    /// Announced as a live stream, and this is its first time as a VOD
+    Vod,
+}
+
+/// Recently announced video codes
+///
+/// Live streams can show up again, as a rebroadcast or when they turn into a
+/// VOD, and the seq alone isn't enough to tell. Every announced video code is
+/// remembered for `ttl`, or until `capacity` newer ones push it out.
+#[derive(Debug, Clone)]
+pub struct Dedup {
+    capacity: usize,
+    ttl: time::Duration,
+    seen: HashMap<String, time::Instant>,
+    /// Codes in the order they were first seen, oldest first
+    order: VecDeque<String>,
+    /// Codes announced as live streams that weren't seen as a VOD yet
+    lives: HashSet<String>,
+}
+
+impl Dedup {
+    pub fn new(capacity: usize, ttl: time::Duration) -> Self {
+        Dedup {
+            capacity,
+            ttl,
+            seen: HashMap::new(),
+            order: VecDeque::new(),
+            lives: HashSet::new(),
+        }
+    }
+
+    /// Remember `code` seen as `video_type`
+    ///
+    /// A live stream turning into a VOD keeps its code, so the VOD is
+    /// `Sighting::Vod` the first time instead of `Sighting::Again`
+    pub fn sight(&mut self, code: &str, video_type: VideoType, now: time::Instant) -> Sighting {
+        if self.check(code, now) {
+            if video_type == VideoType::VOD && self.lives.remove(code) {
+                return Sighting::Vod;
+            }
+            return Sighting::Again;
+        }
+        if video_type == VideoType::LIVE && self.capacity != 0 {
+            self.lives.insert(code.to_string());
+        }
+        Sighting::New
+    }
+
+    /// Remember `code`, returns `true` if it was already seen
+    pub fn check(&mut self, code: &str, now: time::Instant) -> bool {
+        self.expire(now);
+        if self.capacity == 0 {
+            return false;
+        }
+        if self.seen.contains_key(code) {
+            return true;
+        }
+
+        self.seen.insert(code.to_string(), now);
+        self.order.push_back(code.to_string());
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+                self.lives.remove(&oldest);
+            }
+        }
+        false
+    }
+
+    /// Every remembered code with when it was first seen, oldest first
+    pub fn entries(&self) -> Vec<(String, SystemTime)> {
+        let (now, system_now) = (time::Instant::now(), SystemTime::now());
+        self.order.iter()
+            .filter_map(|code| {
+                let age = now.duration_since(*self.seen.get(code)?);
+                Some((code.clone(), system_now - age))
+            })
+            .collect()
+    }
+
+    /// Codes announced as live streams that weren't seen as a VOD yet,
+    /// oldest first
+    pub fn lives(&self) -> Vec<String> {
+        self.order.iter().filter(|code| self.lives.contains(*code)).cloned().collect()
+    }
+
+    /// Remember codes from `lives` as announced live streams, if they're
+    /// remembered at all. Restore the entries first
+    pub fn restore_lives(&mut self, lives: &[String]) {
+        let seen = &self.seen;
+        self.lives.extend(lives.iter().filter(|code| seen.contains_key(*code)).cloned());
+    }
+
+    /// Remember codes from `entries`, as if they were seen back then
+    ///
+    /// Anything the ttl has already expired is ignored
+    pub fn restore(&mut self, entries: &[(String, SystemTime)]) {
+        let (now, system_now) = (time::Instant::now(), SystemTime::now());
+        for (code, at) in entries {
+            let age = system_now.duration_since(*at).unwrap_or_default();
+            if let Some(at) = now.checked_sub(age) {
+                self.check(code, at);
+            }
+        }
+        self.expire(now);
+    }
+
+    /// Forget `code`, so it can be announced again
+    pub fn forget(&mut self, code: &str) {
+        if self.seen.remove(code).is_some() {
+            self.order.retain(|other| other != code);
+            self.lives.remove(code);
+        }
+    }
+
+    /// Forget every code, so they can be announced again
+    pub fn clear(&mut self) {
+        self.seen.clear();
+        self.order.clear();
+        self.lives.clear();
+    }
+
+    /// Forget everything older than the ttl
+    fn expire(&mut self, now: time::Instant) {
+        while let Some(oldest) = self.order.front() {
+            match self.seen.get(oldest) {
+                Some(&at) if now.duration_since(at) < self.ttl => break,
+                _ => {
+                    if let Some(oldest) = self.order.pop_front() {
+                        self.seen.remove(&oldest);
+                        self.lives.remove(&oldest);
+                    }
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! Retrying events the callback failed to deliver
+
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Mutex;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
+
+use super::Event;
+#[cfg(not(target_arch = "wasm32"))]
+use super::{Ctx, Flow, VLiveCallback};
+
+/// Why a callback couldn't deliver an event, see `VLiveCallback::deliver`
+pub type DeliveryError = Box<dyn Error + Send + Sync>;
+
+/// An event the callback kept failing to deliver
+///
+/// Passed to `VLiveCallback::on_dead_letter` once every attempt is used up,
+/// or when the listener stops with the event still waiting for a retry or
+/// for quiet hours to end. Also kept around for `VLiveStopper::dead_letters`
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub event: Event,
+    /// How many times delivery was tried
+    pub attempts: u32,
+    /// Why the last attempt failed
+    pub error: Arc<dyn Error + Send + Sync>,
+}
+
+impl fmt::Display for DeadLetter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Gave up delivering {} after {} attempts: {}",
+            self.event.video.video_id, self.attempts, self.error)
+    }
+}
+
+/// Dead letters shared between the dispatcher and `VLiveStopper`
+#[derive(Default)]
+pub(crate) struct DeadLetters {
+    pub letters: Vec<DeadLetter>,
+    /// Set by `VLiveStopper::retry_dead_letters`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub retry: bool,
+}
+
+/// An event waiting for its next attempt
+#[cfg(not(target_arch = "wasm32"))]
+struct Pending {
+    event: Event,
+    attempts: u32,
+    due: Instant,
+    error: DeliveryError,
+}
+
+/// Events on their way to the callback, with the ones that failed
+///
+/// A failed event is retried after `backoff`, doubling with every attempt,
+/// until `max_attempts` is reached. Other events are delivered in the
+/// meantime, so one bad event doesn't hold up the rest.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct Outbox {
+    max_attempts: u32,
+    backoff: Duration,
+    pending: Vec<Pending>,
+    dead: Arc<Mutex<DeadLetters>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Outbox {
+    pub fn new(max_attempts: u32, backoff: Duration) -> Self {
+        Outbox {
+            max_attempts: max_attempts.max(1),
+            backoff,
+            pending: Vec::new(),
+            dead: Arc::new(Mutex::new(DeadLetters::default())),
+        }
+    }
+
+    /// Where events end up when delivery is given up on
+    pub fn dead_letters(&self) -> Arc<Mutex<DeadLetters>> {
+        self.dead.clone()
+    }
+
+    /// Give up on delivering `event` for good
+    fn bury<CB>(&mut self, callback: &mut CB, event: Event, attempts: u32, error: DeliveryError) -> Flow
+        where CB: VLiveCallback
+    {
+        let letter = DeadLetter { event, attempts, error: Arc::from(error) };
+        self.dead.lock().unwrap().letters.push(letter.clone());
+        callback.on_dead_letter(letter)
+    }
+
+    /// Try to deliver `event`, scheduling a retry if it fails
+    pub fn send<CB>(&mut self, callback: &mut CB, ctx: &Ctx, event: Event, attempts: u32) -> Flow where CB: VLiveCallback {
+        let error = match callback.deliver(ctx, &event) {
+            Ok(flow) => return flow,
+            Err(why) => why,
+        };
+
+        let attempts = attempts + 1;
+        if attempts >= self.max_attempts {
+            return self.bury(callback, event, attempts, error);
+        }
+
+        let delay = self.backoff.saturating_mul(1 << (attempts - 1).min(16));
+        self.pending.push(Pending { event, attempts, due: Instant::now() + delay, error });
+        Flow::Continue
+    }
+
+    /// When the next retry is due
+    pub fn next_due(&self) -> Option<Instant> {
+        self.pending.iter().map(|pending| pending.due).min()
+    }
+
+    /// Retry every event that is due at `now`
+    ///
+    /// Dead letters are tried again from scratch if somebody asked for it
+    pub fn retry<CB>(&mut self, callback: &mut CB, ctx: &Ctx, now: Instant) -> Flow where CB: VLiveCallback {
+        let revived = {
+            let mut dead = self.dead.lock().unwrap();
+            if dead.retry {
+                dead.retry = false;
+                dead.letters.drain(..).collect()
+            } else {
+                Vec::new()
+            }
+        };
+        for letter in revived {
+            if self.send(callback, ctx, letter.event, 0) == Flow::Stop {
+                return Flow::Stop;
+            }
+        }
+
+        while let Some(index) = self.pending.iter().position(|pending| pending.due <= now) {
+            let pending = self.pending.remove(index);
+            if self.send(callback, ctx, pending.event, pending.attempts) == Flow::Stop {
+                return Flow::Stop;
+            }
+        }
+        Flow::Continue
+    }
+
+    /// Give up on everything still waiting, the listener is stopping
+    pub fn abandon<CB>(&mut self, callback: &mut CB) where CB: VLiveCallback {
+        for pending in std::mem::take(&mut self.pending) {
+            let Pending { event, attempts, error, .. } = pending;
+            self.bury(callback, event, attempts, error);
+        }
+    }
+
+    /// Give up on `event` before it was ever tried, because of `why`
+    pub fn give_up<CB>(&mut self, callback: &mut CB, event: Event, why: &str) where CB: VLiveCallback {
+        self.bury(callback, event, 0, why.into());
+    }
+}
@@ -0,0 +1,63 @@
+//! Collecting new videos into digests
+//!
+//! With `VLive::digest` set, new videos are added to a `Digest` instead of
+//! going to `on_new` one by one. The dispatcher hands the whole batch to
+//! `on_digest` once it's full or has waited long enough.
+
+use std::mem;
+use std::time::{Duration, Instant};
+
+use super::VLiveVideo;
+
+/// New videos being collected for `VLiveCallback::on_digest`
+///
+/// A digest is sent `interval` after its first video arrived, or as soon as
+/// it holds `max` videos, whichever comes first. Nothing is sent while
+/// there's nothing new.
+pub struct Digest {
+    interval: Duration,
+    max: usize,
+    videos: Vec<VLiveVideo>,
+    /// When the digest being collected is due, `None` while it's empty
+    due: Option<Instant>,
+}
+
+impl Digest {
+    pub fn new(interval: Duration, max: usize) -> Self {
+        Digest {
+            interval,
+            max: max.max(1),
+            videos: Vec::new(),
+            due: None,
+        }
+    }
+
+    /// Add a video, returns the digest if it's full
+    pub fn push(&mut self, video: VLiveVideo, now: Instant) -> Option<Vec<VLiveVideo>> {
+        self.due = self.due.or(Some(now + self.interval));
+        self.videos.push(video);
+        if self.videos.len() >= self.max {
+            return Some(self.take());
+        }
+        None
+    }
+
+    /// When the digest being collected is due
+    pub fn next_due(&self) -> Option<Instant> {
+        self.due
+    }
+
+    /// The digest, if it's due at `now`
+    pub fn take_due(&mut self, now: Instant) -> Option<Vec<VLiveVideo>> {
+        match self.due {
+            Some(due) if due <= now => Some(self.take()),
+            _ => None,
+        }
+    }
+
+    /// Everything collected so far, due or not
+    pub fn take(&mut self) -> Vec<VLiveVideo> {
+        self.due = None;
+        mem::take(&mut self.videos)
+    }
+}
@@ -0,0 +1,121 @@
+//! Adding to new videos before they are dispatched
+
+use std::future::Future;
+#[cfg(not(target_arch = "wasm32"))]
+use std::mem;
+#[cfg(not(target_arch = "wasm32"))]
+use std::pin::pin;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::task::{Context, Poll, Wake, Waker};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread::{self, Thread};
+
+use super::VLiveVideo;
+#[cfg(not(target_arch = "wasm32"))]
+use super::{Event, VLiveError, VLiveEvent};
+
+/// Adds something to new videos before they reach the callback
+///
+/// Meant for things like machine translation or romanization of titles,
+/// which take a network request. Enrichment runs on the poller, with every
+/// new video found in one poll handed over at once, so a translation API can
+/// do a whole batch in a single request instead of holding up every video
+/// on its own. See `VLive::enrich`
+pub trait Enrich: Send + Sync + 'static {
+    fn enrich<'a>(&'a self, video: &'a mut VLiveVideo) -> impl Future<Output = ()> + Send + 'a;
+
+    /// Enrich every new video from one poll
+    ///
+    /// By default this calls `enrich` for one video after another. Implement
+    /// this if your service can take several at once
+    fn enrich_batch<'a>(&'a self, videos: &'a mut [VLiveVideo]) -> impl Future<Output = ()> + Send + 'a {
+        async move {
+            for video in videos {
+                self.enrich(video).await;
+            }
+        }
+    }
+}
+
+/// An `Enrich` that can be stored as a trait object, blocking until done
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) trait Enricher: Send + Sync + 'static {
+    fn enrich_all(&self, videos: &mut [VLiveVideo]);
+}
+
+/// Runs an `Enrich` on the calling thread
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct Blocking<E> {
+    pub enrich: E,
+    /// Runtime for enrichers that need tokio, if there was one
+    #[cfg(feature = "async-tokio")]
+    pub runtime: Option<tokio::runtime::Handle>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<E> Enricher for Blocking<E> where E: Enrich {
+    fn enrich_all(&self, videos: &mut [VLiveVideo]) {
+        #[cfg(feature = "async-tokio")]
+        if let Some(ref runtime) = self.runtime {
+            return runtime.block_on(self.enrich.enrich_batch(videos));
+        }
+        block_on(self.enrich.enrich_batch(videos))
+    }
+}
+
+/// Wakes a thread parked in `block_on`
+#[cfg(not(target_arch = "wasm32"))]
+struct Unpark(Thread);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Poll `future` to completion on this thread
+#[cfg(not(target_arch = "wasm32"))]
+fn block_on<F>(future: F) -> F::Output where F: Future {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// Run every new video in `events` through `enricher`, keeping the order
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn enrich_events(events: &mut Vec<Result<VLiveEvent, VLiveError>>, enricher: &dyn Enricher) {
+    //New videos are taken out, and put back where the `None`s are
+    let mut slots = Vec::new();
+    let mut stamps = Vec::new();
+    let mut videos = Vec::new();
+    for event in mem::take(events) {
+        match event {
+            Ok(VLiveEvent::New(event)) => {
+                slots.push(None);
+                stamps.push((event.id, event.poll_id, event.detected_at));
+                videos.push(event.video);
+            },
+            other => slots.push(Some(other)),
+        }
+    }
+    if !videos.is_empty() {
+        enricher.enrich_all(&mut videos);
+    }
+
+    let mut enriched = stamps.into_iter().zip(videos);
+    for slot in slots {
+        events.push(slot.unwrap_or_else(|| {
+            let ((id, poll_id, detected_at), video) = enriched.next().expect("one video per new event");
+            Ok(VLiveEvent::New(Event { id, poll_id, detected_at, video }))
+        }));
+    }
+}
@@ -0,0 +1,242 @@
+//! Everything that can go wrong while listening, `VLiveError`
+
+use std::error::Error;
+use std::time::Duration;
+use std::{fmt, io};
+
+use super::delivery::DeadLetter;
+use super::transport::{HttpError, TransportError};
+use super::Feed;
+
+/// Everything that can go wrong while listening
+///
+/// Errors don't stop the listener, they are passed to
+/// `VLiveCallback::on_error` and polling carries on.
+#[derive(Debug)]
+pub enum VLiveError {
+    /// Fetching a page failed
+    Transport(TransportError),
+    /// A video on the page couldn't be parsed. Also passed to
+    /// `VLiveCallback::on_parse_error`
+    Parse(ParseFailure),
+    /// Not a single video could be parsed from `feed` for `polls` polls in a
+    /// row. Most likely VLive changed its layout and this crate needs fixing
+    ///
+    /// What happens to polling afterwards is up to `VLive::layout_changed`
+    LayoutChanged {
+        feed: Feed,
+        polls: u32,
+    },
+    /// VLive answered, but not with videos. Nothing is wrong on our side and
+    /// polling carries on as usual
+    ServiceUnavailable(Unavailable),
+    /// Writing to the journal failed. The event is still delivered
+    Journal(io::Error),
+    /// The callback kept failing to deliver an event. Also passed to
+    /// `VLiveCallback::on_dead_letter`
+    Undelivered(Box<DeadLetter>),
+    /// The callback took `elapsed` to handle an event, more than the
+    /// `deadline` set with `VLive::callback_deadline`. Everything queued
+    /// behind it was held up for as long
+    SlowCallback {
+        elapsed: Duration,
+        deadline: Duration,
+    },
+    /// Fetching or parsing panicked with this message. The poll was
+    /// abandoned, but the listener carries on
+    Panic(String),
+}
+
+impl fmt::Display for VLiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VLiveError::Transport(ref why) => write!(f, "{}", why),
+            VLiveError::Parse(ref why) => write!(f, "Could not parse video: {}", why),
+            VLiveError::LayoutChanged { ref feed, polls } => write!(f,
+                "Nothing could be parsed from {:?} for {} polls, the layout might have changed", feed, polls),
+            VLiveError::ServiceUnavailable(ref why) => write!(f, "VLive is unavailable: {}", why),
+            VLiveError::Journal(ref why) => write!(f, "Could not write to journal: {}", why),
+            VLiveError::Undelivered(ref letter) => write!(f, "{}", letter),
+            VLiveError::SlowCallback { elapsed, deadline } => write!(f,
+                "Callback took {:?}, longer than the deadline of {:?}", elapsed, deadline),
+            VLiveError::Panic(ref message) => write!(f, "Poll panicked: {}", message),
+        }
+    }
+}
+
+impl VLiveError {
+    /// Short name of what went wrong, to group and filter errors by in
+    /// reporting and metrics, like `"transport"` or `"layout_changed"`
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            VLiveError::Transport(_) => "transport",
+            VLiveError::Parse(_) => "parse",
+            VLiveError::LayoutChanged { .. } => "layout_changed",
+            VLiveError::ServiceUnavailable(_) => "service_unavailable",
+            VLiveError::Journal(_) => "journal",
+            VLiveError::Undelivered(_) => "undelivered",
+            VLiveError::SlowCallback { .. } => "slow_callback",
+            VLiveError::Panic(_) => "panic",
+        }
+    }
+
+    /// A copy to hand to another callback
+    ///
+    /// Transport and journal errors can't be cloned, so the copy has their
+    /// message instead, except for an `HttpError`
+    pub(crate) fn duplicate(&self) -> VLiveError {
+        match *self {
+            VLiveError::Transport(ref why) => VLiveError::Transport(match why.downcast_ref::<HttpError>() {
+                Some(http) => Box::new(http.clone()),
+                None => why.to_string().into(),
+            }),
+            VLiveError::Parse(ref failure) => VLiveError::Parse(failure.clone()),
+            VLiveError::LayoutChanged { ref feed, polls } => VLiveError::LayoutChanged { feed: feed.clone(), polls },
+            VLiveError::ServiceUnavailable(why) => VLiveError::ServiceUnavailable(why),
+            VLiveError::Journal(ref why) => VLiveError::Journal(io::Error::new(why.kind(), why.to_string())),
+            VLiveError::Undelivered(ref letter) => VLiveError::Undelivered(letter.clone()),
+            VLiveError::SlowCallback { elapsed, deadline } => VLiveError::SlowCallback { elapsed, deadline },
+            VLiveError::Panic(ref message) => VLiveError::Panic(message.clone()),
+        }
+    }
+}
+
+impl Error for VLiveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            VLiveError::Transport(ref why) => Some(&**why),
+            VLiveError::Parse(ref why) => Some(why),
+            VLiveError::Journal(ref why) => Some(why),
+            VLiveError::Undelivered(ref letter) => Some(&*letter.error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ParseFailure> for VLiveError {
+    fn from(failure: ParseFailure) -> Self {
+        VLiveError::Parse(failure)
+    }
+}
+
+/// Longest HTML snippet kept in a `ParseFailure`, in bytes
+const SNIPPET_LEN: usize = 1024;
+
+/// A video that couldn't be parsed, along with its markup
+///
+/// Attach a reporter to `VLiveCallback::on_parse_error` to collect these,
+/// the snippet is exactly what's needed to fix the parser
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFailure {
+    pub error: ParseError,
+    /// HTML of the video element, cut to around a kilobyte
+    pub snippet: String,
+    /// Name of the `Strategy` the error came from, `None` for videos from
+    /// other platforms, which aren't parsed with strategies
+    pub strategy: Option<&'static str>,
+}
+
+impl ParseFailure {
+    pub fn new(error: ParseError, html: &str) -> Self {
+        ParseFailure { error, snippet: snippet(html), strategy: None }
+    }
+
+    /// Blame the strategy called `name`
+    pub fn strategy(mut self, name: &'static str) -> Self {
+        self.strategy = Some(name);
+        self
+    }
+}
+
+/// Cut `html` to around a kilobyte, on a char boundary
+pub(crate) fn snippet(html: &str) -> String {
+    let mut end = html.len().min(SNIPPET_LEN);
+    while !html.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    let mut snippet = html[..end].to_string();
+    if end < html.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+impl fmt::Display for ParseFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.strategy {
+            Some(strategy) => write!(f, "{} with {} in {}", self.error, strategy, self.snippet),
+            None => write!(f, "{} in {}", self.error, self.snippet),
+        }
+    }
+}
+
+impl Error for ParseFailure {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Why a page had no videos on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unavailable {
+    /// The page was empty, or had an empty list of videos
+    Empty,
+    /// We were sent to the login page
+    Login,
+    /// VLive is down for maintenance
+    Maintenance,
+}
+
+impl fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Unavailable::Empty => "no videos on the page",
+            Unavailable::Login => "redirected to login",
+            Unavailable::Maintenance => "down for maintenance",
+        })
+    }
+}
+
+/// What exactly went wrong parsing a video
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// Nothing on the page matched `selector`
+    MissingElement {
+        selector: &'static str,
+    },
+    /// The element matched by `selector` has no `attribute`
+    MissingAttribute {
+        selector: &'static str,
+        attribute: &'static str,
+    },
+    /// `attribute` of the element matched by `selector` has an unexpected value
+    InvalidAttribute {
+        selector: &'static str,
+        attribute: &'static str,
+        value: String,
+    },
+    /// Embedded JSON was malformed or had unexpected fields
+    InvalidJson {
+        reason: String,
+    },
+    /// An XML feed was malformed or had unexpected elements
+    InvalidXml {
+        reason: String,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::MissingElement { selector } => write!(f, "no element matches `{}`", selector),
+            ParseError::MissingAttribute { selector, attribute } => write!(f, "no `{}` on `{}`", attribute, selector),
+            ParseError::InvalidAttribute { selector, attribute, ref value } => write!(f,
+                "unexpected `{}` of `{}`: {:?}", attribute, selector, value),
+            ParseError::InvalidJson { ref reason } => write!(f, "invalid embedded JSON: {}", reason),
+            ParseError::InvalidXml { ref reason } => write!(f, "invalid XML: {}", reason),
+        }
+    }
+}
+
+impl Error for ParseError {}
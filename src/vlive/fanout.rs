@@ -0,0 +1,166 @@
+//! Sending every event to several sinks at once
+//!
+//! A slow Telegram API shouldn't hold up a fast file sink. `Fanout` hands
+//! each event to all of its sinks on threads of their own, and waits for
+//! all of them before the next event:
+//!
+//! ```rust,ignore
+//! let sinks = Fanout::new()
+//!     .sink(telegram)
+//!     .sink(archive);
+//! VLive::new(sinks, Duration::from_secs(5)).run();
+//! ```
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::{ContentEvent, Ctx, DeadLetter, DeliveryError, Event, Flow, ParseFailure, VLiveCallback, VLiveError, VLiveEvent, VLiveVideo};
+
+/// Passes every event to all of its sinks
+///
+/// Sinks run side by side, each on its own thread, unless `sequential` is
+/// set. Either way they're isolated from each other: a sink that panics
+/// is reported to stderr and skipped for that event, and one that fails to
+/// deliver doesn't make the others deliver again. The listener stops if
+/// any sink asks it to
+#[derive(Default)]
+pub struct Fanout {
+    sinks: Vec<Box<dyn VLiveCallback>>,
+    sequential: bool,
+}
+
+impl Fanout {
+    pub fn new() -> Self {
+        Fanout::default()
+    }
+
+    /// Pass events to `callback` too
+    pub fn sink<CB>(mut self, callback: CB) -> Self where CB: VLiveCallback {
+        self.sinks.push(Box::new(callback));
+        self
+    }
+
+    /// Run the sinks one after the other, in the order they were added
+    ///
+    /// For when ordering across sinks matters, like a sink reading what
+    /// the one before it wrote
+    pub fn sequential(mut self) -> Self {
+        self.sequential = true;
+        self
+    }
+
+    /// Call every sink, returning what each of them returned, or `None` if
+    /// it panicked
+    fn each<F, T>(&mut self, call: F) -> Vec<Option<T>>
+        where F: Fn(&mut dyn VLiveCallback) -> T + Sync, T: Send
+    {
+        if self.sequential || self.sinks.len() <= 1 {
+            return self.sinks.iter_mut()
+                .enumerate()
+                .map(|(index, sink)| isolated(index, panic::catch_unwind(AssertUnwindSafe(|| call(&mut **sink)))))
+                .collect();
+        }
+
+        let call = &call;
+        thread::scope(|scope| {
+            let running: Vec<_> = self.sinks.iter_mut()
+                .map(|sink| scope.spawn(move || call(&mut **sink)))
+                .collect();
+            running.into_iter()
+                .enumerate()
+                .map(|(index, thread)| isolated(index, thread.join()))
+                .collect()
+        })
+    }
+
+    /// Call every sink, stopping if any of them says so
+    fn flow<F>(&mut self, call: F) -> Flow where F: Fn(&mut dyn VLiveCallback) -> Flow + Sync {
+        let stop = self.each(call).contains(&Some(Flow::Stop));
+        if stop { Flow::Stop } else { Flow::Continue }
+    }
+}
+
+/// What a sink returned, reporting it if it panicked
+fn isolated<T>(index: usize, result: thread::Result<T>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(panic) => {
+            let message = panic.downcast_ref::<&str>().copied()
+                .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            eprintln!("VLive Error: Sink {} panicked: {}", index, message);
+            None
+        },
+    }
+}
+
+impl VLiveCallback for Fanout {
+    fn on_new(&mut self, ctx: &Ctx, video: VLiveVideo) -> Flow {
+        self.flow(|sink| sink.on_new(ctx, video.clone()))
+    }
+
+    fn on_content(&mut self, ctx: &Ctx, event: ContentEvent) -> Flow {
+        self.flow(|sink| sink.on_content(ctx, event.clone()))
+    }
+
+    fn on_event(&mut self, ctx: &Ctx, event: VLiveEvent) -> Flow {
+        self.flow(|sink| sink.on_event(ctx, event.clone()))
+    }
+
+    /// Fails only if every sink failed, so a retry doesn't deliver twice to
+    /// the sinks that made it. Sinks that failed while others didn't get
+    /// the event as a dead letter right away
+    fn deliver(&mut self, ctx: &Ctx, event: &Event) -> Result<Flow, DeliveryError> {
+        let results = self.each(|sink| sink.deliver(ctx, event));
+        if !results.is_empty() && results.iter().all(|result| matches!(result, Some(Err(_)))) {
+            return results.into_iter().flatten().find_map(Result::err).map_or(Ok(Flow::Continue), Err);
+        }
+
+        let mut flow = Flow::Continue;
+        for (sink, result) in self.sinks.iter_mut().zip(results) {
+            let result = match result {
+                Some(Ok(result)) => result,
+                Some(Err(error)) => sink.on_dead_letter(DeadLetter {
+                    event: event.clone(),
+                    attempts: 1,
+                    error: Arc::from(error),
+                }),
+                None => Flow::Continue,
+            };
+            if result == Flow::Stop {
+                flow = Flow::Stop;
+            }
+        }
+        Ok(flow)
+    }
+
+    fn on_digest(&mut self, ctx: &Ctx, videos: Vec<VLiveVideo>) -> Flow {
+        self.flow(|sink| sink.on_digest(ctx, videos.clone()))
+    }
+
+    fn on_error(&mut self, error: VLiveError) -> Flow {
+        self.flow(|sink| sink.on_error(error.duplicate()))
+    }
+
+    fn on_parse_error(&mut self, failure: ParseFailure) -> Flow {
+        self.flow(|sink| sink.on_parse_error(failure.clone()))
+    }
+
+    fn on_dead_letter(&mut self, letter: DeadLetter) -> Flow {
+        self.flow(|sink| sink.on_dead_letter(letter.clone()))
+    }
+
+    fn on_started(&mut self, ctx: &Ctx) {
+        self.each(|sink| sink.on_started(ctx));
+    }
+
+    fn on_stopped(&mut self) {
+        self.each(|sink| sink.on_stopped());
+    }
+
+    fn on_reconnected(&mut self, outage: Duration) {
+        self.each(|sink| sink.on_reconnected(outage));
+    }
+}
@@ -0,0 +1,202 @@
+//! C API, for embedding the listener outside of Rust
+//!
+//! Needs the `ffi` feature. The header is `include/vlive.h`, generated with
+//! `cbindgen --config cbindgen.toml --output include/vlive.h`.
+//!
+//! ```c
+//! #include "vlive.h"
+//!
+//! int on_video(const char *video, void *user_data) {
+//!     printf("New video %s\n", video);
+//!     return 0;
+//! }
+//!
+//! VLiveHandle *vlive = vlive_new(5000);
+//! vlive_watch(vlive, "FE619", 5000);
+//! vlive_set_callback(vlive, on_video, NULL);
+//! vlive_start(vlive);
+//! ...
+//! vlive_stop(vlive);
+//! vlive_free(vlive);
+//! ```
+//!
+//! Videos are handed to the callback as JSON, with the fields of
+//! `VLiveVideo`. The callback runs on the listener's dispatcher thread, not
+//! the thread that called `vlive_start`.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::Duration;
+
+use super::{Ctx, Feed, Flow, VLive, VLiveCallback, VLiveStopper, VLiveVideo};
+
+/// Called with every new video as JSON and the `user_data` given to
+/// `vlive_set_callback`. The JSON is only valid during the call. Return 0 to
+/// keep listening, anything else to stop
+pub type VLiveCallbackFn = extern "C" fn(video: *const c_char, user_data: *mut c_void) -> c_int;
+
+/// A listener, configured until `vlive_start` and running until `vlive_stop`
+pub struct VLiveHandle {
+    wait: Duration,
+    feeds: Vec<(Feed, Duration)>,
+    callback: Option<Callback>,
+    stopper: Option<VLiveStopper>,
+    /// Disconnected once the dispatcher is done with the callback
+    finished: Option<Receiver<()>>,
+}
+
+struct Callback {
+    function: VLiveCallbackFn,
+    user_data: *mut c_void,
+    /// Dropped along with the callback, never sent on
+    _alive: Sender<()>,
+}
+
+//Whoever passed `user_data` promised it can be used from the dispatcher thread
+unsafe impl Send for Callback {}
+
+impl VLiveCallback for Callback {
+    fn on_new(&mut self, _: &Ctx, video: VLiveVideo) -> Flow {
+        let json = match serde_json::to_string(&video).map(CString::new) {
+            Ok(Ok(json)) => json,
+            _ => {
+                eprintln!("VLive Error: Couldn't pass video {} to C", video.video_seq);
+                return Flow::Continue;
+            },
+        };
+        match (self.function)(json.as_ptr(), self.user_data) {
+            0 => Flow::Continue,
+            _ => Flow::Stop,
+        }
+    }
+}
+
+/// New listener polling every `wait_ms` milliseconds, see `VLive::new`
+///
+/// Free it with `vlive_free`
+#[no_mangle]
+pub extern "C" fn vlive_new(wait_ms: u64) -> *mut VLiveHandle {
+    Box::into_raw(Box::new(VLiveHandle {
+        wait: Duration::from_millis(wait_ms),
+        feeds: Vec::new(),
+        callback: None,
+        stopper: None,
+        finished: None,
+    }))
+}
+
+/// Poll the channel `code` every `wait_ms` milliseconds, see `VLive::watch`
+///
+/// Returns 0, or -1 if `handle` or `code` is unusable or the listener is
+/// already running
+///
+/// # Safety
+///
+/// `handle` must come from `vlive_new` and `code` must be a NUL terminated
+/// string, or either can be NULL
+#[no_mangle]
+pub unsafe extern "C" fn vlive_watch(handle: *mut VLiveHandle, code: *const c_char, wait_ms: u64) -> c_int {
+    let handle = match handle.as_mut() {
+        Some(handle) if handle.stopper.is_none() => handle,
+        _ => return -1,
+    };
+    if code.is_null() {
+        return -1;
+    }
+    match CStr::from_ptr(code).to_str() {
+        Ok(code) => {
+            handle.feeds.push((Feed::Channel(code.to_string()), Duration::from_millis(wait_ms)));
+            0
+        },
+        Err(_) => -1,
+    }
+}
+
+/// Call `callback` with every new video, replacing any earlier callback
+///
+/// Returns 0, or -1 if `handle` is NULL or the listener is already running
+///
+/// # Safety
+///
+/// `handle` must come from `vlive_new` or be NULL. `user_data` is handed to
+/// `callback` from another thread, and must stay valid until `vlive_stop`
+/// or `vlive_free` returns
+#[no_mangle]
+pub unsafe extern "C" fn vlive_set_callback(handle: *mut VLiveHandle, callback: VLiveCallbackFn, user_data: *mut c_void) -> c_int {
+    match handle.as_mut() {
+        Some(handle) if handle.stopper.is_none() => {
+            let (alive, finished) = channel();
+            handle.callback = Some(Callback { function: callback, user_data, _alive: alive });
+            handle.finished = Some(finished);
+            0
+        },
+        _ => -1,
+    }
+}
+
+/// Start listening in the background, see `VLive::run_async`
+///
+/// Returns 0, or -1 if `handle` is NULL, has no callback or is already
+/// running
+///
+/// # Safety
+///
+/// `handle` must come from `vlive_new` or be NULL
+#[no_mangle]
+pub unsafe extern "C" fn vlive_start(handle: *mut VLiveHandle) -> c_int {
+    let handle = match handle.as_mut() {
+        Some(handle) if handle.stopper.is_none() => handle,
+        _ => return -1,
+    };
+    let callback = match handle.callback.take() {
+        Some(callback) => callback,
+        None => return -1,
+    };
+    let mut vlive = VLive::new(callback, handle.wait);
+    for (feed, wait) in handle.feeds.drain(..) {
+        vlive = vlive.feed(feed, wait);
+    }
+    handle.stopper = Some(vlive.run_async());
+    0
+}
+
+/// Stop a listener started with `vlive_start`, and wait for it
+///
+/// Videos already found are still handed to the callback, and a poll
+/// that's running is finished first. Once this returns the callback won't
+/// be called again, and `user_data` can be freed. Does nothing if the
+/// listener isn't running
+///
+/// # Safety
+///
+/// `handle` must come from `vlive_new` or be NULL. Don't call this from
+/// the callback, it would wait for itself, return non-zero instead
+#[no_mangle]
+pub unsafe extern "C" fn vlive_stop(handle: *mut VLiveHandle) {
+    let handle = match handle.as_mut() {
+        Some(handle) => handle,
+        None => return,
+    };
+    if let Some(stopper) = handle.stopper.take() {
+        stopper.stop();
+        if let Some(finished) = handle.finished.take() {
+            let _ = finished.recv();
+        }
+    }
+}
+
+/// Stop the listener if it's running, wait for it like `vlive_stop` and
+/// free `handle`
+///
+/// # Safety
+///
+/// `handle` must come from `vlive_new` or be NULL, and must not be used
+/// again. Don't call this from the callback
+#[no_mangle]
+pub unsafe extern "C" fn vlive_free(handle: *mut VLiveHandle) {
+    if !handle.is_null() {
+        vlive_stop(handle);
+        drop(Box::from_raw(handle));
+    }
+}
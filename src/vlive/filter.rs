@@ -0,0 +1,448 @@
+//! Filtering videos with expressions
+//!
+//! For when filters come from a config file or the command line instead of
+//! Rust code. A `Filter` is parsed once and is a middleware stage:
+//!
+//! ```rust,ignore
+//! let filter: Filter = r#"channel_seq in [363, 6] && type == LIVE && title ~ "behind""#.parse()?;
+//! VLive::new(Handler, Duration::from_secs(5)).middleware(filter).run();
+//! ```
+//!
+//! An expression compares fields of the video, the same ones templates use
+//! (see `vlive::template`):
+//!
+//! * `field == value` and `field != value`
+//! * `field < value`, `<=`, `>` and `>=` for numbers like `seq`
+//! * `field ~ "text"`, true if the field contains the text, ignoring case
+//! * `field =~ "regex"` and `field !~ "regex"`, true if the regex matches
+//!   or doesn't, like `title !~ "(?i)\\[teaser\\]"`. Needs the `regex` feature
+//! * `field in [a, b, c]`, true if the field equals any of them
+//! * `field` alone, true if it's true or not empty, like `live` or `paid`
+//!
+//! Values are numbers, `"quoted strings"` or bare words like `LIVE`.
+//! Combine them with `&&`, `||`, `!` and parentheses, `&&` binds tighter
+//! than `||`.
+//!
+//! Filters can also be built in code, and combined with `&`, `|` and `!`:
+//!
+//! ```rust,ignore
+//! let filter = Filter::title_regex("(?i)eng sub")? & !Filter::title_regex(r"\[Teaser\]")?;
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::ops::{BitAnd, BitOr, Not};
+use std::str::FromStr;
+
+#[cfg(feature = "regex")]
+use regex::Regex;
+
+use super::middleware::Middleware;
+use super::template::{self, FIELDS};
+use super::VLiveVideo;
+
+/// An expression that couldn't be parsed, and where
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError {
+    /// Byte offset into the expression
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl Error for FilterError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Truthy(String),
+    Compare(String, Op, String),
+    In(String, Vec<String>),
+    #[cfg(feature = "regex")]
+    Matches(String, Pattern),
+}
+
+/// A compiled regex, equal to another if it was compiled from the same text
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone)]
+struct Pattern(Regex);
+
+#[cfg(feature = "regex")]
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Pattern) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+/// Compile `pattern`, errors point at `position`
+#[cfg(feature = "regex")]
+fn compile(pattern: &str, position: usize) -> Result<Pattern, FilterError> {
+    Regex::new(pattern).map(Pattern).map_err(|why| FilterError { position, message: why.to_string() })
+}
+
+/// A parsed filter expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn parse(source: &str) -> Result<Self, FilterError> {
+        let mut parser = ExprParser {
+            tokens: tokenize(source)?,
+            next: 0,
+            end: source.len(),
+        };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(Filter { expr }),
+            Some(_) => Err(parser.error("Expected `&&`, `||` or the end")),
+        }
+    }
+
+    /// Only videos where `field` matches `pattern`, like `field =~ "pattern"`
+    #[cfg(feature = "regex")]
+    pub fn regex(field: &str, pattern: &str) -> Result<Self, FilterError> {
+        if !FIELDS.contains(&field) {
+            return Err(FilterError { position: 0, message: format!("Unknown field `{}`", field) });
+        }
+        let pattern = compile(pattern, 0)?;
+        Ok(Filter { expr: Expr::Matches(field.to_string(), pattern) })
+    }
+
+    /// Only videos with titles matching `pattern`
+    #[cfg(feature = "regex")]
+    pub fn title_regex(pattern: &str) -> Result<Self, FilterError> {
+        Filter::regex("title", pattern)
+    }
+
+    /// Only videos from channels with names matching `pattern`
+    #[cfg(feature = "regex")]
+    pub fn channel_regex(pattern: &str) -> Result<Self, FilterError> {
+        Filter::regex("channel_name", pattern)
+    }
+
+    /// Only videos passing both filters, same as `&`
+    pub fn and(self, other: Filter) -> Self {
+        Filter { expr: Expr::And(Box::new(self.expr), Box::new(other.expr)) }
+    }
+
+    /// Only videos passing either filter, same as `|`
+    pub fn or(self, other: Filter) -> Self {
+        Filter { expr: Expr::Or(Box::new(self.expr), Box::new(other.expr)) }
+    }
+
+    /// Whether `video` passes the filter
+    pub fn matches(&self, video: &VLiveVideo) -> bool {
+        eval(&self.expr, video)
+    }
+}
+
+impl BitAnd for Filter {
+    type Output = Filter;
+
+    fn bitand(self, other: Filter) -> Filter {
+        self.and(other)
+    }
+}
+
+impl BitOr for Filter {
+    type Output = Filter;
+
+    fn bitor(self, other: Filter) -> Filter {
+        self.or(other)
+    }
+}
+
+impl Not for Filter {
+    type Output = Filter;
+
+    fn not(self) -> Filter {
+        Filter { expr: Expr::Not(Box::new(self.expr)) }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = FilterError;
+
+    fn from_str(source: &str) -> Result<Self, FilterError> {
+        Filter::parse(source)
+    }
+}
+
+impl Middleware for Filter {
+    fn process(&mut self, video: VLiveVideo) -> Option<VLiveVideo> {
+        if self.matches(&video) { Some(video) } else { None }
+    }
+}
+
+fn eval(expr: &Expr, video: &VLiveVideo) -> bool {
+    match *expr {
+        Expr::And(ref a, ref b) => eval(a, video) && eval(b, video),
+        Expr::Or(ref a, ref b) => eval(a, video) || eval(b, video),
+        Expr::Not(ref a) => !eval(a, video),
+        Expr::Truthy(ref field) => template::truthy(video, field),
+        Expr::In(ref field, ref values) => values.contains(&template::value(video, field)),
+        #[cfg(feature = "regex")]
+        Expr::Matches(ref field, ref pattern) => pattern.0.is_match(&template::value(video, field)),
+        Expr::Compare(ref field, op, ref expected) => {
+            let value = template::value(video, field);
+            match op {
+                Op::Eq => value == *expected,
+                Op::Ne => value != *expected,
+                Op::Contains => value.to_lowercase().contains(&expected.to_lowercase()),
+                _ => match (value.parse::<f64>(), expected.parse::<f64>()) {
+                    (Ok(value), Ok(expected)) => match op {
+                        Op::Lt => value < expected,
+                        Op::Le => value <= expected,
+                        Op::Gt => value > expected,
+                        _ => value >= expected,
+                    },
+                    _ => false,
+                },
+            }
+        },
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// A field name or a bare word
+    Word(String),
+    Number(String),
+    Str(String),
+    Symbol(&'static str),
+}
+
+/// Longest first, so `<=` isn't read as `<`
+const SYMBOLS: &[&str] = &["&&", "||", "==", "!=", "=~", "!~", "<=", ">=", "<", ">", "!", "~", "(", ")", "[", "]", ","];
+
+/// Split `source` into tokens and their positions
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, FilterError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(at, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c)) => text.push(c),
+                        None => return Err(FilterError { position: at, message: "Unclosed string".to_string() }),
+                    },
+                    Some((_, c)) => text.push(c),
+                    None => return Err(FilterError { position: at, message: "Unclosed string".to_string() }),
+                }
+            }
+            tokens.push((at, Token::Str(text)));
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-' {
+            let mut word = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-') {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            if word.parse::<f64>().is_ok() {
+                tokens.push((at, Token::Number(word)));
+            } else {
+                tokens.push((at, Token::Word(word)));
+            }
+        } else {
+            let symbol = SYMBOLS.iter().find(|symbol| source[at..].starts_with(**symbol));
+            match symbol {
+                Some(symbol) => {
+                    for _ in 0..symbol.len() {
+                        chars.next();
+                    }
+                    tokens.push((at, Token::Symbol(symbol)));
+                },
+                None => return Err(FilterError { position: at, message: format!("Unexpected `{}`", c) }),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, one method per precedence level
+struct ExprParser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    /// Position reported for errors at the end
+    end: usize,
+}
+
+impl ExprParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    fn error(&self, message: &str) -> FilterError {
+        FilterError {
+            position: self.tokens.get(self.next).map_or(self.end, |&(at, _)| at),
+            message: message.to_string(),
+        }
+    }
+
+    /// Take the next token if it's `symbol`
+    fn eat(&mut self, symbol: &str) -> bool {
+        match self.peek() {
+            Some(&Token::Symbol(next)) if next == symbol => {
+                self.next += 1;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.and()?;
+        while self.eat("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.unary()?;
+        while self.eat("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, FilterError> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let expr = self.or()?;
+            if !self.eat(")") {
+                return Err(self.error("Expected `)`"));
+            }
+            return Ok(expr);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, FilterError> {
+        let field = match self.peek() {
+            Some(Token::Word(word)) if FIELDS.contains(&word.as_str()) => word.clone(),
+            Some(Token::Word(word)) => return Err(self.error(&format!("Unknown field `{}`", word))),
+            _ => return Err(self.error("Expected a field")),
+        };
+        self.next += 1;
+
+        if let Some(Token::Word(word)) = self.peek() {
+            if word == "in" {
+                self.next += 1;
+                return self.list().map(|values| Expr::In(field, values));
+            }
+        }
+        if let Some(&Token::Symbol(symbol)) = self.peek() {
+            if symbol == "=~" || symbol == "!~" {
+                return self.regex(field, symbol == "!~");
+            }
+        }
+        let op = match self.peek() {
+            Some(Token::Symbol("==")) => Op::Eq,
+            Some(Token::Symbol("!=")) => Op::Ne,
+            Some(Token::Symbol("<")) => Op::Lt,
+            Some(Token::Symbol("<=")) => Op::Le,
+            Some(Token::Symbol(">")) => Op::Gt,
+            Some(Token::Symbol(">=")) => Op::Ge,
+            Some(Token::Symbol("~")) => Op::Contains,
+            _ => return Ok(Expr::Truthy(field)),
+        };
+        self.next += 1;
+
+        let ordering = !matches!(op, Op::Eq | Op::Ne | Op::Contains);
+        match self.value()? {
+            Token::Number(value) => Ok(Expr::Compare(field, op, value)),
+            _ if ordering => {
+                self.next -= 1;
+                Err(self.error("Expected a number"))
+            },
+            Token::Word(value) | Token::Str(value) => Ok(Expr::Compare(field, op, value)),
+            Token::Symbol(_) => unreachable!(),
+        }
+    }
+
+    /// The `"pattern"` after `=~` or `!~`
+    #[cfg(feature = "regex")]
+    fn regex(&mut self, field: String, negated: bool) -> Result<Expr, FilterError> {
+        self.next += 1;
+        let position = self.tokens.get(self.next).map_or(self.end, |&(at, _)| at);
+        let expr = match self.value()? {
+            Token::Str(pattern) => Expr::Matches(field, compile(&pattern, position)?),
+            _ => {
+                self.next -= 1;
+                return Err(self.error("Expected a \"quoted\" regex"));
+            },
+        };
+        Ok(if negated { Expr::Not(Box::new(expr)) } else { expr })
+    }
+
+    #[cfg(not(feature = "regex"))]
+    fn regex(&mut self, _: String, _: bool) -> Result<Expr, FilterError> {
+        Err(self.error("Regexes need the `regex` feature"))
+    }
+
+    /// `[value, value, …]`
+    fn list(&mut self) -> Result<Vec<String>, FilterError> {
+        if !self.eat("[") {
+            return Err(self.error("Expected `[`"));
+        }
+        let mut values = Vec::new();
+        if self.eat("]") {
+            return Ok(values);
+        }
+        loop {
+            match self.value()? {
+                Token::Word(value) | Token::Number(value) | Token::Str(value) => values.push(value),
+                Token::Symbol(_) => unreachable!(),
+            }
+            if self.eat("]") {
+                return Ok(values);
+            }
+            if !self.eat(",") {
+                return Err(self.error("Expected `,` or `]`"));
+            }
+        }
+    }
+
+    /// A number, string or bare word, never a symbol
+    fn value(&mut self) -> Result<Token, FilterError> {
+        match self.peek() {
+            Some(Token::Symbol(_)) | None => Err(self.error("Expected a value")),
+            Some(token) => {
+                let token = token.clone();
+                self.next += 1;
+                Ok(token)
+            },
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Keeping a record of every delivered event on disk
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use super::Event;
+
+/// Append-only log of delivered events
+///
+/// Every event is written as one line of JSON before it reaches the
+/// callback. A consumer that crashed can then catch up with `replay`,
+/// without anything being fetched from VLive again. Event ids carry on from
+/// the last one in the journal, so they stay unique across restarts.
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    last_id: u64,
+}
+
+impl Journal {
+    /// Open the journal at `path`, creating it if it doesn't exist
+    pub fn open<P>(path: P) -> io::Result<Self> where P: AsRef<Path> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        //Drop a line left half written by a crash, so appends start on a line of their own
+        let written = std::fs::read(&path)?;
+        let whole = written.iter().rposition(|&byte| byte == b'\n').map_or(0, |end| end + 1);
+        if whole < written.len() {
+            file.set_len(whole as u64)?;
+        }
+
+        let mut last_id = 0;
+        for event in read(&path)? {
+            last_id = last_id.max(event?.id);
+        }
+
+        Ok(Journal {
+            path,
+            file,
+            last_id,
+        })
+    }
+
+    /// Where the journal is on disk
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Id of the newest event in the journal, 0 if it's empty
+    pub fn last_id(&self) -> u64 {
+        self.last_id
+    }
+
+    /// Write `event` to the end of the journal
+    pub fn append(&mut self, event: &Event) -> io::Result<()> {
+        let mut line = serde_json::to_string(event)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.file.flush()?;
+        self.last_id = self.last_id.max(event.id);
+        Ok(())
+    }
+
+    /// Every event in the journal with an id greater than `since`
+    pub fn replay(&self, since: u64) -> io::Result<Vec<Event>> {
+        replay(&self.path, since)
+    }
+}
+
+/// Every event in the journal at `path` with an id greater than `since`
+///
+/// Can be used while a listener is still writing to the journal
+pub fn replay<P>(path: P, since: u64) -> io::Result<Vec<Event>> where P: AsRef<Path> {
+    let mut events = Vec::new();
+    for event in read(path.as_ref())? {
+        let event = event?;
+        if event.id > since {
+            events.push(event);
+        }
+    }
+    Ok(events)
+}
+
+/// Read events line by line, skipping a half written last line
+///
+/// Any other line that isn't an event is an `InvalidData` error
+fn read(path: &Path) -> io::Result<impl Iterator<Item = io::Result<Event>>> {
+    let mut lines = BufReader::new(File::open(path)?).lines().peekable();
+    Ok(std::iter::from_fn(move || {
+        let line = match lines.next()? {
+            Ok(line) => line,
+            Err(why) => return Some(Err(why)),
+        };
+        match serde_json::from_str(&line) {
+            Ok(event) => Some(Ok(event)),
+            Err(_) if lines.peek().is_none() => None,
+            Err(why) => Some(Err(io::Error::new(io::ErrorKind::InvalidData, why))),
+        }
+    }))
+}
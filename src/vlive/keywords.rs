@@ -0,0 +1,111 @@
+//! Notifications for videos mentioning certain words
+//!
+//! Watch for subtitles, an artist's name or a comeback, and send each to
+//! its own callback:
+//!
+//! ```rust,ignore
+//! let keywords = Keywords::new()
+//!     .watch(&["ENG SUB", "English"], subtitled)
+//!     .watch(&["comeback"], discord);
+//! VLive::new(keywords, Duration::from_secs(5)).run();
+//! ```
+//!
+//! Videos no keyword matches go nowhere.
+
+use std::time::Duration;
+
+use super::{ContentEvent, Ctx, Flow, VLiveCallback, VLiveVideo};
+
+/// A callback and the keywords routed to it
+struct Route {
+    /// Lowercase
+    keywords: Vec<String>,
+    callback: Box<dyn VLiveCallback>,
+}
+
+impl Route {
+    /// Whether any of `titles` mentions one of the keywords
+    fn mentioned(&self, titles: &[String]) -> bool {
+        self.keywords.iter().any(|keyword| titles.iter().any(|title| title.contains(keyword.as_str())))
+    }
+}
+
+/// Routes new videos to callbacks by the keywords in their titles
+///
+/// Keywords match anywhere in the title or any of its translations,
+/// ignoring case. A video is passed to every callback with a matching
+/// keyword, once each. Errors aren't about any keyword, they're printed to
+/// stderr as usual
+#[derive(Default)]
+pub struct Keywords {
+    routes: Vec<Route>,
+}
+
+impl Keywords {
+    pub fn new() -> Self {
+        Keywords::default()
+    }
+
+    /// Pass videos mentioning any of `keywords` to `callback`
+    pub fn watch<CB>(mut self, keywords: &[&str], callback: CB) -> Self where CB: VLiveCallback {
+        self.routes.push(Route {
+            keywords: keywords.iter().map(|keyword| keyword.to_lowercase()).collect(),
+            callback: Box::new(callback),
+        });
+        self
+    }
+
+    /// The keywords `video` mentions, in the order they were added
+    pub fn matching(&self, video: &VLiveVideo) -> Vec<&str> {
+        let titles = titles(video);
+        self.routes.iter()
+            .flat_map(|route| route.keywords.iter())
+            .filter(|keyword| titles.iter().any(|title| title.contains(keyword.as_str())))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// The title of `video` and its translations, lowercase
+fn titles(video: &VLiveVideo) -> Vec<String> {
+    Some(&video.video_title).into_iter()
+        .chain(video.video_titles.values())
+        .map(|title| title.to_lowercase())
+        .collect()
+}
+
+impl VLiveCallback for Keywords {
+    fn on_new(&mut self, ctx: &Ctx, video: VLiveVideo) -> Flow {
+        let titles = titles(&video);
+        let mut flow = Flow::Continue;
+        for route in self.routes.iter_mut().filter(|route| route.mentioned(&titles)) {
+            if route.callback.on_new(ctx, video.clone()) == Flow::Stop {
+                flow = Flow::Stop;
+            }
+        }
+        flow
+    }
+
+    fn on_content(&mut self, ctx: &Ctx, event: ContentEvent) -> Flow {
+        let titles = titles(&event.video);
+        let mut flow = Flow::Continue;
+        for route in self.routes.iter_mut().filter(|route| route.mentioned(&titles)) {
+            if route.callback.on_content(ctx, event.clone()) == Flow::Stop {
+                flow = Flow::Stop;
+            }
+        }
+        flow
+    }
+
+    fn on_started(&mut self, ctx: &Ctx) {
+        self.routes.iter_mut().for_each(|route| route.callback.on_started(ctx));
+    }
+
+    fn on_stopped(&mut self) {
+        self.routes.iter_mut().for_each(|route| route.callback.on_stopped());
+    }
+
+    fn on_reconnected(&mut self, outage: Duration) {
+        self.routes.iter_mut().for_each(|route| route.callback.on_reconnected(outage));
+    }
+}
@@ -0,0 +1,175 @@
+//! Following live streams from reservation to VOD
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use super::parser::VideoRef;
+use super::state::LiveState;
+use super::{VLiveEvent, VLiveVideo, VideoStage};
+
+/// A followed live stream moved on to another stage
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transition {
+    /// The video as it is now
+    pub video: VLiveVideo,
+    pub from: VideoStage,
+    pub to: VideoStage,
+    /// When the video was first seen in `from`
+    pub since: SystemTime,
+    /// When the video was first seen in `to`
+    pub at: SystemTime,
+}
+
+/// Where a followed video is
+#[derive(Debug, Clone)]
+struct Live {
+    /// The video as it was last seen
+    video: VLiveVideo,
+    /// When it was first seen in its current stage
+    since: SystemTime,
+    /// Last time it was on any page
+    seen: SystemTime,
+}
+
+/// Live streams being followed, by seq
+///
+/// Announced videos that aren't VODs yet are followed until they are, or
+/// until they haven't been seen for `ttl`
+#[derive(Debug, Clone)]
+pub struct Lifecycle {
+    ttl: Duration,
+    lives: HashMap<u64, Live>,
+}
+
+impl Lifecycle {
+    pub fn new(ttl: Duration) -> Self {
+        Lifecycle {
+            ttl,
+            lives: HashMap::new(),
+        }
+    }
+
+    /// Is the video with `seq` being followed
+    pub fn follows(&self, seq: u64) -> bool {
+        self.lives.contains_key(&seq)
+    }
+
+    /// Is nothing being followed
+    pub fn is_empty(&self) -> bool {
+        self.lives.is_empty()
+    }
+
+    /// Start following a video that was just announced, unless it's a VOD
+    /// already
+    pub fn start(&mut self, video: &VideoRef, at: SystemTime) {
+        if video.video_stage != VideoStage::VOD {
+            self.lives.insert(video.video_seq, Live {
+                video: video.clone().into_owned(),
+                since: at,
+                seen: at,
+            });
+        }
+    }
+
+    /// Look at a followed video again
+    ///
+    /// Returns a `VLiveEvent::Transition` if it's in another stage than last
+    /// time. Once it's a VOD, that's followed by `VLiveEvent::LiveEnded` and
+    /// the video isn't followed anymore
+    pub fn see(&mut self, video: VideoRef, at: SystemTime) -> Vec<VLiveEvent> {
+        let live = match self.lives.get_mut(&video.video_seq) {
+            Some(live) => live,
+            None => return Vec::new(),
+        };
+        live.seen = at;
+        if live.video.video_stage == video.video_stage {
+            return Vec::new();
+        }
+
+        let video = video.into_owned();
+        let transition = Transition {
+            from: live.video.video_stage,
+            to: video.video_stage,
+            since: live.since,
+            at,
+            video: video.clone(),
+        };
+        let previous = std::mem::replace(&mut live.video, video);
+        live.since = at;
+
+        let mut events = vec![VLiveEvent::Transition(transition)];
+        if live.video.video_stage == VideoStage::VOD {
+            let vod = self.lives.remove(&previous.video_seq).map(|live| live.video);
+            events.push(VLiveEvent::LiveEnded { live: previous, vod });
+        }
+        events
+    }
+
+    /// Match a VOD that was just announced under a seq of its own with the
+    /// live stream it's the recording of
+    ///
+    /// The stream has to be live or ended, on the same channel and have the
+    /// VOD's title or a part of it. Returns `VLiveEvent::LiveEnded` if
+    /// there's one, which isn't followed anymore
+    pub fn archive(&mut self, vod: &VideoRef) -> Option<VLiveEvent> {
+        if vod.video_stage != VideoStage::VOD || vod.channel_seq == 0 {
+            return None;
+        }
+        let title = vod.video_title.trim();
+        let seq = self.lives.iter()
+            .filter(|(&seq, live)| {
+                let live = &live.video;
+                seq < vod.video_seq
+                    && live.channel_seq == vod.channel_seq
+                    && matches!(live.video_stage, VideoStage::LIVE | VideoStage::ENDED)
+                    && !live.video_title.trim().is_empty()
+                    && title.contains(live.video_title.trim())
+            })
+            .max_by_key(|(_, live)| live.since)
+            .map(|(&seq, _)| seq)?;
+        let live = self.lives.remove(&seq)?;
+        Some(VLiveEvent::LiveEnded {
+            live: live.video,
+            vod: Some(vod.clone().into_owned()),
+        })
+    }
+
+    /// Give up on videos that haven't been seen for the ttl
+    ///
+    /// Returns `VLiveEvent::LiveEnded` without a VOD for every stream that
+    /// went live but was never seen as a VOD
+    pub fn expire(&mut self, at: SystemTime) -> Vec<VLiveEvent> {
+        let ttl = self.ttl;
+        let expired: Vec<_> = self.lives.iter()
+            .filter(|(_, live)| at.duration_since(live.seen).unwrap_or_default() >= ttl)
+            .map(|(&seq, _)| seq)
+            .collect();
+        expired.into_iter()
+            .filter_map(|seq| self.lives.remove(&seq))
+            .filter(|live| live.video.video_stage != VideoStage::UPCOMING)
+            .map(|live| VLiveEvent::LiveEnded { live: live.video, vod: None })
+            .collect()
+    }
+
+    /// Every followed video, in no particular order
+    pub fn entries(&self) -> Vec<LiveState> {
+        self.lives.values()
+            .map(|live| LiveState {
+                video: live.video.clone(),
+                since: live.since,
+            })
+            .collect()
+    }
+
+    /// Follow the videos in `entries`, as if they were just seen
+    pub fn restore(&mut self, entries: &[LiveState]) {
+        let now = SystemTime::now();
+        for entry in entries {
+            self.lives.insert(entry.video.video_seq, Live {
+                video: entry.video.clone(),
+                since: entry.since,
+                seen: now,
+            });
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Tooting new videos from a Mastodon account
+//!
+//! Make an application under Preferences, Development on the account's
+//! instance with the `write:statuses` scope, and use its access token:
+//!
+//! ```rust,ignore
+//! let mastodon = Mastodon::new("https://mastodon.social", &token).visibility("unlisted");
+//! VLive::new(mastodon, Duration::from_secs(5)).run();
+//! ```
+//!
+//! Every video becomes a status linking to it, which the instance turns
+//! into a preview card with the thumbnail. Paid videos are put behind a
+//! content warning. The wording is a `Template`, see `Mastodon::template`.
+//! Statuses are sent with an idempotency key made from the event, so a
+//! retried delivery doesn't toot the same video twice.
+
+use serde_json::json;
+
+use super::sink::deliver_new;
+use super::template::Template;
+use super::transport::answer;
+use super::{Ctx, DeliveryError, Event, Flow, VLiveCallback, VLiveVideo};
+
+/// Toots every new video
+pub struct Mastodon {
+    instance: String,
+    token: String,
+    agent: ureq::Agent,
+    visibility: String,
+    paid_warning: String,
+    template: Template,
+}
+
+/// The status about a video
+const STATUS: &str = "{{channel_name}} {{#if live}}is live{{else}}uploaded{{/if}}{{#if paid}} (paid){{/if}}: {{title}}\n\n{{url}}";
+
+impl Mastodon {
+    /// Toot from the account of `token` on the instance at `instance`
+    pub fn new(instance: &str, token: &str) -> Self {
+        Mastodon {
+            instance: instance.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            agent: ureq::AgentBuilder::new().build(),
+            visibility: "public".to_string(),
+            paid_warning: "Paid content".to_string(),
+            template: Template::parse(STATUS).expect("the default template parses"),
+        }
+    }
+
+    /// Word the statuses with `template` instead of the English default
+    ///
+    /// ```rust,ignore
+    /// let mastodon = Mastodon::new("https://mastodon.social", &token)
+    ///     .template(Template::parse("{{channel_name}}: {{title}} {{url}} #VLIVE")?);
+    /// ```
+    pub fn template(mut self, template: Template) -> Self {
+        self.template = template;
+        self
+    }
+
+    /// Who sees the statuses, `"public"` by default. `"unlisted"` keeps
+    /// them off the public timelines
+    pub fn visibility(mut self, visibility: &str) -> Self {
+        self.visibility = visibility.to_string();
+        self
+    }
+
+    /// Content warning paid videos are put behind, `"Paid content"` by
+    /// default
+    pub fn paid_warning(mut self, warning: &str) -> Self {
+        self.paid_warning = warning.to_string();
+        self
+    }
+
+    /// Toot about `event`
+    pub fn post(&self, event: &Event) -> Result<(), DeliveryError> {
+        let video = &event.video;
+        let url = format!("{}/api/v1/statuses", self.instance);
+        let request = self.agent.post(&url)
+            .set("Authorization", &format!("Bearer {}", self.token))
+            .set("Idempotency-Key", &format!("vlive-{}-{}", video.video_seq, event.id));
+        let spoiler = if video.video_paid { self.paid_warning.as_str() } else { "" };
+        let body = serde_json::to_string(&json!({
+            "status": self.template.render(video),
+            "spoiler_text": spoiler,
+            "sensitive": video.video_paid,
+            "visibility": self.visibility,
+        }))?;
+        answer(request.set("Content-Type", "application/json").send_string(&body), &url)?;
+        Ok(())
+    }
+}
+
+impl VLiveCallback for Mastodon {
+    fn on_new(&mut self, ctx: &Ctx, video: VLiveVideo) -> Flow {
+        deliver_new(self, ctx, video)
+    }
+
+    fn deliver(&mut self, _: &Ctx, event: &Event) -> Result<Flow, DeliveryError> {
+        self.post(event)?;
+        Ok(Flow::Continue)
+    }
+}
@@ -0,0 +1,187 @@
+//! Posting new videos to a Matrix room
+//!
+//! Talks to the homeserver's client-server API with the access token of a
+//! bot account, which Element shows under Settings, Help & About. Invite
+//! the account to the room first:
+//!
+//! ```rust,ignore
+//! let matrix = Matrix::new("https://matrix.org", &token, "!AbCdEf:matrix.org");
+//! VLive::new(matrix, Duration::from_secs(5)).run();
+//! ```
+//!
+//! Every video becomes an HTML message linking to it, followed by its
+//! thumbnail uploaded to the homeserver. The wording is a `Template`, see
+//! `Matrix::template`. Messages are sent with a
+//! transaction id made from the event, so a retried delivery doesn't post
+//! the same video twice.
+
+use std::sync::Arc;
+
+use serde_json::json;
+
+use super::sink::deliver_new;
+use super::template::Template;
+use super::thumbnails::{download_typed, image_type, ThumbnailCache};
+use super::transport::{answer, percent_encode, TransportError};
+use super::{Ctx, DeliveryError, Event, Flow, ThumbSize, VLiveCallback, VLiveVideo};
+
+/// Posts every new video to a Matrix room
+///
+/// Also a sink for `Subscriptions`, where each subscription's `target` is
+/// the room to post to:
+///
+/// ```rust,ignore
+/// let matrix = Matrix::new("https://matrix.org", &token, "");
+/// let subscriptions = Subscriptions::open("subscriptions.json")?
+///     .sink("matrix", move |subscription, event| matrix.post(&subscription.target, event));
+/// ```
+pub struct Matrix {
+    homeserver: String,
+    token: String,
+    room: String,
+    agent: ureq::Agent,
+    thumbnails: bool,
+    cache: Option<Arc<ThumbnailCache>>,
+    text: Template,
+    html: Option<Template>,
+}
+
+/// The message about a video, for clients without HTML
+const TEXT: &str = "{{channel_name}} {{#if live}}is live{{else}}uploaded{{/if}}{{#if paid}} (paid){{/if}}: {{title}}\n{{url}}";
+
+/// The message about a video
+const HTML: &str = "<b>{{channel_name}}</b> {{#if live}}is live{{else}}uploaded{{/if}}{{#if paid}} (paid){{/if}}: <a href=\"{{url}}\">{{title}}</a>";
+
+impl Matrix {
+    /// Post to `room`, a room id like `!AbCdEf:matrix.org`, on the
+    /// homeserver at `homeserver` as the account of `token`
+    pub fn new(homeserver: &str, token: &str, room: &str) -> Self {
+        Matrix {
+            homeserver: homeserver.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            room: room.to_string(),
+            agent: ureq::AgentBuilder::new().build(),
+            thumbnails: true,
+            cache: None,
+            text: Template::parse(TEXT).expect("the default template parses"),
+            html: Some(Template::parse(HTML).expect("the default template parses")),
+        }
+    }
+
+    /// Word the messages with `template` instead of the English default
+    ///
+    /// Messages are then sent as plain text, give `html_template` too for
+    /// formatting
+    ///
+    /// ```rust,ignore
+    /// let matrix = Matrix::new("https://matrix.org", &token, "!AbCdEf:matrix.org")
+    ///     .template(Template::parse("{{channel_name}}: {{title}} {{url}}")?);
+    /// ```
+    pub fn template(mut self, template: Template) -> Self {
+        self.text = template;
+        self.html = None;
+        self
+    }
+
+    /// Format the messages with `template`, for the clients that show HTML
+    ///
+    /// The fields are escaped, the template itself can be any HTML Matrix
+    /// allows in messages
+    pub fn html_template(mut self, template: Template) -> Self {
+        self.html = Some(template);
+        self
+    }
+
+    /// Upload thumbnails or not, on by default
+    pub fn thumbnails(mut self, upload: bool) -> Self {
+        self.thumbnails = upload;
+        self
+    }
+
+    /// Take thumbnails from `cache` instead of downloading them every time
+    pub fn thumbnail_cache(mut self, cache: Arc<ThumbnailCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Post `event` to `room`
+    ///
+    /// A thumbnail that can't be uploaded is printed to stderr, only the
+    /// message itself failing fails the delivery
+    pub fn post(&self, room: &str, event: &Event) -> Result<(), DeliveryError> {
+        let video = &event.video;
+        let txn = format!("vlive-{}-{}", video.video_seq, event.id);
+        let mut message = json!({
+            "msgtype": "m.text",
+            "body": self.text.render(video),
+        });
+        if let Some(ref html) = self.html {
+            message["format"] = json!("org.matrix.custom.html");
+            message["formatted_body"] = json!(html.render_html(video));
+        }
+        self.send(room, &txn, message)?;
+
+        if self.thumbnails {
+            let result = self.upload(video).and_then(|uploaded| match uploaded {
+                Some((uri, mimetype)) => self.send(room, &format!("{}-thumbnail", txn), json!({
+                    "msgtype": "m.image",
+                    "body": video.video_title,
+                    "url": uri,
+                    "info": { "mimetype": mimetype },
+                })),
+                None => Ok(()),
+            });
+            if let Err(why) = result {
+                eprintln!("VLive Error: Could not post the thumbnail of {} to Matrix: {}", video.video_id, why);
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a message with `content` to `room`
+    fn send(&self, room: &str, txn: &str, content: serde_json::Value) -> Result<(), TransportError> {
+        let url = format!("{}/_matrix/client/v3/rooms/{}/send/m.room.message/{}",
+            self.homeserver, percent_encode(room), percent_encode(txn));
+        let request = self.agent.put(&url)
+            .set("Authorization", &format!("Bearer {}", self.token))
+            .set("Content-Type", "application/json");
+        answer(request.send_string(&serde_json::to_string(&content)?), &url).map(|_| ())
+    }
+
+    /// Upload the thumbnail of `video`, returning its `mxc://` URI and
+    /// type, or `None` if it has no thumbnail
+    fn upload(&self, video: &VLiveVideo) -> Result<Option<(String, String)>, TransportError> {
+        let thumbnail = match self.cache {
+            Some(ref cache) => cache.get(video)?.map(|image| {
+                let content_type = image_type(&image).to_string();
+                (image, content_type)
+            }),
+            None => video.thumbnail_url(ThumbSize::LARGE).as_deref().map(download_typed).transpose()?,
+        };
+        let (image, content_type) = match thumbnail {
+            Some(thumbnail) => thumbnail,
+            None => return Ok(None),
+        };
+
+        let url = format!("{}/_matrix/media/v3/upload?filename=thumbnail", self.homeserver);
+        let request = self.agent.post(&url)
+            .set("Authorization", &format!("Bearer {}", self.token))
+            .set("Content-Type", &content_type);
+        let uploaded: serde_json::Value = serde_json::from_str(&answer(request.send_bytes(&image), &url)?)?;
+        match uploaded["content_uri"].as_str() {
+            Some(uri) => Ok(Some((uri.to_string(), content_type))),
+            None => Err("the homeserver didn't say where the thumbnail went".into()),
+        }
+    }
+}
+
+impl VLiveCallback for Matrix {
+    fn on_new(&mut self, ctx: &Ctx, video: VLiveVideo) -> Flow {
+        deliver_new(self, ctx, video)
+    }
+
+    fn deliver(&mut self, _: &Ctx, event: &Event) -> Result<Flow, DeliveryError> {
+        self.post(&self.room, event)?;
+        Ok(Flow::Continue)
+    }
+}
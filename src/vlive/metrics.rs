@@ -0,0 +1,237 @@
+//! Counting polls, errors and events
+//!
+//! A listener reports to a `Metrics` while it runs: how long every fetch
+//! took, every error and every event handed to the callback, each with the
+//! id of the round of polling it came from. `Statsd` sends
+//! them to a statsd or DogStatsD agent, `JsonLog` writes them out as JSON
+//! lines for a log shipper, implement `Metrics` yourself for anything else.
+
+use std::io::{self, Write};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Value};
+
+use super::{Feed, VLiveError, VLiveEvent, VLiveVideo};
+
+/// Where a listener reports what it's doing
+///
+/// Called from the listener's threads, so keep it quick. Everything does
+/// nothing by default. `poll_id` is the round of polling the fetch, error
+/// or event belongs to, see `Poller::poll_id`, or 0 if none
+pub trait Metrics: Send + Sync + 'static {
+    /// `feed` was fetched, which took `latency`
+    fn poll(&self, _poll_id: u64, _feed: &Feed, _latency: Duration) {}
+
+    /// `error` is about to be passed to the callback
+    fn error(&self, _poll_id: u64, _error: &VLiveError) {}
+
+    /// `event` is about to be passed to the callback
+    fn event(&self, _poll_id: u64, _event: &VLiveEvent) {}
+}
+
+/// Sends metrics to a statsd agent over UDP
+///
+/// Reports `vlive.polls` and `vlive.poll.latency` for every fetch,
+/// `vlive.errors` and `vlive.events` by kind. With `dogstatsd`, kinds and
+/// feeds are tags, otherwise kinds are part of the metric name. Packets that
+/// can't be sent are dropped, like statsd clients do
+///
+/// ```rust,ignore
+/// VLive::new(Handler, Duration::from_secs(5))
+///     .metrics(Arc::new(Statsd::new("127.0.0.1:8125")?.dogstatsd()))
+///     .run();
+/// ```
+pub struct Statsd {
+    socket: UdpSocket,
+    prefix: String,
+    tags: bool,
+}
+
+impl Statsd {
+    /// Send to the agent at `addr`, with metric names starting with `vlive.`
+    pub fn new<A>(addr: A) -> io::Result<Self> where A: ToSocketAddrs {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(addr)?;
+        Ok(Statsd {
+            socket,
+            prefix: "vlive.".to_string(),
+            tags: false,
+        })
+    }
+
+    /// Start metric names with `prefix` instead
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Use DogStatsD tags for kinds and feeds
+    pub fn dogstatsd(mut self) -> Self {
+        self.tags = true;
+        self
+    }
+
+    /// Send one metric, tagged with `tag` if DogStatsD is on
+    fn send(&self, name: &str, value: &str, tag: Option<(&str, &str)>) {
+        let line = match tag {
+            Some((key, tag)) if self.tags => format!("{}{}:{}|#{}:{}", self.prefix, name, value, key, tag),
+            _ => format!("{}{}:{}", self.prefix, name, value),
+        };
+        let _ = self.socket.send(line.as_bytes());
+    }
+
+    /// Count one of `kind`, a tag or the end of the name
+    fn count(&self, name: &str, kind: &str) {
+        if self.tags {
+            self.send(name, "1|c", Some(("kind", kind)));
+        } else {
+            self.send(&format!("{}.{}", name, kind), "1|c", None);
+        }
+    }
+}
+
+impl Metrics for Statsd {
+    fn poll(&self, _poll_id: u64, feed: &Feed, latency: Duration) {
+        let feed = match *feed {
+            Feed::Recent => "recent",
+            Feed::Channel(ref code) => code,
+            Feed::Directory => "directory",
+            Feed::Weverse(ref community) => community,
+            Feed::YouTube(ref channel) | Feed::NaverTv(ref channel) => channel,
+        };
+        self.send("polls", "1|c", Some(("feed", feed)));
+        self.send("poll.latency", &format!("{}|ms", latency.as_millis()), Some(("feed", feed)));
+    }
+
+    fn error(&self, _poll_id: u64, error: &VLiveError) {
+        self.count("errors", error.kind());
+    }
+
+    fn event(&self, _poll_id: u64, event: &VLiveEvent) {
+        self.count("events", event_kind(event));
+    }
+}
+
+/// Name of the kind of `event`, as reported to metrics
+fn event_kind(event: &VLiveEvent) -> &'static str {
+    match *event {
+        VLiveEvent::New(_) => "new",
+        VLiveEvent::PossibleGap { .. } => "possible_gap",
+        VLiveEvent::Transition(_) => "transition",
+        VLiveEvent::LiveEnded { .. } => "live_ended",
+        VLiveEvent::VodPublished(_) => "vod_published",
+        VLiveEvent::FollowerMilestone { .. } => "follower_milestone",
+        VLiveEvent::NewChannelCreated(_) => "new_channel_created",
+    }
+}
+
+/// Writes every poll, error and event as one line of JSON
+///
+/// For shipping a listener's logs to Loki, CloudWatch or anything else
+/// that reads JSON lines, without parsing free-form text. Every line is an
+/// object with `ts`, an RFC 3339 timestamp, `level`, the `poll_id` it
+/// belongs to if any, and `msg`, which is one of:
+///
+/// - `"poll"`, with the `feed` and its `url`, and `latency_ms`
+/// - `"error"`, with its `kind` and `error` message
+/// - `"event"`, with its `kind`, and the `seq`, `code` and `channel` of
+///   the video or channel it's about, or the `feed` it happened on
+///
+/// ```rust,ignore
+/// VLive::new(Handler, Duration::from_secs(5))
+///     .metrics(Arc::new(JsonLog::new()))
+///     .run();
+/// ```
+///
+/// Lines that can't be written are dropped
+pub struct JsonLog {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonLog {
+    /// Write to stderr
+    pub fn new() -> Self {
+        JsonLog::to(io::stderr())
+    }
+
+    /// Write to `out` instead, like a file or stdout
+    pub fn to<W>(out: W) -> Self where W: Write + Send + 'static {
+        JsonLog {
+            out: Mutex::new(Box::new(out)),
+        }
+    }
+
+    /// Stamp `line` with the time and its poll, and write it out
+    fn write(&self, level: &str, msg: &str, poll_id: u64, mut line: Value) {
+        if let Value::Object(ref mut fields) = line {
+            fields.insert("ts".to_string(), Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true).into());
+            fields.insert("level".to_string(), level.into());
+            fields.insert("msg".to_string(), msg.into());
+            if poll_id != 0 {
+                fields.insert("poll_id".to_string(), poll_id.into());
+            }
+        }
+        let mut out = self.out.lock().unwrap();
+        let _ = writeln!(out, "{}", line).and_then(|_| out.flush());
+    }
+}
+
+impl Default for JsonLog {
+    fn default() -> Self {
+        JsonLog::new()
+    }
+}
+
+impl Metrics for JsonLog {
+    fn poll(&self, poll_id: u64, feed: &Feed, latency: Duration) {
+        self.write("info", "poll", poll_id, json!({
+            "feed": feed,
+            "url": feed.url(),
+            "latency_ms": latency.as_millis() as u64,
+        }));
+    }
+
+    fn error(&self, poll_id: u64, error: &VLiveError) {
+        self.write("error", "error", poll_id, json!({
+            "kind": error.kind(),
+            "error": error.to_string(),
+        }));
+    }
+
+    fn event(&self, poll_id: u64, event: &VLiveEvent) {
+        let video = |video: &VLiveVideo| json!({
+            "kind": event_kind(event),
+            "seq": video.video_seq,
+            "code": video.video_code(),
+            "channel": video.channel_name,
+        });
+        let line = match *event {
+            VLiveEvent::New(ref event) => video(&event.video),
+            VLiveEvent::Transition(ref transition) => video(&transition.video),
+            VLiveEvent::LiveEnded { ref live, .. } => video(live),
+            VLiveEvent::VodPublished(ref vod) => video(vod),
+            VLiveEvent::NewChannelCreated(ref channel) => json!({
+                "kind": event_kind(event),
+                "seq": channel.channel_seq,
+                "code": channel.channel_id,
+                "channel": channel.channel_name,
+            }),
+            VLiveEvent::PossibleGap { ref feed, ref missed_range } => json!({
+                "kind": event_kind(event),
+                "feed": feed,
+                "missed_from": missed_range.start,
+                "missed_to": missed_range.end,
+            }),
+            VLiveEvent::FollowerMilestone { ref feed, followers, milestone } => json!({
+                "kind": event_kind(event),
+                "feed": feed,
+                "followers": followers,
+                "milestone": milestone,
+            }),
+        };
+        self.write("info", "event", poll_id, line);
+    }
+}
@@ -0,0 +1,68 @@
+//! Processing stages between the poller and the callback, `Middleware`
+
+use std::{thread, time};
+
+use super::VLiveVideo;
+
+/// A processing stage between the poller and your callback
+///
+/// Stages run in the order they were added with `VLive::middleware`, on the
+/// dispatcher thread. Each one gets a video and either passes it on
+/// (possibly changed) or drops it by returning `None`. Since the dispatcher
+/// has its own thread, a stage is also free to sleep to delay events.
+///
+/// Closures of `FnMut(VLiveVideo) -> Option<VLiveVideo>` are stages too
+pub trait Middleware: Send + 'static {
+    fn process(&mut self, video: VLiveVideo) -> Option<VLiveVideo>;
+}
+
+impl<F> Middleware for F where F: FnMut(VLiveVideo) -> Option<VLiveVideo> + Send + 'static {
+    fn process(&mut self, video: VLiveVideo) -> Option<VLiveVideo> {
+        self(video)
+    }
+}
+
+/// Only pass on videos for which `predicate` returns `true`
+pub fn filter<P>(mut predicate: P) -> impl Middleware
+    where P: FnMut(&VLiveVideo) -> bool + Send + 'static
+{
+    move |video: VLiveVideo| if predicate(&video) { Some(video) } else { None }
+}
+
+/// Change every video passing through, for example to translate the title
+pub fn map<M>(mut map: M) -> impl Middleware
+    where M: FnMut(VLiveVideo) -> VLiveVideo + Send + 'static
+{
+    move |video: VLiveVideo| Some(map(video))
+}
+
+/// Pass on at most one video every `interval`
+///
+/// Videos arriving faster than that are held back, not dropped, so a burst
+/// of uploads gets spread out over time.
+pub struct RateLimit {
+    interval: time::Duration,
+    last: Option<time::Instant>,
+}
+
+impl RateLimit {
+    pub fn new(interval: time::Duration) -> Self {
+        RateLimit {
+            interval,
+            last: None,
+        }
+    }
+}
+
+impl Middleware for RateLimit {
+    fn process(&mut self, video: VLiveVideo) -> Option<VLiveVideo> {
+        if let Some(last) = self.last {
+            let elapsed = last.elapsed();
+            if elapsed < self.interval {
+                thread::sleep(self.interval - elapsed);
+            }
+        }
+        self.last = Some(time::Instant::now());
+        Some(video)
+    }
+}
@@ -0,0 +1,502 @@
+//! Videos, channels, feeds and events, what everything else passes around
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use super::lifecycle::Transition;
+use super::source::{self, Source};
+
+/// VLive video type
+///
+/// A video on VLive can either be a `VOD` (Video on demand), aka normal
+/// video or `LIVE`, aka a live stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum VideoType {
+    VOD,
+    LIVE,
+}
+
+/// Where a video is in its life
+///
+/// Live streams are `UPCOMING` while reserved, then `LIVE`, then `ENDED`
+/// until VLive turns them into a `VOD`. Videos uploaded as VODs are
+/// always `VOD`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum VideoStage {
+    UPCOMING,
+    LIVE,
+    ENDED,
+    #[default]
+    VOD,
+}
+
+/// Which kinds of videos to announce
+///
+/// See `VLive::kinds`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventKinds {
+    live: bool,
+    vod: bool,
+}
+
+impl EventKinds {
+    /// Both live streams and VODs
+    pub const ALL: EventKinds = EventKinds { live: true, vod: true };
+    /// Only live streams
+    pub const LIVE_ONLY: EventKinds = EventKinds { live: true, vod: false };
+    /// Only VODs
+    pub const VOD_ONLY: EventKinds = EventKinds { live: false, vod: true };
+
+    /// Should videos of `video_type` be announced
+    pub fn contains(&self, video_type: VideoType) -> bool {
+        match video_type {
+            VideoType::LIVE => self.live,
+            VideoType::VOD => self.vod,
+        }
+    }
+}
+
+impl Default for EventKinds {
+    fn default() -> Self {
+        EventKinds::ALL
+    }
+}
+
+/// Which channels to announce videos from, by channel code like `FE619` or
+/// seq like `363`
+///
+/// Meant for the recent videos page, where it saves writing a middleware
+/// stage for the usual "only these" or "never these" channels. See
+/// `VLive::allow_channels` and `VLive::block_channels`. Deserializes from
+/// `{"allow": [...], "block": [...]}`, either list can be left out
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelFilter {
+    /// Only these channels, or every channel if empty
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Never these channels, even if allowed
+    #[serde(default)]
+    pub block: Vec<String>,
+}
+
+impl ChannelFilter {
+    /// Only announce videos from `channels`, on top of any allowed before
+    pub fn allow(mut self, channels: &[&str]) -> Self {
+        self.allow.extend(channels.iter().map(|channel| channel.to_string()));
+        self
+    }
+
+    /// Never announce videos from `channels`
+    pub fn block(mut self, channels: &[&str]) -> Self {
+        self.block.extend(channels.iter().map(|channel| channel.to_string()));
+        self
+    }
+
+    /// Should videos from the channel at `channel_id`, like `/channel/FE619`,
+    /// with `channel_seq` be announced
+    pub fn contains(&self, channel_id: &str, channel_seq: u64) -> bool {
+        let code = channel_id.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+        let seq = channel_seq.to_string();
+        let listed = |channels: &[String]| channels.iter()
+            .any(|channel| channel.eq_ignore_ascii_case(code) || *channel == seq);
+        (self.allow.is_empty() || listed(&self.allow)) && !listed(&self.block)
+    }
+}
+
+/// Where a video was found
+///
+/// `VLIVE` for VLive's own pages, the rest come from a `Source`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Platform {
+    #[default]
+    VLIVE,
+    WEVERSE,
+    YOUTUBE,
+    NAVERTV,
+}
+
+/// VLive channel type
+///
+/// A channel can either be a `BASIC` (normal) or a `PLUS` (Channel+), which
+/// is a special premium channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ChannelType {
+    BASIC,
+    PLUS,
+}
+
+/// Information about a VLive video or a live stream
+///
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VLiveVideo {
+    /// Common ID of a video
+    ///
+    /// This is the "user facing" ID of a video, most
+    /// commonly seen in VLive links.
+    /// Something like `"/video/50000"`.
+    ///
+    /// # Examples
+    ///
+    /// To turn this to a valid URL, `video.url()`, which is
+    /// `format!("https://vlive.tv{}", video.video_id);`. The code alone,
+    /// like `"50000"`, is `video.video_code()`
+    ///
+    pub video_id: String,
+    /// Sequential video ID
+    ///
+    /// This ID is the "backend" ID, which is used
+    /// internally withing VLive. If you are doing other
+    /// api calls, you will most likely use this one to
+    /// reference to a video.
+    pub video_seq: u64,
+    /// The visible title of a video
+    ///
+    /// String containing the name of a video, shown to
+    /// users.
+    pub video_title: String,
+    /// The title in other languages, by language code like `"en"`
+    ///
+    /// Only has the languages asked for with `VLive::languages`, and
+    /// only those VLive has a translation for
+    #[serde(default)]
+    pub video_titles: HashMap<String, String>,
+    /// Either `VideoType::VOD` or `VideoType::LIVE`
+    ///
+    /// Video type can either be a `VOD` (video on demand)
+    /// or `LIVE` (live stream).
+    pub video_type: VideoType,
+    /// Where the video is in its life, see `VideoStage`
+    #[serde(default)]
+    pub video_stage: VideoStage,
+    /// Is this a paid VLIVE+ product
+    ///
+    /// Concerts, fanmeetings and the like that are bought one by one.
+    /// Not the same as `ChannelType::PLUS`, which is a subscription to a
+    /// whole channel
+    #[serde(default)]
+    pub video_paid: bool,
+    /// URL to the thumbnail of this video
+    ///
+    /// Some videos don't always have a thumbnail available,
+    /// especially live streams.
+    pub video_thumbnail: Option<String>,
+    /// Common ID of the channel
+    ///
+    /// This ID is the "user facing" ID of a channel, usually
+    /// seen in VLive channel links, something like `"/channels/EBDF"`
+    ///
+    /// # Examples
+    ///
+    /// To turn this to a valid channel URL, `video.channel_url()`, which
+    /// is `format!("https://vlive.tv{}", video.channel_id);`. The code
+    /// alone, like `"EBDF"`, is `video.channel_code()`
+    pub channel_id: String,
+    /// Sequential channel ID
+    ///
+    /// This is the "backend" ID of a channel. Similar to `video_seq`,
+    /// if you are doing more VLive backend calls, you'll probably need
+    /// this one.
+    pub channel_seq: u64,
+    /// Visible name of the channel
+    ///
+    /// User facing name of a channel
+    pub channel_name: String,
+    /// Either `ChannelType::BASIC` or `ChannelType::PLUS`
+    ///
+    /// VLive channels can be either `BASIC`, which is a normal, free to
+    /// view channel, or a `PLUS` which is a special paid Channel+.
+    /// You need a Channel+ subscription to view these videos
+    pub channel_type: ChannelType,
+    /// Which platform the video is on, see `Platform`
+    #[serde(default)]
+    pub platform: Platform,
+    /// Links to the same broadcast on other platforms
+    ///
+    /// Only filled in when simulcasts are collapsed into one video, see
+    /// `VLive::correlate`
+    #[serde(default)]
+    pub video_mirrors: Vec<String>,
+}
+
+impl VLiveVideo {
+    /// Link to the video
+    ///
+    /// `video_id` is a path on vlive.tv for VLive's own videos, and
+    /// already a whole URL for videos from another `Source`. Either way
+    /// this is an absolute `https` URL, use it for anything people click
+    pub fn url(&self) -> String {
+        absolute(&self.video_id)
+    }
+
+    /// Link to the channel, like `url`
+    pub fn channel_url(&self) -> String {
+        absolute(&self.channel_id)
+    }
+
+    /// The video's own code on its platform, without any path or host
+    ///
+    /// `"50000"` for `/video/50000`, or the `v` parameter of a YouTube link
+    pub fn video_code(&self) -> String {
+        match self.platform {
+            Platform::YOUTUBE => self.video_id.rsplit("v=").next().unwrap_or_default().to_string(),
+            _ => code(&self.video_id),
+        }
+    }
+
+    /// The channel's own code on its platform, like `"FE619"` for
+    /// `/channels/FE619`, see `Feed`
+    pub fn channel_code(&self) -> String {
+        code(&self.channel_id)
+    }
+
+    /// Link to the thumbnail scaled to `size`, `None` if there's no
+    /// thumbnail
+    ///
+    /// VLive's image server scales thumbnails by their `type` parameter,
+    /// whatever size was scraped. Thumbnails from elsewhere, like YouTube,
+    /// only come in the size they were found in
+    pub fn thumbnail_url(&self, size: ThumbSize) -> Option<String> {
+        let url = absolute(self.video_thumbnail.as_ref()?);
+        let (base, query) = match url.split_once('?') {
+            Some((base, query)) => (base, query),
+            None => (url.as_str(), ""),
+        };
+        if !base.contains(".pstatic.net/") {
+            return Some(url);
+        }
+        let params = query.split('&')
+            .filter(|param| !param.is_empty() && !param.starts_with("type="))
+            .map(str::to_string)
+            .chain(size.param().map(|size| format!("type={}", size)))
+            .collect::<Vec<_>>();
+        if params.is_empty() {
+            Some(base.to_string())
+        } else {
+            Some(format!("{}?{}", base, params.join("&")))
+        }
+    }
+}
+
+/// Sizes thumbnails can be scaled to, see `VLiveVideo::thumbnail_url`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ThumbSize {
+    /// 228x128, what lists on VLive show
+    SMALL,
+    /// 640x360
+    MEDIUM,
+    /// 1280x720, for embeds and previews
+    LARGE,
+    /// Whatever was uploaded, unscaled
+    ORIGINAL,
+}
+
+impl ThumbSize {
+    /// The `type` parameter asking for this size
+    fn param(self) -> Option<&'static str> {
+        match self {
+            ThumbSize::SMALL => Some("f228_128"),
+            ThumbSize::MEDIUM => Some("f640_360"),
+            ThumbSize::LARGE => Some("f1280_720"),
+            ThumbSize::ORIGINAL => None,
+        }
+    }
+}
+
+/// `id` as an absolute URL, paths are on vlive.tv
+fn absolute(id: &str) -> String {
+    let id = id.trim();
+    if id.starts_with("https://") || id.starts_with("http://") {
+        id.to_string()
+    } else if let Some(rest) = id.strip_prefix("//") {
+        format!("https://{}", rest)
+    } else {
+        format!("https://vlive.tv/{}", id.trim_start_matches('/'))
+    }
+}
+
+/// The last part of a path or URL, without its query
+fn code(id: &str) -> String {
+    let path = id.trim().split(['?', '#']).next().unwrap_or_default();
+    path.trim_end_matches('/').rsplit('/').next().unwrap_or_default().to_string()
+}
+
+/// A VLive channel, as listed in the channel directory
+///
+/// Same fields as the channel fields of `VLiveVideo`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VLiveChannel {
+    pub channel_id: String,
+    pub channel_seq: u64,
+    pub channel_name: String,
+    pub channel_type: ChannelType,
+}
+
+/// Where to look for new videos
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Feed {
+    /// The global list of recent videos on the front page
+    Recent,
+    /// Videos of a single channel, by channel code (like `"EBDF"`)
+    Channel(String),
+    /// The channel directory, newest channels first
+    ///
+    /// Has no videos, but new channels on it are announced as
+    /// `VLiveEvent::NewChannelCreated`
+    Directory,
+    /// Lives and media of a Weverse community, by its URL name (like
+    /// `"bts"`), see `source::Weverse`
+    Weverse(String),
+    /// Uploads of a YouTube channel, by its id, see `source::YouTube`
+    YouTube(String),
+    /// Clips and lives of a Naver TV channel, by its URL name, see
+    /// `source::NaverTv`
+    NaverTv(String),
+}
+
+impl Feed {
+    /// The page this feed is scraped from
+    pub fn url(&self) -> String {
+        match *self {
+            Feed::Recent => recent_url(15),
+            Feed::Channel(ref code) => format!("http://channels.vlive.tv/{}/video", code),
+            Feed::Directory => "http://channels.vlive.tv/home/channel/list?order=new".to_string(),
+            Feed::Weverse(ref community) => source::Weverse.url(community),
+            Feed::YouTube(ref channel) => source::YouTube.url(channel),
+            Feed::NaverTv(ref channel) => source::NaverTv.url(channel),
+        }
+    }
+
+    /// The `Source` parsing this feed, `None` for VLive's own pages
+    pub fn source(&self) -> Option<&'static dyn Source> {
+        match *self {
+            Feed::Weverse(_) => Some(&source::Weverse),
+            Feed::YouTube(_) => Some(&source::YouTube),
+            Feed::NaverTv(_) => Some(&source::NaverTv),
+            _ => None,
+        }
+    }
+
+    /// The page this feed is scraped from, with titles in `language`
+    pub fn localized_url(&self, language: &str) -> String {
+        let url = self.url();
+        let separator = if url.contains('?') { '&' } else { '?' };
+        format!("{}{}lang={}", url, separator, language)
+    }
+}
+
+/// Page of the `page_size` most recent videos
+pub(crate) fn recent_url(page_size: u32) -> String {
+    format!("http://www.vlive.tv/home/video/more?pageNo=1&pageSize={}&viewType=recent", page_size)
+}
+
+/// A new video, along with when and in which order we saw it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    /// Increases by one for every video delivered by a listener, starting
+    /// from 1 or from the last id in the `Journal`. Use it to order events
+    /// or to process each one exactly once
+    pub id: u64,
+    /// Which round of polling found the video, see `Poller::poll_id`
+    ///
+    /// The same id is on the listener's log lines and errors for that
+    /// round, to follow a late notification back through its fetch, parse
+    /// and dispatch. 0 if the video wasn't found by polling
+    #[serde(default)]
+    pub poll_id: u64,
+    /// When the poller first saw the video. Compare with the time you
+    /// receive it to measure notification latency
+    pub detected_at: SystemTime,
+    pub video: VLiveVideo,
+}
+
+/// Ids of `Event::unsequenced`, counting down so they stay clear of the
+/// listener's
+static UNSEQUENCED: AtomicU64 = AtomicU64::new(u64::MAX);
+
+impl Event {
+    /// `video` as an event, for when a callback is handed a bare video
+    ///
+    /// Ids count down from `u64::MAX`, so they're unique and never clash
+    /// with a listener's, which count up
+    pub fn unsequenced(video: VLiveVideo) -> Self {
+        Event {
+            id: UNSEQUENCED.fetch_sub(1, Ordering::Relaxed),
+            poll_id: 0,
+            detected_at: SystemTime::now(),
+            video,
+        }
+    }
+}
+
+/// Something that happened on VLive
+///
+/// Received from `VLive::channel`
+#[derive(Debug, Clone)]
+pub enum VLiveEvent {
+    /// A new video was uploaded or a live stream started
+    New(Event),
+    /// Videos might have been missed
+    ///
+    /// The last video we saw wasn't on the page anymore, so more videos
+    /// were uploaded between two polls than fit on one page. Videos with
+    /// a `video_seq` in `missed_range` may have been skipped, and it's up
+    /// to you to look them up if you need them
+    PossibleGap {
+        feed: Feed,
+        missed_range: Range<u64>,
+    },
+    /// A live stream moved on to another stage, see `VLive::lifecycle`
+    Transition(Transition),
+    /// A followed live stream is over, see `VLive::lifecycle`
+    ///
+    /// `vod` is its recording, which VLive sometimes posts under a seq of
+    /// its own. It's `None` if the stream was never seen as a VOD before
+    /// the lifecycle's ttl ran out
+    LiveEnded {
+        live: VLiveVideo,
+        vod: Option<VLiveVideo>,
+    },
+    /// An announced live stream came back as a VOD under the same code
+    ///
+    /// Sent instead of announcing the video again, while `VLive::dedup`
+    /// still remembers the stream. Streams followed with `VLive::lifecycle`
+    /// end with `VLiveEvent::LiveEnded` instead
+    VodPublished(VLiveVideo),
+    /// A channel's follower count passed `milestone`, see
+    /// `VLive::follower_milestones`
+    ///
+    /// `followers` is the count when it was noticed. If several
+    /// milestones were passed between two polls, only the highest one is
+    /// announced
+    FollowerMilestone {
+        feed: Feed,
+        followers: u64,
+        milestone: u64,
+    },
+    /// A channel showed up in the directory, see `VLive::discover_channels`
+    NewChannelCreated(VLiveChannel),
+}
+
+/// What the listener should do after a callback returns
+///
+/// Returned from `VLiveCallback::on_new`. Callbacks that don't care can
+/// return `()`, which means `Flow::Continue`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Flow {
+    /// Keep listening
+    #[default]
+    Continue,
+    /// Stop the listener, same as calling `VLiveStopper::stop`
+    Stop,
+}
+
+impl From<()> for Flow {
+    fn from(_: ()) -> Self {
+        Flow::Continue
+    }
+}
@@ -0,0 +1,142 @@
+//! Node.js bindings, for Discord bots and the like written in JavaScript
+//!
+//! Needs the `node` feature, build with `napi build --release --features node`.
+//! The listener works like an `EventEmitter`:
+//!
+//! ```js
+//! const { VLive } = require("vlive-notifs");
+//!
+//! const vlive = new VLive(5000);
+//! vlive.watch("FE619", 5000);
+//! vlive.on("video", video => channel.send(`New video ${video.video_title}`));
+//! vlive.on("error", message => console.error(message));
+//! vlive.start();
+//! ```
+//!
+//! Videos are plain objects with the fields of `VLiveVideo`. Listeners are
+//! called on Node's own thread, whenever it gets to them, so a busy event
+//! loop delays notifications but never loses them.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use napi::threadsafe_function::{ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::{Error, JsFunction, JsUnknown, Result};
+use napi_derive::napi;
+use serde_json::Value;
+
+use super::{Ctx, Feed, Flow, VLive, VLiveCallback, VLiveError, VLiveStopper, VLiveVideo};
+
+/// What `on` can listen to
+const EVENTS: &[&str] = &["video", "error"];
+
+type Listener = ThreadsafeFunction<Value, ErrorStrategy::Fatal>;
+
+/// A listener for JavaScript, see the module docs
+#[napi(js_name = "VLive")]
+pub struct NodeVLive {
+    wait: Duration,
+    feeds: Vec<(Feed, Duration)>,
+    listeners: HashMap<String, Vec<Listener>>,
+    stopper: Option<VLiveStopper>,
+}
+
+#[napi]
+impl NodeVLive {
+    /// New listener polling every `wait` milliseconds, see `VLive::new`
+    #[napi(constructor)]
+    pub fn new(wait: u32) -> Self {
+        NodeVLive {
+            wait: Duration::from_millis(wait.into()),
+            feeds: Vec::new(),
+            listeners: HashMap::new(),
+            stopper: None,
+        }
+    }
+
+    /// Poll the channel `code` every `wait` milliseconds, see `VLive::watch`
+    #[napi]
+    pub fn watch(&mut self, code: String, wait: u32) {
+        self.feeds.push((Feed::Channel(code), Duration::from_millis(wait.into())));
+    }
+
+    /// Call `listener` on every `event`, either `"video"` with a new video or
+    /// `"error"` with what went wrong
+    ///
+    /// Errors nobody listens to are printed to stderr. Listeners added after
+    /// `start` are only called after the next `start`
+    #[napi(ts_args_type = "event: 'video' | 'error', listener: (payload: any) => void")]
+    pub fn on(&mut self, event: String, listener: JsFunction) -> Result<()> {
+        if !EVENTS.contains(&event.as_str()) {
+            return Err(Error::from_reason(format!("unknown event {:?}, expected one of {:?}", event, EVENTS)));
+        }
+        let listener: Listener = listener.create_threadsafe_function(0, |ctx: ThreadSafeCallContext<Value>| {
+            ctx.env.to_js_value(&ctx.value).map(|value: JsUnknown| vec![value])
+        })?;
+        self.listeners.entry(event).or_default().push(listener);
+        Ok(())
+    }
+
+    /// Start listening in the background, see `VLive::run_async`
+    #[napi]
+    pub fn start(&mut self) -> Result<()> {
+        if self.stopper.is_some() {
+            return Err(Error::from_reason("already started"));
+        }
+        let mut vlive = VLive::new(Emitter { listeners: self.listeners.clone() }, self.wait);
+        for (feed, wait) in self.feeds.iter().cloned() {
+            vlive = vlive.feed(feed, wait);
+        }
+        self.stopper = Some(vlive.run_async());
+        Ok(())
+    }
+
+    /// Stop listening, see `VLiveStopper::stop`
+    ///
+    /// Also forgets the listeners, since Node doesn't exit while anything
+    /// could still call them. Add them again with `on` before starting again
+    #[napi]
+    pub fn stop(&mut self) {
+        if let Some(stopper) = self.stopper.take() {
+            stopper.stop();
+        }
+        self.listeners.clear();
+    }
+}
+
+/// Hands everything to the JavaScript listeners
+struct Emitter {
+    listeners: HashMap<String, Vec<Listener>>,
+}
+
+impl Emitter {
+    /// Queue a call to every listener of `event`, returns `false` if there
+    /// are none
+    fn emit(&self, event: &str, payload: Value) -> bool {
+        let listeners = match self.listeners.get(event) {
+            Some(listeners) if !listeners.is_empty() => listeners,
+            _ => return false,
+        };
+        for listener in listeners {
+            listener.call(payload.clone(), ThreadsafeFunctionCallMode::NonBlocking);
+        }
+        true
+    }
+}
+
+impl VLiveCallback for Emitter {
+    fn on_new(&mut self, _: &Ctx, video: VLiveVideo) -> Flow {
+        match serde_json::to_value(&video) {
+            Ok(video) => { self.emit("video", video); },
+            Err(why) => eprintln!("VLive Error: Couldn't pass video {} to JavaScript: {}", video.video_seq, why),
+        }
+        Flow::Continue
+    }
+
+    fn on_error(&mut self, error: VLiveError) -> Flow {
+        if !self.emit("error", Value::String(error.to_string())) {
+            eprintln!("VLive Error: {}", error);
+        }
+        Flow::Continue
+    }
+}
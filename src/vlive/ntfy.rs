@@ -0,0 +1,118 @@
+//! Push notifications to phones through ntfy
+//!
+//! No account or API key needed, install the ntfy app, subscribe to a
+//! topic nobody will guess and publish to it:
+//!
+//! ```rust,ignore
+//! VLive::new(Ntfy::new("vlive-fe619-k2x8q"), Duration::from_secs(5)).run();
+//! ```
+//!
+//! Every video becomes a notification titled with what happened, which
+//! opens the video when tapped and has its thumbnail attached. The wording
+//! is up to `Template`s, see `Ntfy::template`.
+
+use super::sink::deliver_new;
+use super::template::Template;
+use super::transport::{answer, percent_encode};
+use super::{Ctx, DeliveryError, Event, Flow, ThumbSize, VLiveCallback, VLiveVideo, VideoType};
+
+/// Publishes every new video to an ntfy topic
+///
+/// Also a sink for `Subscriptions`, where each subscription's `target` is
+/// the topic to publish to:
+///
+/// ```rust,ignore
+/// let ntfy = Ntfy::new("");
+/// let subscriptions = Subscriptions::open("subscriptions.json")?
+///     .sink("ntfy", move |subscription, event| ntfy.post(&subscription.target, event));
+/// ```
+pub struct Ntfy {
+    server: String,
+    topic: String,
+    token: Option<String>,
+    agent: ureq::Agent,
+    title: Template,
+    message: Template,
+}
+
+/// Title of the notification about a video
+const TITLE: &str = "{{channel_name}} {{#if live}}is live{{else}}uploaded{{/if}}{{#if paid}} (paid){{/if}}";
+
+/// Text of the notification about a video
+const MESSAGE: &str = "{{title}}";
+
+impl Ntfy {
+    /// Publish to `topic` on ntfy.sh
+    pub fn new(topic: &str) -> Self {
+        Ntfy {
+            server: "https://ntfy.sh".to_string(),
+            topic: topic.to_string(),
+            token: None,
+            agent: ureq::AgentBuilder::new().build(),
+            title: Template::parse(TITLE).expect("the default template parses"),
+            message: Template::parse(MESSAGE).expect("the default template parses"),
+        }
+    }
+
+    /// Word the notifications with `message` instead of just the title of
+    /// the video
+    ///
+    /// ```rust,ignore
+    /// let ntfy = Ntfy::new("vlive-fe619-k2x8q")
+    ///     .template(Template::parse("{{title}}{{#if mirrors}}, also on {{mirrors}}{{/if}}")?)
+    ///     .title_template(Template::parse("{{channel_name}} {{#if live}}라이브{{else}}새 영상{{/if}}")?);
+    /// ```
+    pub fn template(mut self, message: Template) -> Self {
+        self.message = message;
+        self
+    }
+
+    /// Title the notifications with `title` instead of the English default
+    pub fn title_template(mut self, title: Template) -> Self {
+        self.title = title;
+        self
+    }
+
+    /// Publish to a self-hosted server at `server` instead
+    pub fn server(mut self, server: &str) -> Self {
+        self.server = server.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Publish with an access token, for servers that don't let everyone
+    /// publish
+    pub fn token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Publish `event` to `topic`
+    pub fn post(&self, topic: &str, event: &Event) -> Result<(), DeliveryError> {
+        let video = &event.video;
+        let mut url = format!("{}/{}?title={}&click={}&tags={}", self.server, percent_encode(topic),
+            percent_encode(&self.title.render(video)),
+            percent_encode(&video.url()),
+            if video.video_type == VideoType::LIVE { "red_circle" } else { "tv" });
+        if let Some(thumbnail) = video.thumbnail_url(ThumbSize::LARGE) {
+            url.push_str(&format!("&attach={}", percent_encode(&thumbnail)));
+        }
+
+        let mut request = self.agent.put(&url);
+        if let Some(ref token) = self.token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        answer(request.send_string(&self.message.render(video)), &url)?;
+        Ok(())
+    }
+}
+
+impl VLiveCallback for Ntfy {
+    fn on_new(&mut self, ctx: &Ctx, video: VLiveVideo) -> Flow {
+        deliver_new(self, ctx, video)
+    }
+
+    fn deliver(&mut self, _: &Ctx, event: &Event) -> Result<Flow, DeliveryError> {
+        self.post(&self.topic, event)?;
+        Ok(Flow::Continue)
+    }
+}
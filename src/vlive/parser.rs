@@ -0,0 +1,595 @@
+//! Turning VLive pages into `VLiveVideo`s
+//!
+//! VLive changes its markup every now and then, so instead of one fixed set
+//! of selectors the parser is an ordered list of `Strategy`s. Every video on
+//! the page is tried with each strategy in turn until one of them succeeds,
+//! which lets small redesigns degrade gracefully instead of breaking every
+//! notification at once.
+//!
+//! Strategies produce `VideoRef`s, which borrow their text straight from the
+//! page. Most polls find nothing new, so only the videos that actually get
+//! announced are turned into owned `VLiveVideo`s.
+//!
+//! Pages are parsed with `scraper`, whose compiled CSS selectors are a lot
+//! cheaper to match than walking the tree with `select` predicates.
+//!
+//! Titles and channel names are cleaned up with `normalize` whichever
+//! strategy found them, so they compare equal to what people type.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use scraper::{ElementRef, Html, Selector};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+use super::{ChannelType, ParseError, ParseFailure, Platform, Unavailable, VLiveChannel, VLiveVideo, VideoStage, VideoType};
+
+/// A parsed HTML page
+pub type Document = Html;
+
+/// An element on a page
+pub type Node<'a> = ElementRef<'a>;
+
+/// Selector of the element wrapping each video on a page
+pub const VIDEO: &str = ".video_list_cont";
+
+const FALLBACK_VIDEO: &str = "li[data-seq]";
+const THUMB: &str = ".thumb_area";
+const NAME: &str = ".name";
+const SEQ: &str = "[data-seq]";
+const IMAGE: &str = "img, [data-src], [data-lazy-src], [data-original], [style*=\"background-image\"]";
+const CHANNEL_LINK: &str = "a[href^=\"/channels/\"]";
+const JSON: &str = "script[type=\"application/json\"]";
+const FOLLOWERS: &str = "[data-fan-count], .channel_info .fan_count";
+const PAID_BADGE: &str = ".ico_vlive_plus, [data-ga-product=\"PAID\"]";
+
+/// Compile one of the selectors above, only the first time it's used
+macro_rules! selector {
+    ($css:expr) => {{
+        static SELECTOR: OnceLock<Selector> = OnceLock::new();
+        SELECTOR.get_or_init(|| Selector::parse($css).expect("built-in selectors are valid"))
+    }};
+}
+
+/// A parsed video still borrowing from the page
+///
+/// Same fields as `VLiveVideo`, see there for what they mean
+#[derive(Debug, Clone)]
+pub struct VideoRef<'a> {
+    pub video_id: Cow<'a, str>,
+    pub video_seq: u64,
+    pub video_title: Cow<'a, str>,
+    pub video_type: VideoType,
+    pub video_stage: VideoStage,
+    pub video_paid: bool,
+    pub video_thumbnail: Option<Cow<'a, str>>,
+    pub channel_id: Cow<'a, str>,
+    pub channel_seq: u64,
+    pub channel_name: Cow<'a, str>,
+    pub channel_type: ChannelType,
+    pub platform: Platform,
+}
+
+impl<'a> VideoRef<'a> {
+    /// Copy everything out of the page
+    pub fn into_owned(self) -> VLiveVideo {
+        VLiveVideo {
+            video_id: self.video_id.into_owned(),
+            video_seq: self.video_seq,
+            video_title: self.video_title.into_owned(),
+            video_titles: HashMap::new(),
+            video_type: self.video_type,
+            video_stage: self.video_stage,
+            video_paid: self.video_paid,
+            video_thumbnail: self.video_thumbnail.map(Cow::into_owned),
+            channel_id: self.channel_id.into_owned(),
+            channel_seq: self.channel_seq,
+            channel_name: self.channel_name.into_owned(),
+            channel_type: self.channel_type,
+            platform: self.platform,
+            video_mirrors: Vec::new(),
+        }
+    }
+}
+
+impl<'a> VideoRef<'a> {
+    /// `normalize` the title and channel name
+    pub fn normalized(self) -> Self {
+        VideoRef {
+            video_title: tidy(self.video_title),
+            channel_name: tidy(self.channel_name),
+            ..self
+        }
+    }
+}
+
+impl<'a> From<&'a VLiveVideo> for VideoRef<'a> {
+    /// Borrow an owned video, like one from a `Source`
+    fn from(video: &'a VLiveVideo) -> Self {
+        VideoRef {
+            video_id: Cow::Borrowed(&video.video_id),
+            video_seq: video.video_seq,
+            video_title: Cow::Borrowed(&video.video_title),
+            video_type: video.video_type,
+            video_stage: video.video_stage,
+            video_paid: video.video_paid,
+            video_thumbnail: video.video_thumbnail.as_deref().map(Cow::Borrowed),
+            channel_id: Cow::Borrowed(&video.channel_id),
+            channel_seq: video.channel_seq,
+            channel_name: Cow::Borrowed(&video.channel_name),
+            channel_type: video.channel_type,
+            platform: video.platform,
+        }
+    }
+}
+
+impl<'a> From<VideoRef<'a>> for VLiveVideo {
+    fn from(video: VideoRef<'a>) -> Self {
+        video.into_owned()
+    }
+}
+
+/// One way of parsing a video out of its element
+pub trait Strategy: Send + Sync + 'static {
+    /// Short name to tell strategies apart in diagnostics
+    fn name(&self) -> &'static str;
+
+    fn parse_node<'a>(&self, node: Node<'a>) -> Result<VideoRef<'a>, ParseError>;
+}
+
+/// A list of strategies to try in order
+pub struct Parser {
+    strategies: Vec<Box<dyn Strategy>>,
+}
+
+impl Default for Parser {
+    /// Class selectors first, then data attributes, then embedded JSON
+    fn default() -> Self {
+        Parser::new()
+            .strategy(ClassSelectors)
+            .strategy(DataAttributes)
+            .strategy(JsonIsland)
+    }
+}
+
+impl Parser {
+    /// Parser without any strategies, add them with `strategy`
+    pub fn new() -> Self {
+        Parser {
+            strategies: Vec::new(),
+        }
+    }
+
+    /// Try `strategy` after the ones already added
+    pub fn strategy<S>(mut self, strategy: S) -> Self where S: Strategy {
+        self.strategies.push(Box::new(strategy));
+        self
+    }
+
+    /// Find the elements of every video on the page, newest first
+    ///
+    /// Elements are found lazily, so stopping early skips the rest of the page
+    pub fn videos<'a>(&self, document: &'a Document) -> impl Iterator<Item = Node<'a>> + 'a {
+        let mut videos = document.select(selector!(VIDEO)).peekable();
+
+        //Without the wrapper class, fall back to list items carrying a seq
+        let fallback = match videos.peek() {
+            Some(_) => None,
+            None => Some(document.select(selector!(FALLBACK_VIDEO))),
+        };
+        videos.chain(fallback.into_iter().flatten())
+    }
+
+    /// Quickly read the seq of a video element without parsing the rest
+    ///
+    /// Used to check whether we've already seen a video before doing the
+    /// work of running it through every strategy
+    pub fn peek_seq(&self, node: Node) -> Option<u64> {
+        node.value().attr("data-seq")
+            .or_else(|| node.select(selector!(SEQ)).next().and_then(|seq| seq.value().attr("data-seq")))
+            .and_then(|seq| seq.parse().ok())
+    }
+
+    /// Read the follower count off a channel page
+    ///
+    /// `None` if the page doesn't show one, like the recent videos page
+    pub fn followers(&self, document: &Document) -> Option<u64> {
+        let node = document.select(selector!(FOLLOWERS)).next()?;
+        let count = match node.value().attr("data-fan-count") {
+            Some(count) => count.to_string(),
+            None => node.text().collect(),
+        };
+        count.trim().replace(',', "").parse().ok()
+    }
+
+    /// Every channel linked from the channel directory, in order
+    ///
+    /// Channels linked more than once are only listed the first time
+    pub fn channels(&self, document: &Document) -> Vec<VLiveChannel> {
+        let mut channels: Vec<VLiveChannel> = Vec::new();
+        for link in document.select(selector!(CHANNEL_LINK)) {
+            let channel_id = attr(link, "href");
+            if channels.iter().any(|channel| channel.channel_id == channel_id) {
+                continue;
+            }
+            let channel = link.value();
+            channels.push(VLiveChannel {
+                channel_id: channel_id.into_owned(),
+                channel_seq: number(link, CHANNEL_LINK, "data-ga-cseq").unwrap_or(0),
+                channel_name: match channel.attr("data-ga-cname") {
+                    Some(name) => normalize(name).into_owned(),
+                    None => normalize(&link.text().collect::<String>()).into_owned(),
+                },
+                channel_type: channel_type(channel.attr("data-ga-ctype")),
+            });
+        }
+        channels
+    }
+
+    /// Parse every video on a page, newest first
+    ///
+    /// Never panics, whatever `html` is. A page without any videos is
+    /// `Err`, otherwise every video element gives either a video or the
+    /// reason it couldn't be parsed
+    pub fn parse_page(&self, html: &str) -> Result<Vec<Result<VLiveVideo, ParseFailure>>, Unavailable> {
+        let document = Html::parse_document(html);
+        let videos: Vec<_> = self.videos(&document)
+            .map(|node| self.parse_video(node).map(VideoRef::into_owned))
+            .collect();
+        if videos.is_empty() {
+            return Err(self.unavailable(&document));
+        }
+        Ok(videos)
+    }
+
+    /// Figure out why a page has no videos on it
+    ///
+    /// VLive answers with a login page when a session expires, and with a
+    /// maintenance notice during its regular inspections. Anything else
+    /// without videos counts as an empty list
+    pub fn unavailable(&self, document: &Document) -> Unavailable {
+        let title = document.select(selector!("title")).next()
+            .map(|title| title.text().collect::<String>().to_lowercase())
+            .unwrap_or_default();
+
+        let login = document.select(selector!("form"))
+            .any(|form| form.value().attr("action").is_some_and(|action| action.contains("login")));
+        if login || title.contains("login") || title.contains("로그인") {
+            return Unavailable::Login;
+        }
+
+        let maintenance = document.select(selector!(".maintenance, .inspection")).next().is_some();
+        if maintenance || title.contains("maintenance") || title.contains("점검") {
+            return Unavailable::Maintenance;
+        }
+
+        Unavailable::Empty
+    }
+
+    /// Parse a single video element
+    ///
+    /// If every strategy fails, the error from the first one is returned
+    pub fn parse_node<'a>(&self, node: Node<'a>) -> Result<VideoRef<'a>, ParseError> {
+        self.attempt(node).map_err(|(why, _)| why)
+    }
+
+    /// Parse a single video element, like `parse_node`, but fail with its
+    /// markup and the name of the strategy the error came from
+    pub fn parse_video<'a>(&self, node: Node<'a>) -> Result<VideoRef<'a>, ParseFailure> {
+        self.attempt(node).map_err(|(why, strategy)| {
+            let failure = ParseFailure::new(why, &node.html());
+            match strategy {
+                Some(name) => failure.strategy(name),
+                None => failure,
+            }
+        })
+    }
+
+    /// Try every strategy on `node`, failing with the first error and the
+    /// strategy it came from
+    fn attempt<'a>(&self, node: Node<'a>) -> Result<VideoRef<'a>, (ParseError, Option<&'static str>)> {
+        let mut first = None;
+        for strategy in &self.strategies {
+            match strategy.parse_node(node) {
+                Ok(video) => return Ok(video.normalized()),
+                Err(why) => { first.get_or_insert((why, Some(strategy.name()))); },
+            }
+        }
+        Err(first.unwrap_or((ParseError::MissingElement { selector: VIDEO }, None)))
+    }
+}
+
+/// The original layout, with `.thumb_area` and `.name` elements carrying
+/// Google Analytics attributes
+pub struct ClassSelectors;
+
+impl Strategy for ClassSelectors {
+    fn name(&self) -> &'static str {
+        "class selectors"
+    }
+
+    fn parse_node<'a>(&self, node: Node<'a>) -> Result<VideoRef<'a>, ParseError> {
+        //Parse the 2 divs that have our needed attributes
+        let html_thumb = node.select(selector!(THUMB)).last()
+            .ok_or(ParseError::MissingElement { selector: THUMB })?;
+        let html_name = node.select(selector!(NAME)).last()
+            .ok_or(ParseError::MissingElement { selector: NAME })?;
+        let thumb = html_thumb.value();
+
+        Ok(VideoRef {
+            video_id: attr(html_thumb, "href"),
+            video_seq: seq(html_thumb, THUMB, "data-seq")?,
+            video_title: attr(html_thumb, "data-ga-name"),
+            video_type: video_type(thumb.attr("data-ga-type")),
+            video_stage: video_stage(thumb.attr("data-ga-type"), thumb.attr("data-ga-status")),
+            video_paid: paid(node),
+            video_thumbnail: thumbnail(html_thumb),
+            channel_id: attr(html_name, "href"),
+            channel_seq: number(html_thumb, THUMB, "data-ga-cseq")?,
+            channel_name: attr(html_thumb, "data-ga-cname"),
+            channel_type: channel_type(thumb.attr("data-ga-ctype")),
+            platform: Platform::VLIVE,
+        })
+    }
+}
+
+/// Ignores class names, looks for whichever element carries `data-seq` and
+/// the first link to a channel
+pub struct DataAttributes;
+
+impl Strategy for DataAttributes {
+    fn name(&self) -> &'static str {
+        "data attributes"
+    }
+
+    fn parse_node<'a>(&self, node: Node<'a>) -> Result<VideoRef<'a>, ParseError> {
+        let html_video = if node.value().attr("data-seq").is_some() {
+            node
+        } else {
+            node.select(selector!(SEQ)).next()
+                .ok_or(ParseError::MissingElement { selector: SEQ })?
+        };
+        let html_channel = node.select(selector!(CHANNEL_LINK)).next()
+            .ok_or(ParseError::MissingElement { selector: CHANNEL_LINK })?;
+        let video = html_video.value();
+
+        Ok(VideoRef {
+            video_id: attr(html_video, "href"),
+            video_seq: seq(html_video, SEQ, "data-seq")?,
+            video_title: Cow::Borrowed(video.attr("data-ga-name")
+                .or_else(|| video.attr("title"))
+                .unwrap_or("")),
+            video_type: video_type(video.attr("data-ga-type")),
+            video_stage: video_stage(video.attr("data-ga-type"), video.attr("data-ga-status")),
+            video_paid: paid(node),
+            video_thumbnail: thumbnail(node),
+            channel_id: attr(html_channel, "href"),
+            channel_seq: number(html_video, SEQ, "data-ga-cseq")?,
+            channel_name: match video.attr("data-ga-cname") {
+                Some(name) => Cow::Borrowed(name),
+                None => Cow::Owned(html_channel.text().collect()),
+            },
+            channel_type: channel_type(video.attr("data-ga-ctype")),
+            platform: Platform::VLIVE,
+        })
+    }
+}
+
+/// Reads the JSON blob some layouts embed in a `<script>` next to the markup
+pub struct JsonIsland;
+
+impl Strategy for JsonIsland {
+    fn name(&self) -> &'static str {
+        "json island"
+    }
+
+    fn parse_node<'a>(&self, node: Node<'a>) -> Result<VideoRef<'a>, ParseError> {
+        let script = node.select(selector!(JSON)).next()
+            .ok_or(ParseError::MissingElement { selector: JSON })?;
+        let json: serde_json::Value = serde_json::from_str(&script.text().collect::<String>())
+            .map_err(|why| ParseError::InvalidJson { reason: why.to_string() })?;
+
+        //The JSON only lives as long as this call, so everything is copied
+        let text = |field: &str| json.get(field).and_then(|value| value.as_str());
+        let owned = |field: &str| Cow::Owned(text(field).unwrap_or("").to_string());
+        let number = |field: &'static str| match json.get(field) {
+            None => Ok(0),
+            Some(value) => value.as_u64()
+                .or_else(|| value.as_str().and_then(|value| value.parse().ok()))
+                .ok_or_else(|| ParseError::InvalidJson { reason: format!("`{}` is not a seq: {}", field, value) }),
+        };
+
+        //Without a seq the video can't be told apart from others
+        let video_seq = match number("videoSeq")? {
+            0 => return Err(ParseError::InvalidJson { reason: "`videoSeq` is missing or 0".to_string() }),
+            seq => seq,
+        };
+        Ok(VideoRef {
+            video_id: Cow::Owned(format!("/video/{}", video_seq)),
+            video_seq,
+            video_title: owned("title"),
+            video_type: video_type(text("videoType")),
+            video_stage: video_stage(text("videoType"), text("status")),
+            video_paid: text("productType") == Some("PAID"),
+            video_thumbnail: text("thumb").map(|thumb| Cow::Owned(thumb.to_string())),
+            channel_id: Cow::Owned(text("channelCode").map_or(String::new(), |code| format!("/channels/{}", code))),
+            channel_seq: number("channelSeq")?,
+            channel_name: owned("channelName"),
+            channel_type: channel_type(text("channelPlusType")),
+            platform: Platform::VLIVE,
+        })
+    }
+}
+
+pub(crate) fn video_type(value: Option<&str>) -> VideoType {
+    match value {
+        Some("LIVE") => VideoType::LIVE,
+        _ => VideoType::VOD,
+    }
+}
+
+/// Lives are reserved before they start and linger after they end, until
+/// they are turned into a VOD
+pub(crate) fn video_stage(value: Option<&str>, status: Option<&str>) -> VideoStage {
+    match (video_type(value), status) {
+        (VideoType::VOD, _) => VideoStage::VOD,
+        (VideoType::LIVE, Some("RESERVED")) => VideoStage::UPCOMING,
+        (VideoType::LIVE, Some("ENDED")) => VideoStage::ENDED,
+        (VideoType::LIVE, _) => VideoStage::LIVE,
+    }
+}
+
+fn channel_type(value: Option<&str>) -> ChannelType {
+    match value {
+        Some("PLUS") => ChannelType::PLUS,
+        _ => ChannelType::BASIC,
+    }
+}
+
+/// Does the video carry the VLIVE+ badge
+fn paid(node: Node) -> bool {
+    node.value().attr("data-ga-product") == Some("PAID") || node.select(selector!(PAID_BADGE)).next().is_some()
+}
+
+/// The last image in `node`, or `node` itself
+fn thumbnail(node: Node) -> Option<Cow<str>> {
+    std::iter::once(node)
+        .chain(node.select(selector!(IMAGE)))
+        .filter_map(image)
+        .last()
+        .map(Cow::Borrowed)
+}
+
+/// Attributes lazy loading scripts keep the real image in, before `src`
+/// which is often a placeholder until the image scrolls into view
+const LAZY: &[&str] = &["data-src", "data-lazy-src", "data-original", "src"];
+
+/// The image an element shows, either in an attribute or as its background
+fn image<'a>(element: Node<'a>) -> Option<&'a str> {
+    let element = element.value();
+    LAZY.iter()
+        .filter_map(|attribute| element.attr(attribute))
+        .chain(element.attr("style").and_then(background))
+        .map(str::trim)
+        .find(|url| !placeholder(url))
+}
+
+/// The url of a `background-image` in a `style` attribute
+fn background(style: &str) -> Option<&str> {
+    let start = style.find("background-image")?;
+    let url = style[start..].split_once("url(")?.1;
+    let url = url.split(')').next()?;
+    Some(url.trim().trim_matches(|c| c == '"' || c == '\''))
+}
+
+/// Is `url` an empty stand-in for an image yet to load
+fn placeholder(url: &str) -> bool {
+    let name = url.rsplit('/').next().unwrap_or_default().to_lowercase();
+    url.is_empty() || url.starts_with("data:") || ["blank", "spacer", "pixel", "transparent", "1x1"].iter().any(|stub| name.contains(stub))
+}
+
+/// Borrow a text attribute, missing ones are empty
+fn attr<'a>(node: Node<'a>, attribute: &str) -> Cow<'a, str> {
+    Cow::Borrowed(node.value().attr(attribute).unwrap_or(""))
+}
+
+/// Parse a numeric attribute, missing ones are 0
+fn number(node: Node, selector: &'static str, attribute: &'static str) -> Result<u64, ParseError> {
+    match node.value().attr(attribute) {
+        Some(value) => value.trim().parse().map_err(|_| ParseError::InvalidAttribute {
+            selector,
+            attribute,
+            value: value.to_string(),
+        }),
+        None => Ok(0),
+    }
+}
+
+/// Parse the seq a video is told apart by, which can't be missing or 0
+fn seq(node: Node, selector: &'static str, attribute: &'static str) -> Result<u64, ParseError> {
+    match number(node, selector, attribute)? {
+        0 if node.value().attr(attribute).is_none() => Err(ParseError::MissingAttribute { selector, attribute }),
+        0 => Err(ParseError::InvalidAttribute { selector, attribute, value: "0".to_string() }),
+        seq => Ok(seq),
+    }
+}
+
+/// Zero width characters that sneak into titles copied from elsewhere
+const INVISIBLE: &[char] = &['\u{200b}', '\u{200c}', '\u{200d}', '\u{2060}', '\u{feff}'];
+
+/// Clean up text scraped from a page
+///
+/// Decodes HTML entities left over from double escaping like `&#39;`,
+/// turns runs of whitespace into single spaces, trims the ends, drops zero
+/// width characters and normalizes to NFC, so `"Comeback &amp; Show\n"` is
+/// `"Comeback & Show"`. Text that's already clean is borrowed as is
+pub fn normalize(text: &str) -> Cow<'_, str> {
+    let clean = !text.contains(|c: char| c == '&' || (c.is_whitespace() && c != ' ') || INVISIBLE.contains(&c))
+        && !text.starts_with(' ')
+        && !text.ends_with(' ')
+        && !text.contains("  ");
+    if clean && is_nfc(text) {
+        return Cow::Borrowed(text);
+    }
+    let decoded: String = decode_entities(text).chars().filter(|c| !INVISIBLE.contains(c)).collect();
+    let spaced = decoded.split_whitespace().collect::<Vec<_>>().join(" ");
+    Cow::Owned(spaced.nfc().collect())
+}
+
+/// `normalize` without giving up on borrowing
+fn tidy(text: Cow<'_, str>) -> Cow<'_, str> {
+    match text {
+        Cow::Borrowed(text) => normalize(text),
+        Cow::Owned(text) => match normalize(&text) {
+            Cow::Owned(normalized) => Cow::Owned(normalized),
+            Cow::Borrowed(_) => Cow::Owned(text),
+        },
+    }
+}
+
+/// Decode `&name;`, `&#decimal;` and `&#xhex;` entities once, anything
+/// unknown is left alone
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        decoded.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let entity = rest.find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| entity(&rest[1..end]).map(|c| (c, end)));
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// The character an entity without its `&` and `;` stands for
+fn entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => {
+            let number = name.strip_prefix('#')?;
+            let code = match number.strip_prefix('x').or_else(|| number.strip_prefix('X')) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            char::from_u32(code)
+        },
+    }
+}
@@ -0,0 +1,182 @@
+//! Filters and sinks as WASM plugins
+//!
+//! Needs the `plugins` feature. Plugins are WebAssembly modules in a
+//! directory, loaded when the listener starts, so filters and sinks can be
+//! distributed precompiled and added without recompiling anything.
+//!
+//! A plugin exports its `memory`, and `alloc(len: i32) -> i32` which returns
+//! where `len` bytes can be written. Videos are handed to it as JSON, the
+//! same as `VLiveVideo` serializes to, written where `alloc` said. Then it
+//! exports one or both of
+//!
+//! * `filter(ptr: i32, len: i32) -> i32`, returning 0 to drop the video
+//! * `on_new(ptr: i32, len: i32) -> i32`, returning 0 once the video was
+//!   delivered, anything else if it should be retried
+//!
+//! `Plugins` is a middleware stage running the filters, and a callback
+//! running the sinks. Load the directory once for each:
+//!
+//! ```rust,ignore
+//! VLive::new(Plugins::load("/etc/vlive/plugins")?, Duration::from_secs(5))
+//!     .middleware(Plugins::load("/etc/vlive/plugins")?)
+//!     .run();
+//! ```
+//!
+//! Plugins run with fuel, so one stuck in a loop fails instead of stalling
+//! the dispatcher.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+use wasmtime::{Config, Engine, Instance, Module, Store};
+
+use super::middleware::Middleware;
+use super::{Ctx, DeliveryError, Event, Flow, VLiveCallback, VLiveVideo};
+
+/// Instructions a plugin may run per video
+const FUEL: u64 = 10_000_000;
+
+/// A plugin directory that couldn't be loaded
+#[derive(Debug)]
+pub enum PluginError {
+    Io(io::Error),
+    /// The module at `path` didn't compile, instantiate or export `alloc`
+    Wasm {
+        path: PathBuf,
+        why: wasmtime::Error,
+    },
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PluginError::Io(ref why) => write!(f, "Couldn't read plugins: {}", why),
+            PluginError::Wasm { ref path, ref why } => write!(f, "Couldn't load plugin {}: {}", path.display(), why),
+        }
+    }
+}
+
+impl Error for PluginError {}
+
+impl From<io::Error> for PluginError {
+    fn from(why: io::Error) -> Self {
+        PluginError::Io(why)
+    }
+}
+
+/// One instantiated module
+struct Plugin {
+    path: PathBuf,
+    store: Store<()>,
+    instance: Instance,
+    filter: bool,
+    sink: bool,
+}
+
+impl Plugin {
+    fn load(engine: &Engine, path: &Path) -> Result<Self, wasmtime::Error> {
+        let module = Module::from_file(engine, path)?;
+        let mut store = Store::new(engine, ());
+        store.set_fuel(FUEL)?;
+        let instance = Instance::new(&mut store, &module, &[])?;
+        instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+        let filter = instance.get_typed_func::<(i32, i32), i32>(&mut store, "filter").is_ok();
+        let sink = instance.get_typed_func::<(i32, i32), i32>(&mut store, "on_new").is_ok();
+        Ok(Plugin {
+            path: path.to_path_buf(),
+            store,
+            instance,
+            filter,
+            sink,
+        })
+    }
+
+    /// Hand `video` to the export `name`, returning what it returned
+    fn call(&mut self, name: &str, video: &VLiveVideo) -> Result<i32, wasmtime::Error> {
+        let json = serde_json::to_vec(video)?;
+        self.store.set_fuel(FUEL)?;
+
+        let alloc = self.instance.get_typed_func::<i32, i32>(&mut self.store, "alloc")?;
+        let ptr = alloc.call(&mut self.store, json.len() as i32)?;
+        let memory = self.instance.get_memory(&mut self.store, "memory")
+            .ok_or_else(|| wasmtime::Error::msg("no exported memory"))?;
+        memory.write(&mut self.store, ptr as usize, &json)?;
+
+        let export = self.instance.get_typed_func::<(i32, i32), i32>(&mut self.store, name)?;
+        export.call(&mut self.store, (ptr, json.len() as i32))
+    }
+}
+
+/// Every plugin in a directory, in the order of their file names
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+}
+
+impl Plugins {
+    /// Load every `.wasm` file in `dir`
+    pub fn load<P>(dir: P) -> Result<Self, PluginError> where P: AsRef<Path> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "wasm"))
+            .collect();
+        paths.sort();
+
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|why| PluginError::Wasm { path: PathBuf::new(), why })?;
+
+        let plugins = paths.into_iter()
+            .map(|path| Plugin::load(&engine, &path).map_err(|why| PluginError::Wasm { path, why }))
+            .collect::<Result<_, _>>()?;
+        Ok(Plugins { plugins })
+    }
+
+    /// Hand `video` to every sink, stopping at the first that fails
+    fn sink(&mut self, video: &VLiveVideo) -> Result<(), DeliveryError> {
+        for plugin in self.plugins.iter_mut().filter(|plugin| plugin.sink) {
+            match plugin.call("on_new", video) {
+                Ok(0) => (),
+                Ok(code) => return Err(format!("Plugin {} failed with {}", plugin.path.display(), code).into()),
+                Err(why) => return Err(format!("Plugin {} failed: {}", plugin.path.display(), why).into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// File names of the loaded plugins
+    pub fn names(&self) -> Vec<String> {
+        self.plugins.iter()
+            .filter_map(|plugin| plugin.path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect()
+    }
+}
+
+impl Middleware for Plugins {
+    fn process(&mut self, video: VLiveVideo) -> Option<VLiveVideo> {
+        for plugin in self.plugins.iter_mut().filter(|plugin| plugin.filter) {
+            match plugin.call("filter", &video) {
+                Ok(0) => return None,
+                Ok(_) => (),
+                //Like scripts, a broken filter shouldn't cost a notification
+                Err(why) => eprintln!("VLive Error: Plugin {} failed for {}: {}", plugin.path.display(), video.video_id, why),
+            }
+        }
+        Some(video)
+    }
+}
+
+impl VLiveCallback for Plugins {
+    fn on_new(&mut self, _: &Ctx, video: VLiveVideo) -> Flow {
+        if let Err(why) = self.sink(&video) {
+            eprintln!("VLive Error: {}", why);
+        }
+        Flow::Continue
+    }
+
+    fn deliver(&mut self, _: &Ctx, event: &Event) -> Result<Flow, DeliveryError> {
+        self.sink(&event.video).map(|()| Flow::Continue)
+    }
+}
@@ -0,0 +1,914 @@
+//! Deciding what to poll and what to announce, without doing any of it
+//!
+//! `Poller` holds everything a listener knows about the feeds, but never
+//! fetches a page, sleeps or spawns a thread. You tell it what time it is
+//! and hand it the pages, it tells you which feeds are due and which events
+//! came out of them. `VLive` drives one from its own thread, drive it
+//! yourself if threads don't fit your runtime, or let a `Ticker` do the
+//! fetching and call `tick` whenever it suits you:
+//!
+//! ```rust,ignore
+//! let mut poller = Poller::new(vec![(Feed::Recent, Duration::from_secs(5))], Instant::now());
+//! loop {
+//!     let now = Instant::now();
+//!     for feed in poller.due(now, SystemTime::now()) {
+//!         let html = fetch(&poller.feed(feed).url())?;
+//!         for event in poller.page(feed, &html, &parser, now, SystemTime::now()) {
+//!             println!("{:?}", event);
+//!         }
+//!     }
+//!     sleep_until(poller.next_due().unwrap());
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(not(target_arch = "wasm32"))]
+use super::clock::{Clock, SystemClock};
+use super::lifecycle::Lifecycle;
+use super::parser::{self, Parser, VideoRef};
+use super::schedule::Schedule;
+use super::transport::Transport;
+#[cfg(not(target_arch = "wasm32"))]
+use super::transport;
+use super::state::{FeedState, PollerState};
+use super::{ChannelFilter, Dedup, Event, EventKinds, Feed, Flow, ParseFailure, Sighting, Unavailable, VLiveError, VLiveEvent, VLiveVideo};
+
+/// How many polls in a row can fail to parse anything before we
+/// report `VLiveError::LayoutChanged`, by default
+const LAYOUT_CHANGED_AFTER: u32 = 3;
+
+/// Hook of `ParsePolicy::Rescue`
+pub type Rescue = Arc<dyn Fn(&Feed, u32) -> Flow + Send + Sync>;
+
+/// What to do once a feed keeps failing to parse, see `VLive::layout_changed`
+///
+/// `VLiveError::LayoutChanged` is reported either way, the policy decides
+/// what happens to polling afterwards. A hobby bot can carry on and wait
+/// for a fix, a service is better off stopping where someone notices
+#[derive(Clone, Default)]
+pub enum ParsePolicy {
+    /// Keep polling the feed as usual
+    #[default]
+    KeepGoing,
+    /// Wait twice as long before every poll of the feed that still fails,
+    /// up to `max`, and go back to its schedule once it parses again
+    Backoff { max: Duration },
+    /// Stop the listener, once the error has been passed to the callback
+    Stop,
+    /// Call the hook with the feed and how many polls in a row failed, and
+    /// stop the listener if it returns `Flow::Stop`. It's called from the
+    /// polling thread, so anything it does holds up polling, like switching
+    /// over to a mirror or paging someone
+    Rescue(Rescue),
+}
+
+impl ParsePolicy {
+    /// `ParsePolicy::Rescue` calling `hook`
+    pub fn rescue<F>(hook: F) -> Self where F: Fn(&Feed, u32) -> Flow + Send + Sync + 'static {
+        ParsePolicy::Rescue(Arc::new(hook))
+    }
+}
+
+/// How many seqs each feed remembers, a dozen pages or so
+const RECENT: usize = 256;
+
+/// Seqs recently seen on a feed
+///
+/// Pinned and boosted videos show up above newer ones, so where the last
+/// poll left off isn't enough to tell what's new
+#[derive(Debug, Clone, Default)]
+struct Recent {
+    seqs: HashSet<u64>,
+    /// Oldest first
+    order: VecDeque<u64>,
+}
+
+impl Recent {
+    fn contains(&self, seq: u64) -> bool {
+        self.seqs.contains(&seq)
+    }
+
+    fn insert(&mut self, seq: u64) {
+        if self.seqs.insert(seq) {
+            self.order.push_back(seq);
+        }
+        while self.order.len() > RECENT {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seqs.remove(&oldest);
+            }
+        }
+    }
+
+    fn remove(&mut self, seq: u64) {
+        if self.seqs.remove(&seq) {
+            self.order.retain(|&other| other != seq);
+        }
+    }
+
+    /// The oldest seq remembered
+    fn floor(&self) -> Option<u64> {
+        self.seqs.iter().copied().min()
+    }
+
+    /// Forget everything but the cursor, `last_seen`
+    fn reset(&mut self, last_seen: u64) {
+        self.seqs.clear();
+        self.order.clear();
+        if last_seen != 0 {
+            self.insert(last_seen);
+        }
+    }
+}
+
+/// A feed along with its schedule and cursor
+struct Source {
+    feed: Feed,
+    wait: Schedule,
+    /// When this feed should be polled next
+    due: Instant,
+    /// Seq of the newest video seen, 0 if never polled
+    last_seen: u64,
+    /// Videos seen lately, whether they were new or not
+    recent: Recent,
+    /// Polls in a row nothing could be parsed
+    failures: u32,
+    /// Follower count last time, if it's a channel and we're counting
+    followers: Option<u64>,
+}
+
+impl Source {
+    /// Count a poll nothing could be parsed from, escalating once it's
+    /// happened `after` times in a row
+    fn failed(&mut self, after: u32) -> Option<VLiveError> {
+        self.failures += 1;
+        if self.failures != after {
+            return None;
+        }
+        Some(VLiveError::LayoutChanged {
+            feed: self.feed.clone(),
+            polls: self.failures,
+        })
+    }
+
+    /// How long to wait before the next poll, `wait` unless it's backing
+    /// off after failing to parse `after` times in a row
+    fn backoff(&self, wait: Duration, after: u32, max: Option<Duration>) -> Duration {
+        match max {
+            Some(max) if self.failures >= after => {
+                let doublings = (self.failures + 1 - after).min(16);
+                wait.saturating_mul(1 << doublings).min(max).max(wait)
+            },
+            _ => wait,
+        }
+    }
+
+    /// Is a video with `seq` that wasn't seen lately new, given the oldest
+    /// seq seen lately before this page
+    ///
+    /// Everything is new on the first poll. After that, videos older than
+    /// anything seen lately were pinned or boosted from before. YouTube
+    /// seqs are only ordered down to the second they were published in
+    fn is_new(&self, seq: u64, floor: Option<u64>) -> bool {
+        let second = |seq: u64| match self.feed {
+            Feed::YouTube(_) => seq >> 20,
+            _ => seq,
+        };
+        self.last_seen == 0 || second(seq) >= second(self.last_seen) && seq != self.last_seen
+            || floor.is_some_and(|floor| seq > floor)
+    }
+}
+
+/// A new video waiting out the settle delay, see `Poller::settle_delay`
+struct Held {
+    /// Index of the feed it was found on
+    source: usize,
+    due: Instant,
+    event: Event,
+}
+
+/// Polling state machine
+///
+/// Feeds are referred to by their index in the list given to `new`
+pub struct Poller {
+    sources: Vec<Source>,
+    dedup: Dedup,
+    kinds: EventKinds,
+    channel_filter: ChannelFilter,
+    lifecycle: Option<Lifecycle>,
+    /// Announce every time follower counts pass a multiple of this
+    milestones: Option<u64>,
+    /// Channels in the directory, `None` until it's first polled
+    channels: Option<HashSet<String>>,
+    /// How long new videos are held before they're announced
+    settle_delay: Option<Duration>,
+    /// New videos being held, oldest first
+    held: Vec<Held>,
+    /// Id of the latest round of polling
+    poll_id: u64,
+    /// Polls in a row a feed can fail to parse before it's reported
+    layout_changed_after: u32,
+    /// Longest wait when backing off from a feed that fails to parse
+    backoff: Option<Duration>,
+}
+
+impl Poller {
+    /// Poll every feed on its own `wait`, starting at `now`
+    ///
+    /// `wait` is a `Duration` or a `Schedule`
+    pub fn new<S>(feeds: Vec<(Feed, S)>, now: Instant) -> Self where S: Into<Schedule> {
+        Poller {
+            sources: feeds.into_iter()
+                .map(|(feed, wait)| Source {
+                    feed,
+                    wait: wait.into(),
+                    due: now,
+                    last_seen: 0,
+                    recent: Recent::default(),
+                    failures: 0,
+                    followers: None,
+                })
+                .collect(),
+            dedup: Dedup::new(512, Duration::from_secs(24 * 60 * 60)),
+            kinds: EventKinds::ALL,
+            channel_filter: ChannelFilter::default(),
+            lifecycle: None,
+            milestones: None,
+            channels: None,
+            settle_delay: None,
+            held: Vec::new(),
+            poll_id: 0,
+            layout_changed_after: LAYOUT_CHANGED_AFTER,
+            backoff: None,
+        }
+    }
+
+    /// Remember announced videos with `dedup`, see `VLive::dedup`
+    pub fn dedup(mut self, dedup: Dedup) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Only announce some kinds of videos, see `VLive::kinds`
+    pub fn kinds(mut self, kinds: EventKinds) -> Self {
+        self.kinds = kinds;
+        self
+    }
+
+    /// Only announce videos from some channels, see `VLive::allow_channels`
+    pub fn channel_filter(mut self, filter: ChannelFilter) -> Self {
+        self.channel_filter = filter;
+        self
+    }
+
+    /// Report feeds that fail to parse `after` polls in a row, and back off
+    /// from them if that's the `policy`, see `VLive::layout_changed`
+    pub fn layout_changed(mut self, after: u32, policy: &ParsePolicy) -> Self {
+        self.layout_changed_after = after.max(1);
+        self.backoff = match *policy {
+            ParsePolicy::Backoff { max } => Some(max),
+            _ => None,
+        };
+        self
+    }
+
+    /// Follow live streams with `lifecycle`, see `VLive::lifecycle`
+    pub fn lifecycle(mut self, lifecycle: Lifecycle) -> Self {
+        self.lifecycle = Some(lifecycle);
+        self
+    }
+
+    /// Announce follower milestones every `step` followers, see
+    /// `VLive::follower_milestones`
+    pub fn milestones(mut self, step: u64) -> Self {
+        self.milestones = Some(step.max(1));
+        self
+    }
+
+    /// Hold new videos for `delay` before announcing them, see
+    /// `VLive::settle_delay`
+    pub fn settle_delay(mut self, delay: Duration) -> Self {
+        self.settle_delay = Some(delay);
+        self
+    }
+
+    /// Pick up where `state` left off at `now`, see `VLive::restore`
+    pub fn restore(mut self, state: &PollerState, now: Instant) -> Self {
+        for source in &mut self.sources {
+            if let Some(state) = state.feeds.iter().find(|state| state.feed == source.feed) {
+                source.last_seen = state.last_seen;
+                source.recent.reset(state.last_seen);
+                for &seq in &state.recent {
+                    source.recent.insert(seq);
+                }
+                source.failures = state.failures;
+                source.followers = state.followers;
+            }
+        }
+        self.dedup.restore(&state.announced);
+        self.dedup.restore_lives(&state.announced_lives);
+        if let Some(ref mut lifecycle) = self.lifecycle {
+            lifecycle.restore(&state.lives);
+        }
+        self.channels = state.channels.as_ref().map(|channels| channels.iter().cloned().collect());
+
+        //Held videos wait out the whole delay again
+        let due = now + self.settle_delay.unwrap_or_default();
+        for (feed, event) in &state.held {
+            if let Some(source) = self.sources.iter().position(|source| source.feed == *feed) {
+                self.held.push(Held { source, due, event: event.clone() });
+            }
+        }
+        self
+    }
+
+    /// Everything there is to remember, see `VLiveStopper::snapshot`
+    pub fn state(&self) -> PollerState {
+        PollerState {
+            feeds: self.sources.iter()
+                .map(|source| FeedState {
+                    feed: source.feed.clone(),
+                    last_seen: source.last_seen,
+                    failures: source.failures,
+                    recent: source.recent.order.iter().copied().collect(),
+                    followers: source.followers,
+                })
+                .collect(),
+            announced: self.dedup.entries(),
+            announced_lives: self.dedup.lives(),
+            lives: self.lifecycle.as_ref().map_or_else(Vec::new, Lifecycle::entries),
+            channels: self.channels.as_ref().map(|channels| channels.iter().cloned().collect()),
+            held: self.held.iter().map(|held| (self.sources[held.source].feed.clone(), held.event.clone())).collect(),
+        }
+    }
+
+    /// The feed at `index`
+    pub fn feed(&self, index: usize) -> &Feed {
+        &self.sources[index].feed
+    }
+
+    /// Seq of the newest video seen on any feed
+    pub fn last_seen(&self) -> u64 {
+        self.sources.iter().map(|source| source.last_seen).max().unwrap_or(0)
+    }
+
+    /// Carry on as if `seq` was the last video seen on every VLive feed, see
+    /// `VLiveStopper::set_last_seen`
+    pub fn seek(&mut self, seq: u64) {
+        for source in self.sources.iter_mut().filter(|source| source.feed.source().is_none()) {
+            source.last_seen = seq;
+            source.recent.reset(seq);
+        }
+        self.dedup.clear();
+    }
+
+    /// When the next feed is due, `None` if there are no feeds
+    pub fn next_due(&self) -> Option<Instant> {
+        self.sources.iter().map(|source| source.due).min()
+    }
+
+    /// Id of the latest round of polling, 0 before the first
+    ///
+    /// Counts up by one every time `due` returns feeds. New videos found in
+    /// that round carry it as `Event::poll_id`, to follow them from the
+    /// fetch to their delivery
+    pub fn poll_id(&self) -> u64 {
+        self.poll_id
+    }
+
+    /// Make every feed due at `now`, instead of waiting for its schedule
+    pub fn poll_now(&mut self, now: Instant) {
+        for source in &mut self.sources {
+            source.due = source.due.min(now);
+        }
+    }
+
+    /// Feeds that should be polled at `now`
+    ///
+    /// They are scheduled for their next poll right away, going by their
+    /// schedule at wall clock time `at`, so every returned feed is expected
+    /// to be fetched and handed to `page`. Starts a new round of polling,
+    /// see `poll_id`
+    pub fn due(&mut self, now: Instant, at: SystemTime) -> Vec<usize> {
+        let mut due = Vec::new();
+        for (index, source) in self.sources.iter_mut().enumerate() {
+            if source.due <= now {
+                source.due = now + source.backoff(source.wait.wait(at), self.layout_changed_after, self.backoff);
+                due.push(index);
+            }
+        }
+        if !due.is_empty() {
+            self.poll_id += 1;
+        }
+        due
+    }
+
+    /// Find everything newer than we've seen on a page fetched from the feed
+    /// at `index`
+    ///
+    /// Returns the events to deliver in order, and errors to report along
+    /// the way. New videos are stamped with `detected_at`. If fetching the
+    /// page failed, report that yourself
+    ///
+    /// VLive pages are walked newest first and parsed down to the last
+    /// video seen, skipping pinned ones above it, so a video that turns up
+    /// below that is missed. Feeds with a `Source` are parsed whole
+    pub fn page(&mut self, index: usize, html: &str, parser: &Parser, now: Instant, detected_at: SystemTime)
+        -> Vec<Result<VLiveEvent, VLiveError>>
+    {
+        let mut events = Vec::new();
+        let mut followed = Vec::new();
+        let source = &mut self.sources[index];
+        if let Some(ref mut lifecycle) = self.lifecycle {
+            events.extend(lifecycle.expire(detected_at).into_iter().map(Ok));
+        }
+
+        //Other platforms answer with JSON their `Source` parses all at once
+        if let Some(platform) = source.feed.source() {
+            let videos = platform.parse(html);
+            return self.listed(index, videos, now, detected_at, events);
+        }
+
+        //Login and maintenance pages are VLive's problem, not a layout change
+        let document = parser::Document::parse_document(html);
+        if source.feed == Feed::Directory {
+            return self.directory(index, &document, parser);
+        }
+
+        //Channel pages show the follower count, so counting is free
+        if let (Some(step), Feed::Channel(_)) = (self.milestones, &source.feed) {
+            if let Some(followers) = parser.followers(&document) {
+                if source.followers.is_some_and(|previous| followers / step > previous / step) {
+                    events.push(Ok(VLiveEvent::FollowerMilestone {
+                        feed: source.feed.clone(),
+                        followers,
+                        milestone: followers / step * step,
+                    }));
+                }
+                source.followers = Some(followers);
+            }
+        }
+
+        //Nothing to parse isn't failing to parse, a new channel has no videos yet
+        let mut videos = parser.videos(&document).peekable();
+        if videos.peek().is_none() {
+            events.push(Err(VLiveError::ServiceUnavailable(parser.unavailable(&document))));
+            return events;
+        }
+
+        //Below the cursor everything is older, so only look further down
+        //for held videos and live streams that might have moved there
+        let cursor = source.last_seen;
+        let watching = self.held.iter().any(|held| held.source == index)
+            || self.lifecycle.as_ref().is_some_and(|lifecycle| !lifecycle.is_empty());
+        let floor = source.recent.floor();
+        let mut parsed = 0;
+        let mut seen = None;
+        let mut found = false;
+        let mut below = false;
+        let mut on_page = HashSet::new();
+        for node in videos {
+            let peeked = parser.peek_seq(node);
+            on_page.extend(peeked);
+            if below {
+                followed.extend(follow(&mut self.lifecycle, parser, node, detected_at));
+                continue;
+            }
+            if cursor != 0 && peeked == Some(cursor) {
+                found = true;
+                parsed += 1;
+                followed.extend(follow(&mut self.lifecycle, parser, node, detected_at));
+                if !watching {
+                    break;
+                }
+                below = true;
+                continue;
+            }
+
+            //Pinned videos can be above the cursor, skip them without parsing
+            if peeked.is_some_and(|seq| source.recent.contains(seq)) {
+                found = true;
+                parsed += 1;
+                followed.extend(follow(&mut self.lifecycle, parser, node, detected_at));
+                continue;
+            }
+
+            let video = match parser.parse_video(node) {
+                Ok(value) => value,
+                Err(failure) => {
+                    events.push(Err(VLiveError::Parse(failure)));
+                    continue;
+                },
+            };
+            parsed += 1;
+            on_page.insert(video.video_seq);
+
+            if source.recent.contains(video.video_seq) {
+                found = true;
+                followed.extend(follow(&mut self.lifecycle, parser, node, detected_at));
+                continue;
+            }
+            //Not remembered, so it's skipped again next time without moving the floor
+            if !source.is_new(video.video_seq, floor) {
+                followed.extend(follow(&mut self.lifecycle, parser, node, detected_at));
+                continue;
+            }
+            source.recent.insert(video.video_seq);
+            seen = Some(widen(seen, video.video_seq));
+
+            //Already announced, only its stage can be news
+            if let Some(ref mut lifecycle) = self.lifecycle {
+                if lifecycle.follows(video.video_seq) {
+                    followed.extend(lifecycle.see(video, detected_at));
+                    continue;
+                }
+            }
+            let ended = self.lifecycle.as_mut().and_then(|lifecycle| lifecycle.archive(&video));
+            let archived = ended.is_some();
+            followed.extend(ended);
+
+            if !self.kinds.contains(video.video_type) || !self.channel_filter.contains(&video.channel_id, video.channel_seq) {
+                continue;
+            }
+
+            //Same content under a new seq, or showing up in two feeds
+            let code = if video.video_id.is_empty() { video.video_seq.to_string() } else { video.video_id.to_string() };
+            match self.dedup.sight(&code, video.video_type, now) {
+                Sighting::New => (),
+                Sighting::Vod if !archived => {
+                    events.push(Ok(VLiveEvent::VodPublished(video.into_owned())));
+                    continue;
+                },
+                Sighting::Vod | Sighting::Again => continue,
+            }
+            events.push(Ok(VLiveEvent::New(Event {
+                id: 0,
+                poll_id: self.poll_id,
+                detected_at,
+                video: video.into_owned(),
+            })));
+        }
+
+        events.extend(followed.into_iter().map(Ok));
+        settle(source, parsed, seen, found, self.layout_changed_after, &mut events);
+        if parsed > 0 {
+            self.hold(index, &on_page, now, &mut events);
+        }
+        events
+    }
+
+    /// Same as `page`, for feeds with a `Source` that parsed the whole
+    /// response already
+    fn listed(&mut self, index: usize, videos: Result<Vec<Result<VLiveVideo, ParseFailure>>, Unavailable>,
+        now: Instant, detected_at: SystemTime, mut events: Vec<Result<VLiveEvent, VLiveError>>)
+        -> Vec<Result<VLiveEvent, VLiveError>>
+    {
+        let source = &mut self.sources[index];
+        let videos = match videos {
+            Ok(videos) => videos,
+            Err(why) => {
+                events.push(Err(VLiveError::ServiceUnavailable(why)));
+                return events;
+            },
+        };
+
+        let floor = source.recent.floor();
+        let mut followed = Vec::new();
+        let mut parsed = 0;
+        let mut seen = None;
+        let mut found = false;
+        let mut on_page = HashSet::new();
+        for video in videos {
+            let video = match video {
+                Ok(video) => video,
+                Err(failure) => {
+                    events.push(Err(VLiveError::Parse(failure)));
+                    continue;
+                },
+            };
+            parsed += 1;
+            on_page.insert(video.video_seq);
+
+            let known = source.recent.contains(video.video_seq);
+            found |= known;
+            if known || !source.is_new(video.video_seq, floor) {
+                if let Some(ref mut lifecycle) = self.lifecycle {
+                    if lifecycle.follows(video.video_seq) {
+                        followed.extend(lifecycle.see(VideoRef::from(&video), detected_at));
+                    }
+                }
+                continue;
+            }
+            source.recent.insert(video.video_seq);
+            seen = Some(widen(seen, video.video_seq));
+
+            if let Some(ref mut lifecycle) = self.lifecycle {
+                if lifecycle.follows(video.video_seq) {
+                    followed.extend(lifecycle.see(VideoRef::from(&video), detected_at));
+                    continue;
+                }
+            }
+            let ended = self.lifecycle.as_mut().and_then(|lifecycle| lifecycle.archive(&VideoRef::from(&video)));
+            let archived = ended.is_some();
+            followed.extend(ended);
+
+            if !self.kinds.contains(video.video_type) || !self.channel_filter.contains(&video.channel_id, video.channel_seq) {
+                continue;
+            }
+            match self.dedup.sight(&video.video_id, video.video_type, now) {
+                Sighting::New => (),
+                Sighting::Vod if !archived => {
+                    events.push(Ok(VLiveEvent::VodPublished(video)));
+                    continue;
+                },
+                Sighting::Vod | Sighting::Again => continue,
+            }
+            events.push(Ok(VLiveEvent::New(Event {
+                id: 0,
+                poll_id: self.poll_id,
+                detected_at,
+                video,
+            })));
+        }
+
+        events.extend(followed.into_iter().map(Ok));
+        settle(source, parsed, seen, found, self.layout_changed_after, &mut events);
+        if parsed > 0 {
+            self.hold(index, &on_page, now, &mut events);
+        }
+        events
+    }
+
+    /// Hold the new videos in `events` found on the feed at `index`, and
+    /// release those held long enough
+    ///
+    /// `on_page` is every seq on the page, videos held from this feed that
+    /// aren't on it anymore are dropped. Lives are followed from when they
+    /// are released
+    fn hold(&mut self, index: usize, on_page: &HashSet<u64>, now: Instant, events: &mut Vec<Result<VLiveEvent, VLiveError>>) {
+        if let Some(delay) = self.settle_delay {
+            let mut kept = Vec::with_capacity(events.len());
+            for event in events.drain(..) {
+                match event {
+                    Ok(VLiveEvent::New(event)) => self.held.push(Held { source: index, due: now + delay, event }),
+                    other => kept.push(other),
+                }
+            }
+            *events = kept;
+        }
+
+        let mut held = Vec::with_capacity(self.held.len());
+        for entry in std::mem::take(&mut self.held) {
+            if entry.source != index {
+                held.push(entry);
+                continue;
+            }
+            //Deleted again, it can be announced if it's ever back
+            let video = &entry.event.video;
+            if !on_page.contains(&video.video_seq) {
+                self.sources[index].recent.remove(video.video_seq);
+                self.dedup.forget(&if video.video_id.is_empty() { video.video_seq.to_string() } else { video.video_id.clone() });
+                continue;
+            }
+            if entry.due > now {
+                held.push(entry);
+                continue;
+            }
+            events.push(Ok(VLiveEvent::New(entry.event)));
+        }
+        self.held = held;
+
+        if let Some(ref mut lifecycle) = self.lifecycle {
+            for event in events.iter() {
+                if let Ok(VLiveEvent::New(ref event)) = *event {
+                    lifecycle.start(&VideoRef::from(&event.video), event.detected_at);
+                }
+            }
+        }
+    }
+
+    /// Find channels we haven't seen on a page of the channel directory
+    fn directory(&mut self, index: usize, document: &parser::Document, parser: &Parser)
+        -> Vec<Result<VLiveEvent, VLiveError>>
+    {
+        let source = &mut self.sources[index];
+        let channels = parser.channels(document);
+        if channels.is_empty() {
+            let mut events = vec![Err(VLiveError::ServiceUnavailable(parser.unavailable(document)))];
+            events.extend(source.failed(self.layout_changed_after).map(Err));
+            return events;
+        }
+        source.failures = 0;
+
+        //Everything is new the first time, so nothing is
+        let known = match self.channels {
+            Some(ref mut known) => known,
+            None => {
+                self.channels = Some(channels.into_iter().map(|channel| channel.channel_id).collect());
+                return Vec::new();
+            },
+        };
+        channels.into_iter()
+            .filter(|channel| known.insert(channel.channel_id.clone()))
+            .map(|channel| Ok(VLiveEvent::NewChannelCreated(channel)))
+            .collect()
+    }
+}
+
+/// Bookkeeping after a page: count failures, report gaps and move the
+/// cursor to the newest video, if there was anything new (`seen` is the
+/// newest and oldest of those). Failing to parse is escalated after `after`
+/// polls in a row
+fn settle(source: &mut Source, parsed: u32, seen: Option<(u64, u64)>, found: bool, after: u32,
+    events: &mut Vec<Result<VLiveEvent, VLiveError>>)
+{
+    let id = source.last_seen;
+
+    //Escalate once when a feed stops parsing completely
+    if parsed == 0 {
+        events.extend(source.failed(after).map(Err));
+    } else {
+        source.failures = 0;
+    }
+
+    //Is there a new video?
+    if let Some((newest, oldest)) = seen {
+        //Whole page is new, so there might be more we never saw
+        if !found && id != 0 && oldest > id && oldest - id > 1 {
+            events.push(Ok(VLiveEvent::PossibleGap {
+                feed: source.feed.clone(),
+                missed_range: id + 1..oldest,
+            }));
+        }
+
+        //Okay go back to your eternal slumber, until you are required again
+        source.last_seen = source.last_seen.max(newest);
+    }
+}
+
+/// Add `seq` to the newest and oldest seqs seen so far
+fn widen(seen: Option<(u64, u64)>, seq: u64) -> (u64, u64) {
+    match seen {
+        Some((newest, oldest)) => (newest.max(seq), oldest.min(seq)),
+        None => (seq, seq),
+    }
+}
+
+/// Look at `node` again if it's a stream `lifecycle` follows
+fn follow(lifecycle: &mut Option<Lifecycle>, parser: &Parser, node: parser::Node, at: SystemTime) -> Vec<VLiveEvent> {
+    match (lifecycle.as_mut(), parser.peek_seq(node)) {
+        (Some(lifecycle), Some(seq)) if lifecycle.follows(seq) => match parser.parse_node(node) {
+            Ok(video) => lifecycle.see(video, at),
+            Err(_) => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// Polls on your thread, whenever you call `tick`
+///
+/// No threads are spawned and nothing runs in the background. Call `tick`
+/// from your own loop, a cron job or an async interval, and get the new
+/// events back
+///
+/// ```rust,ignore
+/// let mut ticker = Ticker::new(Poller::new(vec![(Feed::Recent, Duration::from_secs(5))], Instant::now()));
+/// loop {
+///     for event in ticker.tick()? {
+///         println!("{:?}", event);
+///     }
+///     thread::sleep(Duration::from_secs(5));
+/// }
+/// ```
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Ticker {
+    poller: Poller,
+    transport: Arc<dyn Transport>,
+    parser: Parser,
+    clock: Arc<dyn Clock>,
+    languages: Vec<String>,
+    /// Results of the last poll not handed out yet
+    pending: VecDeque<Result<VLiveEvent, VLiveError>>,
+    next_id: u64,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Ticker {
+    pub fn new(poller: Poller) -> Self {
+        Ticker {
+            poller,
+            transport: Arc::new(transport::Compressed::default()),
+            parser: Parser::default(),
+            clock: Arc::new(SystemClock),
+            languages: Vec::new(),
+            pending: VecDeque::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Fetch pages through `transport`, see `VLive::transport`
+    pub fn transport<T>(mut self, transport: Arc<T>) -> Self where T: Transport {
+        self.transport = transport;
+        self
+    }
+
+    /// Parse pages with `parser`, see `VLive::parser`
+    pub fn parser(mut self, parser: Parser) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// Tell the time with `clock`, see `VLive::clock`
+    pub fn clock<C>(mut self, clock: Arc<C>) -> Self where C: Clock {
+        self.clock = clock;
+        self
+    }
+
+    /// Look up titles in `languages` too, see `VLive::languages`
+    pub fn languages(mut self, languages: &[&str]) -> Self {
+        self.languages = languages.iter().map(|language| language.to_string()).collect();
+        self
+    }
+
+    /// The state machine being driven
+    pub fn poller(&self) -> &Poller {
+        &self.poller
+    }
+
+    /// Poll every feed that is due and return what's new
+    ///
+    /// Feeds that aren't due yet are left alone, so calling this more often
+    /// than the feeds' `wait` is harmless. Errors come back one at a time in
+    /// the order they happened, and the events after an error are kept for
+    /// the next call, so call `tick` again to carry on
+    pub fn tick(&mut self) -> Result<Vec<VLiveEvent>, VLiveError> {
+        if self.pending.is_empty() {
+            let now = self.clock.now();
+            for feed in self.poller.due(now, self.clock.system_now()) {
+                match self.transport.get(&self.poller.feed(feed).url()) {
+                    Ok(html) => {
+                        let detected_at = self.clock.system_now();
+                        let mut events = self.poller.page(feed, &html, &self.parser, now, detected_at);
+                        translate(&mut events, self.poller.feed(feed), &self.languages, &*self.transport, &self.parser);
+                        self.pending.extend(events);
+                    },
+                    Err(why) => self.pending.push_back(Err(VLiveError::Transport(why))),
+                }
+            }
+        }
+
+        let mut events = Vec::new();
+        while let Some(next) = self.pending.pop_front() {
+            match next {
+                Ok(VLiveEvent::New(mut event)) => {
+                    self.next_id += 1;
+                    event.id = self.next_id;
+                    events.push(VLiveEvent::New(event));
+                },
+                Ok(event) => events.push(event),
+                Err(why) if events.is_empty() => return Err(why),
+                Err(why) => {
+                    self.pending.push_front(Err(why));
+                    break;
+                },
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// Fill in `VLiveVideo::video_titles` for new videos in `events`
+///
+/// Fetches `feed` once for every language, unless there's nothing new.
+/// Failed fetches are added to `events` as errors. Feeds with a
+/// `Feed::source` aren't VLive pages and are left alone
+pub fn translate(events: &mut Vec<Result<VLiveEvent, VLiveError>>, feed: &Feed, languages: &[String],
+    transport: &dyn Transport, parser: &Parser)
+{
+    if feed.source().is_some() || !events.iter().any(|event| matches!(event, Ok(VLiveEvent::New(_)))) {
+        return;
+    }
+
+    for language in languages {
+        let html = match transport.get(&feed.localized_url(language)) {
+            Ok(html) => html,
+            Err(why) => {
+                events.push(Err(VLiveError::Transport(why)));
+                continue;
+            },
+        };
+        let titles: HashMap<_, _> = parser.parse_page(&html).unwrap_or_default().into_iter()
+            .filter_map(Result::ok)
+            .map(|video| (video.video_seq, video.video_title))
+            .collect();
+        for event in events.iter_mut() {
+            if let Ok(VLiveEvent::New(ref mut event)) = *event {
+                if let Some(title) = titles.get(&event.video.video_seq) {
+                    event.video.video_titles.insert(language.clone(), title.clone());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,17 @@
+//! The types most listeners need, for a glob import
+//!
+//! ```rust,ignore
+//! use vlive_notifs::vlive::prelude::*;
+//! ```
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use super::client::{VLive, VLiveStopper};
+pub use super::ctx::Ctx;
+pub use super::error::VLiveError;
+pub use super::filter::Filter;
+pub use super::middleware::Middleware;
+pub use super::model::{ChannelType, Event, Feed, Flow, Platform, VLiveEvent, VLiveVideo, VideoStage, VideoType};
+pub use super::schedule::Schedule;
+pub use super::sink::VLiveCallback;
+pub use super::source::Source;
+pub use super::template::Template;
@@ -0,0 +1,158 @@
+//! The bounded queue between the poller and the dispatcher thread
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+
+/// What to do when the dispatch queue is full
+///
+/// The poller pushes every new video into a bounded queue, which is then
+/// drained by the dispatcher thread calling your callback. If the callback
+/// is slower than VLive is uploading, the queue eventually fills up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// Wait until the dispatcher makes room. No videos are lost, but polling
+    /// stalls for as long as the callback is busy
+    Block,
+    /// Throw away the oldest queued video to make room for the new one
+    DropOldest,
+    /// Throw away the video that was about to be queued
+    DropNewest,
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    closed: bool,
+    /// Set by `wake`, makes the next `pop_until` return early
+    woken: bool,
+}
+
+struct Inner<T> {
+    state: Mutex<State<T>>,
+    capacity: usize,
+    policy: QueuePolicy,
+    /// Signaled when an item is pushed or the queue is closed
+    not_empty: Condvar,
+    /// Signaled when an item is popped
+    not_full: Condvar,
+}
+
+/// Bounded multi-producer, single-consumer queue between the poller and the
+/// dispatcher thread
+pub struct Queue<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Clone for Queue<T> {
+    fn clone(&self) -> Self {
+        Queue {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Queue<T> {
+    pub fn new(capacity: usize, policy: QueuePolicy) -> Self {
+        Queue {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State {
+                    items: VecDeque::with_capacity(capacity),
+                    closed: false,
+                    woken: false,
+                }),
+                capacity: if capacity == 0 { 1 } else { capacity },
+                policy,
+                not_empty: Condvar::new(),
+                not_full: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Push a new item, applying the queue policy if there is no room
+    ///
+    /// Returns the item that was dropped to make room, if any. Items pushed
+    /// after the queue was closed are silently discarded
+    pub fn push(&self, item: T) -> Option<T> {
+        let inner = &self.inner;
+        let mut state = inner.state.lock().unwrap();
+        if state.closed {
+            return None;
+        }
+
+        let mut dropped = None;
+        if state.items.len() >= inner.capacity {
+            match inner.policy {
+                QueuePolicy::Block => {
+                    while state.items.len() >= inner.capacity && !state.closed {
+                        state = inner.not_full.wait(state).unwrap();
+                    }
+                }
+                QueuePolicy::DropOldest => dropped = state.items.pop_front(),
+                QueuePolicy::DropNewest => return Some(item),
+            }
+        }
+
+        if state.closed {
+            return None;
+        }
+
+        state.items.push_back(item);
+        inner.not_empty.notify_one();
+        dropped
+    }
+
+    /// Wait for the next item, but not past `deadline`
+    ///
+    /// Returns `Ok(None)` once the queue has been closed and fully drained,
+    /// and `Err(())` if nothing arrived in time or somebody called `wake`
+    pub fn pop_until(&self, deadline: Option<Instant>) -> Result<Option<T>, ()> {
+        let inner = &self.inner;
+        let mut state = inner.state.lock().unwrap();
+
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                inner.not_full.notify_one();
+                return Ok(Some(item));
+            }
+            if state.closed {
+                return Ok(None);
+            }
+            if state.woken {
+                state.woken = false;
+                return Err(());
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(());
+                    }
+                    inner.not_empty.wait_timeout(state, deadline - now).unwrap().0
+                },
+                None => inner.not_empty.wait(state).unwrap(),
+            };
+        }
+    }
+
+    /// How many items are waiting
+    pub fn len(&self) -> usize {
+        self.inner.state.lock().unwrap().items.len()
+    }
+
+    /// Interrupt a waiting `pop_until`, so the consumer can look at
+    /// something other than the queue
+    pub fn wake(&self) {
+        self.inner.state.lock().unwrap().woken = true;
+        self.inner.not_empty.notify_all();
+    }
+
+    /// Stop accepting new items
+    ///
+    /// Items already in the queue are still handed out by `pop`
+    pub fn close(&self) {
+        let mut state = self.inner.state.lock().unwrap();
+        state.closed = true;
+        self.inner.not_empty.notify_all();
+        self.inner.not_full.notify_all();
+    }
+}
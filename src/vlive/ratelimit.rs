@@ -0,0 +1,140 @@
+//! Keeping a sink under its platform's rate limit
+//!
+//! Platforms limit how fast a webhook can be posted to, and a backfill of
+//! 40 videos can get it blocked. Wrap the sink in `Throttled` and events
+//! wait their turn instead:
+//!
+//! ```rust,ignore
+//! let discord = Throttled::new(discord, 5, Duration::from_secs(10));
+//! VLive::new(Fanout::new().sink(discord).sink(archive), Duration::from_secs(5)).run();
+//! ```
+
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{ContentEvent, Ctx, DeadLetter, DeliveryError, Event, Flow, ParseFailure, VLiveCallback, VLiveError, VLiveEvent, VLiveVideo};
+
+/// Passes at most `max` events every `per` to `callback`
+///
+/// Events over the limit wait for a slot, holding up the dispatcher
+/// meanwhile, so they queue up in the listener's queue rather than being
+/// dropped. Put it inside a `Fanout` to only hold up this sink. Digests
+/// are passed on a video at a time, each taking a slot. Errors, events
+/// other than new videos and the lifecycle hooks aren't limited
+pub struct Throttled<CB> {
+    callback: CB,
+    max: usize,
+    per: Duration,
+    /// Wait at least `per / max` between events, instead of bursting
+    smooth: bool,
+    /// When the last events within `per` were passed on, oldest first
+    sent: VecDeque<Instant>,
+}
+
+impl<CB> Throttled<CB> where CB: VLiveCallback {
+    pub fn new(callback: CB, max: usize, per: Duration) -> Self {
+        Throttled {
+            callback,
+            max: max.max(1),
+            per,
+            smooth: false,
+            sent: VecDeque::new(),
+        }
+    }
+
+    /// Space events out evenly over `per` instead of passing `max` at once
+    pub fn smooth(mut self) -> Self {
+        self.smooth = true;
+        self
+    }
+
+    /// How long until the next event can be passed on at `now`
+    fn wait(&mut self, now: Instant) -> Duration {
+        while self.sent.front().is_some_and(|&sent| now.duration_since(sent) >= self.per) {
+            self.sent.pop_front();
+        }
+
+        let mut wait = Duration::ZERO;
+        if self.sent.len() >= self.max {
+            if let Some(&oldest) = self.sent.front() {
+                wait = (oldest + self.per).saturating_duration_since(now);
+            }
+        }
+        if self.smooth {
+            if let Some(&last) = self.sent.back() {
+                let spacing = self.per / self.max as u32;
+                wait = wait.max((last + spacing).saturating_duration_since(now));
+            }
+        }
+        wait
+    }
+
+    /// Block until there's a slot for another event, and take it
+    fn take(&mut self) {
+        let wait = self.wait(Instant::now());
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+        let now = Instant::now();
+        self.wait(now);
+        self.sent.push_back(now);
+    }
+}
+
+impl<CB> VLiveCallback for Throttled<CB> where CB: VLiveCallback {
+    fn on_new(&mut self, ctx: &Ctx, video: VLiveVideo) -> Flow {
+        self.take();
+        self.callback.on_new(ctx, video)
+    }
+
+    fn on_content(&mut self, ctx: &Ctx, event: ContentEvent) -> Flow {
+        self.take();
+        self.callback.on_content(ctx, event)
+    }
+
+    fn on_event(&mut self, ctx: &Ctx, event: VLiveEvent) -> Flow {
+        if let VLiveEvent::New(_) = event {
+            self.take();
+        }
+        self.callback.on_event(ctx, event)
+    }
+
+    fn deliver(&mut self, ctx: &Ctx, event: &Event) -> Result<Flow, DeliveryError> {
+        self.take();
+        self.callback.deliver(ctx, event)
+    }
+
+    fn on_digest(&mut self, ctx: &Ctx, videos: Vec<VLiveVideo>) -> Flow {
+        for video in videos {
+            if self.on_new(ctx, video) == Flow::Stop {
+                return Flow::Stop;
+            }
+        }
+        Flow::Continue
+    }
+
+    fn on_error(&mut self, error: VLiveError) -> Flow {
+        self.callback.on_error(error)
+    }
+
+    fn on_parse_error(&mut self, failure: ParseFailure) -> Flow {
+        self.callback.on_parse_error(failure)
+    }
+
+    fn on_dead_letter(&mut self, letter: DeadLetter) -> Flow {
+        self.callback.on_dead_letter(letter)
+    }
+
+    fn on_started(&mut self, ctx: &Ctx) {
+        self.callback.on_started(ctx)
+    }
+
+    fn on_stopped(&mut self) {
+        self.callback.on_stopped()
+    }
+
+    fn on_reconnected(&mut self, outage: Duration) {
+        self.callback.on_reconnected(outage)
+    }
+}
@@ -0,0 +1,332 @@
+//! Rules depending on the time of day
+
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc};
+
+use super::{VLiveVideo, VideoType};
+
+const DAY: u32 = 24 * 60 * 60;
+
+/// Seconds from `from` to `to`, going past midnight if needed
+fn until(from: NaiveTime, to: NaiveTime) -> u32 {
+    (to.num_seconds_from_midnight() + DAY - from.num_seconds_from_midnight()) % DAY
+}
+
+/// How many days ahead to look for a match of a cron expression, long
+/// enough to find the next 29th of February
+const CRON_DAYS: u32 = 8 * 366;
+
+/// Date and time at `at`, in `offset` or local time
+fn date_time(at: SystemTime, offset: Option<FixedOffset>) -> NaiveDateTime {
+    match offset {
+        Some(offset) => DateTime::<Utc>::from(at).with_timezone(&offset).naive_local(),
+        None => DateTime::<Local>::from(at).naive_local(),
+    }
+}
+
+/// Time of day at `at`, in `offset` or local time
+fn time_of_day(at: SystemTime, offset: Option<FixedOffset>) -> NaiveTime {
+    date_time(at, offset).time()
+}
+
+/// Is `now` between `start` and `end`, going past midnight if needed
+fn within(start: NaiveTime, end: NaiveTime, now: NaiveTime) -> bool {
+    until(start, now) < until(start, end)
+}
+
+/// How often to poll a feed, depending on the time of day
+///
+/// VLive is busiest in the Korean evening, so there's little point in
+/// polling as often at 4 AM. A schedule polls every `wait` by default, and
+/// on its own interval inside each window added with `between`. Windows are
+/// checked in the order they were added. Any `Duration` works where a
+/// schedule is expected, meaning the same wait all day
+///
+/// ```rust,ignore
+/// let kst = FixedOffset::east_opt(9 * 60 * 60)?;
+/// let evenings = Schedule::every(Duration::from_secs(5 * 60))
+///     .between(NaiveTime::from_hms_opt(17, 0, 0)?, NaiveTime::from_hms_opt(23, 0, 0)?, Duration::from_secs(10))
+///     .offset(kst);
+/// VLive::new(Handler, Duration::from_secs(5))
+///     .feed(Feed::Recent, evenings)
+///     .run();
+/// ```
+///
+/// The same with a cron expression, which also polls on the times it
+/// matches:
+///
+/// ```rust,ignore
+/// let evenings = Schedule::every(Duration::from_secs(5 * 60))
+///     .cron("*/10 * 17-22 * * *")?
+///     .offset(kst);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    wait: Duration,
+    windows: Vec<(NaiveTime, NaiveTime, Duration)>,
+    crons: Vec<Cron>,
+    /// Time zone the windows are in, local time if `None`
+    offset: Option<FixedOffset>,
+}
+
+impl Schedule {
+    /// Poll every `wait`, all day
+    pub fn every(wait: Duration) -> Self {
+        Schedule {
+            wait,
+            windows: Vec::new(),
+            crons: Vec::new(),
+            offset: None,
+        }
+    }
+
+    /// Poll every `wait` from `start` until `end`, going past midnight if
+    /// `end` is earlier than `start`
+    pub fn between(mut self, start: NaiveTime, end: NaiveTime, wait: Duration) -> Self {
+        self.windows.push((start, end, wait));
+        self
+    }
+
+    /// Also poll whenever the cron expression `expression` matches
+    ///
+    /// Takes the usual 5 fields, minute, hour, day of month, month and day
+    /// of week, or 6 with seconds in front. Each field is `*`, a number, a
+    /// range like `17-22`, any of those with a step like `*/10`, or a list
+    /// of them separated by commas. Sunday is both 0 and 7. Like in cron, a
+    /// day matches either the day of month or the day of week if both are
+    /// given
+    pub fn cron(mut self, expression: &str) -> Result<Self, CronError> {
+        self.crons.push(Cron::parse(expression)?);
+        Ok(self)
+    }
+
+    /// Read the windows and cron expressions in a fixed time zone instead
+    /// of local time
+    pub fn offset(mut self, offset: FixedOffset) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// How long to wait after polling at `at`
+    ///
+    /// Never waits past the start of another window or the next time a
+    /// cron expression matches, so a slow schedule doesn't overshoot into a
+    /// busy one
+    pub fn wait(&self, at: SystemTime) -> Duration {
+        let date_time = date_time(at, self.offset);
+        let now = date_time.time();
+        let current = self.windows.iter()
+            .find(|&&(start, end, _)| within(start, end, now))
+            .map_or(self.wait, |&(_, _, wait)| wait);
+        let wait = self.windows.iter()
+            .filter(|&&(start, end, _)| !within(start, end, now))
+            .map(|&(start, _, _)| until(now, start))
+            .filter(|&secs| secs > 0)
+            .map(|secs| Duration::from_secs(u64::from(secs)))
+            .fold(current, Duration::min);
+        self.crons.iter()
+            .filter_map(|cron| cron.until(date_time))
+            .map(Duration::from_secs)
+            .fold(wait, Duration::min)
+    }
+}
+
+impl From<Duration> for Schedule {
+    fn from(wait: Duration) -> Self {
+        Schedule::every(wait)
+    }
+}
+
+/// A cron expression that went wrong while parsing, see `Schedule::cron`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CronError {
+    /// Not 5 or 6 fields, but this many
+    Fields(usize),
+    /// A field that isn't a number, range, step or list within its bounds
+    Invalid {
+        field: &'static str,
+        value: String,
+    },
+}
+
+impl fmt::Display for CronError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CronError::Fields(fields) => write!(f, "Expected 5 or 6 fields, found {}", fields),
+            CronError::Invalid { field, ref value } => write!(f, "`{}` is not a valid {}", value, field),
+        }
+    }
+}
+
+impl Error for CronError {}
+
+/// A parsed cron expression, with a bit set for every value a field allows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cron {
+    seconds: u64,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    /// Sunday is 0
+    weekdays: u64,
+    /// Both the day of month and day of week were given, so either of them
+    /// matching is enough
+    either_day: bool,
+}
+
+impl Cron {
+    fn parse(expression: &str) -> Result<Cron, CronError> {
+        let mut fields: Vec<&str> = expression.split_whitespace().collect();
+        match fields.len() {
+            5 => fields.insert(0, "0"),
+            6 => (),
+            count => return Err(CronError::Fields(count)),
+        }
+        let weekdays = field(fields[5], "day of week", 0, 7)?;
+        Ok(Cron {
+            seconds: field(fields[0], "second", 0, 59)?,
+            minutes: field(fields[1], "minute", 0, 59)?,
+            hours: field(fields[2], "hour", 0, 23)?,
+            days: field(fields[3], "day of month", 1, 31)?,
+            months: field(fields[4], "month", 1, 12)?,
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            either_day: !fields[3].starts_with('*') && !fields[5].starts_with('*'),
+        })
+    }
+
+    /// Does `date` match the day fields
+    fn on(&self, date: NaiveDate) -> bool {
+        if self.months & 1 << date.month() == 0 {
+            return false;
+        }
+        let day = self.days & 1 << date.day() != 0;
+        let weekday = self.weekdays & 1 << date.weekday().num_days_from_sunday() != 0;
+        if self.either_day { day || weekday } else { day && weekday }
+    }
+
+    /// First second of the day from `from` on that matches the time fields
+    fn first(&self, from: u32) -> Option<u32> {
+        let (hour, minute, second) = (from / 3600, from / 60 % 60, from % 60);
+        for h in (hour..24).filter(|&h| self.hours & 1 << h != 0) {
+            let m_from = if h == hour { minute } else { 0 };
+            for m in (m_from..60).filter(|&m| self.minutes & 1 << m != 0) {
+                let s_from = if h == hour && m == minute { second } else { 0 };
+                if let Some(s) = (s_from..60).find(|&s| self.seconds & 1 << s != 0) {
+                    return Some(h * 3600 + m * 60 + s);
+                }
+            }
+        }
+        None
+    }
+
+    /// Seconds from `now` until the next time after it that matches, `None`
+    /// if that's years away
+    fn until(&self, now: NaiveDateTime) -> Option<u64> {
+        let mut date = now.date();
+        let mut from = now.time().num_seconds_from_midnight() + 1;
+        for _ in 0..CRON_DAYS {
+            if self.on(date) {
+                if let Some(secs) = self.first(from) {
+                    let next = date.and_time(NaiveTime::from_num_seconds_from_midnight_opt(secs, 0)?);
+                    return (next - now).to_std().ok().map(|wait| wait.as_secs());
+                }
+            }
+            date = date.succ_opt()?;
+            from = 0;
+        }
+        None
+    }
+}
+
+/// Parse the field called `name` of a cron expression, allowing values
+/// from `min` to `max`
+fn field(text: &str, name: &'static str, min: u32, max: u32) -> Result<u64, CronError> {
+    let invalid = || CronError::Invalid { field: name, value: text.to_string() };
+    let number = |number: &str| number.parse::<u32>().map_err(|_| invalid());
+
+    let mut bits = 0;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, number(step)?),
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (number(start)?, number(end)?),
+            //`5/15` starts at 5 and carries on stepping
+            None if part.contains('/') => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(invalid());
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+/// Hours during which nothing is delivered, see `VLive::quiet_hours`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietHours {
+    start: NaiveTime,
+    end: NaiveTime,
+    exempt_live: bool,
+    offset: Option<FixedOffset>,
+}
+
+impl QuietHours {
+    /// Quiet from `start` until `end`, in local time
+    ///
+    /// If `end` is earlier than `start` the quiet hours go past midnight, so
+    /// 01:00 to 08:00 and 23:00 to 07:00 both work as expected
+    ///
+    /// ```rust,ignore
+    /// let night = QuietHours::new(NaiveTime::from_hms_opt(1, 0, 0)?, NaiveTime::from_hms_opt(8, 0, 0)?);
+    /// ```
+    pub fn new(start: NaiveTime, end: NaiveTime) -> Self {
+        QuietHours {
+            start,
+            end,
+            exempt_live: false,
+            offset: None,
+        }
+    }
+
+    /// Deliver live streams right away even during quiet hours
+    pub fn exempt_live(mut self, exempt: bool) -> Self {
+        self.exempt_live = exempt;
+        self
+    }
+
+    /// Read `start` and `end` in `offset` rather than local time
+    pub fn offset(mut self, offset: FixedOffset) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// How long until the quiet hours end, `None` if it isn't quiet at `at`
+    pub fn remaining(&self, at: SystemTime) -> Option<Duration> {
+        let now = time_of_day(at, self.offset);
+        let into = until(self.start, now);
+        let length = until(self.start, self.end);
+        if into < length {
+            Some(Duration::from_secs(u64::from(length - into)))
+        } else {
+            None
+        }
+    }
+
+    /// Should `video` be held back at `at`
+    pub fn holds(&self, video: &VLiveVideo, at: SystemTime) -> bool {
+        if self.exempt_live && video.video_type == VideoType::LIVE {
+            return false;
+        }
+        self.remaining(at).is_some()
+    }
+}
@@ -0,0 +1,112 @@
+//! Filtering and changing videos with Rhai scripts
+//!
+//! Needs the `rhai` feature. For logic a `Filter` can't express, without
+//! recompiling: a `Script` is a middleware stage that runs a
+//! [Rhai](https://rhai.rs) script for every video.
+//!
+//! The script sees the video as `video`, an object map with the same fields
+//! as `VLiveVideo`. It can change them, and if it evaluates to `false` the
+//! video is dropped:
+//!
+//! ```rust,ignore
+//! let script = Script::new(r#"
+//!     if video.channel_seq == 363 {
+//!         video.video_title = "[BTS] " + video.video_title;
+//!     }
+//!     !video.video_paid
+//! "#)?;
+//! VLive::new(Handler, Duration::from_secs(5)).middleware(script).run();
+//! ```
+//!
+//! A script that fails while running passes the video on unchanged, since a
+//! bug in a script shouldn't cost a notification.
+
+use std::error::Error;
+use std::path::Path;
+use std::{fmt, fs, io};
+
+use rhai::{Dynamic, Engine, Scope, AST};
+
+use super::middleware::Middleware;
+use super::VLiveVideo;
+
+/// Operations a script may take per video, so a runaway loop doesn't stall
+/// the dispatcher
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// A script that couldn't be loaded
+#[derive(Debug)]
+pub enum ScriptError {
+    Io(io::Error),
+    Parse(rhai::ParseError),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScriptError::Io(ref why) => write!(f, "Couldn't read script: {}", why),
+            ScriptError::Parse(ref why) => write!(f, "Couldn't parse script: {}", why),
+        }
+    }
+}
+
+impl Error for ScriptError {}
+
+impl From<io::Error> for ScriptError {
+    fn from(why: io::Error) -> Self {
+        ScriptError::Io(why)
+    }
+}
+
+impl From<rhai::ParseError> for ScriptError {
+    fn from(why: rhai::ParseError) -> Self {
+        ScriptError::Parse(why)
+    }
+}
+
+/// A compiled Rhai script, run for every video
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Script {
+    pub fn new(source: &str) -> Result<Self, ScriptError> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine.compile(source)?;
+        Ok(Script { engine, ast })
+    }
+
+    /// Read the script from `path`
+    pub fn load<P>(path: P) -> Result<Self, ScriptError> where P: AsRef<Path> {
+        Script::new(&fs::read_to_string(path)?)
+    }
+
+    /// Run the script for `video`, `None` if it should be dropped
+    pub fn run(&self, video: &VLiveVideo) -> Result<Option<VLiveVideo>, Box<rhai::EvalAltResult>> {
+        let mut scope = Scope::new();
+        scope.push("video", rhai::serde::to_dynamic(video)?);
+        let keep = self.engine.eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast)?;
+        if let Ok(false) = keep.as_bool() {
+            return Ok(None);
+        }
+
+        match scope.get_value::<Dynamic>("video") {
+            Some(changed) => rhai::serde::from_dynamic(&changed).map(Some),
+            None => Ok(Some(video.clone())),
+        }
+    }
+}
+
+impl Middleware for Script {
+    fn process(&mut self, video: VLiveVideo) -> Option<VLiveVideo> {
+        match self.run(&video) {
+            Ok(video) => video,
+            Err(why) => {
+                eprintln!("VLive Error: Script failed for {}: {}", video.video_id, why);
+                Some(video)
+            },
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Reporting errors to Sentry
+//!
+//! Needs the `sentry` feature. Set Sentry up as usual with `sentry::init`,
+//! then wrap your callback in `Sentry`, and scraper breakage shows up as an
+//! alert instead of days of silence:
+//!
+//! ```rust,ignore
+//! let _guard = sentry::init("https://key@sentry.io/42");
+//! VLive::new(Sentry::new(Handler), Duration::from_secs(5)).run();
+//! ```
+
+use std::time::Duration;
+
+use super::transport::HttpError;
+use super::{ContentEvent, Ctx, DeadLetter, DeliveryError, Event, Feed, Flow, ParseFailure, VLiveCallback, VLiveError, VLiveEvent, VLiveVideo};
+
+/// Reports every error to Sentry, then passes it on to `callback`
+///
+/// Errors are tagged with their kind and the poll they came from, its
+/// `poll_id`, the URL of the feed and its channel, and carry whatever
+/// context they have, like the HTTP status and body of a failed fetch or
+/// the markup of a video that couldn't be parsed. Everything else goes
+/// straight to `callback`
+pub struct Sentry<CB> {
+    callback: CB,
+    /// Handle on the listener, to find out which poll an error came from
+    ctx: Option<Ctx>,
+}
+
+impl<CB> Sentry<CB> where CB: VLiveCallback {
+    pub fn new(callback: CB) -> Self {
+        Sentry { callback, ctx: None }
+    }
+}
+
+impl<CB> VLiveCallback for Sentry<CB> where CB: VLiveCallback {
+    fn on_new(&mut self, ctx: &Ctx, video: VLiveVideo) -> Flow {
+        self.callback.on_new(ctx, video)
+    }
+
+    fn on_content(&mut self, ctx: &Ctx, event: ContentEvent) -> Flow {
+        self.callback.on_content(ctx, event)
+    }
+
+    fn on_event(&mut self, ctx: &Ctx, event: VLiveEvent) -> Flow {
+        self.callback.on_event(ctx, event)
+    }
+
+    fn deliver(&mut self, ctx: &Ctx, event: &Event) -> Result<Flow, DeliveryError> {
+        self.callback.deliver(ctx, event)
+    }
+
+    fn on_digest(&mut self, ctx: &Ctx, videos: Vec<VLiveVideo>) -> Flow {
+        self.callback.on_digest(ctx, videos)
+    }
+
+    fn on_error(&mut self, error: VLiveError) -> Flow {
+        report(&error, self.ctx.as_ref());
+        self.callback.on_error(error)
+    }
+
+    fn on_parse_error(&mut self, failure: ParseFailure) -> Flow {
+        report(&VLiveError::Parse(failure.clone()), self.ctx.as_ref());
+        self.callback.on_parse_error(failure)
+    }
+
+    fn on_dead_letter(&mut self, letter: DeadLetter) -> Flow {
+        report(&VLiveError::Undelivered(Box::new(letter.clone())), self.ctx.as_ref());
+        self.callback.on_dead_letter(letter)
+    }
+
+    fn on_started(&mut self, ctx: &Ctx) {
+        self.ctx = Some(ctx.clone());
+        self.callback.on_started(ctx)
+    }
+
+    fn on_stopped(&mut self) {
+        self.callback.on_stopped()
+    }
+
+    fn on_reconnected(&mut self, outage: Duration) {
+        self.callback.on_reconnected(outage)
+    }
+}
+
+/// Send `error` to Sentry along with its context, and that of the poll
+/// `ctx` is handling
+fn report(error: &VLiveError, ctx: Option<&Ctx>) {
+    ::sentry::with_scope(|scope| {
+        scope.set_tag("vlive.error", error.kind());
+        if let Some(ctx) = ctx {
+            scope.set_tag("vlive.poll_id", ctx.poll_id());
+            if let Some(feed) = ctx.feed() {
+                scope.set_tag("vlive.url", feed.url());
+                if let Some(channel) = channel(&feed) {
+                    scope.set_tag("vlive.channel", channel);
+                }
+            }
+        }
+        match *error {
+            VLiveError::Transport(ref why) => {
+                if let Some(http) = why.downcast_ref::<HttpError>() {
+                    scope.set_tag("http.status", http.status);
+                    scope.set_extra("url", http.url.clone().into());
+                    scope.set_extra("body", http.body.clone().into());
+                }
+            },
+            VLiveError::Parse(ref failure) => {
+                scope.set_extra("snippet", failure.snippet.clone().into());
+                if let Some(strategy) = failure.strategy {
+                    scope.set_tag("vlive.strategy", strategy);
+                }
+            },
+            VLiveError::LayoutChanged { ref feed, polls } => {
+                scope.set_extra("feed", feed.url().into());
+                scope.set_extra("polls", polls.into());
+            },
+            VLiveError::ServiceUnavailable(ref why) => scope.set_tag("vlive.unavailable", why),
+            VLiveError::Undelivered(ref letter) => {
+                scope.set_extra("event", letter.event.id.into());
+                scope.set_extra("attempts", letter.attempts.into());
+            },
+            _ => (),
+        }
+    }, || ::sentry::capture_error(error));
+}
+
+/// The channel, community or account `feed` follows, `None` for the ones
+/// covering everyone
+fn channel(feed: &Feed) -> Option<&str> {
+    match *feed {
+        Feed::Channel(ref channel) | Feed::Weverse(ref channel) | Feed::YouTube(ref channel) | Feed::NaverTv(ref channel) => Some(channel),
+        Feed::Recent | Feed::Directory => None,
+    }
+}
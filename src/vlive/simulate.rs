@@ -0,0 +1,171 @@
+//! Trying a configuration out without sending anything
+//!
+//! `DryRun` is a callback that prints what it would have sent instead of
+//! sending it, and `Simulation` feeds pages you saved through a poller, your
+//! middleware and a callback, without a network or threads. Together they
+//! show what a new filter or template would do before it goes live:
+//!
+//! ```rust,ignore
+//! let template = Template::parse("{{channel_name}}: {{title}} {{url}}")?;
+//! let mut simulation = Simulation::new(DryRun::new().template(template), vec![Feed::Recent])
+//!     .middleware(filter);
+//! simulation.page(&Feed::Recent, &fs::read_to_string("fixtures/recent.html")?);
+//! simulation.page(&Feed::Recent, &fs::read_to_string("fixtures/recent-later.html")?);
+//! ```
+
+use std::time::{Duration, Instant, SystemTime};
+
+use super::middleware::Middleware;
+use super::poller::Poller;
+use super::template::Template;
+use super::{Ctx, DeliveryError, Event, Feed, Flow, Parser, VLiveCallback, VLiveError, VLiveEvent, VLiveVideo};
+
+/// A callback that only prints what it would have sent
+///
+/// Every video is printed to stdout, formatted with the template if there
+/// is one, and kept in `sent` too
+#[derive(Default)]
+pub struct DryRun {
+    template: Option<Template>,
+    pub sent: Vec<String>,
+}
+
+impl DryRun {
+    pub fn new() -> Self {
+        DryRun::default()
+    }
+
+    /// Format videos with `template`, like the sink being tried out would
+    pub fn template(mut self, template: Template) -> Self {
+        self.template = Some(template);
+        self
+    }
+
+    fn send(&mut self, what: String) {
+        println!("VLive Dry run: Would send {}", what);
+        self.sent.push(what);
+    }
+}
+
+impl VLiveCallback for DryRun {
+    fn on_new(&mut self, _: &Ctx, video: VLiveVideo) -> Flow {
+        let message = match self.template {
+            Some(ref template) => template.render(&video),
+            None => format!("{} ({})", video.video_title, video.video_seq),
+        };
+        self.send(message);
+        Flow::Continue
+    }
+
+    fn on_event(&mut self, ctx: &Ctx, event: VLiveEvent) -> Flow {
+        match event {
+            VLiveEvent::New(event) => self.on_new(ctx, event.video),
+            event => {
+                self.send(format!("{:?}", event));
+                Flow::Continue
+            },
+        }
+    }
+
+    fn deliver(&mut self, ctx: &Ctx, event: &Event) -> Result<Flow, DeliveryError> {
+        Ok(self.on_new(ctx, event.video.clone()))
+    }
+}
+
+/// Runs pages through a poller, middleware and a callback, right away
+///
+/// New videos go through the middleware and are delivered with ids from 1,
+/// other events and errors go to the callback as they would in a listener.
+/// Failed deliveries aren't retried
+pub struct Simulation<CB> where CB: VLiveCallback {
+    poller: Poller,
+    feeds: Vec<Feed>,
+    parser: Parser,
+    middleware: Vec<Box<dyn Middleware>>,
+    callback: CB,
+    next_id: u64,
+    ctx: Ctx,
+}
+
+impl<CB> Simulation<CB> where CB: VLiveCallback {
+    /// Simulate a listener watching `feeds`
+    pub fn new(callback: CB, feeds: Vec<Feed>) -> Self {
+        //Pages come when they're handed in, so the schedule doesn't matter
+        let poller = Poller::new(feeds.iter().map(|feed| (feed.clone(), Duration::ZERO)).collect(), Instant::now());
+        Simulation {
+            poller,
+            feeds,
+            parser: Parser::default(),
+            middleware: Vec::new(),
+            callback,
+            next_id: 0,
+            ctx: Ctx::detached(),
+        }
+    }
+
+    /// Set up the poller, like with `Poller::dedup`
+    pub fn poller<F>(mut self, setup: F) -> Self where F: FnOnce(Poller) -> Poller {
+        self.poller = setup(self.poller);
+        self
+    }
+
+    /// Parse pages with `parser`, see `VLive::parser`
+    pub fn parser(mut self, parser: Parser) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// Add a middleware stage, see `VLive::middleware`
+    pub fn middleware<M>(mut self, middleware: M) -> Self where M: Middleware {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Pretend `html` was just fetched from `feed`
+    ///
+    /// Returns `Flow::Stop` if the callback asked to stop. Panics if the
+    /// simulation wasn't created with `feed`
+    pub fn page(&mut self, feed: &Feed, html: &str) -> Flow {
+        let index = self.feeds.iter().position(|known| known == feed)
+            .unwrap_or_else(|| panic!("{} isn't simulated", feed.url()));
+        let events = self.poller.page(index, html, &self.parser, Instant::now(), SystemTime::now());
+
+        'events: for event in events {
+            let flow = match event {
+                Ok(VLiveEvent::New(mut event)) => {
+                    for stage in &mut self.middleware {
+                        event.video = match stage.process(event.video) {
+                            Some(video) => video,
+                            None => continue 'events,
+                        };
+                    }
+                    self.next_id += 1;
+                    event.id = self.next_id;
+                    match self.callback.deliver(&self.ctx, &event) {
+                        Ok(flow) => flow,
+                        Err(why) => {
+                            eprintln!("VLive Error: Couldn't deliver {}: {}", event.id, why);
+                            Flow::Continue
+                        },
+                    }
+                },
+                Ok(event) => self.callback.on_event(&self.ctx, event),
+                Err(VLiveError::Parse(failure)) => self.callback.on_parse_error(failure),
+                Err(why) => self.callback.on_error(why),
+            };
+            if flow == Flow::Stop {
+                return Flow::Stop;
+            }
+        }
+        Flow::Continue
+    }
+
+    /// The callback, to look at what it got
+    pub fn callback(&self) -> &CB {
+        &self.callback
+    }
+
+    pub fn into_callback(self) -> CB {
+        self.callback
+    }
+}
@@ -0,0 +1,320 @@
+//! Where new videos end up, `VLiveCallback` and what implements it
+
+use std::future::Future;
+#[cfg(feature = "async-tokio")]
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use super::{ContentEvent, Ctx, DeadLetter, DeliveryError, Event, Flow, ParseFailure, VLiveError, VLiveEvent, VLiveVideo};
+
+/// Implement this in your own listener
+///
+/// Callbacks are owned by the listener and only ever called from its
+/// dispatcher thread, so they get `&mut self` and can keep plain state
+/// like counters or a `HashMap` without a `Mutex`
+pub trait VLiveCallback: Send + 'static {
+    /// Called for every new video
+    ///
+    /// Return `Flow::Stop` to stop the listener from within the callback,
+    /// for example when waiting for the next live stream to start. `ctx`
+    /// is a handle on the listener, to poll again right away, pause it or
+    /// look at its stats and state, see `Ctx`
+    fn on_new(&mut self, ctx: &Ctx, video: VLiveVideo) -> Flow;
+
+    /// Called for every new video, with its platform and the ids it has
+    /// there, see `ContentEvent`
+    ///
+    /// By default this calls `on_new`. Implement this if you handle
+    /// videos from several platforms differently
+    fn on_content(&mut self, ctx: &Ctx, event: ContentEvent) -> Flow {
+        self.on_new(ctx, event.video)
+    }
+
+    /// Called for every event
+    ///
+    /// By default this calls `on_content` for new videos and ignores the
+    /// rest. Implement this if you want to know about the other events
+    /// like `VLiveEvent::PossibleGap` too
+    fn on_event(&mut self, ctx: &Ctx, event: VLiveEvent) -> Flow {
+        match event {
+            VLiveEvent::New(event) => self.on_content(ctx, event.into()),
+            _ => Flow::Continue,
+        }
+    }
+
+    /// Called for every new video, to find out if it was delivered
+    ///
+    /// By default this calls `on_event` and always succeeds. Implement
+    /// this if delivering can fail, like posting to a webhook that might
+    /// be down. Failed events are retried later, see `VLive::retry`, and
+    /// passed to `on_dead_letter` if they never go through
+    fn deliver(&mut self, ctx: &Ctx, event: &Event) -> Result<Flow, DeliveryError> {
+        Ok(self.on_event(ctx, VLiveEvent::New(event.clone())))
+    }
+
+    /// Called with every digest of new videos, see `VLive::digest`
+    ///
+    /// Videos are in the order they were found. By default this calls
+    /// `on_new` for each of them
+    fn on_digest(&mut self, ctx: &Ctx, videos: Vec<VLiveVideo>) -> Flow {
+        for video in videos {
+            if self.on_new(ctx, video) == Flow::Stop {
+                return Flow::Stop;
+            }
+        }
+        Flow::Continue
+    }
+
+    /// Called when something goes wrong
+    ///
+    /// The listener keeps going after errors, by default they are just
+    /// printed to stderr. Watch out for `VLiveError::LayoutChanged`, it
+    /// means notifications have stopped coming until this crate is fixed
+    fn on_error(&mut self, error: VLiveError) -> Flow {
+        eprintln!("VLive Error: {}", error);
+        Flow::Continue
+    }
+
+    /// Called when a video on the page couldn't be parsed
+    ///
+    /// Network trouble comes and goes, but parse failures mean VLive
+    /// changed something. `failure` has the offending HTML, so you can
+    /// file it somewhere for fixing. By default this goes to `on_error`
+    fn on_parse_error(&mut self, failure: ParseFailure) -> Flow {
+        self.on_error(VLiveError::Parse(failure))
+    }
+
+    /// Called when `deliver` kept failing for an event
+    ///
+    /// This is your last chance to save it somewhere, otherwise the event
+    /// is lost. By default this goes to `on_error`
+    fn on_dead_letter(&mut self, letter: DeadLetter) -> Flow {
+        self.on_error(VLiveError::Undelivered(Box::new(letter)))
+    }
+
+    /// Called once the listener is running, before anything else
+    ///
+    /// A good place to announce the notifier is up. `ctx` is the same
+    /// handle the callbacks about videos get, clone it to act on the
+    /// listener from another thread. Does nothing by default
+    fn on_started(&mut self, ctx: &Ctx) {
+        let _ = ctx;
+    }
+
+    /// Called last, once the listener has stopped and everything left
+    /// over was delivered. Does nothing by default
+    fn on_stopped(&mut self) {}
+
+    /// Called when VLive can be reached again after every poll for
+    /// `outage` failed to fetch anything
+    ///
+    /// The errors themselves went to `on_error` as they happened. Does
+    /// nothing by default
+    fn on_reconnected(&mut self, outage: Duration) {
+        let _ = outage;
+    }
+}
+
+/// Any closure taking a `VLiveVideo` works as a callback
+///
+/// The closure can return either `()` or a `Flow`, and is free to mutate
+/// anything it captured. It doesn't get the `Ctx`, implement
+/// `VLiveCallback` for that
+impl<F, R> VLiveCallback for F where F: FnMut(VLiveVideo) -> R + Send + 'static, R: Into<Flow> {
+    fn on_new(&mut self, _: &Ctx, video: VLiveVideo) -> Flow {
+        self(video).into()
+    }
+}
+
+/// `on_new` for callbacks that do their work in `deliver`
+///
+/// Delivers `video` as an `Event::unsequenced`, a failure is printed to
+/// stderr since there's no retrying it
+///
+/// ```rust,ignore
+/// impl VLiveCallback for Webhook {
+///     fn on_new(&mut self, ctx: &Ctx, video: VLiveVideo) -> Flow {
+///         deliver_new(self, ctx, video)
+///     }
+///
+///     fn deliver(&mut self, _: &Ctx, event: &Event) -> Result<Flow, DeliveryError> {
+///         self.post(event)?;
+///         Ok(Flow::Continue)
+///     }
+/// }
+/// ```
+pub fn deliver_new<CB>(callback: &mut CB, ctx: &Ctx, video: VLiveVideo) -> Flow where CB: VLiveCallback + ?Sized {
+    let event = Event::unsequenced(video);
+    match callback.deliver(ctx, &event) {
+        Ok(flow) => flow,
+        Err(why) => {
+            eprintln!("VLive Error: Delivering {} failed: {}", event.video.video_id, why);
+            Flow::Continue
+        },
+    }
+}
+
+/// Stop the listener once nobody is receiving from a channel anymore
+fn sent(ok: bool) -> Flow {
+    if ok { Flow::Continue } else { Flow::Stop }
+}
+
+/// Forward new videos into a channel
+///
+/// Handy when you already have a loop consuming a channel. The listener
+/// stops when the receiving end is dropped.
+impl VLiveCallback for Sender<VLiveVideo> {
+    fn on_new(&mut self, _: &Ctx, video: VLiveVideo) -> Flow {
+        sent(self.send(video).is_ok())
+    }
+}
+
+impl VLiveCallback for Sender<VLiveEvent> {
+    fn on_new(&mut self, ctx: &Ctx, video: VLiveVideo) -> Flow {
+        self.on_event(ctx, VLiveEvent::New(Event::unsequenced(video)))
+    }
+
+    fn on_event(&mut self, _: &Ctx, event: VLiveEvent) -> Flow {
+        sent(self.send(event).is_ok())
+    }
+}
+
+#[cfg(feature = "crossbeam-channel")]
+impl VLiveCallback for crossbeam_channel::Sender<VLiveVideo> {
+    fn on_new(&mut self, _: &Ctx, video: VLiveVideo) -> Flow {
+        sent(self.send(video).is_ok())
+    }
+}
+
+/// Blocks the dispatcher thread while the channel is full
+#[cfg(feature = "async-tokio")]
+impl VLiveCallback for tokio::sync::mpsc::Sender<VLiveVideo> {
+    fn on_new(&mut self, _: &Ctx, video: VLiveVideo) -> Flow {
+        sent(self.blocking_send(video).is_ok())
+    }
+}
+
+#[cfg(feature = "async-tokio")]
+impl VLiveCallback for tokio::sync::mpsc::UnboundedSender<VLiveVideo> {
+    fn on_new(&mut self, _: &Ctx, video: VLiveVideo) -> Flow {
+        sent(self.send(video).is_ok())
+    }
+}
+
+/// Asynchronous version of `VLiveCallback`
+///
+/// Implement this if your listener needs to await something, like posting
+/// to a Discord or Telegram webhook. Every new video runs as its own task,
+/// so a slow request doesn't hold up the ones after it.
+/// See `VLive::new_async`
+pub trait AsyncVLiveCallback: Send + Sync + 'static {
+    fn on_new(&self, video: VLiveVideo) -> impl Future<Output = ()> + Send;
+}
+
+/// Asynchronous sink that pushes back when it's busy
+///
+/// Unlike `AsyncVLiveCallback`, events are sent one at a time, in order,
+/// and only once the sink says it's `ready`. Events wait in a bounded
+/// queue meanwhile, and once that's full the dispatcher waits too, then
+/// the poller, as set with `VLive::queue`. A slow service holds up
+/// polling instead of piling up events in memory during a storm. See
+/// `VLive::new_sink`
+///
+/// ```rust,ignore
+/// impl Sink for Webhook {
+///     async fn ready(&mut self) {
+///         self.limiter.until_ready().await
+///     }
+///
+///     async fn send(&mut self, event: Event) -> Result<(), DeliveryError> {
+///         self.client.post(&self.url).json(&event.video).send().await?;
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait Sink: Send + 'static {
+    /// Wait until the sink can take another event. Ready right away by
+    /// default
+    fn ready(&mut self) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// Send `event` on. Failures are printed to stderr, the event isn't
+    /// retried
+    fn send(&mut self, event: Event) -> impl Future<Output = Result<(), DeliveryError>> + Send;
+}
+
+/// Runs a `Sink` on a tokio runtime
+///
+/// Created by `VLive::new_sink`. New videos wait for the sink in a queue
+/// of `capacity`, the dispatcher blocks while it's full
+#[cfg(feature = "async-tokio")]
+pub struct TokioSink {
+    events: tokio::sync::mpsc::Sender<Event>,
+}
+
+#[cfg(feature = "async-tokio")]
+impl TokioSink {
+    /// Spawn a task on `runtime` sending events to `sink`
+    pub fn new<S>(sink: S, capacity: usize, runtime: tokio::runtime::Handle) -> Self where S: Sink {
+        let (events, mut queued) = tokio::sync::mpsc::channel::<Event>(capacity.max(1));
+        runtime.spawn(async move {
+            let mut sink = sink;
+            loop {
+                sink.ready().await;
+                let event = match queued.recv().await {
+                    Some(event) => event,
+                    None => break,
+                };
+                let code = event.video.video_id.clone();
+                if let Err(why) = sink.send(event).await {
+                    eprintln!("VLive Error: Sink failed to send {}: {}", code, why);
+                }
+            }
+        });
+        TokioSink { events }
+    }
+}
+
+#[cfg(feature = "async-tokio")]
+impl VLiveCallback for TokioSink {
+    fn on_new(&mut self, ctx: &Ctx, video: VLiveVideo) -> Flow {
+        deliver_new(self, ctx, video)
+    }
+
+    /// Stops the listener if the sink's task is gone
+    fn deliver(&mut self, _: &Ctx, event: &Event) -> Result<Flow, DeliveryError> {
+        Ok(sent(self.events.blocking_send(event.clone()).is_ok()))
+    }
+}
+
+/// Runs an `AsyncVLiveCallback` on a tokio runtime
+///
+/// Created by `VLive::new_async`, spawns a task for every new video
+#[cfg(feature = "async-tokio")]
+pub struct TokioCallback<CB> where CB: AsyncVLiveCallback {
+    pub(crate) callback: Arc<CB>,
+    pub(crate) runtime: tokio::runtime::Handle,
+    /// Tasks running longer than this are cancelled
+    pub(crate) abandon_after: Option<std::time::Duration>,
+}
+
+#[cfg(feature = "async-tokio")]
+impl<CB> VLiveCallback for TokioCallback<CB> where CB: AsyncVLiveCallback {
+    fn on_new(&mut self, _: &Ctx, video: VLiveVideo) -> Flow {
+        let callback = self.callback.clone();
+        let abandon_after = self.abandon_after;
+        self.runtime.spawn(async move {
+            let code = video.video_id.clone();
+            let task = callback.on_new(video);
+            match abandon_after {
+                Some(deadline) => if tokio::time::timeout(deadline, task).await.is_err() {
+                    eprintln!("VLive Error: Callback for {} abandoned after {:?}", code, deadline);
+                },
+                None => task.await,
+            }
+        });
+        Flow::Continue
+    }
+}
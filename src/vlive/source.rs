@@ -0,0 +1,270 @@
+//! Platforms other than VLive's own pages
+//!
+//! VLive's pages are HTML and go through the `Parser` with its fallback
+//! strategies. Other platforms list their videos in JSON APIs or feeds, and
+//! a `Source` turns one of those responses into `VLiveVideo`s. The poller
+//! treats them like any other feed, so dedup, lifecycles, middleware and
+//! callbacks work the same whichever platform a video came from.
+//!
+//! Videos from a `Source` carry an absolute URL in `video_id` and
+//! `channel_id`, use `VLiveVideo::url` instead of prefixing them with
+//! `https://vlive.tv`.
+
+use std::convert::TryFrom;
+
+use serde_json::Value;
+
+use super::parser;
+use super::transport::stable_hash;
+use super::{ChannelType, ParseError, ParseFailure, Platform, Unavailable, VLiveVideo, VideoStage, VideoType};
+
+/// A platform that lists videos in an API or a feed
+pub trait Source: Send + Sync + 'static {
+    /// Which platform this is, the same as `platform` of its videos
+    fn platform(&self) -> Platform;
+
+    /// Where the videos of `channel` are listed, newest first
+    fn url(&self, channel: &str) -> String;
+
+    /// Every video in a response from `url`, newest first
+    ///
+    /// Like `Parser::parse_page`, a response without any videos is `Err`,
+    /// otherwise every entry gives a video or why it couldn't be parsed
+    fn parse(&self, body: &str) -> Result<Vec<Result<VLiveVideo, ParseFailure>>, Unavailable>;
+}
+
+/// Weverse, where VLive's lives and media moved to
+///
+/// Channels are Weverse communities by their URL name, like `"bts"`.
+/// Weverse only answers signed in users, so poll it with a
+/// `transport::Session` carrying your cookie
+pub struct Weverse;
+
+impl Source for Weverse {
+    fn platform(&self) -> Platform {
+        Platform::WEVERSE
+    }
+
+    fn url(&self, channel: &str) -> String {
+        format!("https://weverse.io/api/wevweb/post/v1.0/community/{}/liveTabPosts?fieldSet=postsV1&limit=20", channel)
+    }
+
+    fn parse(&self, body: &str) -> Result<Vec<Result<VLiveVideo, ParseFailure>>, Unavailable> {
+        let json: Value = serde_json::from_str(body).map_err(|_| unavailable(body))?;
+        let posts = match json.get("data").and_then(Value::as_array) {
+            Some(posts) if !posts.is_empty() => posts,
+            _ => return Err(Unavailable::Empty),
+        };
+        Ok(posts.iter()
+            .map(|post| weverse_post(post).map_err(|why| ParseFailure::new(why, &post.to_string())))
+            .collect())
+    }
+}
+
+/// Naver TV, where a lot of VLive's shows are mirrored or exclusive
+///
+/// Channels are by their URL name, like `"bts"` for `tv.naver.com/bts`.
+/// Naver TV is run by the same people as VLive, and its clips and lives
+/// carry the same types and statuses
+pub struct NaverTv;
+
+impl Source for NaverTv {
+    fn platform(&self) -> Platform {
+        Platform::NAVERTV
+    }
+
+    fn url(&self, channel: &str) -> String {
+        format!("https://tv.naver.com/api/{}/clips?sortType=newest&page=1&pageSize=20", channel)
+    }
+
+    fn parse(&self, body: &str) -> Result<Vec<Result<VLiveVideo, ParseFailure>>, Unavailable> {
+        let json: Value = serde_json::from_str(body).map_err(|_| unavailable(body))?;
+        let clips = match json.get("clips").and_then(Value::as_array) {
+            Some(clips) if !clips.is_empty() => clips,
+            _ => return Err(Unavailable::Empty),
+        };
+        let channel = json.get("channel").unwrap_or(&Value::Null);
+        Ok(clips.iter()
+            .map(|clip| naver_clip(clip, channel).map_err(|why| ParseFailure::new(why, &clip.to_string())))
+            .collect())
+    }
+}
+
+/// One clip of a Naver TV channel, lives included
+fn naver_clip(clip: &Value, channel: &Value) -> Result<VLiveVideo, ParseError> {
+    let path = text(channel, "channelUrlPath")?;
+    let kind = clip.get("clipType").and_then(Value::as_str);
+    let video_seq = seq(clip, "clipNo")?;
+    let video_type = parser::video_type(kind);
+
+    Ok(VLiveVideo {
+        //Lives have their own pages until they're turned into a clip
+        video_id: match video_type {
+            VideoType::LIVE => format!("https://tv.naver.com/l/{}", video_seq),
+            VideoType::VOD => format!("https://tv.naver.com/v/{}", video_seq),
+        },
+        video_seq,
+        video_title: parser::normalize(text(clip, "title")?).into_owned(),
+        video_titles: Default::default(),
+        video_type,
+        video_stage: parser::video_stage(kind, clip.get("status").and_then(Value::as_str)),
+        video_paid: clip.get("paid").and_then(Value::as_bool).unwrap_or(false),
+        video_thumbnail: clip.get("thumbnailImageUrl").and_then(Value::as_str).map(str::to_string),
+        channel_id: format!("https://tv.naver.com/{}", path),
+        channel_seq: seq(channel, "channelNo")?,
+        channel_name: parser::normalize(text(channel, "channelName")?).into_owned(),
+        channel_type: ChannelType::BASIC,
+        platform: Platform::NAVERTV,
+        video_mirrors: Vec::new(),
+    })
+}
+
+/// YouTube channels, through their RSS feeds
+///
+/// Channels are by their id, like `"UC3IZKseVpdzPSBaWxBxundA"`. The feed
+/// needs no API key, but it doesn't say which videos are live streams, so
+/// they're all VODs. YouTube ids aren't numbers, so `video_seq` is when the
+/// video was published, in seconds since 1970, shifted up by 20 bits with a
+/// hash of the id below. Videos published in the same second still get a
+/// seq of their own, and `video_seq >> 20` is the time
+pub struct YouTube;
+
+impl Source for YouTube {
+    fn platform(&self) -> Platform {
+        Platform::YOUTUBE
+    }
+
+    fn url(&self, channel: &str) -> String {
+        format!("https://www.youtube.com/feeds/videos.xml?channel_id={}", channel)
+    }
+
+    fn parse(&self, body: &str) -> Result<Vec<Result<VLiveVideo, ParseFailure>>, Unavailable> {
+        if !body.contains("<feed") {
+            return Err(unavailable(body));
+        }
+        let entries: Vec<_> = body.split("<entry>").skip(1)
+            .map(|entry| entry.split("</entry>").next().unwrap_or(entry))
+            .collect();
+        if entries.is_empty() {
+            return Err(Unavailable::Empty);
+        }
+        Ok(entries.into_iter()
+            .map(|entry| youtube_entry(entry).map_err(|why| ParseFailure::new(why, entry)))
+            .collect())
+    }
+}
+
+/// `video_seq` of the YouTube video `id` published at `published`
+fn youtube_seq(published: i64, id: &str) -> Option<u64> {
+    let published = u64::try_from(published).ok().filter(|&published| published < 1 << 44)?;
+    Some(published << 20 | stable_hash(id) & 0xf_ffff)
+}
+
+/// One `<entry>` of a YouTube channel's feed
+fn youtube_entry(entry: &str) -> Result<VLiveVideo, ParseError> {
+    let id = element(entry, "yt:videoId")?;
+    let published = element(entry, "published")?;
+    let published = chrono::DateTime::parse_from_rfc3339(&published)
+        .map_err(|why| invalid_xml(&format!("`published` is not a date: {}", why)))?;
+    let channel = element(entry, "yt:channelId")?;
+
+    Ok(VLiveVideo {
+        video_id: format!("https://www.youtube.com/watch?v={}", id),
+        video_seq: youtube_seq(published.timestamp(), &id).ok_or_else(|| invalid_xml("`published` doesn't fit a seq"))?,
+        video_title: element(entry, "title")?,
+        video_titles: Default::default(),
+        video_type: VideoType::VOD,
+        video_stage: VideoStage::VOD,
+        video_paid: false,
+        video_thumbnail: thumbnail(entry),
+        channel_id: format!("https://www.youtube.com/channel/{}", channel),
+        channel_seq: 0,
+        channel_name: element(entry, "name")?,
+        channel_type: ChannelType::BASIC,
+        platform: Platform::YOUTUBE,
+        video_mirrors: Vec::new(),
+    })
+}
+
+/// The text of the first `name` element in `xml`, unescaped and tidied up
+/// with `parser::normalize`
+fn element(xml: &str, name: &str) -> Result<String, ParseError> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = xml.find(&open).ok_or_else(|| invalid_xml(&format!("no `<{}>`", name)))? + open.len();
+    let end = xml[start..].find(&close).ok_or_else(|| invalid_xml(&format!("`<{}>` is never closed", name)))?;
+    Ok(parser::normalize(&xml[start..start + end]).into_owned())
+}
+
+/// The url of the entry's `<media:thumbnail>`
+fn thumbnail(entry: &str) -> Option<String> {
+    let start = entry.find("<media:thumbnail")?;
+    let url = entry[start..].split("url=\"").nth(1)?;
+    Some(parser::normalize(url.split('"').next()?).into_owned())
+}
+
+fn invalid_xml(reason: &str) -> ParseError {
+    ParseError::InvalidXml { reason: reason.to_string() }
+}
+
+/// One post of a Weverse community's live tab
+fn weverse_post(post: &Value) -> Result<VLiveVideo, ParseError> {
+    let video = post.pointer("/extension/video")
+        .ok_or_else(|| invalid("post has no `extension.video`"))?;
+    let community = post.get("community")
+        .ok_or_else(|| invalid("post has no `community`"))?;
+    let path = text(community, "urlPath")?;
+    let post_id = text(post, "postId")?;
+    let kind = video.get("type").and_then(Value::as_str);
+
+    Ok(VLiveVideo {
+        video_id: format!("https://weverse.io/{}/live/{}", path, post_id),
+        video_seq: seq(video, "videoId")?,
+        video_title: parser::normalize(text(post, "title")?).into_owned(),
+        video_titles: Default::default(),
+        video_type: parser::video_type(kind),
+        video_stage: parser::video_stage(kind, video.get("status").and_then(Value::as_str)),
+        video_paid: video.get("paid").and_then(Value::as_bool).unwrap_or(false),
+        video_thumbnail: video.get("thumb").and_then(Value::as_str).map(str::to_string),
+        channel_id: format!("https://weverse.io/{}", path),
+        channel_seq: seq(community, "communityId")?,
+        channel_name: parser::normalize(text(community, "communityName")?).into_owned(),
+        //Membership only posts are Weverse's Channel+
+        channel_type: match post.get("membershipOnly").and_then(Value::as_bool) {
+            Some(true) => ChannelType::PLUS,
+            _ => ChannelType::BASIC,
+        },
+        platform: Platform::WEVERSE,
+        video_mirrors: Vec::new(),
+    })
+}
+
+/// A text field of `json`
+fn text<'a>(json: &'a Value, field: &str) -> Result<&'a str, ParseError> {
+    json.get(field).and_then(Value::as_str)
+        .ok_or_else(|| invalid(&format!("`{}` is missing or not text", field)))
+}
+
+/// A numeric field of `json` that's a seq, as a number or as text
+fn seq(json: &Value, field: &str) -> Result<u64, ParseError> {
+    json.get(field)
+        .and_then(|value| value.as_u64().or_else(|| value.as_str().and_then(|value| value.parse().ok())))
+        .filter(|&value| value != 0)
+        .ok_or_else(|| invalid(&format!("`{}` is missing or not a seq", field)))
+}
+
+fn invalid(reason: &str) -> ParseError {
+    ParseError::InvalidJson { reason: reason.to_string() }
+}
+
+/// Why an API answered with something other than JSON
+fn unavailable(body: &str) -> Unavailable {
+    let body = body.to_lowercase();
+    if body.contains("login") || body.contains("로그인") {
+        Unavailable::Login
+    } else if body.contains("maintenance") || body.contains("점검") {
+        Unavailable::Maintenance
+    } else {
+        Unavailable::Empty
+    }
+}
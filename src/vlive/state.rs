@@ -0,0 +1,61 @@
+//! Saving and restoring where a listener is
+
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Event, Feed, VLiveVideo};
+
+/// Everything the poller remembers between polls
+///
+/// Taken with `VLiveStopper::snapshot` and put back with `VLive::restore`.
+/// Serialize it however your application persists things, a listener
+/// restored from it picks up exactly where the old one stopped, without
+/// announcing anything twice
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct PollerState {
+    pub feeds: Vec<FeedState>,
+    /// Video codes already announced, with when they were first seen,
+    /// oldest first
+    pub announced: Vec<(String, SystemTime)>,
+    /// Codes in `announced` that were live streams and haven't been seen
+    /// as a VOD yet
+    #[serde(default)]
+    pub announced_lives: Vec<String>,
+    /// Live streams being followed, see `VLive::lifecycle`
+    #[serde(default)]
+    pub lives: Vec<LiveState>,
+    /// Channel ids seen in the directory, see `VLive::discover_channels`.
+    /// `None` if it was never polled
+    #[serde(default)]
+    pub channels: Option<Vec<String>>,
+    /// New videos held back with their feed, see `VLive::settle_delay`
+    #[serde(default)]
+    pub held: Vec<(Feed, Event)>,
+}
+
+/// Where the poller is on a single feed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeedState {
+    pub feed: Feed,
+    /// Seq of the newest video seen on this feed, 0 if never polled
+    pub last_seen: u64,
+    /// Polls in a row nothing could be parsed from this feed
+    pub failures: u32,
+    /// Seqs of the videos seen on this feed lately, oldest first
+    #[serde(default)]
+    pub recent: Vec<u64>,
+    /// Follower count of the channel when last polled, see
+    /// `VLive::follower_milestones`
+    #[serde(default)]
+    pub followers: Option<u64>,
+}
+
+/// A live stream being followed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LiveState {
+    /// The video as it was last seen
+    pub video: VLiveVideo,
+    /// When it was first seen in its current stage
+    pub since: SystemTime,
+}
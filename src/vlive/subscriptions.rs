@@ -0,0 +1,370 @@
+//! Serving many consumers from one listener
+//!
+//! A public bot polls once for everyone, but every Discord guild or
+//! Telegram chat wants its own channels. `Subscriptions` keeps track of
+//! who wants what and where to send it, and is the listener's callback:
+//!
+//! ```rust,ignore
+//! let subscriptions = Subscriptions::open("subscriptions.json")?
+//!     .sink("discord", |subscription, event| discord.post(&subscription.target, &event.video));
+//! VLive::new(subscriptions.clone(), Duration::from_secs(5)).run_async();
+//!
+//! //Later, from a bot command
+//! subscriptions.subscribe(Subscription {
+//!     subscriber: guild_id.to_string(),
+//!     channels: ChannelFilter::default().allow(&["FE619"]),
+//!     sink: "discord".to_string(),
+//!     target: webhook_url,
+//!     live_only: true,
+//!     mention: Some("<@&1234>".to_string()),
+//!     ..Subscription::default()
+//! })?;
+//! ```
+//!
+//! Subscriptions can be changed at any time from any clone, and are saved
+//! to the file they were opened from on every change. Sinks are code, so
+//! they're registered again on every start and subscriptions refer to them
+//! by name.
+//!
+//! Each subscriber also has their own preferences, applied before the sink
+//! is called: `live_only`, `quiet` hours in their own time zone, and a
+//! `language` the sink gets the title in. `mention` is left to the sink,
+//! since only it knows how to ping someone.
+//!
+//! Moving a bot to another host takes a `Backup` of the subscriptions and
+//! where the listener was:
+//!
+//! ```rust,ignore
+//! //Old host
+//! subscriptions.export(Some(stopper.snapshot())).write(File::create("backup.json")?)?;
+//!
+//! //New host
+//! let state = subscriptions.import(Backup::read(File::open("backup.json")?)?)?;
+//! let mut listener = VLive::new(subscriptions.clone(), Duration::from_secs(5));
+//! if let Some(state) = state {
+//!     listener = listener.restore(state);
+//! }
+//! ```
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use chrono::{FixedOffset, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+use super::sink::deliver_new;
+use super::{ChannelFilter, Ctx, DeliveryError, Event, Flow, PollerState, QuietHours, VLiveCallback, VLiveVideo, VideoType};
+
+/// What one consumer is subscribed to, and where it goes
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subscription {
+    /// Who it's for, like a Discord guild id. One subscription each
+    pub subscriber: String,
+    /// Which channels they want, all of them by default
+    #[serde(default)]
+    pub channels: ChannelFilter,
+    /// Name of the sink to deliver with, see `Subscriptions::sink`
+    pub sink: String,
+    /// Where the sink should deliver to, like a webhook URL or a chat id
+    #[serde(default)]
+    pub target: String,
+    /// Only live streams, no VODs
+    #[serde(default)]
+    pub live_only: bool,
+    /// Language code like `"en"` to give the sink titles in, when VLive has
+    /// the title in it. See `VLive::languages`
+    #[serde(default)]
+    pub language: Option<String>,
+    /// When not to deliver anything
+    #[serde(default)]
+    pub quiet: Option<Quiet>,
+    /// Who the sink should mention, like a Discord role. Passed on as is
+    #[serde(default)]
+    pub mention: Option<String>,
+}
+
+impl Subscription {
+    /// Does this subscription want `video` at `at`
+    pub fn wants(&self, video: &VLiveVideo, at: SystemTime) -> bool {
+        if self.live_only && video.video_type != VideoType::LIVE {
+            return false;
+        }
+        if let Some(hours) = self.quiet.as_ref().and_then(Quiet::hours) {
+            if hours.holds(video, at) {
+                return false;
+            }
+        }
+        self.channels.contains(&video.channel_id, video.channel_seq)
+    }
+
+    /// `event` with the title in this subscription's `language`, if the
+    /// video has one in it
+    pub fn localize<'a>(&self, event: &'a Event) -> Cow<'a, Event> {
+        let title = self.language.as_ref()
+            .and_then(|language| event.video.video_titles.get(language));
+        match title {
+            Some(title) if *title != event.video.video_title => {
+                let mut event = event.clone();
+                event.video.video_title = title.clone();
+                Cow::Owned(event)
+            },
+            _ => Cow::Borrowed(event),
+        }
+    }
+}
+
+/// Quiet hours of one subscriber, like
+/// `{"start": "01:00", "end": "08:00", "utc_offset": 32400}`
+///
+/// Videos that come up during them aren't delivered to the subscriber at
+/// all, unlike `VLive::quiet_hours` which holds them back for everyone
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quiet {
+    /// Quiet from, `HH:MM`
+    pub start: String,
+    /// Until, `HH:MM`. Earlier than `start` goes past midnight
+    pub end: String,
+    /// Seconds east of UTC `start` and `end` are in, like 32400 for KST
+    #[serde(default)]
+    pub utc_offset: i32,
+    /// Deliver live streams anyway
+    #[serde(default)]
+    pub exempt_live: bool,
+}
+
+impl Quiet {
+    /// These quiet hours, `None` if `start`, `end` or `utc_offset` are
+    /// nonsense
+    pub fn hours(&self) -> Option<QuietHours> {
+        let time = |text: &str| {
+            let (hour, minute) = text.trim().split_once(':')?;
+            NaiveTime::from_hms_opt(hour.parse().ok()?, minute.parse().ok()?, 0)
+        };
+        let offset = FixedOffset::east_opt(self.utc_offset)?;
+        Some(QuietHours::new(time(&self.start)?, time(&self.end)?)
+            .exempt_live(self.exempt_live)
+            .offset(offset))
+    }
+}
+
+/// Subscriptions and where the listener was, as one JSON document
+///
+/// See `Subscriptions::export` and `Subscriptions::import`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Backup {
+    /// Every subscription, with its channels and preferences
+    #[serde(default)]
+    pub subscriptions: Vec<Subscription>,
+    /// Feed cursors and announced videos, from `VLiveStopper::snapshot`
+    #[serde(default)]
+    pub state: Option<PollerState>,
+}
+
+impl Backup {
+    /// Read a backup written by `write`
+    pub fn read<R>(from: R) -> io::Result<Self> where R: Read {
+        Ok(serde_json::from_reader(from)?)
+    }
+
+    /// Write this backup as JSON to `out`
+    pub fn write<W>(&self, out: W) -> io::Result<()> where W: Write {
+        Ok(serde_json::to_writer_pretty(out, self)?)
+    }
+}
+
+/// Delivers an event for one subscription
+type Sink = Box<dyn Fn(&Subscription, &Event) -> Result<(), DeliveryError> + Send + Sync>;
+
+struct Registry {
+    /// By subscriber
+    subscriptions: BTreeMap<String, Subscription>,
+    sinks: HashMap<String, Sink>,
+    /// Saved here on every change, if it was opened from a file
+    path: Option<PathBuf>,
+}
+
+impl Registry {
+    /// Write every subscription to `path`, through a temporary file so a
+    /// crash can't leave half of them behind
+    fn save(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let subscriptions: Vec<_> = self.subscriptions.values().collect();
+        let partial = path.with_extension("partial");
+        serde_json::to_writer_pretty(File::create(&partial)?, &subscriptions)?;
+        fs::rename(partial, path)
+    }
+}
+
+/// Subscriptions of many consumers, delivering every new video to those
+/// who want it
+///
+/// Cheap to clone, every clone shares the same subscriptions. A video is
+/// delivered once to every subscription that `wants` it, with the sink it
+/// names and the title in its `language`. Failed deliveries are printed to stderr, and only
+/// fail the event, so it's retried, if every subscription failed
+#[derive(Clone)]
+pub struct Subscriptions {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        Subscriptions {
+            registry: Arc::new(Mutex::new(Registry {
+                subscriptions: BTreeMap::new(),
+                sinks: HashMap::new(),
+                path: None,
+            })),
+        }
+    }
+}
+
+impl Subscriptions {
+    /// No subscriptions, kept in memory only
+    pub fn new() -> Self {
+        Subscriptions::default()
+    }
+
+    /// Subscriptions saved at `path`, none if it doesn't exist yet
+    ///
+    /// Every change is saved back to `path`
+    pub fn open<P>(path: P) -> io::Result<Self> where P: AsRef<Path> {
+        let path = path.as_ref().to_path_buf();
+        let saved: Vec<Subscription> = match File::open(&path) {
+            Ok(file) => serde_json::from_reader(file)?,
+            Err(why) if why.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(why) => return Err(why),
+        };
+
+        let subscriptions = Subscriptions::new();
+        {
+            let mut registry = subscriptions.registry.lock().unwrap();
+            registry.subscriptions = saved.into_iter()
+                .map(|subscription| (subscription.subscriber.clone(), subscription))
+                .collect();
+            registry.path = Some(path);
+        }
+        Ok(subscriptions)
+    }
+
+    /// Deliver subscriptions naming `name` with `sink`
+    pub fn sink<F>(self, name: &str, sink: F) -> Self
+        where F: Fn(&Subscription, &Event) -> Result<(), DeliveryError> + Send + Sync + 'static
+    {
+        self.registry.lock().unwrap().sinks.insert(name.to_string(), Box::new(sink));
+        self
+    }
+
+    /// Add `subscription`, replacing the subscriber's old one if they had
+    /// one
+    ///
+    /// Fails with `InvalidInput` if its `quiet` hours can't be read
+    pub fn subscribe(&self, subscription: Subscription) -> io::Result<()> {
+        check(&subscription)?;
+        let mut registry = self.registry.lock().unwrap();
+        registry.subscriptions.insert(subscription.subscriber.clone(), subscription);
+        registry.save()
+    }
+
+    /// Remove the subscription of `subscriber`, returning it if there was
+    /// one
+    pub fn unsubscribe(&self, subscriber: &str) -> io::Result<Option<Subscription>> {
+        let mut registry = self.registry.lock().unwrap();
+        let removed = registry.subscriptions.remove(subscriber);
+        if removed.is_some() {
+            registry.save()?;
+        }
+        Ok(removed)
+    }
+
+    /// The subscription of `subscriber`
+    pub fn get(&self, subscriber: &str) -> Option<Subscription> {
+        self.registry.lock().unwrap().subscriptions.get(subscriber).cloned()
+    }
+
+    /// Every subscription, by subscriber
+    pub fn list(&self) -> Vec<Subscription> {
+        self.registry.lock().unwrap().subscriptions.values().cloned().collect()
+    }
+
+    /// How many subscriptions there are
+    pub fn len(&self) -> usize {
+        self.registry.lock().unwrap().subscriptions.len()
+    }
+
+    /// Whether nobody is subscribed
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every subscription along with `state`, to be written somewhere safe
+    pub fn export(&self, state: Option<PollerState>) -> Backup {
+        Backup {
+            subscriptions: self.list(),
+            state,
+        }
+    }
+
+    /// Replace every subscription with the ones in `backup`, returning its
+    /// state for `VLive::restore`
+    ///
+    /// Nothing is replaced if any of them would be refused by `subscribe`
+    pub fn import(&self, backup: Backup) -> io::Result<Option<PollerState>> {
+        for subscription in &backup.subscriptions {
+            check(subscription)?;
+        }
+        let mut registry = self.registry.lock().unwrap();
+        registry.subscriptions = backup.subscriptions.into_iter()
+            .map(|subscription| (subscription.subscriber.clone(), subscription))
+            .collect();
+        registry.save()?;
+        Ok(backup.state)
+    }
+}
+
+/// Refuse `subscription` if its `quiet` hours can't be read
+fn check(subscription: &Subscription) -> io::Result<()> {
+    if subscription.quiet.as_ref().is_some_and(|quiet| quiet.hours().is_none()) {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "quiet hours must be HH:MM"));
+    }
+    Ok(())
+}
+
+impl VLiveCallback for Subscriptions {
+    fn on_new(&mut self, ctx: &Ctx, video: VLiveVideo) -> Flow {
+        deliver_new(self, ctx, video)
+    }
+
+    fn deliver(&mut self, _: &Ctx, event: &Event) -> Result<Flow, DeliveryError> {
+        let registry = self.registry.lock().unwrap();
+        let now = SystemTime::now();
+        let mut delivered = false;
+        let mut failed = None;
+        for subscription in registry.subscriptions.values().filter(|subscription| subscription.wants(&event.video, now)) {
+            let result = match registry.sinks.get(&subscription.sink) {
+                Some(sink) => sink(subscription, &subscription.localize(event)),
+                None => Err(format!("no sink called {}", subscription.sink).into()),
+            };
+            match result {
+                Ok(()) => delivered = true,
+                Err(why) => {
+                    eprintln!("VLive Error: Delivering to {} failed: {}", subscription.subscriber, why);
+                    failed = Some(why);
+                },
+            }
+        }
+
+        match failed {
+            Some(why) if !delivered => Err(why),
+            _ => Ok(Flow::Continue),
+        }
+    }
+}
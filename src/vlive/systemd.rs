@@ -0,0 +1,112 @@
+//! Telling systemd how the listener is doing
+//!
+//! Run as a `Type=notify` service, a listener with `VLive::systemd` tells
+//! systemd once it's up, and keeps the watchdog fed for as long as polls
+//! succeed. If polling wedges, the keep-alives stop and systemd restarts
+//! the service:
+//!
+//! ```ini
+//! [Service]
+//! Type=notify
+//! ExecStart=/usr/local/bin/vlive-bot
+//! WatchdogSec=60
+//! Restart=on-failure
+//! ```
+//!
+//! Pick a `WatchdogSec` a good deal longer than the slowest feed's wait,
+//! since the watchdog is only fed after polls.
+
+use std::env;
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+/// Sends notifications to systemd's `$NOTIFY_SOCKET`
+///
+/// `VLive::systemd` takes care of it, use one directly to send your own,
+/// like a `STATUS=` line
+pub struct Notify {
+    socket: UnixDatagram,
+    path: PathBuf,
+    /// How often systemd expects a keep-alive, if the watchdog is on
+    watchdog: Option<Duration>,
+}
+
+impl Notify {
+    /// The socket systemd handed us, `None` if we weren't started by
+    /// systemd as a `Type=notify` service
+    ///
+    /// The watchdog interval comes from `$WATCHDOG_USEC`, if it was meant
+    /// for this process
+    pub fn from_env() -> Option<Self> {
+        let path = env::var_os("NOTIFY_SOCKET")?;
+        let watchdog = env::var("WATCHDOG_USEC").ok()
+            .filter(|_| env::var("WATCHDOG_PID").map_or(true, |pid| pid == process::id().to_string()))
+            .and_then(|usec| usec.parse().ok())
+            .filter(|&usec| usec > 0)
+            .map(Duration::from_micros);
+        match Notify::new(path, watchdog) {
+            Ok(notify) => Some(notify),
+            Err(why) => {
+                eprintln!("VLive Error: Could not open the systemd notify socket: {}", why);
+                None
+            },
+        }
+    }
+
+    /// Send to the socket at `path`, expecting a keep-alive every
+    /// `watchdog`
+    ///
+    /// Paths starting with `@` are abstract sockets, which only Linux has
+    pub fn new<P>(path: P, watchdog: Option<Duration>) -> io::Result<Self> where P: Into<PathBuf> {
+        Ok(Notify {
+            socket: UnixDatagram::unbound()?,
+            path: path.into(),
+            watchdog,
+        })
+    }
+
+    /// How often systemd expects `watchdog` to be called, `None` if the
+    /// watchdog is off
+    pub fn watchdog_every(&self) -> Option<Duration> {
+        self.watchdog
+    }
+
+    /// Send `state`, newline separated `KEY=value` pairs like `"READY=1"`
+    pub fn send(&self, state: &str) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            use std::os::unix::ffi::OsStrExt;
+            use std::os::unix::net::SocketAddr;
+
+            if let Some(name) = self.path.as_os_str().as_bytes().strip_prefix(b"@") {
+                let addr = SocketAddr::from_abstract_name(name)?;
+                return self.socket.send_to_addr(state.as_bytes(), &addr).map(|_| ());
+            }
+        }
+        self.socket.send_to(state.as_bytes(), &self.path).map(|_| ())
+    }
+
+    /// Startup is done
+    pub fn ready(&self) -> io::Result<()> {
+        self.send("READY=1")
+    }
+
+    /// Still alive, resets the watchdog
+    pub fn watchdog(&self) -> io::Result<()> {
+        self.send("WATCHDOG=1")
+    }
+
+    /// Shutting down, so systemd doesn't mistake it for a crash
+    pub fn stopping(&self) -> io::Result<()> {
+        self.send("STOPPING=1")
+    }
+
+    /// Free-form status shown by `systemctl status`
+    pub fn status(&self, status: &str) -> io::Result<()> {
+        self.send(&format!("STATUS={}", status))
+    }
+}
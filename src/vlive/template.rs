@@ -0,0 +1,242 @@
+//! Formatting messages about videos from user templates
+//!
+//! Sinks format their messages with a `Template`, so wording and language
+//! are up to you instead of being hard-coded. The syntax is a small part of
+//! Handlebars:
+//!
+//! ```rust,ignore
+//! let template = Template::parse("{{channel_name}} is {{#if live}}LIVE{{else}}uploaded{{/if}}: {{title}} {{url}}")?;
+//! println!("{}", template.render(&video));
+//! ```
+//!
+//! `{{field}}` is replaced with a field of the video, and
+//! `{{#if field}}…{{else}}…{{/if}}` picks one of two branches, the first one
+//! if the field is true or not empty. `{{else}}` is optional. The fields are
+//!
+//! * `title`, `seq`, `id`, `code`, `url`, `thumbnail` and `type` (`LIVE` or
+//!   `VOD`)
+//! * `channel_name`, `channel_seq`, `channel_id`, `channel_code` and
+//!   `channel_url`
+//! * `url` and `channel_url` are always whole links, `id` and `channel_id`
+//!   may only be paths, `code` and `channel_code` are just the codes
+//! * `live`, `paid` and `plus`, which are true or false
+//! * `platform`, like `VLIVE` or `YOUTUBE`
+//! * `mirrors`, links to the same broadcast on other platforms, separated by
+//!   spaces, see `VLive::correlate`
+//!
+//! Unknown fields and unbalanced `{{#if}}`s are caught by `parse`, so a
+//! broken template is found when it's loaded, not when the first video is.
+
+use std::error::Error;
+use std::fmt;
+
+use super::{ChannelType, VLiveVideo, VideoType};
+
+/// Every field a template can use
+pub(crate) const FIELDS: &[&str] = &[
+    "title", "seq", "id", "code", "url", "thumbnail", "type",
+    "channel_name", "channel_seq", "channel_id", "channel_code", "channel_url",
+    "live", "paid", "plus", "platform", "mirrors",
+];
+
+/// A template that went wrong while parsing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    /// A `{{` without a `}}`
+    Unclosed,
+    /// No video has this field
+    UnknownField(String),
+    /// An `{{else}}` or `{{/if}}` outside of an `{{#if}}`
+    Unexpected(String),
+    /// An `{{#if}}` without an `{{/if}}`
+    MissingEndIf,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TemplateError::Unclosed => write!(f, "`{{{{` is never closed"),
+            TemplateError::UnknownField(ref field) => write!(f, "Unknown field `{}`", field),
+            TemplateError::Unexpected(ref tag) => write!(f, "`{{{{{}}}}}` outside of an `{{{{#if}}}}`", tag),
+            TemplateError::MissingEndIf => write!(f, "`{{{{#if}}}}` is never closed with `{{{{/if}}}}`"),
+        }
+    }
+}
+
+impl Error for TemplateError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Field(String),
+    If {
+        field: String,
+        then: Vec<Part>,
+        otherwise: Vec<Part>,
+    },
+}
+
+/// A parsed message template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let mut tokens = Tokens { rest: source };
+        let (parts, end) = parse_parts(&mut tokens)?;
+        match end {
+            None => Ok(Template { parts }),
+            Some(tag) => Err(TemplateError::Unexpected(tag.to_string())),
+        }
+    }
+
+    /// Fill the template in with `video`
+    pub fn render(&self, video: &VLiveVideo) -> String {
+        let mut out = String::new();
+        render(&self.parts, video, &|value| value, &mut out);
+        out
+    }
+
+    /// Fill the template in with `video`, escaping the fields for HTML
+    ///
+    /// Only what's put in is escaped, the template itself can be markup
+    pub fn render_html(&self, video: &VLiveVideo) -> String {
+        let mut out = String::new();
+        render(&self.parts, video, &|value| escape(&value), &mut out);
+        out
+    }
+}
+
+/// Splits a template into text and the insides of `{{…}}`
+struct Tokens<'a> {
+    rest: &'a str,
+}
+
+enum Token<'a> {
+    Text(&'a str),
+    Tag(&'a str),
+}
+
+impl<'a> Tokens<'a> {
+    fn next(&mut self) -> Result<Option<Token<'a>>, TemplateError> {
+        if self.rest.is_empty() {
+            return Ok(None);
+        }
+        match self.rest.find("{{") {
+            Some(0) => {
+                let end = self.rest.find("}}").ok_or(TemplateError::Unclosed)?;
+                let tag = self.rest[2..end].trim();
+                self.rest = &self.rest[end + 2..];
+                Ok(Some(Token::Tag(tag)))
+            },
+            Some(start) => {
+                let text = &self.rest[..start];
+                self.rest = &self.rest[start..];
+                Ok(Some(Token::Text(text)))
+            },
+            None => {
+                let text = self.rest;
+                self.rest = "";
+                Ok(Some(Token::Text(text)))
+            },
+        }
+    }
+}
+
+/// Parse until the end or an `{{else}}` or `{{/if}}`, which is returned
+fn parse_parts<'a>(tokens: &mut Tokens<'a>) -> Result<(Vec<Part>, Option<&'a str>), TemplateError> {
+    let mut parts = Vec::new();
+    while let Some(token) = tokens.next()? {
+        let tag = match token {
+            Token::Text(text) => {
+                parts.push(Part::Text(text.to_string()));
+                continue;
+            },
+            Token::Tag(tag) => tag,
+        };
+
+        if tag == "else" || tag == "/if" {
+            return Ok((parts, Some(tag)));
+        }
+        match tag.strip_prefix("#if ") {
+            Some(field) => {
+                let field = known(field.trim())?;
+                let (then, end) = parse_parts(tokens)?;
+                let otherwise = match end {
+                    Some("else") => match parse_parts(tokens)? {
+                        (otherwise, Some("/if")) => otherwise,
+                        (_, Some(tag)) => return Err(TemplateError::Unexpected(tag.to_string())),
+                        (_, None) => return Err(TemplateError::MissingEndIf),
+                    },
+                    Some(_) => Vec::new(),
+                    None => return Err(TemplateError::MissingEndIf),
+                };
+                parts.push(Part::If { field, then, otherwise });
+            },
+            None => parts.push(Part::Field(known(tag)?)),
+        }
+    }
+    Ok((parts, None))
+}
+
+/// Make sure videos have `field`
+fn known(field: &str) -> Result<String, TemplateError> {
+    if FIELDS.contains(&field) {
+        Ok(field.to_string())
+    } else {
+        Err(TemplateError::UnknownField(field.to_string()))
+    }
+}
+
+fn render(parts: &[Part], video: &VLiveVideo, escape: &dyn Fn(String) -> String, out: &mut String) {
+    for part in parts {
+        match *part {
+            Part::Text(ref text) => out.push_str(text),
+            Part::Field(ref field) => out.push_str(&escape(value(video, field))),
+            Part::If { ref field, ref then, ref otherwise } => {
+                let branch = if truthy(video, field) { then } else { otherwise };
+                render(branch, video, escape, out);
+            },
+        }
+    }
+}
+
+/// `text` safe to put in HTML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Whether a field of `video` is true or not empty
+pub(crate) fn truthy(video: &VLiveVideo, field: &str) -> bool {
+    let value = value(video, field);
+    !value.is_empty() && value != "false"
+}
+
+/// A field of `video` as text, one of `FIELDS`
+pub(crate) fn value(video: &VLiveVideo, field: &str) -> String {
+    match field {
+        "title" => video.video_title.clone(),
+        "seq" => video.video_seq.to_string(),
+        "id" => video.video_id.clone(),
+        "code" => video.video_code(),
+        "url" => video.url(),
+        "thumbnail" => video.video_thumbnail.clone().unwrap_or_default(),
+        "type" => format!("{:?}", video.video_type),
+        "channel_name" => video.channel_name.clone(),
+        "channel_seq" => video.channel_seq.to_string(),
+        "channel_id" => video.channel_id.clone(),
+        "channel_code" => video.channel_code(),
+        "channel_url" => video.channel_url(),
+        "live" => (video.video_type == VideoType::LIVE).to_string(),
+        "paid" => video.video_paid.to_string(),
+        "plus" => (video.channel_type == ChannelType::PLUS).to_string(),
+        "platform" => format!("{:?}", video.platform),
+        "mirrors" => video.video_mirrors.join(" "),
+        _ => String::new(),
+    }
+}
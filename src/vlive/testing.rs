@@ -0,0 +1,158 @@
+//! Videos and pages for testing your own callbacks and sinks
+//!
+//! `video()` starts a video with every field filled in, so a test only sets
+//! what it cares about:
+//!
+//! ```rust,ignore
+//! let video = testing::video().seq(42).title("Behind").live().build();
+//! assert_eq!(handler.on_new(&Ctx::detached(), video), Flow::Continue);
+//! ```
+//!
+//! `RECENT_PAGE` is a saved page of the recent videos feed, and
+//! `recent_videos` what it parses to, for going through a `Simulation` or a
+//! `Poller`.
+
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use super::{ChannelType, Event, Platform, VLiveVideo, VideoStage, VideoType};
+
+/// A page of the recent videos feed, with a VLIVE+ VOD, a live stream and
+/// a Channel+ VOD
+pub const RECENT_PAGE: &str = include_str!("../fixtures/recent.html");
+
+/// The videos on `RECENT_PAGE`, newest first
+pub fn recent_videos() -> Vec<VLiveVideo> {
+    vec![
+        video().seq(50002)
+            .title("[VLIVE+] Behind the stage full ver.")
+            .paid()
+            .thumbnail("https://v-phinf.pstatic.net/20180101_1/thumb.jpg?type=f228_128")
+            .build(),
+        video().seq(50001)
+            .title("Comeback countdown LIVE")
+            .live()
+            .channel("/channels/EDBF", 6, "TWICE")
+            .build(),
+        video().seq(50000)
+            .title("Fanmeeting full concert")
+            .thumbnail("https://v-phinf.pstatic.net/20180101_2/thumb.jpg?type=f228_128")
+            .channel("/channels/C1B7AF+", 1024, "GFRIEND+")
+            .plus()
+            .build(),
+    ]
+}
+
+/// Start a video, a free VOD on BTS's channel until told otherwise
+pub fn video() -> VideoBuilder {
+    VideoBuilder {
+        video: VLiveVideo {
+            video_id: "/video/50000".to_string(),
+            video_seq: 50000,
+            video_title: "Test video".to_string(),
+            video_titles: HashMap::new(),
+            video_type: VideoType::VOD,
+            video_stage: VideoStage::VOD,
+            video_paid: false,
+            video_thumbnail: None,
+            channel_id: "/channels/FE619".to_string(),
+            channel_seq: 363,
+            channel_name: "BTS".to_string(),
+            channel_type: ChannelType::BASIC,
+            platform: Platform::VLIVE,
+            video_mirrors: Vec::new(),
+        },
+    }
+}
+
+/// `video` as it would be delivered, with `id`
+pub fn event(id: u64, video: VLiveVideo) -> Event {
+    Event {
+        id,
+        poll_id: 0,
+        detected_at: SystemTime::now(),
+        video,
+    }
+}
+
+/// A `VLiveVideo` being put together, see `video`
+#[derive(Debug, Clone)]
+pub struct VideoBuilder {
+    video: VLiveVideo,
+}
+
+impl VideoBuilder {
+    /// Set `video_seq`, and `video_id` to match
+    pub fn seq(mut self, seq: u64) -> Self {
+        self.video.video_seq = seq;
+        self.video.video_id = format!("/video/{}", seq);
+        self
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.video.video_title = title.to_string();
+        self
+    }
+
+    /// Add a translated title, see `VLive::languages`
+    pub fn translated(mut self, language: &str, title: &str) -> Self {
+        self.video.video_titles.insert(language.to_string(), title.to_string());
+        self
+    }
+
+    /// Make it a live stream that's on right now
+    pub fn live(self) -> Self {
+        self.stage(VideoStage::LIVE)
+    }
+
+    /// Set `video_stage`, and `video_type` to match
+    pub fn stage(mut self, stage: VideoStage) -> Self {
+        self.video.video_type = match stage {
+            VideoStage::VOD => VideoType::VOD,
+            _ => VideoType::LIVE,
+        };
+        self.video.video_stage = stage;
+        self
+    }
+
+    /// Make it a paid VLIVE+ product
+    pub fn paid(mut self) -> Self {
+        self.video.video_paid = true;
+        self
+    }
+
+    pub fn thumbnail(mut self, url: &str) -> Self {
+        self.video.video_thumbnail = Some(url.to_string());
+        self
+    }
+
+    /// Move it to another channel
+    pub fn channel(mut self, id: &str, seq: u64, name: &str) -> Self {
+        self.video.channel_id = id.to_string();
+        self.video.channel_seq = seq;
+        self.video.channel_name = name.to_string();
+        self
+    }
+
+    /// Make the channel a Channel+
+    pub fn plus(mut self) -> Self {
+        self.video.channel_type = ChannelType::PLUS;
+        self
+    }
+
+    /// Say it was found on another platform
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.video.platform = platform;
+        self
+    }
+
+    pub fn build(self) -> VLiveVideo {
+        self.video
+    }
+}
+
+impl From<VideoBuilder> for VLiveVideo {
+    fn from(builder: VideoBuilder) -> Self {
+        builder.build()
+    }
+}
@@ -0,0 +1,146 @@
+//! Keeping thumbnails on disk
+//!
+//! Every sink that attaches a thumbnail would download it again, so with
+//! Discord, Telegram and a feed reader that's three downloads of the same
+//! image. A `ThumbnailCache` downloads each thumbnail once and hands out the
+//! copy on disk after that.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use super::transport::TransportError;
+use super::VLiveVideo;
+
+/// Downloads an image
+type Fetch = Box<dyn Fn(&str) -> Result<Vec<u8>, TransportError> + Send + Sync>;
+
+/// Thumbnails on disk by video code, up to `max_bytes` in total
+///
+/// When the cache grows past `max_bytes`, the thumbnails used longest ago
+/// are deleted. Share one between sinks with an `Arc`
+///
+/// ```rust,ignore
+/// let thumbnails = ThumbnailCache::new("/var/cache/vlive/thumbs", 64 * 1024 * 1024);
+/// if let Some(image) = thumbnails.get(&video)? {
+///     discord.attach("thumb.jpg", image);
+/// }
+/// ```
+pub struct ThumbnailCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    fetch: Fetch,
+    /// Held while evicting, so two sinks don't delete the same files
+    evicting: Mutex<()>,
+}
+
+impl ThumbnailCache {
+    /// Keep thumbnails in `dir`, which is created if needed
+    pub fn new<P>(dir: P, max_bytes: u64) -> Self where P: Into<PathBuf> {
+        ThumbnailCache {
+            dir: dir.into(),
+            max_bytes,
+            fetch: Box::new(download),
+            evicting: Mutex::new(()),
+        }
+    }
+
+    /// Download thumbnails with `fetch` instead, like through a proxy
+    pub fn fetch<F>(mut self, fetch: F) -> Self where F: Fn(&str) -> Result<Vec<u8>, TransportError> + Send + Sync + 'static {
+        self.fetch = Box::new(fetch);
+        self
+    }
+
+    /// Where the thumbnail of `video` is kept
+    pub fn path(&self, video: &VLiveVideo) -> PathBuf {
+        let code: String = video.video_id.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        self.dir.join(format!("{}.jpg", code.trim_matches('_')))
+    }
+
+    /// The thumbnail of `video`, downloaded if it isn't on disk yet
+    ///
+    /// `None` if the video has no thumbnail
+    pub fn get(&self, video: &VLiveVideo) -> Result<Option<Vec<u8>>, TransportError> {
+        let url = match video.video_thumbnail {
+            Some(ref url) => url,
+            None => return Ok(None),
+        };
+        let path = self.path(video);
+        if let Ok(image) = fs::read(&path) {
+            //Mark it as recently used
+            let _ = File::options().write(true).open(&path).and_then(|file| file.set_modified(SystemTime::now()));
+            return Ok(Some(image));
+        }
+
+        let image = (self.fetch)(url)?;
+        fs::create_dir_all(&self.dir)?;
+        fs::write(&path, &image)?;
+        self.evict();
+        Ok(Some(image))
+    }
+
+    /// Delete the thumbnails used longest ago until the rest fit
+    fn evict(&self) {
+        let _evicting = self.evicting.lock().unwrap();
+        let mut files: Vec<_> = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .filter_map(|entry| {
+                    let metadata = entry.metadata().ok()?;
+                    Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+                })
+                .collect(),
+            Err(_) => return,
+        };
+        files.sort();
+
+        let mut total: u64 = files.iter().map(|&(_, len, _)| len).sum();
+        for (_, len, path) in files {
+            if total <= self.max_bytes {
+                break;
+            }
+            if fs::remove_file(path).is_ok() {
+                total -= len;
+            }
+        }
+    }
+}
+
+/// Download `url` with ureq
+pub(crate) fn download(url: &str) -> Result<Vec<u8>, TransportError> {
+    download_typed(url).map(|(image, _)| image)
+}
+
+/// Download `url` with ureq, along with its type like `"image/png"`
+///
+/// The type is the response's `Content-Type`, or guessed from the image
+/// itself if the server didn't say it's an image
+pub(crate) fn download_typed(url: &str) -> Result<(Vec<u8>, String), TransportError> {
+    let response = ureq::get(url).call()?;
+    let content_type = response.content_type().to_string();
+    let mut image = Vec::new();
+    response.into_reader().read_to_end(&mut image)?;
+    let content_type = if content_type.starts_with("image/") {
+        content_type
+    } else {
+        image_type(&image).to_string()
+    };
+    Ok((image, content_type))
+}
+
+/// Type of `image` by its first bytes, JPEG if it's none of the others
+pub(crate) fn image_type(image: &[u8]) -> &'static str {
+    if image.starts_with(b"\x89PNG") {
+        "image/png"
+    } else if image.starts_with(b"GIF8") {
+        "image/gif"
+    } else if image.starts_with(b"RIFF") && image.get(8..12) == Some(b"WEBP") {
+        "image/webp"
+    } else {
+        "image/jpeg"
+    }
+}
@@ -0,0 +1,372 @@
+//! How pages are fetched, `Transport` and the wrappers around it
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::{fmt, fs, thread, time};
+
+#[cfg(not(target_arch = "wasm32"))]
+use super::error::snippet;
+
+/// Error returned by a `Transport`
+pub type TransportError = Box<dyn Error + Send + Sync>;
+
+/// How pages are fetched from VLive
+///
+/// Every listener fetches through a transport, `Compressed` by default. If your
+/// program runs several listeners, create one transport, wrap it in an `Arc`
+/// and hand it to all of them with `VLive::transport`, so connection pools,
+/// cookies and rate limits are shared.
+///
+/// Browsers only fetch asynchronously, so in WebAssembly there are no
+/// built-in transports, see `browser::fetch` instead
+pub trait Transport: Send + Sync + 'static {
+    /// Fetch `url` and return the response body
+    fn get(&self, url: &str) -> Result<String, TransportError>;
+}
+
+/// Share one transport between listeners
+impl<T> Transport for Arc<T> where T: Transport + ?Sized {
+    fn get(&self, url: &str) -> Result<String, TransportError> {
+        (**self).get(url)
+    }
+}
+
+/// VLive, or a service a sink posts to, answered with an error status
+///
+/// Returned by the built-in transports and sinks, so error reporters can
+/// tell a rate limit from a maintenance page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpError {
+    pub url: String,
+    pub status: u16,
+    /// Response body, cut to around a kilobyte
+    pub body: String,
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} answered with status {}", self.url, self.status)
+    }
+}
+
+impl Error for HttpError {}
+
+/// FNV-1a hash of `text`, the same on every build unlike `DefaultHasher`,
+/// for whatever outlives the process
+pub(crate) fn stable_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// `text` made safe for a URL path segment or query value
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Send `request` for `url`, error statuses become `HttpError`s
+#[cfg(not(target_arch = "wasm32"))]
+fn fetch(request: ureq::Request, url: &str) -> Result<String, TransportError> {
+    answer(request.call(), url)
+}
+
+/// Body of the `response` from `url`, error statuses become `HttpError`s
+///
+/// For the sinks that post somewhere with ureq
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn answer(response: Result<ureq::Response, ureq::Error>, url: &str) -> Result<String, TransportError> {
+    match response {
+        Ok(response) => Ok(response.into_string()?),
+        Err(ureq::Error::Status(status, response)) => Err(Box::new(HttpError {
+            url: url.to_string(),
+            status,
+            body: snippet(&response.into_string().unwrap_or_default()),
+        })),
+        Err(why) => Err(why.into()),
+    }
+}
+
+/// Fetches pages with gzip or brotli compression
+///
+/// The recent videos page is around 100 KB of HTML, which compresses down to
+/// a fifth of that. Worth it if you poll every few seconds on a metered
+/// connection. Keeps its connections alive between polls
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone)]
+pub struct Compressed {
+    agent: ureq::Agent,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for Compressed {
+    fn default() -> Self {
+        Compressed {
+            agent: ureq::AgentBuilder::new().build(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Transport for Compressed {
+    fn get(&self, url: &str) -> Result<String, TransportError> {
+        //ureq decompresses the body according to Content-Encoding
+        fetch(self.agent.get(url).set("Accept-Encoding", "gzip, br"), url)
+    }
+}
+
+/// Fetches pages logged in to a VLive account
+///
+/// Channel+ and Fanship videos only show up for accounts that have access to
+/// them. Compressed like `Compressed`
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone)]
+pub struct Session {
+    agent: ureq::Agent,
+    cookie: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Session {
+    /// `cookie` is the `Cookie` header of a browser logged in to the
+    /// account, it needs at least `NEO_SES`
+    pub fn new(cookie: &str) -> Self {
+        Session {
+            agent: ureq::AgentBuilder::new().build(),
+            cookie: cookie.to_string(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Transport for Session {
+    fn get(&self, url: &str) -> Result<String, TransportError> {
+        fetch(self.agent.get(url).set("Accept-Encoding", "gzip, br").set("Cookie", &self.cookie), url)
+    }
+}
+
+/// Fetches each channel's pages through the transport that can see them
+///
+/// For memberships spread over several accounts, give every account a
+/// `Session` and route its channels through it. Everything else, like the
+/// recent videos page, goes through the default transport
+///
+/// ```rust,ignore
+/// let fanship = Arc::new(Session::new("NEO_SES=..."));
+/// let transport = Routed::new(Compressed::default())
+///     .route("FE619", fanship.clone())
+///     .route("EBDF", fanship)
+///     .route("F001E5", Arc::new(Session::new("NEO_SES=...")));
+/// ```
+pub struct Routed {
+    default: Box<dyn Transport>,
+    /// Transports by channel code
+    routes: HashMap<String, Arc<dyn Transport>>,
+}
+
+impl Routed {
+    pub fn new<T>(default: T) -> Self where T: Transport {
+        Routed {
+            default: Box::new(default),
+            routes: HashMap::new(),
+        }
+    }
+
+    /// Fetch pages of the channel `code` through `transport`
+    pub fn route<T>(mut self, code: &str, transport: Arc<T>) -> Self where T: Transport {
+        self.routes.insert(code.to_string(), transport);
+        self
+    }
+}
+
+impl Transport for Routed {
+    fn get(&self, url: &str) -> Result<String, TransportError> {
+        let route = channel_code(url).and_then(|code| self.routes.get(code));
+        match route {
+            Some(transport) => transport.get(url),
+            None => self.default.get(url),
+        }
+    }
+}
+
+/// Channel code out of the URL of a `Feed::Channel` page
+fn channel_code(url: &str) -> Option<&str> {
+    let path = url.split_once("://").map_or(url, |(_, rest)| rest);
+    path.strip_prefix("channels.vlive.tv/")?.split(['/', '?']).next()
+}
+
+/// Fetches pages with the `requests` crate, without compression
+///
+/// Needs the `transport-requests` feature
+#[cfg(all(feature = "transport-requests", not(target_arch = "wasm32")))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Requests;
+
+#[cfg(all(feature = "transport-requests", not(target_arch = "wasm32")))]
+impl Transport for Requests {
+    fn get(&self, url: &str) -> Result<String, TransportError> {
+        let response = requests::get(url)?;
+        match response.text() {
+            Some(text) => Ok(text.to_string()),
+            None => Err("response was not valid text".into()),
+        }
+    }
+}
+
+/// Makes sure requests through `transport` are at least `interval` apart
+///
+/// Share one of these between listeners to stay under a global rate limit,
+/// no matter how many of them are polling.
+pub struct RateLimited<T> {
+    transport: T,
+    interval: time::Duration,
+    last: Mutex<Option<time::Instant>>,
+}
+
+impl<T> RateLimited<T> where T: Transport {
+    pub fn new(transport: T, interval: time::Duration) -> Self {
+        RateLimited {
+            transport,
+            interval,
+            last: Mutex::new(None),
+        }
+    }
+}
+
+impl<T> Transport for RateLimited<T> where T: Transport {
+    fn get(&self, url: &str) -> Result<String, TransportError> {
+        {
+            let mut last = self.last.lock().unwrap();
+            if let Some(last) = *last {
+                let elapsed = last.elapsed();
+                if elapsed < self.interval {
+                    thread::sleep(self.interval - elapsed);
+                }
+            }
+            *last = Some(time::Instant::now());
+        }
+        self.transport.get(url)
+    }
+}
+
+/// Sees the URL and body of a response
+pub(crate) type Hook = Box<dyn Fn(&str, &str) + Send + Sync>;
+
+/// Hands every response to `hook` before it's parsed
+///
+/// `hook` gets the URL and the body of every successful fetch. Archive them
+/// as regression fixtures, or log them to find out why something didn't
+/// parse in production. See `VLive::on_raw_response`
+pub struct Tap<T> {
+    transport: T,
+    hook: Hook,
+}
+
+impl<T> Tap<T> where T: Transport {
+    pub fn new<F>(transport: T, hook: F) -> Self where F: Fn(&str, &str) + Send + Sync + 'static {
+        Tap {
+            transport,
+            hook: Box::new(hook),
+        }
+    }
+}
+
+impl<T> Transport for Tap<T> where T: Transport {
+    fn get(&self, url: &str) -> Result<String, TransportError> {
+        let body = self.transport.get(url)?;
+        (self.hook)(url, &body);
+        Ok(body)
+    }
+}
+
+/// Remembers responses for `max_age`, in memory and optionally on disk
+///
+/// Meant for lookups that are repeated a lot, like channel info fetched by
+/// an `Enrich` for every new video. Don't put it in front of the feeds a
+/// listener polls, or new videos show up `max_age` late. Only successful
+/// responses are cached
+///
+/// ```rust,ignore
+/// let lookups = Cached::new(Compressed::default(), Duration::from_secs(60 * 60))
+///     .on_disk("/var/cache/vlive");
+/// ```
+pub struct Cached<T> {
+    transport: T,
+    max_age: time::Duration,
+    /// Responses by URL, with when they were fetched
+    memory: Mutex<HashMap<String, (time::Instant, String)>>,
+    dir: Option<PathBuf>,
+}
+
+impl<T> Cached<T> where T: Transport {
+    pub fn new(transport: T, max_age: time::Duration) -> Self {
+        Cached {
+            transport,
+            max_age,
+            memory: Mutex::new(HashMap::new()),
+            dir: None,
+        }
+    }
+
+    /// Keep responses in `dir` too, so they survive restarts
+    ///
+    /// The directory is created if needed. Files are named after a hash of
+    /// the URL, start with the URL itself on a line of its own, and go by
+    /// their modification time
+    pub fn on_disk<P>(mut self, dir: P) -> Self where P: Into<PathBuf> {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// Where the response for `url` is kept on disk
+    fn path(&self, url: &str) -> Option<PathBuf> {
+        Some(self.dir.as_ref()?.join(format!("{:016x}", stable_hash(url))))
+    }
+
+    /// A response for `url` on disk, if it's still fresh
+    fn read(&self, url: &str) -> Option<String> {
+        let path = self.path(url)?;
+        let age = fs::metadata(&path).ok()?.modified().ok()?.elapsed().ok()?;
+        if age >= self.max_age {
+            return None;
+        }
+        //Another URL with the same hash is a miss
+        let cached = fs::read_to_string(path).ok()?;
+        match cached.split_once('\n') {
+            Some((cached_url, body)) if cached_url == url => Some(body.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl<T> Transport for Cached<T> where T: Transport {
+    fn get(&self, url: &str) -> Result<String, TransportError> {
+        let now = time::Instant::now();
+        {
+            let mut memory = self.memory.lock().unwrap();
+            memory.retain(|_, (at, _)| now.duration_since(*at) < self.max_age);
+            if let Some((_, body)) = memory.get(url) {
+                return Ok(body.clone());
+            }
+        }
+
+        let body = match self.read(url) {
+            Some(body) => body,
+            None => {
+                let body = self.transport.get(url)?;
+                //A cache that can't be written is just a slower cache
+                if let (Some(dir), Some(path)) = (&self.dir, self.path(url)) {
+                    let _ = fs::create_dir_all(dir).and_then(|_| fs::write(path, format!("{}\n{}", url, body)));
+                }
+                body
+            },
+        };
+        self.memory.lock().unwrap().insert(url.to_string(), (now, body.clone()));
+        Ok(body)
+    }
+}